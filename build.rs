@@ -0,0 +1,16 @@
+//! Generates the GL bindings used for the GL state that glium does not
+//! manage, since the bindings of glium are private.
+
+extern crate gl_generator;
+
+fn main() {
+  use gl_generator::{Api, Fallbacks, Profile, Registry, StructGenerator};
+  if std::env::var_os ("CARGO_FEATURE_GLIUM").is_none() {
+    return
+  }
+  let out_dir = std::env::var ("OUT_DIR").unwrap();
+  let mut file = std::fs::File::create (
+    std::path::Path::new (&out_dir).join ("gl_bindings.rs")).unwrap();
+  Registry::new (Api::Gl, (4, 5), Profile::Core, Fallbacks::All, [])
+    .write_bindings (StructGenerator, &mut file).unwrap();
+}
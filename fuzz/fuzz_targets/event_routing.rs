@@ -0,0 +1,198 @@
+//! Feeds arbitrary sequences of subscriptions, hang-ups, events and receiver
+//! polls through an `EventRouter` and checks the delivered events against a
+//! model of the routing rules:
+//!
+//! - audio device events go to the audio subscriber, window events to the
+//!   subscriber of their window, and everything else to the broadcast receiver
+//! - a subscriber that has hung up or been unsubscribed receives nothing more
+//!   and its events fall back to the broadcast receiver
+//! - every event is received exactly once and in order, with consecutive
+//!   text input of a window coalesced by `EventReceiver::drain_frame`
+
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate glium_sdl2_hack;
+
+use glium_sdl2_hack::{Event, EventReceiver, EventRouter, SystemTheme};
+
+///////////////////////////////////////////////////////////////////////////////
+//  constants                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+const WINDOWS : usize = 4;
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// A receiver with the events it is expected to receive, in order.
+struct Tracked {
+  receiver : EventReceiver,
+  expected : std::collections::VecDeque <Event>
+}
+
+/// The router under test with the model of where its events should go.
+struct Harness {
+  router    : EventRouter,
+  /// Every receiver created, `None` once dropped; index 0 is the broadcast
+  /// receiver
+  receivers : Vec <Option <Tracked>>,
+  /// Receiver each window is subscribed to, as far as the router knows
+  windows   : [Option <usize>; WINDOWS],
+  audio     : Option <usize>
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+impl Tracked {
+  fn new (receiver : EventReceiver) -> Self {
+    Tracked { receiver, expected: std::collections::VecDeque::new() }
+  }
+
+  fn try_recv (&mut self) {
+    assert_eq!(self.receiver.pending(), self.expected.len());
+    assert_eq!(self.receiver.try_recv(), self.expected.pop_front());
+  }
+
+  fn drain_frame (&mut self) {
+    assert_eq!(self.receiver.pending(), self.expected.len());
+    let frame = self.receiver.drain_frame();
+    assert_eq!(frame.len(), coalesced_len (&self.expected));
+    let expected = coalesce_text (self.expected.drain (..));
+    assert_eq!(frame.collect::<Vec <_>>(), expected);
+    assert_eq!(self.receiver.pending(), 0);
+  }
+}
+
+impl Harness {
+  fn new() -> Self {
+    let (router, receiver) = EventRouter::new();
+    Harness {
+      router,
+      receivers: vec![Some (Tracked::new (receiver))],
+      windows:   [None; WINDOWS],
+      audio:     None
+    }
+  }
+
+  fn step (&mut self, op : u8, arg : u8) {
+    let window = arg as usize % WINDOWS;
+    match op % 9 {
+      0 => {
+        let receiver = self.router.subscribe (window as u32);
+        self.windows[window] = Some (self.track (receiver));
+      }
+      1 => {
+        self.router.unsubscribe (window as u32);
+        self.windows[window] = None;
+      }
+      2 => if let Some (index) = self.windows[window] {
+        self.receivers[index] = None;
+      },
+      3 => {
+        let receiver = self.router.subscribe_audio();
+        self.audio = Some (self.track (receiver));
+      }
+      4 => if let Some (index) = self.audio {
+        self.receivers[index] = None;
+      },
+      5 | 6 => self.send (arg),
+      7 => if let Some (tracked) = self.target (arg) {
+        tracked.try_recv();
+      },
+      8 => if let Some (tracked) = self.target (arg) {
+        tracked.drain_frame();
+      },
+      _ => unreachable!()
+    }
+  }
+
+  fn send (&mut self, arg : u8) {
+    let window_id = (arg / 4) as u32 % WINDOWS as u32;
+    let event = match arg % 4 {
+      0 => Event::FullscreenResumed { window_id },
+      1 => Event::TextInput { window_id, text: format!("{}", arg) },
+      2 => Event::ThemeChanged (SystemTheme::Dark),
+      3 => Event::AudioDeviceRemoved { device: arg as u32, capture: false },
+      _ => unreachable!()
+    };
+    let index = self.route (&event);
+    self.receivers[index].as_mut().unwrap().expected.push_back (event.clone());
+    self.router.send (event);
+  }
+
+  /// Index of the receiver the event should go to, forgetting subscribers
+  /// that have hung up as the router does.
+  fn route (&mut self, event : &Event) -> usize {
+    if let Event::AudioDeviceRemoved { .. } = *event {
+      match self.audio {
+        Some (index) if self.receivers[index].is_some() => return index,
+        _ => self.audio = None
+      }
+    }
+    if let Some (window_id) = event.window_id() {
+      let window = window_id as usize;
+      match self.windows[window] {
+        Some (index) if self.receivers[index].is_some() => return index,
+        _ => self.windows[window] = None
+      }
+    }
+    0
+  }
+
+  fn track (&mut self, receiver : EventReceiver) -> usize {
+    self.receivers.push (Some (Tracked::new (receiver)));
+    self.receivers.len() - 1
+  }
+
+  fn target (&mut self, arg : u8) -> Option <&mut Tracked> {
+    let index = arg as usize % self.receivers.len();
+    self.receivers[index].as_mut()
+  }
+
+  /// Every receiver still alive must have received all of its events.
+  fn finish (&mut self) {
+    for tracked in self.receivers.iter_mut().filter_map (Option::as_mut) {
+      tracked.drain_frame();
+      assert_eq!(tracked.receiver.try_recv(), None);
+    }
+  }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  functions                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+fuzz_target!(|data : &[u8]| {
+  let mut harness = Harness::new();
+  for step in data.chunks (2) {
+    harness.step (step[0], step.get (1).cloned().unwrap_or (0));
+  }
+  harness.finish();
+});
+
+/// Model of frame coalescing: text input is appended to the previous event
+/// if it is text input of the same window.
+fn coalesce_text <I : Iterator <Item=Event>> (events : I) -> Vec <Event> {
+  let mut coalesced : Vec <Event> = Vec::new();
+  for event in events {
+    if let Event::TextInput { window_id, ref text } = event {
+      if let Some (&mut Event::TextInput { window_id: last, text: ref mut run })
+        = coalesced.last_mut()
+      {
+        if last == window_id {
+          run.push_str (text);
+          continue
+        }
+      }
+    }
+    coalesced.push (event);
+  }
+  coalesced
+}
+
+fn coalesced_len (events : &std::collections::VecDeque <Event>) -> usize {
+  coalesce_text (events.iter().cloned()).len()
+}
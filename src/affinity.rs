@@ -0,0 +1,87 @@
+//! Tokens recording the threads that a window backend and its display belong
+//! to.
+//!
+//! A window backend captures a `MainThreadToken` for the thread that built
+//! it, and a display facade a `RenderThreadToken` for the thread that built
+//! glium on it. In debug builds every method of the facade asserts that it
+//! is called on that render thread, so that misuse such as a facade smuggled
+//! back to the main thread panics early instead of causing undefined
+//! behavior.
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// The thread a window backend was built on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MainThreadToken {
+  thread_id : std::thread::ThreadId
+}
+
+/// The thread glium was built on for a display.
+#[cfg(feature = "glium")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RenderThreadToken {
+  thread_id : std::thread::ThreadId
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+impl MainThreadToken {
+  /// Capture the calling thread.
+  pub(crate) fn capture() -> Self {
+    MainThreadToken { thread_id: std::thread::current().id() }
+  }
+
+  pub fn thread_id (&self) -> std::thread::ThreadId {
+    self.thread_id
+  }
+
+  /// Returns true if called on the thread of the token.
+  pub fn is_current (&self) -> bool {
+    self.thread_id == std::thread::current().id()
+  }
+}
+
+#[cfg(feature = "glium")]
+impl RenderThreadToken {
+  /// Capture the calling thread.
+  pub(crate) fn capture() -> Self {
+    RenderThreadToken { thread_id: std::thread::current().id() }
+  }
+
+  pub fn thread_id (&self) -> std::thread::ThreadId {
+    self.thread_id
+  }
+
+  /// Returns true if called on the thread of the token.
+  pub fn is_current (&self) -> bool {
+    self.thread_id == std::thread::current().id()
+  }
+
+  /// Panic in debug builds unless called on the thread of the token.
+  pub(crate) fn debug_assert_current (&self) {
+    debug_assert!(self.is_current(),
+      "display used on thread {:?} but built on render thread {:?}",
+      std::thread::current().id(), self.thread_id);
+  }
+}
+
+#[cfg(all(test, feature = "glium"))]
+mod test {
+  use super::*;
+  #[test]
+  fn test_thread_tokens() {
+    let main_thread   = MainThreadToken::capture();
+    let render_thread = RenderThreadToken::capture();
+    assert!(main_thread.is_current());
+    assert_eq!(main_thread.thread_id(), render_thread.thread_id());
+    render_thread.debug_assert_current();
+    std::thread::spawn (move || {
+      assert!(!main_thread.is_current());
+      assert!(!render_thread.is_current());
+    }).join().unwrap();
+  }
+}
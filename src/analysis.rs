@@ -0,0 +1,323 @@
+//! Analysis of rendered frames for visual regression testing.
+//!
+//! A `FrameAnalysis` installed with `SdlGliumDisplayFacade::set_frame_analysis`
+//! reads back the back buffer right before each buffer swap, so frames are
+//! checked exactly as they are about to be presented, and computes a
+//! `FrameSignature` for it: an exact hash of the pixels together with a
+//! coarse grid of average colors. Signatures are compared to golden values,
+//! either exactly or with a tolerance on the grid averages so that small
+//! differences between drivers do not fail a test.
+//!
+//! Golden values are stored as text, one frame per line, e.g.:
+//!
+//! ```ignore
+//! let mut analysis = FrameAnalysis::new (Tolerance::Exact);
+//! analysis.load_goldens (include_str!("goldens.txt")).unwrap();
+//! display.set_frame_analysis (Some (analysis));
+//! // ... render frames ...
+//! let analysis = display.take_frame_analysis().unwrap();
+//! assert!(analysis.mismatches().is_empty(), "{}", analysis.to_goldens());
+//! ```
+
+///////////////////////////////////////////////////////////////////////////////
+//  constants                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Frames are divided into a grid of this many blocks on each side for the
+/// coarse signature
+pub const SIGNATURE_GRID : usize = 8;
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Summary of the pixels of a frame.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FrameSignature {
+  pub width  : u32,
+  pub height : u32,
+  /// FNV-1a hash of the RGBA8 pixels
+  pub hash   : u64,
+  /// Average RGBA color of each block of a `SIGNATURE_GRID` by
+  /// `SIGNATURE_GRID` grid, in rows from bottom to top
+  pub blocks : Vec <[u8; 4]>
+}
+
+/// Result of analyzing one frame.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FrameResult {
+  /// Number of the frame since the analysis was installed
+  pub frame     : u64,
+  pub signature : FrameSignature,
+  pub status    : FrameStatus
+}
+
+/// Computes signatures of presented frames and compares them to golden
+/// values.
+#[derive(Clone, Debug)]
+pub struct FrameAnalysis {
+  tolerance : Tolerance,
+  goldens   : std::collections::BTreeMap <u64, FrameSignature>,
+  frame     : u64,
+  results   : Vec <FrameResult>
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  enums                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+/// How closely a frame must match its golden value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tolerance {
+  /// The pixel hashes must be equal
+  Exact,
+  /// The block averages may differ by at most this much in each channel
+  BlockAverage (u8)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FrameStatus {
+  /// There is no golden value for the frame
+  NoGolden,
+  Match,
+  Mismatch {
+    /// The largest difference in a channel of the block averages
+    max_difference : u8
+  }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AnalysisError {
+  /// A golden value line could not be parsed; carries the line number
+  InvalidGolden (usize)
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+impl FrameSignature {
+  /// Compute the signature of RGBA8 pixels.
+  pub fn new (width : u32, height : u32, pixels : &[u8]) -> Self {
+    let (width_us, height_us) = (width as usize, height as usize);
+    assert_eq!(pixels.len(), 4 * width_us * height_us);
+    let mut hash = 0xcbf29ce484222325u64;
+    for byte in pixels {
+      hash ^= *byte as u64;
+      hash = hash.wrapping_mul (0x100000001b3);
+    }
+    let mut sums = vec![([0u64; 4], 0u64); SIGNATURE_GRID * SIGNATURE_GRID];
+    for y in 0..height_us {
+      let block_y = y * SIGNATURE_GRID / height_us;
+      for x in 0..width_us {
+        let block_x = x * SIGNATURE_GRID / width_us;
+        let (ref mut sum, ref mut count)
+          = sums[block_y * SIGNATURE_GRID + block_x];
+        let pixel = &pixels[4 * (y * width_us + x)..][..4];
+        for channel in 0..4 {
+          sum[channel] += pixel[channel] as u64;
+        }
+        *count += 1;
+      }
+    }
+    let blocks = sums.into_iter().map (|(sum, count)| {
+      let mut average = [0u8; 4];
+      for channel in 0..4 {
+        // blocks without pixels average to zero
+        average[channel] = (sum[channel] + count / 2).checked_div (count)
+          .unwrap_or (0) as u8;
+      }
+      average
+    }).collect();
+    FrameSignature { width, height, hash, blocks }
+  }
+
+  /// Compare to a golden signature.
+  pub fn compare (&self, golden : &FrameSignature, tolerance : Tolerance)
+    -> FrameStatus
+  {
+    if self.width != golden.width || self.height != golden.height {
+      return FrameStatus::Mismatch { max_difference: std::u8::MAX }
+    }
+    let max_difference = self.blocks.iter().zip (golden.blocks.iter())
+      .flat_map (|(a, b)| (0..4).map (move |channel|
+        (a[channel] as i16 - b[channel] as i16).unsigned_abs() as u8))
+      .max().unwrap_or (0);
+    let matches = match tolerance {
+      Tolerance::Exact => self.hash == golden.hash,
+      Tolerance::BlockAverage (max) => max_difference <= max
+    };
+    if matches {
+      FrameStatus::Match
+    } else {
+      FrameStatus::Mismatch { max_difference }
+    }
+  }
+
+  /// Encode as text: `<width>x<height> <hash> <blocks>` in hexadecimal.
+  pub fn encode (&self) -> String {
+    let mut text
+      = format!("{}x{} {:016x} ", self.width, self.height, self.hash);
+    for block in self.blocks.iter() {
+      for channel in block.iter() {
+        text.push_str (&format!("{:02x}", channel));
+      }
+    }
+    text
+  }
+
+  /// Decode text produced by `encode`.
+  pub fn decode (text : &str) -> Option <Self> {
+    let mut fields = text.split_whitespace();
+    let mut dimensions = try_opt!(fields.next()).split ('x');
+    let width  = try_opt!(dimensions.next().and_then (|w| w.parse().ok()));
+    let height = try_opt!(dimensions.next().and_then (|h| h.parse().ok()));
+    let hash = try_opt!(
+      fields.next().and_then (|h| u64::from_str_radix (h, 16).ok()));
+    let blocks_hex = try_opt!(fields.next());
+    if fields.next().is_some() || dimensions.next().is_some() ||
+      blocks_hex.len() != 8 * SIGNATURE_GRID * SIGNATURE_GRID
+    {
+      return None
+    }
+    let mut blocks = Vec::with_capacity (SIGNATURE_GRID * SIGNATURE_GRID);
+    for block_hex in blocks_hex.as_bytes().chunks (8) {
+      let mut block = [0u8; 4];
+      for channel in 0..4 {
+        let hex = try_opt!(
+          std::str::from_utf8 (&block_hex[2 * channel..][..2]).ok());
+        block[channel] = try_opt!(u8::from_str_radix (hex, 16).ok());
+      }
+      blocks.push (block);
+    }
+    Some (FrameSignature { width, height, hash, blocks })
+  }
+}
+
+impl FrameAnalysis {
+  pub fn new (tolerance : Tolerance) -> Self {
+    FrameAnalysis {
+      tolerance,
+      goldens: std::collections::BTreeMap::new(),
+      frame:   0,
+      results: Vec::new()
+    }
+  }
+
+  /// Set the golden value for the given frame number.
+  pub fn set_golden (&mut self, frame : u64, signature : FrameSignature) {
+    self.goldens.insert (frame, signature);
+  }
+
+  /// Load golden values from text produced by `to_goldens`, returning the
+  /// number of values loaded.
+  ///
+  /// Each non-empty line not starting with `#` is a frame number followed by
+  /// an encoded `FrameSignature`.
+  pub fn load_goldens (&mut self, text : &str)
+    -> Result <usize, AnalysisError>
+  {
+    let mut count = 0;
+    for (number, line) in text.lines().enumerate() {
+      let line = line.trim();
+      if line.is_empty() || line.starts_with ('#') {
+        continue
+      }
+      let mut fields = line.splitn (2, ' ');
+      let frame = fields.next().and_then (|frame| frame.parse().ok());
+      let signature = fields.next().and_then (FrameSignature::decode);
+      match (frame, signature) {
+        (Some (frame), Some (signature)) => {
+          self.goldens.insert (frame, signature);
+          count += 1;
+        }
+        _ => return Err (AnalysisError::InvalidGolden (number + 1))
+      }
+    }
+    Ok (count)
+  }
+
+  /// The signatures of all analyzed frames as golden value text, e.g. for
+  /// recording new golden values.
+  pub fn to_goldens (&self) -> String {
+    let mut text = String::new();
+    for result in self.results.iter() {
+      text.push_str (
+        &format!("{} {}\n", result.frame, result.signature.encode()));
+    }
+    text
+  }
+
+  /// Analyze the next frame.
+  pub fn analyze (&mut self, width : u32, height : u32, pixels : &[u8])
+    -> &FrameResult
+  {
+    let frame = self.frame;
+    self.frame += 1;
+    let signature = FrameSignature::new (width, height, pixels);
+    let status = match self.goldens.get (&frame) {
+      Some (golden) => signature.compare (golden, self.tolerance),
+      None => FrameStatus::NoGolden
+    };
+    self.results.push (FrameResult { frame, signature, status });
+    self.results.last().unwrap()
+  }
+
+  pub fn results (&self) -> &[FrameResult] {
+    &self.results
+  }
+
+  /// Results of frames that did not match their golden values.
+  pub fn mismatches (&self) -> Vec <&FrameResult> {
+    self.results.iter()
+      .filter (|result| matches!(result.status, FrameStatus::Mismatch { .. }))
+      .collect()
+  }
+
+  /// Golden values for which no frame has been analyzed yet.
+  pub fn missing_frames (&self) -> Vec <u64> {
+    self.goldens.keys().cloned().filter (|frame| *frame >= self.frame)
+      .collect()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  #[test]
+  fn test_signature() {
+    let pixels = (0..16 * 16).flat_map (|i| vec![i as u8, 0, 255, 255])
+      .collect::<Vec <u8>>();
+    let signature = FrameSignature::new (16, 16, &pixels);
+    assert_eq!(signature.blocks.len(), SIGNATURE_GRID * SIGNATURE_GRID);
+    assert_eq!(FrameSignature::decode (&signature.encode()),
+      Some (signature.clone()));
+    let mut changed = pixels.clone();
+    changed[0] += 4;
+    let changed = FrameSignature::new (16, 16, &changed);
+    assert_eq!(changed.compare (&signature, Tolerance::Exact),
+      FrameStatus::Mismatch { max_difference: 1 });
+    assert_eq!(changed.compare (&signature, Tolerance::BlockAverage (1)),
+      FrameStatus::Match);
+    assert_eq!(
+      FrameSignature::new (8, 32, &pixels).compare (
+        &signature, Tolerance::BlockAverage (255)),
+      FrameStatus::Mismatch { max_difference: 255 });
+  }
+  #[test]
+  fn test_goldens() {
+    let pixels = vec![128u8; 4 * 4 * 4];
+    let mut analysis = FrameAnalysis::new (Tolerance::Exact);
+    analysis.analyze (4, 4, &pixels);
+    let goldens = analysis.to_goldens();
+    let mut analysis = FrameAnalysis::new (Tolerance::Exact);
+    assert_eq!(analysis.load_goldens (&format!("# comment\n{}", goldens)),
+      Ok (1));
+    assert_eq!(analysis.missing_frames(), vec![0]);
+    assert_eq!(analysis.analyze (4, 4, &pixels).status, FrameStatus::Match);
+    assert_eq!(analysis.analyze (4, 4, &pixels).status, FrameStatus::NoGolden);
+    assert!(analysis.mismatches().is_empty());
+    assert_eq!(analysis.load_goldens ("0 4x4 00"),
+      Err (AnalysisError::InvalidGolden (1)));
+  }
+}
@@ -0,0 +1,227 @@
+//! Setting GL attributes before the window and context of a backend are
+//! created.
+//!
+//! The pixel format and context version are fixed when
+//! `SdlGlWindowBuilder::build_backend` creates the window and context, so
+//! attributes such as the depth and stencil bits, multisampling or the GL
+//! version are set with a closure given to
+//! `SdlGlWindowBuilder::build_backend_with_attr`:
+//!
+//! ```ignore
+//! let window_backend = video_subsystem.window ("title", 640, 480)
+//!   .build_backend_with_attr (BackendConfig::default(), |gl_attr| {
+//!     try!{ gl_attr.set_context_version (3, 3) };
+//!     try!{ gl_attr.set_context_profile (sdl2::video::GLProfile::Core) };
+//!     try!{ gl_attr.set_depth_size (24) };
+//!     gl_attr.set_stencil_size (8)
+//!   }).unwrap();
+//! ```
+//!
+//! An error returned by the closure fails the build with
+//! `BackendBuildError::GlAttrError` before the window is created.
+//!
+//! This is the equivalent of `sdl2::VideoSubsystem::gl_attr` for when the
+//! video subsystem is not at hand. Attributes are global to SDL and remain
+//! set for windows built afterwards.
+//!
+//! Alternatively a list of `ContextVersion`s to try in order can be given in
+//! `BackendConfig::context_versions`, e.g. `ContextVersion::FALLBACK`.
+//!
+//! # OpenGL ES
+//!
+//! On embedded targets such as the Raspberry Pi, where desktop GL is not
+//! available, build the backend with `BackendConfig::gles`, which tries
+//! `ContextVersion::ES_FALLBACK`. When only OpenGL ES versions are given,
+//! the `SDL_OPENGL_ES_DRIVER` hint is set so that SDL loads the native
+//! OpenGL ES library through EGL instead of creating an ES profile context
+//! with desktop GL; like the attributes, the hint is global and only takes
+//! effect when SDL first loads a GL library. glium detects OpenGL ES from
+//! the version of the context, so it needs no configuration of its own, but
+//! shaders must be written for GLSL ES.
+
+use sdl2;
+use sdl2_sys;
+
+use sdl2_sys::SDL_GLattr;
+
+///////////////////////////////////////////////////////////////////////////////
+//  constants                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+/// `SDL_GLprofile` values
+const PROFILE_CORE          : i32 = 0x1;
+const PROFILE_COMPATIBILITY : i32 = 0x2;
+const PROFILE_ES            : i32 = 0x4;
+/// Use the native OpenGL ES library rather than an ES profile of desktop GL
+const HINT_OPENGL_ES_DRIVER : &str = "SDL_OPENGL_ES_DRIVER";
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Access to the GL attributes of SDL, given to the closure of
+/// `SdlGlWindowBuilder::build_backend_with_attr`.
+///
+/// Setting an attribute fails with the SDL error if the attribute is unknown
+/// to the SDL version in use.
+#[derive(Debug)]
+pub struct GlAttr {
+  _private : ()
+}
+
+/// A GL version and profile to create a context for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ContextVersion {
+  pub major   : u8,
+  pub minor   : u8,
+  pub profile : sdl2::video::GLProfile
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+impl GlAttr {
+  /// Only created by the builder, since SDL must be initialized.
+  pub(crate) fn new() -> Self {
+    GlAttr { _private: () }
+  }
+
+  /// Set any attribute, e.g. one without a method of its own.
+  pub fn set (&self, attr : SDL_GLattr, value : i32) -> Result <(), String> {
+    if 0 == unsafe { sdl2_sys::SDL_GL_SetAttribute (attr, value) } {
+      Ok (())
+    } else {
+      Err (sdl2::get_error())
+    }
+  }
+
+  /// The value an attribute is currently set to.
+  pub fn get (&self, attr : SDL_GLattr) -> Result <i32, String> {
+    let mut value = 0;
+    if 0 == unsafe { sdl2_sys::SDL_GL_GetAttribute (attr, &mut value) } {
+      Ok (value)
+    } else {
+      Err (sdl2::get_error())
+    }
+  }
+
+  pub fn set_context_version (&self, major : u8, minor : u8)
+    -> Result <(), String>
+  {
+    try!{ self.set (SDL_GLattr::SDL_GL_CONTEXT_MAJOR_VERSION, major as i32) };
+    self.set (SDL_GLattr::SDL_GL_CONTEXT_MINOR_VERSION, minor as i32)
+  }
+
+  pub fn set_context_profile (&self, profile : sdl2::video::GLProfile)
+    -> Result <(), String>
+  {
+    self.set (SDL_GLattr::SDL_GL_CONTEXT_PROFILE_MASK, profile_mask (profile))
+  }
+
+  /// Minimum number of bits in the depth buffer.
+  pub fn set_depth_size (&self, bits : u8) -> Result <(), String> {
+    self.set (SDL_GLattr::SDL_GL_DEPTH_SIZE, bits as i32)
+  }
+
+  /// Minimum number of bits in the stencil buffer.
+  pub fn set_stencil_size (&self, bits : u8) -> Result <(), String> {
+    self.set (SDL_GLattr::SDL_GL_STENCIL_SIZE, bits as i32)
+  }
+
+  /// Request a multisampled default framebuffer with the given number of
+  /// samples, or no multisampling for 0.
+  pub fn set_multisample (&self, samples : u8) -> Result <(), String> {
+    try!{
+      self.set (SDL_GLattr::SDL_GL_MULTISAMPLEBUFFERS, (samples > 0) as i32)
+    };
+    self.set (SDL_GLattr::SDL_GL_MULTISAMPLESAMPLES, samples as i32)
+  }
+
+  pub fn set_double_buffer (&self, double_buffer : bool)
+    -> Result <(), String>
+  {
+    self.set (SDL_GLattr::SDL_GL_DOUBLEBUFFER, double_buffer as i32)
+  }
+}
+
+impl ContextVersion {
+  /// Core profile versions from 4.5 down to 3.3, then OpenGL ES 3.0.
+  pub const FALLBACK : &'static [ContextVersion] = &[
+    ContextVersion::core (4, 5),
+    ContextVersion::core (4, 1),
+    ContextVersion::core (3, 3),
+    ContextVersion::es (3, 0)
+  ];
+
+  /// OpenGL ES versions from 3.2 down to 2.0.
+  pub const ES_FALLBACK : &'static [ContextVersion] = &[
+    ContextVersion::es (3, 2),
+    ContextVersion::es (3, 1),
+    ContextVersion::es (3, 0),
+    ContextVersion::es (2, 0)
+  ];
+
+  pub const fn core (major : u8, minor : u8) -> Self {
+    ContextVersion { major, minor, profile: sdl2::video::GLProfile::Core }
+  }
+
+  pub const fn es (major : u8, minor : u8) -> Self {
+    ContextVersion { major, minor, profile: sdl2::video::GLProfile::GLES }
+  }
+
+  pub fn is_es (&self) -> bool {
+    self.profile == sdl2::video::GLProfile::GLES
+  }
+
+  /// The SDL attributes requesting this version.
+  pub(crate) fn attributes (&self) -> [(SDL_GLattr, i32); 3] {
+    [ (SDL_GLattr::SDL_GL_CONTEXT_MAJOR_VERSION, self.major as i32),
+      (SDL_GLattr::SDL_GL_CONTEXT_MINOR_VERSION, self.minor as i32),
+      (SDL_GLattr::SDL_GL_CONTEXT_PROFILE_MASK,  profile_mask (self.profile))
+    ]
+  }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  functions                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Set the `SDL_OPENGL_ES_DRIVER` hint if all versions are OpenGL ES
+/// versions.
+pub(crate) fn select_es_driver (versions : &[ContextVersion]) {
+  if !versions.is_empty() && versions.iter().all (ContextVersion::is_es) {
+    sdl2::hint::set (HINT_OPENGL_ES_DRIVER, "1");
+  }
+}
+
+//
+// private
+//
+
+/// The `SDL_GLprofile` value of a profile.
+fn profile_mask (profile : sdl2::video::GLProfile) -> i32 {
+  use sdl2::video::GLProfile;
+  match profile {
+    GLProfile::Core           => PROFILE_CORE,
+    GLProfile::Compatibility  => PROFILE_COMPATIBILITY,
+    GLProfile::GLES           => PROFILE_ES,
+    GLProfile::Unknown (mask) => mask
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  #[test]
+  fn test_context_version_attributes() {
+    assert_eq!(ContextVersion::es (3, 0).attributes(), [
+      (SDL_GLattr::SDL_GL_CONTEXT_MAJOR_VERSION, 3),
+      (SDL_GLattr::SDL_GL_CONTEXT_MINOR_VERSION, 0),
+      (SDL_GLattr::SDL_GL_CONTEXT_PROFILE_MASK,  PROFILE_ES)
+    ]);
+    assert_eq!(ContextVersion::FALLBACK[0], ContextVersion::core (4, 5));
+    assert!(ContextVersion::ES_FALLBACK.iter().all (ContextVersion::is_es));
+    assert!(!ContextVersion::FALLBACK.iter().all (ContextVersion::is_es));
+  }
+}
@@ -0,0 +1,157 @@
+//! Bounded forwarding of the SDL event pump to the render thread.
+//!
+//! An `EventBridge` owns the event pump and an `EventForwarder` on the main
+//! thread. Each call to `pump` or `wait_timeout` moves events from SDL to the
+//! receivers of the forwarder, typically on the render thread, as long as
+//! fewer than `capacity` events are waiting to be received there. Once the
+//! capacity is reached, events are left in the SDL queue until the render
+//! thread catches up, while the window system is still serviced so that the
+//! window stays responsive. Events are never dropped by the bridge.
+//!
+//! The main thread still sees every event before it is forwarded, to handle
+//! quit requests, command queue wake-ups and timers:
+//!
+//! ```ignore
+//! let (mut bridge, receiver)
+//!   = EventBridge::new (sdl_context.event_pump().unwrap(), 256);
+//! // ... move the receiver to the render thread
+//! while !shutdown::shutdown_requested() {
+//!   bridge.wait_timeout (std::time::Duration::from_millis (10), |event| {
+//!     if executor.is_wake_event (event) {
+//!       executor.execute_pending();
+//!     }
+//!   });
+//! }
+//! ```
+//!
+//! The capacity bounds the number of forwarded events, which may differ from
+//! the number of SDL events: some events are not forwarded and some are
+//! forwarded as several events, so it may be exceeded by a few events. Mouse
+//! motion is coalesced by the forwarder and flushed at the end of each pump.
+
+use sdl2;
+
+use event::{EventForwarder, EventReceiver};
+
+///////////////////////////////////////////////////////////////////////////////
+//  constants                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+/// How often the window system is serviced while the receivers are full, in
+/// milliseconds
+const FULL_POLL_INTERVAL_MS : u64 = 2;
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Main thread end of a bounded event channel.
+pub struct EventBridge {
+  event_pump : sdl2::EventPump,
+  forwarder  : EventForwarder,
+  capacity   : usize
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+impl EventBridge {
+  /// Create a bridge with a new forwarder, returning the broadcast receiver.
+  pub fn new (event_pump : sdl2::EventPump, capacity : usize)
+    -> (EventBridge, EventReceiver)
+  {
+    let (forwarder, receiver) = EventForwarder::new();
+    (EventBridge::with_forwarder (event_pump, forwarder, capacity), receiver)
+  }
+
+  /// Create a bridge with a forwarder that has already been configured.
+  pub fn with_forwarder (
+    event_pump : sdl2::EventPump, forwarder : EventForwarder, capacity : usize
+  ) -> Self {
+    EventBridge { event_pump, forwarder, capacity }
+  }
+
+  pub fn capacity (&self) -> usize {
+    self.capacity
+  }
+
+  /// The forwarder, e.g. to subscribe windows or add timers.
+  pub fn forwarder (&mut self) -> &mut EventForwarder {
+    &mut self.forwarder
+  }
+
+  /// Returns true if no more events will be forwarded until the receivers
+  /// have received some.
+  pub fn is_full (&self) -> bool {
+    self.capacity <= self.forwarder.pending()
+  }
+
+  /// Forward the events queued in SDL while there is room, passing each one
+  /// to `handler` first. Returns the number of SDL events handled.
+  ///
+  /// This does not block.
+  pub fn pump <F> (&mut self, mut handler : F) -> usize where
+    F : FnMut (&sdl2::event::Event)
+  {
+    self.event_pump.pump_events();
+    let mut count = 0;
+    while !self.is_full() {
+      match self.event_pump.poll_event() {
+        Some (event) => self.handle (&event, &mut handler),
+        None => break
+      }
+      count += 1;
+    }
+    self.forwarder.flush();
+    count
+  }
+
+  /// Block until an event arrives or the timeout expires, then `pump`.
+  ///
+  /// While the receivers are full this only services the window system until
+  /// the timeout expires or there is room again.
+  pub fn wait_timeout <F> (&mut self,
+    timeout : std::time::Duration, mut handler : F
+  ) -> usize where
+    F : FnMut (&sdl2::event::Event)
+  {
+    let deadline = std::time::Instant::now() + timeout;
+    let interval = std::time::Duration::from_millis (FULL_POLL_INTERVAL_MS);
+    while self.is_full() {
+      self.event_pump.pump_events();
+      let now = std::time::Instant::now();
+      if deadline <= now {
+        return 0
+      }
+      std::thread::sleep (std::cmp::min (deadline - now, interval));
+    }
+    let now = std::time::Instant::now();
+    let remaining = if now < deadline {
+      deadline - now
+    } else {
+      std::time::Duration::from_secs (0)
+    };
+    let millis = remaining.as_secs() as u32 * 1000
+      + remaining.subsec_millis();
+    match self.event_pump.wait_event_timeout (millis) {
+      Some (event) => {
+        self.handle (&event, &mut handler);
+        1 + self.pump (handler)
+      }
+      None => 0
+    }
+  }
+
+  /// Take back the event pump and forwarder.
+  pub fn into_inner (self) -> (sdl2::EventPump, EventForwarder) {
+    (self.event_pump, self.forwarder)
+  }
+
+  fn handle <F> (&mut self, event : &sdl2::event::Event, handler : &mut F) where
+    F : FnMut (&sdl2::event::Event)
+  {
+    handler (event);
+    self.forwarder.forward (event);
+  }
+}
@@ -0,0 +1,557 @@
+//! Main-thread command queue.
+//!
+//! Most SDL video functions are only safe to call from the main thread. A
+//! `CommandSender` can be moved to the render thread to queue requests, such
+//! as changing the title or fullscreen mode of the window, which are executed
+//! by the `CommandExecutor` the next time the main thread drains it. Each
+//! queued command returns a `CommandReply` that can be waited on for the
+//! result.
+//!
+//! Sending a command pushes an SDL user event so that a main thread blocked in
+//! `EventPump::wait_event` wakes up to service the queue.
+
+use sdl2;
+use sdl2_sys;
+
+use SdlGlWindowBackend;
+use pause::PauseHandle;
+use proxy::WindowProxy;
+use registry::{WindowController, WindowHandle};
+use renderdoc;
+use system::{self, KeyboardLayout, WindowPlacement};
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Render thread handle for queueing commands on the main thread.
+///
+/// Commands sent through this handle target the window it was created for.
+#[derive(Clone)]
+pub struct CommandSender {
+  window_id  : u32,
+  wake_event : u32,
+  sender     : std::sync::mpsc::Sender <Request>
+}
+
+/// Main thread end of the command queue.
+pub struct CommandExecutor {
+  wake_event    : u32,
+  sender        : std::sync::mpsc::Sender <Request>,
+  receiver      : std::sync::mpsc::Receiver <Request>,
+  pause_handles : std::collections::HashMap <u32, PauseHandle>
+}
+
+/// Pending result of a queued command.
+pub struct CommandReply {
+  receiver : std::sync::mpsc::Receiver <Result <CommandResponse, CommandError>>
+}
+
+/// Desktop mouse position and button state.
+///
+/// Unlike window mouse events this is reported even when the cursor is
+/// outside of the window.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GlobalMouseState {
+  /// Desktop x coordinate
+  pub x       : i32,
+  /// Desktop y coordinate
+  pub y       : i32,
+  /// SDL button mask
+  pub buttons : u32
+}
+
+/// State of a window queried on the main thread, see `proxy`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WindowState {
+  pub title         : String,
+  /// Size of the client area in window coordinates, which differ from
+  /// pixels on high-DPI displays
+  pub size          : (u32, u32),
+  /// Size of the drawable area in pixels
+  pub drawable_size : (u32, u32),
+  /// Position of the window in desktop coordinates
+  pub position      : (i32, i32),
+  /// SDL window flags, see `sdl2_sys::SDL_WindowFlags`
+  pub flags         : u32
+}
+
+//
+// private
+//
+
+struct Request {
+  window_id : u32,
+  command   : WindowCommand,
+  reply     : std::sync::mpsc::Sender <Result <CommandResponse, CommandError>>
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  enums                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+/// A request to be executed on the main thread.
+#[derive(Clone, Debug, PartialEq)]
+pub enum WindowCommand {
+  /// Replies with `CommandResponse::GlobalMouseState`
+  QueryGlobalMouseState,
+  /// Replies with `CommandResponse::KeyboardLayout`
+  QueryKeyboardLayout,
+  /// Replies with `CommandResponse::WindowState`
+  QueryWindowState,
+  /// Move the mouse cursor to the given window-relative position.
+  ///
+  /// Coordinates are in drawable pixels (the same units as the framebuffer
+  /// dimensions) and are scaled to window coordinates on high-DPI displays.
+  WarpMouse { x : i32, y : i32 },
+  /// Center the window on the given display.
+  ///
+  /// Replies with `CommandResponse::WindowPlacement`.
+  MigrateToDisplay (i32),
+  /// Pause the render loop of the window, optionally releasing its context.
+  ///
+  /// Requires a pause handle to have been registered with
+  /// `CommandExecutor::register_pause_handle`.
+  Pause { release_context : bool },
+  /// Resume a paused render loop
+  Resume,
+  SetTitle (String),
+  SetFullscreen (sdl2::video::FullscreenType),
+  /// Set the window icon from 8-bit RGBA pixels, in rows from the top left
+  SetIcon { width : u32, height : u32, pixels : Vec <u8> },
+  /// Resize the client area, in window coordinates
+  Resize { width : u32, height : u32 },
+  /// Move the window to the given desktop position
+  SetPosition { x : i32, y : i32 },
+  Show,
+  Hide,
+  Raise,
+  Minimize,
+  Maximize,
+  Restore,
+  /// Capture the next frame presented with RenderDoc, see `renderdoc`.
+  ///
+  /// Fails with `CommandError::RenderDocNotLoaded` if the application is not
+  /// running under RenderDoc.
+  TriggerCapture
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum CommandResponse {
+  Done,
+  GlobalMouseState (GlobalMouseState),
+  KeyboardLayout (KeyboardLayout),
+  WindowPlacement (WindowPlacement),
+  WindowState (WindowState)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum CommandError {
+  /// The executor was dropped before the command was executed
+  Disconnected,
+  /// The target window no longer exists
+  WindowNotFound (u32),
+  /// No pause handle is registered for the target window
+  NoPauseHandle (u32),
+  /// The command was received but returned an unexpected response
+  UnexpectedResponse (CommandResponse),
+  /// The application is not running under RenderDoc
+  RenderDocNotLoaded,
+  SdlError (String)
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+impl CommandSender {
+  /// SDL window ID that commands are sent to.
+  pub fn window_id (&self) -> u32 {
+    self.window_id
+  }
+
+  /// Queue a command for execution on the main thread.
+  ///
+  /// If the executor has been dropped the returned reply will resolve to
+  /// `CommandError::Disconnected`.
+  pub fn send (&self, command : WindowCommand) -> CommandReply {
+    let (reply, receiver) = std::sync::mpsc::channel();
+    let request = Request { window_id: self.window_id, command, reply };
+    if self.sender.send (request).is_ok() {
+      push_user_event (self.wake_event, 0);
+    }
+    CommandReply { receiver }
+  }
+
+  /// Query the global mouse state, blocking until the main thread has
+  /// serviced the request.
+  pub fn global_mouse_state (&self)
+    -> Result <GlobalMouseState, CommandError>
+  {
+    match try!{ self.send (WindowCommand::QueryGlobalMouseState).wait() } {
+      CommandResponse::GlobalMouseState (state) => Ok (state),
+      response => Err (CommandError::UnexpectedResponse (response))
+    }
+  }
+
+  /// Query the current keyboard layout, blocking until the main thread has
+  /// serviced the request.
+  pub fn keyboard_layout (&self) -> Result <KeyboardLayout, CommandError> {
+    match try!{ self.send (WindowCommand::QueryKeyboardLayout).wait() } {
+      CommandResponse::KeyboardLayout (layout) => Ok (layout),
+      response => Err (CommandError::UnexpectedResponse (response))
+    }
+  }
+}
+
+impl CommandExecutor {
+  /// Create a new command queue.
+  ///
+  /// This registers a custom SDL event type used to wake the main thread and
+  /// so should be called after SDL has been initialized. Fails if SDL has
+  /// no user event types left.
+  pub fn new() -> Result <Self, String> {
+    let wake_event = unsafe { sdl2_sys::SDL_RegisterEvents (1) };
+    if wake_event == std::u32::MAX {
+      return Err ("failed to register the command wake event".to_string())
+    }
+    let (sender, receiver) = std::sync::mpsc::channel();
+    Ok (CommandExecutor {
+      wake_event, sender, receiver,
+      pause_handles: std::collections::HashMap::new()
+    })
+  }
+
+  /// Register the pause handle of a display so that the `Pause` and `Resume`
+  /// commands can be used for the window.
+  ///
+  /// The handle can be obtained with `SdlGliumDisplayFacade::pause_handle`
+  /// on the render thread and sent back to the main thread.
+  pub fn register_pause_handle (&mut self,
+    window_id : u32, handle : PauseHandle
+  ) {
+    self.pause_handles.insert (window_id, handle);
+  }
+
+  /// Give the backend a `WindowProxy` queueing commands to its window,
+  /// replacing any previous proxy.
+  pub fn attach (&self, window_backend : &mut SdlGlWindowBackend) {
+    let sender = self.sender (window_backend);
+    window_backend.proxy = Some (WindowProxy::new (sender));
+  }
+
+  /// Create a sender for queueing commands to the given window.
+  pub fn sender (&self, window_backend : &SdlGlWindowBackend) -> CommandSender {
+    self.sender_for_id (window_backend.window_id())
+  }
+
+  /// Create a sender for queueing commands to a registered window.
+  pub fn sender_for (&self, handle : WindowHandle) -> CommandSender {
+    self.sender_for_id (handle.id())
+  }
+
+  fn sender_for_id (&self, window_id : u32) -> CommandSender {
+    CommandSender {
+      window_id,
+      wake_event: self.wake_event,
+      sender:     self.sender.clone()
+    }
+  }
+
+  /// Returns true if the event was pushed to wake the main thread.
+  ///
+  /// Such events carry no other information and can be ignored after calling
+  /// `execute_pending`.
+  pub fn is_wake_event (&self, event : &sdl2::event::Event) -> bool {
+    match *event {
+      sdl2::event::Event::User { type_, .. } => type_ == self.wake_event,
+      _ => false
+    }
+  }
+
+  /// Execute all queued commands, returning the number executed.
+  ///
+  /// This must be called on the main thread.
+  pub fn execute_pending (&mut self) -> usize {
+    let mut count = 0;
+    while let Ok (request) = self.receiver.try_recv() {
+      let _span = trace_span!(INFO, "window_command",
+        window_id = request.window_id, command = ?request.command);
+      let result = self.execute (request.window_id, request.command);
+      // the requester may not be waiting on the reply
+      let _ = request.reply.send (result);
+      count += 1;
+    }
+    count
+  }
+
+  fn execute (&mut self, window_id : u32, command : WindowCommand)
+    -> Result <CommandResponse, CommandError>
+  {
+    // only looked up by the commands acting on the window, so that global
+    // commands do not fail for a window that has been closed
+    let find_window = || WindowController::from_id (window_id)
+      .ok_or (CommandError::WindowNotFound (window_id));
+    match command {
+      WindowCommand::QueryGlobalMouseState =>
+        Ok (CommandResponse::GlobalMouseState (GlobalMouseState::query())),
+      WindowCommand::QueryKeyboardLayout =>
+        Ok (CommandResponse::KeyboardLayout (KeyboardLayout::query())),
+      WindowCommand::QueryWindowState => {
+        let window_raw = try!{ find_window() }.raw();
+        Ok (CommandResponse::WindowState (WindowState::query (window_raw)))
+      }
+      WindowCommand::WarpMouse { x, y } => {
+        let window_raw = try!{ find_window() }.raw();
+        let (x, y) = drawable_to_window (window_raw, x, y);
+        unsafe { sdl2_sys::SDL_WarpMouseInWindow (window_raw, x, y) };
+        Ok (CommandResponse::Done)
+      }
+      WindowCommand::MigrateToDisplay (display) => {
+        let window_raw = try!{ find_window() }.raw();
+        let count = unsafe { sdl2_sys::SDL_GetNumVideoDisplays() };
+        if display < 0 || count <= display {
+          return Err (CommandError::SdlError (
+            format!("invalid display index: {}", display)))
+        }
+        Ok (CommandResponse::WindowPlacement (
+          system::migrate_window (window_raw, display)))
+      }
+      WindowCommand::Pause { release_context } => {
+        let handle = try!{ self.pause_handle (window_id) };
+        if release_context {
+          handle.pause_and_release_context();
+        } else {
+          handle.pause();
+        }
+        Ok (CommandResponse::Done)
+      }
+      WindowCommand::Resume => {
+        try!{ self.pause_handle (window_id) }.resume();
+        Ok (CommandResponse::Done)
+      }
+      WindowCommand::SetTitle (title) => {
+        try!{
+          try!{ find_window() }.set_title (&title)
+            .map_err (|err| CommandError::SdlError (err.to_string()))
+        };
+        Ok (CommandResponse::Done)
+      }
+      WindowCommand::SetFullscreen (fullscreen) => {
+        let window_raw = try!{ find_window() }.raw();
+        if 0 != unsafe {
+          sdl2_sys::SDL_SetWindowFullscreen (window_raw, fullscreen as u32)
+        } {
+          return Err (CommandError::SdlError (sdl2::get_error()))
+        }
+        Ok (CommandResponse::Done)
+      }
+      WindowCommand::SetIcon { width, height, pixels } => {
+        let window_raw = try!{ find_window() }.raw();
+        try!{
+          set_window_icon (window_raw, width, height, &pixels)
+            .map_err (CommandError::SdlError)
+        };
+        Ok (CommandResponse::Done)
+      }
+      WindowCommand::Resize { width, height } => {
+        let window_raw = try!{ find_window() }.raw();
+        unsafe {
+          sdl2_sys::SDL_SetWindowSize (window_raw, width as i32, height as i32)
+        };
+        Ok (CommandResponse::Done)
+      }
+      WindowCommand::SetPosition { x, y } => {
+        let window_raw = try!{ find_window() }.raw();
+        unsafe { sdl2_sys::SDL_SetWindowPosition (window_raw, x, y) };
+        Ok (CommandResponse::Done)
+      }
+      WindowCommand::Show => {
+        let window_raw = try!{ find_window() }.raw();
+        unsafe { sdl2_sys::SDL_ShowWindow (window_raw) };
+        Ok (CommandResponse::Done)
+      }
+      WindowCommand::Hide => {
+        let window_raw = try!{ find_window() }.raw();
+        unsafe { sdl2_sys::SDL_HideWindow (window_raw) };
+        Ok (CommandResponse::Done)
+      }
+      WindowCommand::Raise => {
+        let window_raw = try!{ find_window() }.raw();
+        unsafe { sdl2_sys::SDL_RaiseWindow (window_raw) };
+        Ok (CommandResponse::Done)
+      }
+      WindowCommand::Minimize => {
+        let window_raw = try!{ find_window() }.raw();
+        unsafe { sdl2_sys::SDL_MinimizeWindow (window_raw) };
+        Ok (CommandResponse::Done)
+      }
+      WindowCommand::Maximize => {
+        let window_raw = try!{ find_window() }.raw();
+        unsafe { sdl2_sys::SDL_MaximizeWindow (window_raw) };
+        Ok (CommandResponse::Done)
+      }
+      WindowCommand::Restore => {
+        let window_raw = try!{ find_window() }.raw();
+        unsafe { sdl2_sys::SDL_RestoreWindow (window_raw) };
+        Ok (CommandResponse::Done)
+      }
+      WindowCommand::TriggerCapture => if renderdoc::trigger_capture() {
+        Ok (CommandResponse::Done)
+      } else {
+        Err (CommandError::RenderDocNotLoaded)
+      }
+    }
+  }
+
+  fn pause_handle (&self, window_id : u32)
+    -> Result <&PauseHandle, CommandError>
+  {
+    self.pause_handles.get (&window_id)
+      .ok_or (CommandError::NoPauseHandle (window_id))
+  }
+}
+
+impl CommandReply {
+  /// Block until the command has been executed.
+  pub fn wait (self) -> Result <CommandResponse, CommandError> {
+    self.receiver.recv().unwrap_or (Err (CommandError::Disconnected))
+  }
+
+  /// Returns the result if the command has been executed, without blocking.
+  pub fn try_wait (&self) -> Option <Result <CommandResponse, CommandError>> {
+    match self.receiver.try_recv() {
+      Ok (result) => Some (result),
+      Err (std::sync::mpsc::TryRecvError::Empty) => None,
+      Err (std::sync::mpsc::TryRecvError::Disconnected) =>
+        Some (Err (CommandError::Disconnected))
+    }
+  }
+}
+
+impl GlobalMouseState {
+  /// Query the global mouse state directly.
+  ///
+  /// This must be called on the main thread; from other threads use
+  /// `CommandSender::global_mouse_state`.
+  pub fn query() -> Self {
+    let mut x = 0;
+    let mut y = 0;
+    let buttons = unsafe { sdl2_sys::SDL_GetGlobalMouseState (&mut x, &mut y) };
+    GlobalMouseState { x, y, buttons }
+  }
+
+  /// Returns true if the given button is held.
+  pub fn is_pressed (&self, button : sdl2::mouse::MouseButton) -> bool {
+    let index = button as u32;
+    index != 0 && self.buttons & (1 << (index - 1)) != 0
+  }
+}
+
+impl WindowState {
+  /// True for both exclusive and desktop fullscreen.
+  pub fn is_fullscreen (&self) -> bool {
+    self.has_flag (sdl2_sys::SDL_WindowFlags::SDL_WINDOW_FULLSCREEN)
+  }
+
+  pub fn is_minimized (&self) -> bool {
+    self.has_flag (sdl2_sys::SDL_WindowFlags::SDL_WINDOW_MINIMIZED)
+  }
+
+  pub fn has_input_focus (&self) -> bool {
+    self.has_flag (sdl2_sys::SDL_WindowFlags::SDL_WINDOW_INPUT_FOCUS)
+  }
+
+  fn has_flag (&self, flag : sdl2_sys::SDL_WindowFlags) -> bool {
+    self.flags & flag as u32 != 0
+  }
+
+  /// Must be called on the main thread.
+  fn query (window_raw : *mut sdl2_sys::SDL_Window) -> Self {
+    let (mut width, mut height) = (0, 0);
+    let (mut drawable_width, mut drawable_height) = (0, 0);
+    let (mut x, mut y) = (0, 0);
+    unsafe {
+      sdl2_sys::SDL_GetWindowSize (window_raw, &mut width, &mut height);
+      sdl2_sys::SDL_GL_GetDrawableSize (window_raw,
+        &mut drawable_width, &mut drawable_height);
+      sdl2_sys::SDL_GetWindowPosition (window_raw, &mut x, &mut y);
+      WindowState {
+        title: std::ffi::CStr::from_ptr (
+          sdl2_sys::SDL_GetWindowTitle (window_raw)
+        ).to_string_lossy().into_owned(),
+        size:          (width as u32, height as u32),
+        drawable_size: (drawable_width as u32, drawable_height as u32),
+        position:      (x, y),
+        flags:         sdl2_sys::SDL_GetWindowFlags (window_raw)
+      }
+    }
+  }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  functions                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Push a user event of the given type and code, e.g. to wake up the main
+/// thread.
+///
+/// `SDL_PushEvent` is safe to call from any thread.
+pub(crate) fn push_user_event (event_type : u32, code : i32) {
+  unsafe {
+    let mut event : sdl2_sys::SDL_Event = std::mem::zeroed();
+    event.user.type_ = event_type;
+    event.user.code  = code;
+    sdl2_sys::SDL_PushEvent (&mut event);
+  }
+}
+
+/// Set the window icon from RGBA pixels.
+fn set_window_icon (window_raw : *mut sdl2_sys::SDL_Window,
+  width : u32, height : u32, pixels : &[u8]
+) -> Result <(), String> {
+  if pixels.len() as u64 != 4 * width as u64 * height as u64 {
+    return Err (format!("icon data is {} bytes, expected {}x{} RGBA pixels",
+      pixels.len(), width, height))
+  }
+  // masks for the bytes R, G, B, A in memory order
+  let (r, g, b, a) = if cfg!(target_endian = "little") {
+    (0x0000_00ff, 0x0000_ff00, 0x00ff_0000, 0xff00_0000)
+  } else {
+    (0xff00_0000, 0x00ff_0000, 0x0000_ff00, 0x0000_00ff)
+  };
+  unsafe {
+    // the surface refers to the pixels, which SDL copies when setting the
+    // icon, so they are not modified
+    let surface = sdl2_sys::SDL_CreateRGBSurfaceFrom (
+      pixels.as_ptr() as *mut std::os::raw::c_void,
+      width as i32, height as i32, 32, 4 * width as i32, r, g, b, a);
+    if surface.is_null() {
+      return Err (sdl2::get_error())
+    }
+    sdl2_sys::SDL_SetWindowIcon (window_raw, surface);
+    sdl2_sys::SDL_FreeSurface (surface);
+  }
+  Ok (())
+}
+
+/// Convert drawable pixel coordinates to window coordinates.
+fn drawable_to_window (window_raw : *mut sdl2_sys::SDL_Window, x : i32, y : i32)
+  -> (i32, i32)
+{
+  let (mut window_width, mut window_height) = (0, 0);
+  let (mut drawable_width, mut drawable_height) = (0, 0);
+  unsafe {
+    sdl2_sys::SDL_GetWindowSize (
+      window_raw, &mut window_width, &mut window_height);
+    sdl2_sys::SDL_GL_GetDrawableSize (
+      window_raw, &mut drawable_width, &mut drawable_height);
+  }
+  if drawable_width <= 0 || drawable_height <= 0 {
+    return (x, y)
+  }
+  let scale = |value : i32, window : i32, drawable : i32|
+    (value as i64 * window as i64 / drawable as i64) as i32;
+  ( scale (x, window_width,  drawable_width),
+    scale (y, window_height, drawable_height) )
+}
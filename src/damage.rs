@@ -0,0 +1,297 @@
+//! Damage tracking for applications that only redraw the changed parts of a
+//! frame.
+//!
+//! The render code reports the regions it changes each frame to a
+//! `DamageTracker`. At the start of the next frame the tracker combines the
+//! reported damage with the age of the back buffer to determine the region
+//! that must be repainted, and at the end of the frame it passes the damage to
+//! the window system with the buffer swap:
+//!
+//! ```ignore
+//! let repaint = damage_tracker.begin_frame (&display);
+//! let mut frame = display.draw();
+//! repaint.clear (&mut frame, background);
+//! // draw using `repaint.scissor()` in the draw parameters
+//! damage_tracker.end_frame (&display);
+//! frame.finish().unwrap();
+//! ```
+
+use glium;
+
+use SdlGliumDisplayFacade;
+
+///////////////////////////////////////////////////////////////////////////////
+//  constants                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Damage is simplified to at most this many rectangles
+pub const MAX_DAMAGE_RECTS   : usize = 16;
+/// Number of previous frames whose damage is kept; older back buffers are
+/// repainted in full
+pub const MAX_DAMAGE_HISTORY : usize = 4;
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Accumulates the damaged regions of each frame.
+///
+/// Rectangles are in framebuffer pixels with the origin at the bottom left.
+#[derive(Clone, Debug)]
+pub struct DamageTracker {
+  dimensions : (u32, u32),
+  /// Damage of the frame being drawn
+  current    : Vec <glium::Rect>,
+  /// Damage of previous frames, most recent first
+  history    : std::collections::VecDeque <Vec <glium::Rect>>
+}
+
+/// The region of the back buffer to repaint for a frame.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Repaint {
+  dimensions : (u32, u32),
+  /// `None` if the whole frame must be repainted
+  rects      : Option <Vec <glium::Rect>>
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+impl DamageTracker {
+  /// Create a tracker for a framebuffer of the given dimensions; the first
+  /// frame is fully damaged.
+  pub fn new (dimensions : (u32, u32)) -> Self {
+    let mut tracker = DamageTracker {
+      dimensions,
+      current: Vec::new(),
+      history: std::collections::VecDeque::new()
+    };
+    tracker.add_all();
+    tracker
+  }
+
+  /// Mark a region of the current frame as damaged.
+  pub fn add (&mut self, rect : glium::Rect) {
+    if let Some (rect) = clip (rect, self.dimensions) {
+      self.current.push (rect);
+      if self.current.len() > 4 * MAX_DAMAGE_RECTS {
+        self.current = simplify (std::mem::take (&mut self.current));
+      }
+    }
+  }
+
+  /// Mark the whole current frame as damaged.
+  pub fn add_all (&mut self) {
+    self.current = vec![full_rect (self.dimensions)];
+  }
+
+  /// Update the framebuffer dimensions, damaging the whole frame and
+  /// discarding the damage of previous frames if they changed.
+  pub fn set_dimensions (&mut self, dimensions : (u32, u32)) {
+    if dimensions != self.dimensions {
+      self.dimensions = dimensions;
+      self.history.clear();
+      self.add_all();
+    }
+  }
+
+  pub fn dimensions (&self) -> (u32, u32) {
+    self.dimensions
+  }
+
+  /// The damage of the current frame, simplified.
+  pub fn damage (&self) -> Vec <glium::Rect> {
+    simplify (self.current.clone())
+  }
+
+  /// Region to repaint given the age of the back buffer.
+  ///
+  /// This is the damage of the current frame combined with that of the
+  /// `buffer_age - 1` previous frames. If the age is unknown or zero, or
+  /// older than the kept history, the whole frame is repainted.
+  pub fn repaint (&self, buffer_age : Option <u32>) -> Repaint {
+    let full = Repaint { dimensions: self.dimensions, rects: None };
+    let age = match buffer_age {
+      Some (age) if age > 0 => age as usize,
+      _ => return full
+    };
+    if age - 1 > self.history.len() {
+      return full
+    }
+    let mut rects = self.current.clone();
+    for frame in self.history.iter().take (age - 1) {
+      rects.extend_from_slice (frame);
+    }
+    let rects = simplify (rects);
+    if rects.len() == 1 && rects[0] == full_rect (self.dimensions) {
+      return full
+    }
+    Repaint { dimensions: self.dimensions, rects: Some (rects) }
+  }
+
+  /// Update the dimensions from the display and return the region to repaint
+  /// for the back buffer of the frame about to be drawn.
+  pub fn begin_frame (&mut self, display : &SdlGliumDisplayFacade) -> Repaint {
+    self.set_dimensions (display.framebuffer_dimensions());
+    self.repaint (display.buffer_age())
+  }
+
+  /// Pass the damage of the current frame to the display for the next buffer
+  /// swap and start a new frame.
+  pub fn end_frame (&mut self, display : &SdlGliumDisplayFacade) {
+    display.set_swap_damage (&self.damage());
+    self.next_frame();
+  }
+
+  /// Start a new frame with no damage, without passing the damage of the
+  /// current frame to a display.
+  pub fn next_frame (&mut self) {
+    let damage = simplify (std::mem::take (&mut self.current));
+    self.history.push_front (damage);
+    self.history.truncate (MAX_DAMAGE_HISTORY);
+  }
+}
+
+impl Repaint {
+  pub fn is_full (&self) -> bool {
+    self.rects.is_none()
+  }
+
+  /// Rectangles to repaint.
+  pub fn rects (&self) -> Vec <glium::Rect> {
+    match self.rects {
+      Some (ref rects) => rects.clone(),
+      None => vec![full_rect (self.dimensions)]
+    }
+  }
+
+  /// Bounding box of the region to repaint, for use as the scissor rectangle
+  /// in the draw parameters; `None` if the whole frame is repainted.
+  pub fn scissor (&self) -> Option <glium::Rect> {
+    self.rects.as_ref().and_then (|rects| {
+      let mut rects = rects.iter();
+      rects.next().map (|first| rects.fold (*first, |a, b| union (&a, b)))
+    })
+  }
+
+  /// Clear the region to repaint to the given color.
+  pub fn clear <S : glium::Surface> (&self,
+    surface : &mut S, color : (f32, f32, f32, f32)
+  ) {
+    match self.rects {
+      Some (ref rects) => for rect in rects {
+        surface.clear (Some (rect), Some (color), false, None, None);
+      },
+      None => surface.clear (None, Some (color), false, None, None)
+    }
+  }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  functions                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Merge overlapping rectangles and reduce the number of rectangles to at
+/// most `MAX_DAMAGE_RECTS`.
+///
+/// Two rectangles are merged into their bounding box whenever this covers no
+/// more area than the rectangles themselves, e.g. if one contains the other
+/// or they are adjacent and aligned. While there are too many rectangles, the
+/// pair whose bounding box adds the least area is merged.
+pub fn simplify (mut rects : Vec <glium::Rect>) -> Vec <glium::Rect> {
+  rects.retain (|rect| rect.width > 0 && rect.height > 0);
+  loop {
+    let mut best : Option <(usize, usize, u64)> = None;
+    for i in 0..rects.len() {
+      for j in i+1..rects.len() {
+        let merged = area (&union (&rects[i], &rects[j]));
+        let waste = merged.saturating_sub (area (&rects[i]) + area (&rects[j]));
+        if best.is_none_or (|(_, _, best_waste)| waste < best_waste) {
+          best = Some ((i, j, waste));
+        }
+      }
+    }
+    match best {
+      Some ((i, j, waste)) if waste == 0 || rects.len() > MAX_DAMAGE_RECTS => {
+        let merged = union (&rects[i], &rects[j]);
+        rects.swap_remove (j);
+        rects[i] = merged;
+      }
+      _ => return rects
+    }
+  }
+}
+
+fn full_rect (dimensions : (u32, u32)) -> glium::Rect {
+  glium::Rect {
+    left: 0, bottom: 0, width: dimensions.0, height: dimensions.1
+  }
+}
+
+/// Clip a rectangle to the framebuffer, returning `None` if it is empty.
+fn clip (rect : glium::Rect, dimensions : (u32, u32)) -> Option <glium::Rect> {
+  if rect.left >= dimensions.0 || rect.bottom >= dimensions.1 {
+    return None
+  }
+  let width  = std::cmp::min (rect.width,  dimensions.0 - rect.left);
+  let height = std::cmp::min (rect.height, dimensions.1 - rect.bottom);
+  if width == 0 || height == 0 {
+    return None
+  }
+  Some (glium::Rect { width, height, .. rect })
+}
+
+fn union (a : &glium::Rect, b : &glium::Rect) -> glium::Rect {
+  let left   = std::cmp::min (a.left, b.left);
+  let bottom = std::cmp::min (a.bottom, b.bottom);
+  let right  = std::cmp::max (a.left + a.width, b.left + b.width);
+  let top    = std::cmp::max (a.bottom + a.height, b.bottom + b.height);
+  glium::Rect { left, bottom, width: right - left, height: top - bottom }
+}
+
+fn area (rect : &glium::Rect) -> u64 {
+  rect.width as u64 * rect.height as u64
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  fn rect (left : u32, bottom : u32, width : u32, height : u32)
+    -> glium::Rect
+  {
+    glium::Rect { left, bottom, width, height }
+  }
+  #[test]
+  fn test_simplify() {
+    // contained and adjacent rectangles are merged, disjoint ones are not
+    let rects = simplify (vec![
+      rect (0, 0, 10, 10), rect (2, 2, 4, 4), rect (10, 0, 5, 10),
+      rect (50, 50, 10, 10)
+    ]);
+    assert_eq!(rects, vec![rect (0, 0, 15, 10), rect (50, 50, 10, 10)]);
+    let rects = simplify (
+      (0..2 * MAX_DAMAGE_RECTS as u32).map (|i| rect (i * 20, 0, 10, 10))
+        .collect());
+    assert_eq!(rects.len(), MAX_DAMAGE_RECTS);
+  }
+  #[test]
+  fn test_repaint() {
+    let mut tracker = DamageTracker::new ((100, 100));
+    assert!(tracker.repaint (Some (1)).is_full());
+    tracker.next_frame();
+    tracker.add (rect (10, 10, 10, 10));
+    tracker.next_frame();
+    tracker.add (rect (90, 90, 20, 20));
+    assert!(tracker.repaint (None).is_full());
+    assert!(tracker.repaint (Some (0)).is_full());
+    assert_eq!(tracker.repaint (Some (1)).rects(), vec![rect (90, 90, 10, 10)]);
+    assert_eq!(tracker.repaint (Some (2)).scissor(),
+      Some (rect (10, 10, 90, 90)));
+    // the first frame was fully damaged
+    assert!(tracker.repaint (Some (3)).is_full());
+    assert!(tracker.repaint (Some (4)).is_full());
+    tracker.set_dimensions ((200, 100));
+    assert!(tracker.repaint (Some (1)).is_full());
+  }
+}
@@ -0,0 +1,253 @@
+//! GL debug output delivered over a channel.
+//!
+//! A window backend built with `BackendConfig::debug` has a debug context
+//! (`SDL_GL_CONTEXT_DEBUG_FLAG`) with a `glDebugMessageCallback` installed
+//! that sends every debug message to a channel, instead of glium printing
+//! them to stderr. The receiver is taken from the backend on the main thread
+//! and can be drained on any thread:
+//!
+//! ```ignore
+//! let config = BackendConfig { debug: true, .. BackendConfig::default() };
+//! let mut window_backend = video_subsystem.window ("title", 640, 480)
+//!   .build_backend_with (config).unwrap();
+//! let debug_messages = window_backend.take_debug_receiver().unwrap();
+//! // ... send the backend to the render thread, then each frame
+//! for message in debug_messages.try_iter() {
+//!   log_gl_message (&message);
+//! }
+//! ```
+//!
+//! The callback requires OpenGL 4.3, `GL_KHR_debug` or
+//! `GL_ARB_debug_output`; building a debug backend fails otherwise. The
+//! callback is installed again when glium is built on the backend, replacing
+//! the one glium installs for its `DebugCallbackBehavior`. Output is not
+//! synchronous, so messages may arrive some time after the GL call that
+//! caused them.
+//!
+//! Without a debug backend, glium's own callback can forward its messages to
+//! a channel instead with `debug_channel`, e.g. for the main thread to show
+//! GL errors in a UI while rendering happens on the render thread:
+//!
+//! ```ignore
+//! let (behavior, debug_messages) = debug::debug_channel (true);
+//! let display = window_backend.build_glium_debug (behavior).unwrap();
+//! main_sender.send (debug_messages).unwrap();
+//! ```
+
+#[cfg(feature = "glium")]
+use glium;
+
+use std::os::raw::{c_char, c_void};
+
+///////////////////////////////////////////////////////////////////////////////
+//  constants                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+const GL_DEBUG_OUTPUT : u32 = 0x92E0;
+
+///////////////////////////////////////////////////////////////////////////////
+//  typedefs                                                                 //
+///////////////////////////////////////////////////////////////////////////////
+
+type DebugProc = extern "system" fn (
+  source : u32, type_ : u32, id : u32, severity : u32, length : i32,
+  message : *const c_char, user_param : *mut c_void);
+type DebugMessageCallback = unsafe extern "system" fn (
+  callback : DebugProc, user_param : *const c_void);
+type Enable = unsafe extern "system" fn (cap : u32);
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// A message from the GL debug output.
+///
+/// The fields are the GL enum values passed to the callback, e.g.
+/// `GL_DEBUG_SOURCE_API`, `GL_DEBUG_TYPE_ERROR` and
+/// `GL_DEBUG_SEVERITY_HIGH`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GlDebugMessage {
+  pub source   : u32,
+  pub type_    : u32,
+  pub id       : u32,
+  pub severity : u32,
+  pub message  : String
+}
+
+/// A message from glium's debug callback, see `debug_channel`.
+#[cfg(feature = "glium")]
+#[derive(Clone, Debug)]
+pub struct GliumDebugMessage {
+  pub severity : glium::debug::Severity,
+  pub source   : glium::debug::Source,
+  pub type_    : glium::debug::MessageType,
+  pub id       : u32,
+  pub message  : String
+}
+
+/// Sending end of the debug output of a context.
+pub(crate) struct DebugOutput {
+  /// Boxed so that the address given to GL is stable; locked since the
+  /// callback may be called on driver threads
+  sender : Box <std::sync::Mutex <std::sync::mpsc::Sender <GlDebugMessage>>>
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+impl DebugOutput {
+  pub(crate) fn new()
+    -> (Self, std::sync::mpsc::Receiver <GlDebugMessage>)
+  {
+    let (sender, receiver) = std::sync::mpsc::channel();
+    (DebugOutput { sender: Box::new (std::sync::Mutex::new (sender)) },
+      receiver)
+  }
+
+  /// Install the callback on the current context, loading the GL functions
+  /// with `get_proc_address`.
+  ///
+  /// # Safety
+  ///
+  /// The context must be deleted, or the callback replaced, before this is
+  /// dropped.
+  pub(crate) unsafe fn install <F> (&self, get_proc_address : F)
+    -> Result <(), String>
+  where F : Fn (&str) -> *const c_void {
+    let callback = ["glDebugMessageCallback", "glDebugMessageCallbackKHR",
+      "glDebugMessageCallbackARB"].iter()
+      .map (|symbol| get_proc_address (symbol))
+      .find (|address| !address.is_null());
+    let callback = match callback {
+      Some (callback) => callback,
+      None => return Err ("GL debug output is not supported".to_string())
+    };
+    let enable = get_proc_address ("glEnable");
+    if enable.is_null() {
+      return Err ("failed to load glEnable".to_string())
+    }
+    let callback : DebugMessageCallback = std::mem::transmute (callback);
+    let enable   : Enable = std::mem::transmute (enable);
+    callback (debug_callback,
+      &*self.sender as *const std::sync::Mutex <_> as *const c_void);
+    // debug contexts have debug output enabled, but other contexts may not
+    enable (GL_DEBUG_OUTPUT);
+    Ok (())
+  }
+}
+
+#[cfg(feature = "glium")]
+impl PartialEq for GliumDebugMessage {
+  fn eq (&self, other : &Self) -> bool {
+    // glium's Source and MessageType are not PartialEq, but are represented
+    // by their GL enum values
+    self.severity == other.severity &&
+      self.source as u32 == other.source as u32 &&
+      self.type_ as u32 == other.type_ as u32 &&
+      self.id == other.id &&
+      self.message == other.message
+  }
+}
+
+#[cfg(feature = "glium")]
+impl Eq for GliumDebugMessage {}
+
+///////////////////////////////////////////////////////////////////////////////
+//  functions                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+/// A glium debug callback behavior that sends every message to the returned
+/// receiver, to be given to `SdlGlWindowBackend::build_glium_debug`.
+///
+/// With `synchronous` set, messages are reported during the GL call that
+/// caused them, at some cost in performance. The callback is replaced for a
+/// backend built with `BackendConfig::debug`.
+#[cfg(feature = "glium")]
+pub fn debug_channel (synchronous : bool) -> (
+  glium::debug::DebugCallbackBehavior,
+  std::sync::mpsc::Receiver <GliumDebugMessage>
+) {
+  let (sender, receiver) = std::sync::mpsc::channel();
+  let callback = move |source, type_, severity, id, _, message : &str| {
+    // the receiver may have been dropped
+    let _ = sender.send (GliumDebugMessage {
+      severity, source, type_, id, message: message.to_string()
+    });
+  };
+  let behavior = glium::debug::DebugCallbackBehavior::Custom {
+    callback: Box::new (callback),
+    synchronous
+  };
+  (behavior, receiver)
+}
+
+//
+// private
+//
+
+extern "system" fn debug_callback (
+  source : u32, type_ : u32, id : u32, severity : u32, length : i32,
+  message : *const c_char, user_param : *mut c_void
+) {
+  let sender = unsafe {
+    &*(user_param as *const std::sync::Mutex <
+      std::sync::mpsc::Sender <GlDebugMessage>>)
+  };
+  let message = unsafe {
+    if length < 0 {
+      std::ffi::CStr::from_ptr (message).to_bytes()
+    } else {
+      std::slice::from_raw_parts (message as *const u8, length as usize)
+    }
+  };
+  let message = GlDebugMessage {
+    source, type_, id, severity,
+    message: String::from_utf8_lossy (message).into_owned()
+  };
+  // the receiver may have been dropped
+  if let Ok (sender) = sender.lock() {
+    let _ = sender.send (message);
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  #[test]
+  fn test_debug_callback() {
+    let (debug_output, receiver) = DebugOutput::new();
+    let user_param = &*debug_output.sender
+      as *const std::sync::Mutex <_> as *mut c_void;
+    debug_callback (0x8246, 0x824C, 1, 0x9146, 5,
+      b"error\0".as_ptr() as *const c_char, user_param);
+    debug_callback (0x8246, 0x824C, 2, 0x9146, -1,
+      b"terminated\0".as_ptr() as *const c_char, user_param);
+    let messages = receiver.try_iter().collect::<Vec <_>>();
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[0].message, "error");
+    assert_eq!(messages[1].id, 2);
+    assert_eq!(messages[1].message, "terminated");
+  }
+  #[cfg(feature = "glium")]
+  #[test]
+  fn test_debug_channel() {
+    use glium::debug::*;
+    let (behavior, receiver) = debug_channel (false);
+    match behavior {
+      DebugCallbackBehavior::Custom { mut callback, synchronous } => {
+        assert!(!synchronous);
+        callback (Source::Api, MessageType::Error, Severity::High, 1282, true,
+          "invalid operation");
+      }
+      _ => panic!("expected a custom callback")
+    }
+    assert_eq!(receiver.try_recv().unwrap(), GliumDebugMessage {
+      severity: Severity::High,
+      source:   Source::Api,
+      type_:    MessageType::Error,
+      id:       1282,
+      message:  "invalid operation".to_string()
+    });
+  }
+}
@@ -0,0 +1,117 @@
+//! Per-frame summary of GL errors.
+//!
+//! GL errors on the render thread are silent unless checked for. With frame
+//! diagnostics enabled by `SdlGliumDisplayFacade::set_frame_diagnostics`, the
+//! facade drains `glGetError` right before each buffer swap, i.e. when the
+//! frame is finished, and records the errors raised during the frame:
+//!
+//! ```ignore
+//! if cfg!(debug_assertions) {
+//!   display.set_frame_diagnostics (true);
+//! }
+//! // ... each frame
+//! frame.finish().unwrap();
+//! if let Some (diagnostics) = display.frame_diagnostics() {
+//!   for name in diagnostics.error_names() {
+//!     eprintln!("GL error in frame {}: {}", diagnostics.frames, name);
+//!   }
+//! }
+//! ```
+//!
+//! `glGetError` may stall the pipeline on some drivers, so this is intended
+//! for debug builds. GL only keeps one flag per kind of error, so an error
+//! raised several times in a frame is reported once.
+
+use gl;
+
+///////////////////////////////////////////////////////////////////////////////
+//  constants                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Bound on the errors drained per frame, since a lost context may report
+/// `GL_CONTEXT_LOST` indefinitely
+const MAX_ERRORS_PER_FRAME : usize = 16;
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// GL errors of the last finished frame, with totals since diagnostics were
+/// enabled.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FrameDiagnostics {
+  /// Frames checked
+  pub frames             : u64,
+  /// Frames checked that raised errors
+  pub frames_with_errors : u64,
+  /// Error codes raised by the last frame, e.g. `GL_INVALID_OPERATION`
+  pub errors             : Vec <u32>
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+impl FrameDiagnostics {
+  /// Names of the errors raised by the last frame.
+  pub fn error_names (&self) -> Vec <&'static str> {
+    self.errors.iter().map (|error| error_name (*error)).collect()
+  }
+
+  /// Drain the errors of the frame being finished.
+  pub(crate) fn check (&mut self, gl : &gl::Gl) {
+    self.record (|| unsafe { gl.GetError() })
+  }
+
+  fn record <F : FnMut() -> u32> (&mut self, mut get_error : F) {
+    self.errors.clear();
+    while self.errors.len() < MAX_ERRORS_PER_FRAME {
+      match get_error() {
+        gl::NO_ERROR => break,
+        error => self.errors.push (error)
+      }
+    }
+    self.frames += 1;
+    if !self.errors.is_empty() {
+      self.frames_with_errors += 1;
+    }
+  }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  functions                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Name of a GL error code.
+pub fn error_name (error : u32) -> &'static str {
+  match error {
+    0x0500 => "GL_INVALID_ENUM",
+    0x0501 => "GL_INVALID_VALUE",
+    0x0502 => "GL_INVALID_OPERATION",
+    0x0503 => "GL_STACK_OVERFLOW",
+    0x0504 => "GL_STACK_UNDERFLOW",
+    0x0505 => "GL_OUT_OF_MEMORY",
+    0x0506 => "GL_INVALID_FRAMEBUFFER_OPERATION",
+    0x0507 => "GL_CONTEXT_LOST",
+    _      => "unknown GL error"
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  #[test]
+  fn test_frame_diagnostics() {
+    let mut diagnostics = FrameDiagnostics::default();
+    let mut pending = vec![0x0502, 0x0501];
+    diagnostics.record (|| pending.pop().unwrap_or (gl::NO_ERROR));
+    assert_eq!(diagnostics.error_names(),
+      vec!["GL_INVALID_VALUE", "GL_INVALID_OPERATION"]);
+    diagnostics.record (|| gl::NO_ERROR);
+    assert!(diagnostics.errors.is_empty());
+    // a lost context keeps reporting errors
+    diagnostics.record (|| 0x0507);
+    assert_eq!(diagnostics.errors.len(), MAX_ERRORS_PER_FRAME);
+    assert_eq!((diagnostics.frames, diagnostics.frames_with_errors), (3, 2));
+  }
+}
@@ -0,0 +1,1420 @@
+//! Forwarding of events from the main thread to other threads.
+//!
+//! The SDL event pump can only be used on the main thread and
+//! `sdl2::event::Event` is not `Send`. An `EventForwarder` is given each event
+//! pumped on the main thread and forwards the ones of interest as owned
+//! `Event` values to an `EventReceiver`, which can be moved to the render
+//! thread. The routing itself is done by an `EventRouter`, which does not
+//! depend on SDL.
+//!
+//! Events associated with a window are routed to the receiver subscribed to
+//! that window with `EventForwarder::subscribe`; global events, and events for
+//! windows without a subscriber, go to the broadcast receiver returned by
+//! `EventForwarder::new`. Audio device events can be routed to a separate
+//! receiver for the audio thread with `EventForwarder::subscribe_audio`.
+//!
+//! Timers added to the forwarder expire as SDL user events on the main
+//! thread, which can be recognized with `TimerId::from_event`; timers added
+//! with forwarding enabled are also forwarded as `Event::Timer`.
+//!
+//! Window size changes are forwarded immediately, which is enough to update
+//! the viewport. With `EventForwarder::set_resize_debounce`, an
+//! `Event::WindowResizeSettled` is also forwarded once the size of a window
+//! has stopped changing, so that heavy framebuffer-dependent resources are
+//! only reallocated once at the end of a live resize.
+//!
+//! When a window in exclusive fullscreen loses focus,
+//! `Event::FullscreenSuspended` is forwarded so that the render thread can
+//! pause until `Event::FullscreenResumed`; whether the window is minimized
+//! meanwhile is set with `system::set_minimize_on_focus_loss`. Other
+//! reactions to focus changes are configured with a `FocusPolicy`. The render
+//! loop of a window watched with `EventForwarder::watch_visibility` is
+//! throttled or suspended through its `PauseHandle` while the window is
+//! minimized or hidden.
+//!
+//! Key events are forwarded to the window they occurred in. Key downs
+//! generated by OS key repeat are forwarded as-is, dropped, or forwarded as
+//! `Event::KeyRepeat` according to `EventForwarder::set_key_repeat`. Text
+//! input is forwarded once it has been committed, and consecutive text input
+//! events of a window are coalesced into a single `Event::TextInput` by
+//! `EventReceiver::drain_frame`. Mouse motion is coalesced into a single
+//! `Event::MouseMotion` per window and mouse until `EventForwarder::flush`,
+//! which the `EventBridge` calls after each pump, with relative motion
+//! accumulated and the last position kept, so that high polling rate mice do
+//! not flood the receivers; `EventForwarder::set_coalesce_motion` opts out.
+//! Motion is flushed before any other event is forwarded, so the order of
+//! motion and button events is kept. Mouse button events carry click counts
+//! computed with the thresholds set by `EventForwarder::set_click_thresholds`,
+//! and mouse wheel events are normalized into `Event::Scroll`. Game
+//! controller events are forwarded once enabled with
+//! `EventForwarder::enable_gamepads`, and raw joystick events once enabled
+//! with `EventForwarder::enable_joysticks`.
+
+use sdl2;
+use sdl2_sys;
+
+use focus::{FocusChange, FocusPolicy, FocusTracker};
+use gamepad::{self, AxisFilter, GamepadSender, Gamepads, JoystickInfo,
+  Joysticks, TouchPhase};
+use pause::{PauseHandle, VisibilityPolicy};
+use pointer::{ClickCounter, ClickThresholds, Scroll, ScrollDirection};
+use system::{self, DisplayInfo, DisplayOrientation, KeyboardLayout,
+  SystemTheme, ThemeWatcher, WindowPlacement};
+use timer::{TimerId, Timers};
+
+///////////////////////////////////////////////////////////////////////////////
+//  constants                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+/// `SDL_LOCALECHANGED` (SDL 2.0.14) is not known to the `sdl2` crate and is
+/// received as an `Event::Unknown`.
+const SDL_LOCALECHANGED : u32 = 0x107;
+/// `SDL_DISPLAYEVENT` (SDL 2.0.9)
+const SDL_DISPLAYEVENT   : u32 = 0x150;
+/// Audio device events are received as `Event::Unknown`, without their
+/// payload, which is recorded by an event watch instead
+const SDL_AUDIODEVICEADDED   : u32
+  = sdl2_sys::SDL_EventType::SDL_AUDIODEVICEADDED as u32;
+const SDL_AUDIODEVICEREMOVED : u32
+  = sdl2_sys::SDL_EventType::SDL_AUDIODEVICEREMOVED as u32;
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Main thread end of the event channel.
+pub struct EventForwarder {
+  router          : EventRouter,
+  theme           : SystemTheme,
+  /// Set while the theme is watched, see `watch_theme`
+  theme_watcher   : Option <ThemeWatcher>,
+  displays        : Vec <DisplayInfo>,
+  display_policy  : DisplayPolicy,
+  /// Windows checked for migration when a display is disconnected
+  windows         : Vec <u32>,
+  timers          : Timers,
+  resize_debounce : Option <ResizeDebounce>,
+  /// Windows that lost focus while in exclusive fullscreen
+  suspended       : Vec <u32>,
+  focus           : Option <FocusTracker>,
+  /// Pause handles of windows throttled or suspended while hidden
+  visibility      : std::collections::HashMap <u32,
+    (PauseHandle, VisibilityPolicy)>,
+  key_repeat      : KeyRepeatMode,
+  coalesce_motion : bool,
+  /// Coalesced `Event::MouseMotion`s waiting for `flush`
+  motion          : Vec <Event>,
+  clicks          : ClickCounter,
+  scroll          : ScrollDirection,
+  gamepads        : Option <Gamepads>,
+  joysticks       : Option <Joysticks>,
+  /// Repeating timer updating rumble effects while any are playing
+  rumble_timer    : Option <TimerId>,
+  audio_devices   : AudioDeviceWatch
+}
+
+/// Routing of owned events to receivers, independent of SDL.
+///
+/// This is the part of an `EventForwarder` that delivers converted events. It
+/// can also be used on its own to feed synthetic events to receivers, e.g. to
+/// replay a recording or in tests.
+pub struct EventRouter {
+  sender      : EventSender,
+  subscribers : std::collections::HashMap <u32, EventSender>,
+  audio       : Option <EventSender>
+}
+
+/// Receiving end of the event channel.
+pub struct EventReceiver {
+  receiver : std::sync::mpsc::Receiver <Event>,
+  /// Number of events sent but not yet received
+  queued   : std::sync::Arc <std::sync::atomic::AtomicUsize>
+}
+
+/// Iterator over the events taken by `EventReceiver::drain_frame`.
+pub struct FrameEvents {
+  events : std::vec::IntoIter <Event>
+}
+
+//
+// private
+//
+
+struct EventSender {
+  sender : std::sync::mpsc::Sender <Event>,
+  queued : std::sync::Arc <std::sync::atomic::AtomicUsize>
+}
+
+/// Records the payload of audio device events as SDL queues them, since the
+/// `sdl2` crate receives them as `Event::Unknown`.
+struct AudioDeviceWatch {
+  /// Boxed so that the address given to SDL is stable
+  events : Box <std::sync::Mutex <
+    std::collections::VecDeque <sdl2_sys::SDL_AudioDeviceEvent>>>
+}
+
+/// Tracks window size changes until they settle.
+struct ResizeDebounce {
+  delay   : std::time::Duration,
+  /// Last size and time of change of windows being resized
+  pending : std::collections::HashMap <u32, ((i32, i32), std::time::Instant)>,
+  /// Timer expiring at the earliest deadline
+  timer   : Option <TimerId>
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  enums                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+/// An owned event that can be sent to another thread.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+  /// Focus, size, visibility and close request changes of a window
+  Window {
+    window_id : u32,
+    win_event : sdl2::event::WindowEvent
+  },
+  /// The desktop color scheme preference changed, while watched with
+  /// `EventForwarder::watch_theme`
+  ThemeChanged (SystemTheme),
+  /// The keyboard layout changed; carries the new layout
+  KeymapChanged (KeyboardLayout),
+  /// The preferred user locale changed
+  LocaleChanged (Option <String>),
+  DisplayConnected (DisplayInfo),
+  DisplayDisconnected (DisplayInfo),
+  /// A display was rotated; carries the display with its new bounds
+  DisplayOrientationChanged {
+    display     : DisplayInfo,
+    orientation : DisplayOrientation
+  },
+  /// A display was disconnected leaving the window off-screen
+  /// (`DisplayPolicy::Notify`).
+  ///
+  /// The window can be moved with `WindowCommand::MigrateToDisplay`.
+  WindowDisplayLost { window_id : u32 },
+  /// The window was moved to another display
+  WindowMigrated { window_id : u32, placement : WindowPlacement },
+  /// The size of the window has not changed for the resize debounce delay
+  /// (see `EventForwarder::set_resize_debounce`)
+  WindowResizeSettled { window_id : u32, width : i32, height : i32 },
+  /// The window lost focus while in exclusive fullscreen.
+  ///
+  /// If `minimized` the window is being minimized and the desktop display
+  /// mode restored (see `system::set_minimize_on_focus_loss`); otherwise it
+  /// remains fullscreen behind other windows. In either case rendering can
+  /// be paused until `FullscreenResumed`.
+  FullscreenSuspended { window_id : u32, minimized : bool },
+  /// The window lost or gained focus (`EventForwarder::set_focus_policy`)
+  FocusChanged (FocusChange),
+  /// The window regained focus after `FullscreenSuspended` and has been
+  /// restored to fullscreen
+  FullscreenResumed { window_id : u32 },
+  /// A key was pressed, or repeated if `repeat` is true
+  /// (`KeyRepeatMode::Forward`)
+  KeyDown {
+    window_id : u32,
+    keycode   : Option <sdl2::keyboard::Keycode>,
+    scancode  : Option <sdl2::keyboard::Scancode>,
+    keymod    : sdl2::keyboard::Mod,
+    repeat    : bool
+  },
+  /// A held key was repeated by the OS (`KeyRepeatMode::Separate`)
+  KeyRepeat {
+    window_id : u32,
+    keycode   : Option <sdl2::keyboard::Keycode>,
+    scancode  : Option <sdl2::keyboard::Scancode>,
+    keymod    : sdl2::keyboard::Mod
+  },
+  KeyUp {
+    window_id : u32,
+    keycode   : Option <sdl2::keyboard::Keycode>,
+    scancode  : Option <sdl2::keyboard::Scancode>,
+    keymod    : sdl2::keyboard::Mod
+  },
+  /// Text committed by the keyboard or an input method.
+  ///
+  /// Dead key and input method composition has already been resolved, and
+  /// control characters are removed, so the text can be inserted as-is.
+  TextInput { window_id : u32, text : String },
+  /// A mouse button was pressed.
+  ///
+  /// `clicks` is 1 for a single click, 2 for a double click, and so on.
+  MouseButtonDown {
+    window_id : u32,
+    /// Mouse instance ID, `SDL_TOUCH_MOUSEID` for touch input
+    which     : u32,
+    button    : sdl2::mouse::MouseButton,
+    x         : i32,
+    y         : i32,
+    clicks    : u32
+  },
+  /// A mouse button was released; `clicks` is that of the press it ends
+  MouseButtonUp {
+    window_id : u32,
+    which     : u32,
+    button    : sdl2::mouse::MouseButton,
+    x         : i32,
+    y         : i32,
+    clicks    : u32
+  },
+  /// The mouse moved; `x` and `y` are the last position and `xrel` and
+  /// `yrel` the motion since the previous event, summed over the coalesced
+  /// events
+  MouseMotion {
+    window_id : u32,
+    which     : u32,
+    /// Button state, see `sdl2::mouse::MouseState::from_sdl_state`
+    state     : u32,
+    x         : i32,
+    y         : i32,
+    xrel      : i32,
+    yrel      : i32
+  },
+  /// The mouse wheel or a touchpad was scrolled
+  Scroll { window_id : u32, which : u32, scroll : Scroll },
+  /// A game controller was connected and opened; `which` is its joystick
+  /// instance ID
+  ControllerAdded { which : i32, name : String },
+  ControllerRemoved { which : i32 },
+  /// A controller axis moved.
+  ///
+  /// `value` is normalized to -1 to 1 (0 to 1 for the triggers) and filtered
+  /// by the `AxisFilter` of the axis; `raw` is the value reported by SDL.
+  ControllerAxis {
+    which : i32,
+    axis  : sdl2::controller::Axis,
+    value : f32,
+    raw   : i16
+  },
+  ControllerButtonDown { which : i32, button : sdl2::controller::Button },
+  ControllerButtonUp   { which : i32, button : sdl2::controller::Button },
+  /// A finger touched, moved on or left a controller touchpad.
+  ///
+  /// The position is normalized to 0 to 1 from the top left.
+  ControllerTouchpad {
+    which    : i32,
+    touchpad : i32,
+    finger   : i32,
+    phase    : TouchPhase,
+    x        : f32,
+    y        : f32,
+    pressure : f32
+  },
+  /// A joystick was connected and opened (`EventForwarder::enable_joysticks`)
+  JoystickAdded (JoystickInfo),
+  JoystickRemoved { which : i32 },
+  /// A raw joystick axis moved, from -32768 to 32767
+  JoystickAxis { which : i32, axis : u8, value : i16 },
+  JoystickBall { which : i32, ball : u8, xrel : i16, yrel : i16 },
+  JoystickHat { which : i32, hat : u8, state : sdl2::joystick::HatState },
+  JoystickButtonDown { which : i32, button : u8 },
+  JoystickButtonUp   { which : i32, button : u8 },
+  /// An audio device became available; `index` is its device index and
+  /// `capture` is true for recording devices
+  AudioDeviceAdded { index : u32, name : Option <String>, capture : bool },
+  /// An opened audio device was removed; `device` is its device ID
+  AudioDeviceRemoved { device : u32, capture : bool },
+  /// A timer added with forwarding enabled expired
+  Timer (TimerId)
+}
+
+/// What to do with a window whose display has been disconnected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DisplayPolicy {
+  /// Move the window to the primary display and forward
+  /// `Event::WindowMigrated` (the default)
+  Migrate,
+  /// Only forward `Event::WindowDisplayLost`
+  Notify
+}
+
+/// How key downs generated by OS key repeat are forwarded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyRepeatMode {
+  /// Forward as `Event::KeyDown` with `repeat` set (the default)
+  Forward,
+  /// Do not forward, so that every `Event::KeyDown` is a new key press
+  Suppress,
+  /// Forward as `Event::KeyRepeat`
+  Separate
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+impl EventForwarder {
+  /// Create a new event channel.
+  pub fn new() -> (EventForwarder, EventReceiver) {
+    let (router, receiver) = EventRouter::new();
+    let forwarder = EventForwarder {
+      router,
+      theme:           SystemTheme::Unknown,
+      theme_watcher:   None,
+      displays:        system::displays(),
+      display_policy:  DisplayPolicy::Migrate,
+      windows:         Vec::new(),
+      timers:          Timers::new(),
+      resize_debounce: None,
+      suspended:       Vec::new(),
+      focus:           None,
+      visibility:      std::collections::HashMap::new(),
+      key_repeat:      KeyRepeatMode::Forward,
+      coalesce_motion: true,
+      motion:          Vec::new(),
+      clicks:          ClickCounter::new (ClickThresholds::default()),
+      scroll:          ScrollDirection::User,
+      gamepads:        None,
+      joysticks:       None,
+      rumble_timer:    None,
+      audio_devices:   AudioDeviceWatch::new()
+    };
+    (forwarder, receiver)
+  }
+
+  /// Create a receiver for the events of the given window.
+  ///
+  /// Subscribing the same window again replaces the previous receiver.
+  pub fn subscribe (&mut self, window_id : u32) -> EventReceiver {
+    self.router.subscribe (window_id)
+  }
+
+  /// Remove the subscriber for the given window; its events will go to the
+  /// broadcast receiver.
+  pub fn unsubscribe (&mut self, window_id : u32) {
+    self.router.unsubscribe (window_id)
+  }
+
+  /// Create a receiver for audio device events, e.g. for the audio thread.
+  ///
+  /// Subscribing again replaces the previous receiver. Without a subscriber
+  /// audio device events go to the broadcast receiver.
+  pub fn subscribe_audio (&mut self) -> EventReceiver {
+    self.router.subscribe_audio()
+  }
+
+  /// Number of forwarded events not yet received, over all receivers.
+  pub fn pending (&self) -> usize {
+    self.router.pending()
+  }
+
+  /// Track a window so that it is migrated (or reported) when its display is
+  /// disconnected.
+  pub fn add_window (&mut self, window_id : u32) {
+    if !self.windows.contains (&window_id) {
+      self.windows.push (window_id);
+    }
+  }
+
+  /// Stop tracking a window, e.g. once it has been destroyed.
+  pub fn remove_window (&mut self, window_id : u32) {
+    self.windows.retain (|&id| id != window_id);
+  }
+
+  pub fn set_display_policy (&mut self, policy : DisplayPolicy) {
+    self.display_policy = policy;
+  }
+
+  /// Apply a policy to focus changes and forward `Event::FocusChanged`, or
+  /// stop if `None` (the default).
+  ///
+  /// Changing the policy while a window is unfocused does not restore state
+  /// changed by the previous policy.
+  pub fn set_focus_policy (&mut self, policy : Option <FocusPolicy>) {
+    self.focus = policy.map (FocusTracker::new);
+  }
+
+  /// Throttle or suspend the render loop of a window through its pause
+  /// handle while the window is minimized or hidden, restoring the full rate
+  /// when it is restored or shown; see `pause`.
+  ///
+  /// Watching the same window again replaces the previous handle.
+  pub fn watch_visibility (&mut self,
+    window_id : u32, pause : PauseHandle, policy : VisibilityPolicy
+  ) {
+    self.visibility.insert (window_id, (pause, policy));
+  }
+
+  /// Stop watching the visibility of a window, restoring the full rate.
+  pub fn unwatch_visibility (&mut self, window_id : u32) {
+    if let Some ((pause, _)) = self.visibility.remove (&window_id) {
+      pause.set_hidden (None);
+    }
+  }
+
+  /// Start watching the desktop theme, forwarding `Event::ThemeChanged` for
+  /// the first probe and whenever it changes.
+  ///
+  /// SDL2 does not report the theme, so it is probed on a background thread
+  /// with the platform tools, see `system::system_theme`; on GNOME desktops
+  /// a `gsettings monitor` process runs while watching, elsewhere the theme
+  /// is polled every few seconds.
+  pub fn watch_theme (&mut self) {
+    if self.theme_watcher.is_none() {
+      self.theme_watcher = Some (ThemeWatcher::new());
+    }
+  }
+
+  /// Stop watching the desktop theme.
+  pub fn unwatch_theme (&mut self) {
+    self.theme_watcher = None;
+  }
+
+  pub fn set_key_repeat (&mut self, mode : KeyRepeatMode) {
+    self.key_repeat = mode;
+  }
+
+  /// Coalesce mouse motion until `flush` (the default), or forward every
+  /// motion event.
+  pub fn set_coalesce_motion (&mut self, coalesce : bool) {
+    if !coalesce {
+      self.flush();
+    }
+    self.coalesce_motion = coalesce;
+  }
+
+  /// Forward the coalesced mouse motion.
+  ///
+  /// This should be called once per iteration of the event loop, after
+  /// pumping events; `EventBridge` does this itself.
+  pub fn flush (&mut self) {
+    for motion in std::mem::take (&mut self.motion) {
+      self.router.send (motion);
+    }
+  }
+
+  /// Set the time and distance within which consecutive presses of a button
+  /// count as a multiple click.
+  pub fn set_click_thresholds (&mut self, thresholds : ClickThresholds) {
+    self.clicks.set_thresholds (thresholds);
+  }
+
+  /// Set the direction of forwarded `Event::Scroll` amounts.
+  pub fn set_scroll_direction (&mut self, direction : ScrollDirection) {
+    self.scroll = direction;
+  }
+
+  /// Open game controllers as they are connected and forward their events.
+  ///
+  /// SDL reports the controllers connected at initialization as connected
+  /// once events are pumped, so this should be called before the first
+  /// events are pumped.
+  pub fn enable_gamepads (&mut self,
+    subsystem : sdl2::GameControllerSubsystem
+  ) {
+    self.gamepads = Some (Gamepads::new (subsystem));
+  }
+
+  /// Open all joysticks as they are connected and forward their raw events,
+  /// including those of joysticks that are also game controllers.
+  ///
+  /// As with `enable_gamepads` this should be called before the first events
+  /// are pumped.
+  pub fn enable_joysticks (&mut self, subsystem : sdl2::JoystickSubsystem) {
+    self.joysticks = Some (Joysticks::new (subsystem));
+  }
+
+  /// The connected joysticks, if joysticks are enabled.
+  pub fn joysticks (&self) -> &[JoystickInfo] {
+    self.joysticks.as_ref().map_or (&[], |joysticks| joysticks.infos())
+  }
+
+  /// Create a handle for sending commands to the game controllers, e.g. to
+  /// play rumble effects, from other threads.
+  ///
+  /// Returns `None` until gamepads are enabled.
+  pub fn gamepad_sender (&self) -> Option <GamepadSender> {
+    self.gamepads.as_ref().map (|gamepads| gamepads.sender())
+  }
+
+  /// Set the filter applied to the values of a controller axis, or remove it
+  /// if `None`.
+  ///
+  /// Has no effect until gamepads are enabled.
+  pub fn set_axis_filter (&mut self,
+    axis : sdl2::controller::Axis, filter : Option <AxisFilter>
+  ) {
+    if let Some (ref mut gamepads) = self.gamepads {
+      gamepads.set_filter (axis, filter);
+    }
+  }
+
+  /// The most recently observed displays.
+  pub fn displays (&self) -> &[DisplayInfo] {
+    &self.displays
+  }
+
+  /// The most recently observed desktop theme.
+  ///
+  /// This is `SystemTheme::Unknown` unless the theme is watched, see
+  /// `watch_theme`, until the first probe completes.
+  pub fn theme (&self) -> SystemTheme {
+    self.theme
+  }
+
+  /// Forward `Event::WindowResizeSettled` once the size of a window has not
+  /// changed for the given delay, or stop if `None` (the default).
+  pub fn set_resize_debounce (&mut self, delay : Option <std::time::Duration>) {
+    if let Some (debounce) = self.resize_debounce.take() {
+      if let Some (timer) = debounce.timer {
+        self.timers.remove (timer);
+      }
+    }
+    self.resize_debounce = delay.map (ResizeDebounce::new);
+  }
+
+  /// Start a one-shot timer.
+  ///
+  /// The expiration is pumped on the main thread as an SDL user event, and
+  /// if `forward` is true is also forwarded as `Event::Timer` when given to
+  /// `forward`. Returns the SDL error message if the timer could not be
+  /// started.
+  pub fn add_timer (&mut self, delay : std::time::Duration, forward : bool)
+    -> Result <TimerId, String>
+  {
+    self.timers.add (delay, false, forward)
+  }
+
+  /// Start a timer that expires every `interval` until removed.
+  pub fn add_repeating_timer (&mut self,
+    interval : std::time::Duration, forward : bool
+  ) -> Result <TimerId, String> {
+    self.timers.add (interval, true, forward)
+  }
+
+  /// Stop a timer; returns false if it was not running.
+  ///
+  /// An expiration already queued when the timer is removed may still be
+  /// pumped, but will not be forwarded.
+  pub fn remove_timer (&mut self, id : TimerId) -> bool {
+    self.timers.remove (id)
+  }
+
+  /// Process an event pumped on the main thread, forwarding any resulting
+  /// events.
+  pub fn forward (&mut self, event : &sdl2::event::Event) {
+    if let Some (id) = TimerId::from_event (event) {
+      if self.timers.expired (id) == Some (true) {
+        self.send (Event::Timer (id));
+      }
+      let resize_timer = self.resize_debounce.as_ref()
+        .is_some_and (|debounce| debounce.timer == Some (id));
+      if resize_timer {
+        self.settle_resizes();
+      }
+      if self.rumble_timer == Some (id) {
+        self.update_rumble (false);
+      }
+      return
+    }
+    if self.theme_watcher.as_ref()
+      .is_some_and (|watcher| watcher.is_wake_event (event))
+    {
+      self.refresh_theme();
+      return
+    }
+    if self.gamepads.as_ref()
+      .is_some_and (|gamepads| gamepads.is_wake_event (event))
+    {
+      self.update_rumble (true);
+      return
+    }
+    if let Some (key_event) = key_event (event, self.key_repeat) {
+      self.send (key_event);
+    }
+    if let Some (controller_event) = self.gamepads.as_mut()
+      .and_then (|gamepads| gamepads.handle (event))
+    {
+      self.send (controller_event);
+    }
+    if let Some (joystick_event) = self.joysticks.as_mut()
+      .and_then (|joysticks| joysticks.handle (event))
+    {
+      self.send (joystick_event);
+    }
+    let touchpad_events = self.gamepads.as_mut()
+      .map_or (vec![], |gamepads| gamepads.touchpad_events (event));
+    for touchpad_event in touchpad_events {
+      self.send (touchpad_event);
+    }
+    match *event {
+      sdl2::event::Event::Window { window_id, win_event, .. } => {
+        self.send (Event::Window { window_id, win_event });
+        if let sdl2::event::WindowEvent::SizeChanged (width, height)
+          = win_event
+        {
+          self.debounce_resize (window_id, (width, height));
+        }
+        match win_event {
+          sdl2::event::WindowEvent::FocusGained => {
+            if let Some (change) = self.focus.as_mut()
+              .map (|focus| focus.focus_gained (window_id))
+            {
+              self.send (Event::FocusChanged (change));
+            }
+            self.resume_fullscreen (window_id);
+          }
+          sdl2::event::WindowEvent::FocusLost => {
+            if let Some (change) = self.focus.as_mut()
+              .map (|focus| focus.focus_lost (window_id))
+            {
+              self.send (Event::FocusChanged (change));
+            }
+            self.suspend_fullscreen (window_id);
+          }
+          sdl2::event::WindowEvent::Minimized
+            | sdl2::event::WindowEvent::Hidden => self.hide (window_id),
+          sdl2::event::WindowEvent::Restored
+            | sdl2::event::WindowEvent::Maximized
+            | sdl2::event::WindowEvent::Shown => self.show (window_id),
+          sdl2::event::WindowEvent::Close => {
+            self.suspended.retain (|id| *id != window_id);
+            if let Some (ref mut focus) = self.focus {
+              focus.remove_window (window_id);
+            }
+            // the render thread must run to handle the close request
+            self.show (window_id);
+          }
+          _ => {}
+        }
+      }
+      sdl2::event::Event::TextInput { window_id, ref text, .. } => {
+        let text = normalize_text (text);
+        if !text.is_empty() {
+          self.send (Event::TextInput { window_id, text });
+        }
+      }
+      sdl2::event::Event::MouseButtonDown {
+        timestamp, window_id, which, mouse_btn, x, y, ..
+      } => {
+        let clicks
+          = self.clicks.press (window_id, mouse_btn, (x, y), timestamp);
+        self.send (Event::MouseButtonDown {
+          window_id, which, button: mouse_btn, x, y, clicks
+        });
+      }
+      sdl2::event::Event::MouseButtonUp {
+        window_id, which, mouse_btn, x, y, ..
+      } => {
+        let clicks = self.clicks.release (window_id, mouse_btn);
+        self.send (Event::MouseButtonUp {
+          window_id, which, button: mouse_btn, x, y, clicks
+        });
+      }
+      sdl2::event::Event::MouseMotion {
+        window_id, which, mousestate, x, y, xrel, yrel, ..
+      } => self.send (Event::MouseMotion {
+        window_id, which, state: mousestate.to_sdl_state(), x, y, xrel, yrel
+      }),
+      sdl2::event::Event::MouseWheel {
+        window_id, which, x, y, direction, ..
+      } => {
+        let scroll = Scroll::from_wheel (x, y, direction, self.scroll);
+        self.send (Event::Scroll { window_id, which, scroll });
+      }
+      sdl2::event::Event::KeyDown {
+        window_id, scancode: Some (scancode), ..
+      } => if let Some (ref mut focus) = self.focus {
+        focus.key_down (window_id, scancode);
+      },
+      sdl2::event::Event::KeyUp {
+        window_id, scancode: Some (scancode), ..
+      } => if let Some (ref mut focus) = self.focus {
+        focus.key_up (window_id, scancode);
+      },
+      sdl2::event::Event::Unknown { type_, .. } => {
+        if type_ == sdl2_sys::SDL_EventType::SDL_KEYMAPCHANGED as u32 {
+          self.send (Event::KeymapChanged (KeyboardLayout::query()));
+        } else if type_ == SDL_LOCALECHANGED {
+          self.send (Event::LocaleChanged (system::system_locale()));
+        } else if type_ == SDL_DISPLAYEVENT {
+          self.refresh_displays();
+        } else if type_ == SDL_AUDIODEVICEADDED {
+          if let Some (device) = self.audio_devices.take (type_) {
+            self.send (Event::AudioDeviceAdded {
+              index:   device.which,
+              name:    audio_device_name (device.which, device.iscapture),
+              capture: device.iscapture != 0
+            });
+          }
+        } else if type_ == SDL_AUDIODEVICEREMOVED {
+          if let Some (device) = self.audio_devices.take (type_) {
+            self.send (Event::AudioDeviceRemoved {
+              device:  device.which,
+              capture: device.iscapture != 0
+            });
+          }
+        }
+      }
+      _ => {}
+    }
+  }
+
+  /// Forward `Event::ThemeChanged` if the theme watcher has reported a theme
+  /// other than the last observed one.
+  ///
+  /// The theme is probed on a background thread, which wakes the main thread
+  /// when it changes, so this does not block and is called by `forward`.
+  pub fn refresh_theme (&mut self) {
+    let theme = match self.theme_watcher.as_ref()
+      .and_then (ThemeWatcher::changed)
+    {
+      Some (theme) => theme,
+      None => return
+    };
+    if theme != self.theme {
+      self.theme = theme;
+      self.send (Event::ThemeChanged (theme));
+    }
+  }
+
+  /// Enumerate the displays, forwarding connection and orientation changes
+  /// and handling any tracked windows left off-screen according to the
+  /// display policy.
+  ///
+  /// This is called automatically on display events (SDL 2.0.9 and later
+  /// report orientation changes and SDL 2.0.14 and later report hotplugging);
+  /// with older versions of SDL it can be called periodically instead.
+  pub fn refresh_displays (&mut self) {
+    let displays = system::displays();
+    let changes = system::diff_displays (&self.displays, &displays);
+    self.displays = displays;
+    for display in changes.connected {
+      self.send (Event::DisplayConnected (display));
+    }
+    for display in changes.reoriented {
+      let orientation = display.orientation();
+      self.send (Event::DisplayOrientationChanged { display, orientation });
+    }
+    if changes.disconnected.is_empty() {
+      return
+    }
+    for display in changes.disconnected {
+      self.send (Event::DisplayDisconnected (display));
+    }
+    for window_id in self.windows.clone() {
+      let window_raw = unsafe { sdl2_sys::SDL_GetWindowFromID (window_id) };
+      if window_raw.is_null() ||
+        system::window_is_visible (window_raw, &self.displays)
+      {
+        continue
+      }
+      let event = match self.display_policy {
+        DisplayPolicy::Migrate => Event::WindowMigrated {
+          window_id,
+          placement: system::migrate_window (window_raw, 0)
+        },
+        DisplayPolicy::Notify  => Event::WindowDisplayLost { window_id }
+      };
+      self.send (event);
+    }
+  }
+
+  /// Update the playing rumble effects, first executing the queued gamepad
+  /// commands if `execute` is true, and keep the update timer running while
+  /// any are playing.
+  fn update_rumble (&mut self, execute : bool) {
+    let rumbling = match self.gamepads {
+      Some (ref mut gamepads) => {
+        if execute {
+          gamepads.execute_pending();
+        } else {
+          gamepads.update_rumble();
+        }
+        gamepads.is_rumbling()
+      }
+      None => false
+    };
+    match (rumbling, self.rumble_timer) {
+      (true, None) => {
+        let interval
+          = std::time::Duration::from_millis (gamepad::RUMBLE_UPDATE_MS);
+        self.rumble_timer = self.timers.add (interval, true, false).ok();
+      }
+      (false, Some (timer)) => {
+        self.timers.remove (timer);
+        self.rumble_timer = None;
+      }
+      _ => {}
+    }
+  }
+
+  fn suspend_fullscreen (&mut self, window_id : u32) {
+    let window_raw = unsafe { sdl2_sys::SDL_GetWindowFromID (window_id) };
+    if window_raw.is_null() || self.suspended.contains (&window_id) ||
+      !system::is_exclusive_fullscreen (window_raw)
+    {
+      return
+    }
+    self.suspended.push (window_id);
+    let minimized = system::minimize_on_focus_loss();
+    self.send (Event::FullscreenSuspended { window_id, minimized });
+  }
+
+  fn resume_fullscreen (&mut self, window_id : u32) {
+    match self.suspended.iter().position (|id| *id == window_id) {
+      Some (i) => { self.suspended.remove (i); }
+      None => return
+    }
+    let window_raw = unsafe { sdl2_sys::SDL_GetWindowFromID (window_id) };
+    if window_raw.is_null() {
+      return
+    }
+    let minimized = sdl2_sys::SDL_WindowFlags::SDL_WINDOW_MINIMIZED as u32;
+    unsafe {
+      // some window managers focus a minimized window without restoring it,
+      // in which case SDL would not switch back to the fullscreen mode
+      if sdl2_sys::SDL_GetWindowFlags (window_raw) & minimized != 0 {
+        sdl2_sys::SDL_RestoreWindow (window_raw);
+      }
+    }
+    self.send (Event::FullscreenResumed { window_id });
+  }
+
+  /// Apply the visibility policy of a watched window.
+  fn hide (&self, window_id : u32) {
+    if let Some (&(ref pause, policy)) = self.visibility.get (&window_id) {
+      pause.set_hidden (Some (policy));
+    }
+  }
+
+  /// Restore the full rate of a watched window.
+  fn show (&self, window_id : u32) {
+    if let Some ((pause, _)) = self.visibility.get (&window_id) {
+      pause.set_hidden (None);
+    }
+  }
+
+  fn debounce_resize (&mut self, window_id : u32, size : (i32, i32)) {
+    let delay = match self.resize_debounce {
+      Some (ref mut debounce) => {
+        debounce.resized (window_id, size, std::time::Instant::now());
+        if debounce.timer.is_some() {
+          return
+        }
+        debounce.delay
+      }
+      None => return
+    };
+    self.start_resize_timer (delay);
+  }
+
+  /// Forward the resizes that have settled and wait for the rest.
+  fn settle_resizes (&mut self) {
+    let now = std::time::Instant::now();
+    let (settled, next) = match self.resize_debounce {
+      Some (ref mut debounce) => {
+        debounce.timer = None;
+        (debounce.settled (now),
+          debounce.next_deadline().map (|deadline| deadline - now))
+      }
+      None => return
+    };
+    for (window_id, (width, height)) in settled {
+      self.send (Event::WindowResizeSettled { window_id, width, height });
+    }
+    if let Some (delay) = next {
+      self.start_resize_timer (delay);
+    }
+  }
+
+  fn start_resize_timer (&mut self, delay : std::time::Duration) {
+    // without a timer the resize settles at the next size change
+    let timer = self.timers.add (delay, false, false).ok();
+    if let Some (ref mut debounce) = self.resize_debounce {
+      debounce.timer = timer;
+    }
+  }
+
+  fn send (&mut self, event : Event) {
+    match event {
+      Event::MouseMotion { .. } if self.coalesce_motion =>
+        coalesce_motion (&mut self.motion, event),
+      event => {
+        self.flush();
+        self.router.send (event)
+      }
+    }
+  }
+}
+
+impl EventRouter {
+  /// Create a router and its broadcast receiver.
+  pub fn new() -> (EventRouter, EventReceiver) {
+    let (sender, receiver) = event_channel();
+    let router = EventRouter {
+      sender,
+      subscribers: std::collections::HashMap::new(),
+      audio:       None
+    };
+    (router, receiver)
+  }
+
+  /// See `EventForwarder::subscribe`.
+  pub fn subscribe (&mut self, window_id : u32) -> EventReceiver {
+    let (sender, receiver) = event_channel();
+    self.subscribers.insert (window_id, sender);
+    receiver
+  }
+
+  /// See `EventForwarder::unsubscribe`.
+  pub fn unsubscribe (&mut self, window_id : u32) {
+    self.subscribers.remove (&window_id);
+  }
+
+  /// See `EventForwarder::subscribe_audio`.
+  pub fn subscribe_audio (&mut self) -> EventReceiver {
+    let (sender, receiver) = event_channel();
+    self.audio = Some (sender);
+    receiver
+  }
+
+  /// See `EventForwarder::pending`.
+  pub fn pending (&self) -> usize {
+    std::iter::once (&self.sender)
+      .chain (self.subscribers.values())
+      .chain (self.audio.iter())
+      // events queued for a receiver that has hung up will never be received
+      .filter (|sender| std::sync::Arc::strong_count (&sender.queued) > 1)
+      .map (|sender| sender.queued.load (std::sync::atomic::Ordering::SeqCst))
+      .sum()
+  }
+
+  /// Route an event to the subscriber of its window or the audio subscriber,
+  /// falling back to the broadcast receiver.
+  pub fn send (&mut self, event : Event) {
+    let event = match event {
+      Event::AudioDeviceAdded { .. } | Event::AudioDeviceRemoved { .. }
+        if self.audio.is_some() =>
+      {
+        match self.audio.as_ref().unwrap().send (event) {
+          Ok (()) => return,
+          Err (event) => {
+            // the subscriber has hung up
+            self.audio = None;
+            event
+          }
+        }
+      }
+      event => event
+    };
+    let event = match event.window_id() {
+      Some (window_id) if self.subscribers.contains_key (&window_id) => {
+        match self.subscribers[&window_id].send (event) {
+          Ok (()) => return,
+          Err (event) => {
+            // the subscriber has hung up
+            self.subscribers.remove (&window_id);
+            event
+          }
+        }
+      }
+      _ => event
+    };
+    // the receiver may have been dropped if the render thread has exited
+    let _ = self.sender.send (event);
+  }
+}
+
+impl Event {
+  /// The window this event is associated with, if any.
+  pub fn window_id (&self) -> Option <u32> {
+    match *self {
+      Event::Window              { window_id, .. } |
+      Event::WindowDisplayLost   { window_id }     |
+      Event::WindowMigrated      { window_id, .. } |
+      Event::WindowResizeSettled { window_id, .. } |
+      Event::FullscreenSuspended { window_id, .. } |
+      Event::FullscreenResumed   { window_id }     => Some (window_id),
+      Event::KeyDown             { window_id, .. } |
+      Event::KeyRepeat           { window_id, .. } |
+      Event::KeyUp               { window_id, .. } |
+      Event::TextInput           { window_id, .. } |
+      Event::MouseButtonDown     { window_id, .. } |
+      Event::MouseButtonUp       { window_id, .. } |
+      Event::MouseMotion         { window_id, .. } |
+      Event::Scroll              { window_id, .. } => Some (window_id),
+      Event::FocusChanged (ref change) => Some (change.window_id),
+      _ => None
+    }
+  }
+}
+
+impl EventReceiver {
+  /// Returns the next event if one is queued, without blocking.
+  pub fn try_recv (&self) -> Option <Event> {
+    self.receiver.try_recv().ok().map (|event| self.received (1, event))
+  }
+
+  /// Number of events sent to this receiver and not yet received.
+  pub fn pending (&self) -> usize {
+    self.queued.load (std::sync::atomic::Ordering::SeqCst)
+  }
+
+  /// Block until the next event arrives.
+  ///
+  /// Returns `None` if the forwarder has been dropped.
+  pub fn recv (&self) -> Option <Event> {
+    self.receiver.recv().ok().map (|event| self.received (1, event))
+  }
+
+  /// Take all events queued since the last call, without blocking.
+  ///
+  /// Intended to be called once per frame on the render thread. Only the
+  /// events queued at the time of the call are taken, so a flood of events
+  /// arriving while the frame's events are handled is deferred to the next
+  /// frame rather than stalling the current one.
+  ///
+  /// Consecutive `Event::TextInput`s of a window are coalesced into one, so
+  /// that text keeps its order relative to other events such as editing
+  /// keys.
+  pub fn drain_frame (&self) -> FrameEvents {
+    use std::sync::atomic::Ordering;
+    let count = self.queued.load (Ordering::SeqCst);
+    let events : Vec <Event> = self.receiver.try_iter().take (count).collect();
+    self.queued.fetch_sub (events.len(), Ordering::SeqCst);
+    FrameEvents { events: coalesce_text (events).into_iter() }
+  }
+
+  fn received (&self, count : usize, event : Event) -> Event {
+    self.queued.fetch_sub (count, std::sync::atomic::Ordering::SeqCst);
+    event
+  }
+}
+
+impl Iterator for FrameEvents {
+  type Item = Event;
+  fn next (&mut self) -> Option <Event> {
+    self.events.next()
+  }
+  fn size_hint (&self) -> (usize, Option <usize>) {
+    self.events.size_hint()
+  }
+}
+
+impl ExactSizeIterator for FrameEvents { }
+
+impl ResizeDebounce {
+  fn new (delay : std::time::Duration) -> Self {
+    ResizeDebounce {
+      delay,
+      pending: std::collections::HashMap::new(),
+      timer:   None
+    }
+  }
+
+  fn resized (&mut self,
+    window_id : u32, size : (i32, i32), now : std::time::Instant
+  ) {
+    self.pending.insert (window_id, (size, now));
+  }
+
+  /// Remove and return the windows whose size has not changed for the delay.
+  fn settled (&mut self, now : std::time::Instant) -> Vec <(u32, (i32, i32))> {
+    let delay = self.delay;
+    let settled = self.pending.iter()
+      .filter (|&(_, &(_, changed))| now.duration_since (changed) >= delay)
+      .map (|(window_id, &(size, _))| (*window_id, size))
+      .collect::<Vec <_>>();
+    for &(window_id, _) in settled.iter() {
+      self.pending.remove (&window_id);
+    }
+    settled
+  }
+
+  /// Time at which the next pending resize will have settled.
+  fn next_deadline (&self) -> Option <std::time::Instant> {
+    self.pending.values().map (|&(_, changed)| changed + self.delay).min()
+  }
+}
+
+impl AudioDeviceWatch {
+  fn new() -> Self {
+    let watch = AudioDeviceWatch { events: Box::default() };
+    unsafe {
+      sdl2_sys::SDL_AddEventWatch (Some (watch_audio_device), watch.userdata())
+    };
+    watch
+  }
+
+  /// Remove the payload of the next queued audio device event of the given
+  /// type.
+  fn take (&self, type_ : u32) -> Option <sdl2_sys::SDL_AudioDeviceEvent> {
+    let mut events = self.events.lock().unwrap();
+    // skips events that were removed from the queue without being pumped
+    while let Some (event) = events.pop_front() {
+      if event.type_ == type_ {
+        return Some (event)
+      }
+    }
+    None
+  }
+
+  fn userdata (&self) -> *mut std::os::raw::c_void {
+    &*self.events as *const _ as *mut std::os::raw::c_void
+  }
+}
+
+impl Drop for AudioDeviceWatch {
+  fn drop (&mut self) {
+    unsafe {
+      sdl2_sys::SDL_DelEventWatch (Some (watch_audio_device), self.userdata())
+    };
+  }
+}
+
+impl EventSender {
+  /// Returns the event if the receiver has hung up.
+  fn send (&self, event : Event) -> Result <(), Event> {
+    // the count is incremented first so that it never underflows when the
+    // event is received immediately
+    self.queued.fetch_add (1, std::sync::atomic::Ordering::SeqCst);
+    self.sender.send (event).map_err (|std::sync::mpsc::SendError (event)| {
+      self.queued.fetch_sub (1, std::sync::atomic::Ordering::SeqCst);
+      event
+    })
+  }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  functions                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Event watch recording audio device events; called on the thread queueing
+/// the event, which may be the audio hotplug thread.
+unsafe extern "C" fn watch_audio_device (
+  userdata : *mut std::os::raw::c_void,
+  event    : *mut sdl2_sys::SDL_Event
+) -> std::os::raw::c_int {
+  let type_ = (*event).type_;
+  if type_ == SDL_AUDIODEVICEADDED || type_ == SDL_AUDIODEVICEREMOVED {
+    let events = &*(userdata as *const std::sync::Mutex <
+      std::collections::VecDeque <sdl2_sys::SDL_AudioDeviceEvent>>);
+    if let Ok (mut events) = events.lock() {
+      events.push_back ((*event).adevice);
+    }
+  }
+  0
+}
+
+fn audio_device_name (index : u32, capture : u8) -> Option <String> {
+  unsafe {
+    let name_raw = sdl2_sys::SDL_GetAudioDeviceName (
+      index as i32, capture as i32);
+    if name_raw.is_null() {
+      None
+    } else {
+      Some (std::ffi::CStr::from_ptr (name_raw).to_string_lossy().into_owned())
+    }
+  }
+}
+
+/// Convert a key event according to the key repeat mode.
+fn key_event (event : &sdl2::event::Event, key_repeat : KeyRepeatMode)
+  -> Option <Event>
+{
+  match *event {
+    sdl2::event::Event::KeyDown {
+      window_id, keycode, scancode, keymod, repeat, ..
+    } => match key_repeat {
+      KeyRepeatMode::Suppress if repeat => None,
+      KeyRepeatMode::Separate if repeat =>
+        Some (Event::KeyRepeat { window_id, keycode, scancode, keymod }),
+      _ => Some (
+        Event::KeyDown { window_id, keycode, scancode, keymod, repeat })
+    },
+    sdl2::event::Event::KeyUp { window_id, keycode, scancode, keymod, .. } =>
+      Some (Event::KeyUp { window_id, keycode, scancode, keymod }),
+    _ => None
+  }
+}
+
+/// Remove control characters from committed text.
+///
+/// Some platforms deliver e.g. a carriage return or delete character as text
+/// in addition to the corresponding key event.
+fn normalize_text (text : &str) -> String {
+  text.chars().filter (|c| !c.is_control()).collect()
+}
+
+/// Append the text of each `Event::TextInput` to the previous event if it
+/// is a `TextInput` of the same window, so that text is never moved past
+/// other events, e.g. editing keys.
+fn coalesce_text (events : Vec <Event>) -> Vec <Event> {
+  let mut coalesced : Vec <Event> = Vec::with_capacity (events.len());
+  for event in events {
+    if let Event::TextInput { window_id, ref text } = event {
+      if let Some (&mut Event::TextInput {
+        window_id: previous_window, text: ref mut previous
+      }) = coalesced.last_mut() {
+        if previous_window == window_id {
+          previous.push_str (text);
+          continue
+        }
+      }
+    }
+    coalesced.push (event);
+  }
+  coalesced
+}
+
+/// Merge a `Event::MouseMotion` into the pending motion of the same window
+/// and mouse.
+fn coalesce_motion (motion : &mut Vec <Event>, event : Event) {
+  if let Event::MouseMotion {
+    window_id, which, state, x, y, xrel, yrel
+  } = event {
+    for pending in motion.iter_mut() {
+      if let Event::MouseMotion {
+        window_id: pending_window, which: pending_which,
+        state: ref mut pending_state, x: ref mut pending_x,
+        y: ref mut pending_y, xrel: ref mut pending_xrel,
+        yrel: ref mut pending_yrel
+      } = *pending {
+        if pending_window == window_id && pending_which == which {
+          *pending_state = state;
+          *pending_x     = x;
+          *pending_y     = y;
+          *pending_xrel += xrel;
+          *pending_yrel += yrel;
+          return
+        }
+      }
+    }
+  }
+  motion.push (event);
+}
+
+fn event_channel() -> (EventSender, EventReceiver) {
+  let (sender, receiver) = std::sync::mpsc::channel();
+  let queued = std::sync::Arc::new (std::sync::atomic::AtomicUsize::new (0));
+  ( EventSender { sender, queued: queued.clone() },
+    EventReceiver { receiver, queued } )
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  #[test]
+  fn test_drain_frame() {
+    let (sender, receiver) = event_channel();
+    let theme = Event::ThemeChanged (SystemTheme::Dark);
+    sender.send (theme.clone()).unwrap();
+    sender.send (theme.clone()).unwrap();
+    assert_eq!(receiver.try_recv(), Some (theme.clone()));
+    let mut frame = receiver.drain_frame();
+    sender.send (theme.clone()).unwrap();
+    assert_eq!(frame.len(), 1);
+    assert_eq!(frame.next(), Some (theme.clone()));
+    assert_eq!(receiver.drain_frame().collect::<Vec <_>>(), vec![theme]);
+    assert_eq!(receiver.drain_frame().len(), 0);
+  }
+  #[test]
+  fn test_event_router() {
+    let (mut router, broadcast) = EventRouter::new();
+    let resumed = Event::FullscreenResumed { window_id: 1 };
+    let subscriber = router.subscribe (1);
+    router.send (resumed.clone());
+    router.send (Event::ThemeChanged (SystemTheme::Dark));
+    assert_eq!(subscriber.pending(), 1);
+    assert_eq!(subscriber.try_recv(), Some (resumed.clone()));
+    assert_eq!(broadcast.pending(), 1);
+    assert_eq!(router.pending(), 1);
+    router.send (resumed.clone());
+    drop (subscriber);
+    assert_eq!(router.pending(), 1);
+    router.send (resumed.clone());
+    assert_eq!(broadcast.drain_frame().collect::<Vec <_>>(),
+      vec![Event::ThemeChanged (SystemTheme::Dark), resumed]);
+  }
+  #[test]
+  fn test_resize_debounce() {
+    let delay = std::time::Duration::from_millis (100);
+    let start = std::time::Instant::now();
+    let mut debounce = ResizeDebounce::new (delay);
+    debounce.resized (1, (100, 100), start);
+    debounce.resized (2, (200, 200), start);
+    debounce.resized (1, (110, 110), start + delay / 2);
+    assert_eq!(debounce.next_deadline(), Some (start + delay));
+    assert_eq!(debounce.settled (start + delay), vec![(2, (200, 200))]);
+    assert_eq!(debounce.next_deadline(), Some (start + delay * 3 / 2));
+    assert_eq!(debounce.settled (start + delay), vec![]);
+    assert_eq!(debounce.settled (start + delay * 2), vec![(1, (110, 110))]);
+    assert_eq!(debounce.next_deadline(), None);
+  }
+  #[test]
+  fn test_audio_device_watch() {
+    let watch = AudioDeviceWatch::new();
+    let queue = |type_, which| unsafe {
+      let mut event : sdl2_sys::SDL_Event = std::mem::zeroed();
+      event.adevice.type_ = type_;
+      event.adevice.which = which;
+      watch_audio_device (watch.userdata(), &mut event);
+    };
+    queue (SDL_AUDIODEVICEADDED, 1);
+    queue (SDL_AUDIODEVICEREMOVED, 7);
+    queue (SDL_DISPLAYEVENT, 0);
+    queue (SDL_AUDIODEVICEADDED, 2);
+    assert_eq!(watch.take (SDL_AUDIODEVICEADDED).map (|event| event.which),
+      Some (1));
+    // the removal is skipped, as if it had been dropped from the queue
+    assert_eq!(watch.take (SDL_AUDIODEVICEADDED).map (|event| event.which),
+      Some (2));
+    assert!(watch.take (SDL_AUDIODEVICEREMOVED).is_none());
+  }
+  #[test]
+  fn test_key_repeat() {
+    use sdl2::keyboard::{Mod, Scancode};
+    let key_down = |repeat| sdl2::event::Event::KeyDown {
+      timestamp: 0, window_id: 1, keycode: None, scancode: Some (Scancode::A),
+      keymod: Mod::empty(), repeat
+    };
+    let forwarded = |repeat| Event::KeyDown {
+      window_id: 1, keycode: None, scancode: Some (Scancode::A),
+      keymod: Mod::empty(), repeat
+    };
+    assert_eq!(key_event (&key_down (false), KeyRepeatMode::Suppress),
+      Some (forwarded (false)));
+    assert_eq!(key_event (&key_down (true), KeyRepeatMode::Forward),
+      Some (forwarded (true)));
+    assert_eq!(key_event (&key_down (true), KeyRepeatMode::Suppress), None);
+    assert_eq!(key_event (&key_down (true), KeyRepeatMode::Separate),
+      Some (Event::KeyRepeat {
+        window_id: 1, keycode: None, scancode: Some (Scancode::A),
+        keymod: Mod::empty()
+      }));
+    assert_eq!(key_event (
+      &sdl2::event::Event::Quit { timestamp: 0 }, KeyRepeatMode::Forward),
+      None);
+  }
+  #[test]
+  fn test_coalesce_motion() {
+    let state = 0;
+    let motion = |window_id, x, y, xrel, yrel| Event::MouseMotion {
+      window_id, which: 0, state, x, y, xrel, yrel
+    };
+    let mut pending = Vec::new();
+    coalesce_motion (&mut pending, motion (1, 10, 10, 1, 2));
+    coalesce_motion (&mut pending, motion (2, 5, 5, 5, 5));
+    coalesce_motion (&mut pending, motion (1, 13, 9, 3, -1));
+    assert_eq!(pending, vec![motion (1, 13, 9, 4, 1), motion (2, 5, 5, 5, 5)]);
+  }
+  #[test]
+  fn test_coalesce_text() {
+    use sdl2::keyboard::{Keycode, Mod};
+    let text = |window_id, text : &str|
+      Event::TextInput { window_id, text: text.to_string() };
+    let backspace = Event::KeyDown {
+      window_id: 1, keycode: Some (Keycode::Backspace), scancode: None,
+      keymod: Mod::empty(), repeat: false
+    };
+    // only contiguous text of a window is merged
+    assert_eq!(
+      coalesce_text (vec![text (1, "a"), text (1, "b"), backspace.clone(),
+        text (1, "c"), text (1, "d"), text (2, "x"), text (1, "e")]),
+      vec![text (1, "ab"), backspace, text (1, "cd"), text (2, "x"),
+        text (1, "e")]);
+    assert_eq!(normalize_text ("a\rb\u{7f}\u{e9}"), "ab\u{e9}");
+  }
+}
@@ -0,0 +1,171 @@
+//! Policy applied when a window loses and regains input focus.
+//!
+//! A `FocusPolicy` installed with `EventForwarder::set_focus_policy` makes the
+//! forwarder track the keys held in each window and, on a focus change,
+//! forward a single `Event::FocusChanged` carrying everything the render
+//! thread needs to react: the keys to treat as released (since their key up
+//! events go to another application and keys would otherwise be stuck) and
+//! the frame rate to render at. Relative mouse mode, which may only be
+//! changed on the main thread, is disabled by the forwarder while unfocused
+//! and re-enabled when focus returns.
+
+use sdl2;
+use sdl2_sys;
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// What to do when a window loses focus; everything is restored when it
+/// regains focus.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FocusPolicy {
+  /// Report the keys held at the time focus is lost as released
+  pub release_keys           : bool,
+  /// Frame rate to render at while unfocused, or `None` to keep rendering
+  /// at the normal rate
+  pub background_frame_rate  : Option <u32>,
+  /// Disable relative mouse mode while unfocused
+  pub suspend_relative_mouse : bool
+}
+
+/// A change of focus with the resulting state under the focus policy.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FocusChange {
+  pub window_id     : u32,
+  pub focused       : bool,
+  /// Keys that were held when focus was lost, to be treated as released
+  pub released_keys : Vec <sdl2::keyboard::Scancode>,
+  /// Frame rate to render at from now on, or `None` for the normal rate
+  pub frame_rate    : Option <u32>
+}
+
+/// Main thread state for applying a focus policy.
+#[derive(Debug)]
+pub(crate) struct FocusTracker {
+  policy         : FocusPolicy,
+  /// Keys currently held, by window
+  held_keys      : std::collections::HashMap <u32,
+    Vec <sdl2::keyboard::Scancode>>,
+  /// Relative mouse mode was disabled by the policy and is to be restored
+  relative_mouse : bool
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+impl Default for FocusPolicy {
+  /// Release keys, render at 10 frames per second and suspend relative mouse
+  /// mode while unfocused.
+  fn default() -> Self {
+    FocusPolicy {
+      release_keys:           true,
+      background_frame_rate:  Some (10),
+      suspend_relative_mouse: true
+    }
+  }
+}
+
+impl FocusTracker {
+  pub(crate) fn new (policy : FocusPolicy) -> Self {
+    FocusTracker {
+      policy,
+      held_keys:      std::collections::HashMap::new(),
+      relative_mouse: false
+    }
+  }
+
+  pub(crate) fn key_down (&mut self,
+    window_id : u32, scancode : sdl2::keyboard::Scancode
+  ) {
+    let held = self.held_keys.entry (window_id).or_default();
+    if !held.contains (&scancode) {
+      held.push (scancode);
+    }
+  }
+
+  pub(crate) fn key_up (&mut self,
+    window_id : u32, scancode : sdl2::keyboard::Scancode
+  ) {
+    if let Some (held) = self.held_keys.get_mut (&window_id) {
+      held.retain (|key| *key != scancode);
+    }
+  }
+
+  /// Apply the policy to a window losing focus.
+  ///
+  /// This must be called on the main thread.
+  pub(crate) fn focus_lost (&mut self, window_id : u32) -> FocusChange {
+    let held = self.held_keys.remove (&window_id).unwrap_or_default();
+    if self.policy.suspend_relative_mouse && !self.relative_mouse {
+      unsafe {
+        if sdl2_sys::SDL_GetRelativeMouseMode() == sdl2_sys::SDL_bool::SDL_TRUE
+        {
+          sdl2_sys::SDL_SetRelativeMouseMode (sdl2_sys::SDL_bool::SDL_FALSE);
+          self.relative_mouse = true;
+        }
+      }
+    }
+    FocusChange {
+      window_id,
+      focused:       false,
+      released_keys: if self.policy.release_keys { held } else { vec![] },
+      frame_rate:    self.policy.background_frame_rate
+    }
+  }
+
+  /// Restore the state changed by the policy when a window gains focus.
+  ///
+  /// This must be called on the main thread.
+  pub(crate) fn focus_gained (&mut self, window_id : u32) -> FocusChange {
+    if self.relative_mouse {
+      unsafe {
+        sdl2_sys::SDL_SetRelativeMouseMode (sdl2_sys::SDL_bool::SDL_TRUE);
+      }
+      self.relative_mouse = false;
+    }
+    FocusChange {
+      window_id,
+      focused:       true,
+      released_keys: vec![],
+      frame_rate:    None
+    }
+  }
+
+  /// Forget the keys held in a closed window.
+  pub(crate) fn remove_window (&mut self, window_id : u32) {
+    self.held_keys.remove (&window_id);
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  #[test]
+  fn test_focus_tracker() {
+    use sdl2::keyboard::Scancode;
+    let policy = FocusPolicy {
+      suspend_relative_mouse: false, .. FocusPolicy::default()
+    };
+    let mut tracker = FocusTracker::new (policy);
+    tracker.key_down (1, Scancode::A);
+    tracker.key_down (1, Scancode::A);
+    tracker.key_down (1, Scancode::B);
+    tracker.key_down (2, Scancode::Q);
+    tracker.key_up (1, Scancode::B);
+    let change = tracker.focus_lost (1);
+    assert!(!change.focused);
+    assert_eq!(change.released_keys, vec![Scancode::A]);
+    assert_eq!(change.frame_rate, Some (10));
+    let change = tracker.focus_gained (1);
+    assert!(change.focused);
+    assert_eq!(change.frame_rate, None);
+    assert!(tracker.focus_lost (1).released_keys.is_empty());
+    let mut tracker = FocusTracker::new (FocusPolicy {
+      release_keys: false, .. policy
+    });
+    tracker.key_down (2, Scancode::Q);
+    assert!(tracker.focus_lost (2).released_keys.is_empty());
+  }
+}
@@ -0,0 +1,826 @@
+//! Game controller input handled on the main thread.
+//!
+//! Once enabled with `EventForwarder::enable_gamepads`, the forwarder opens
+//! game controllers as they are connected and forwards their events. Axis
+//! values are normalized to the range -1 to 1 (0 to 1 for the triggers) and
+//! passed through the `AxisFilter` set for the axis, if any, so that
+//! deadzones and response curves are applied before the values reach the
+//! render thread.
+//!
+//! Mappings for controllers unknown to SDL, e.g. from the community
+//! `gamecontrollerdb.txt`, can be added at initialization with
+//! `load_mappings` or `load_mappings_file`.
+//!
+//! Rumble is requested from other threads through a `GamepadSender` and
+//! played by the forwarder on the main thread, following the strength
+//! `Envelope` of the request. The rumble functions of SDL 2.0.9 and 2.0.14
+//! are not bound by the `sdl2` crate and are looked up at runtime; on devices
+//! or SDL versions without trigger rumble the trigger strengths are played on
+//! the main motors instead, and without rumble support requests are ignored.
+//! The same applies to the LED color (SDL 2.0.14), e.g. the DualShock 4 and
+//! DualSense light bar.
+//!
+//! Controller touchpads (SDL 2.0.14) are forwarded as
+//! `Event::ControllerTouchpad`. Touchpad events are not bound by the `sdl2`
+//! crate either, so on each of them the forwarder queries the touchpad
+//! fingers of all controllers and forwards the changes.
+//!
+//! For devices that game controller mappings do not cover, such as flight
+//! sticks and pedals, the raw joystick events of all joysticks can be
+//! forwarded as well once enabled with `EventForwarder::enable_joysticks`.
+
+use sdl2;
+use sdl2_sys;
+
+use command;
+use event::Event;
+
+use std::os::raw::{c_char, c_int, c_void};
+
+///////////////////////////////////////////////////////////////////////////////
+//  constants                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Interval at which the strengths of playing rumble envelopes are updated
+pub(crate) const RUMBLE_UPDATE_MS : u64 = 16;
+/// Duration passed to SDL with each update, long enough that the motors do
+/// not stop between updates
+const RUMBLE_DURATION_MS : u32 = 100;
+/// `SDL_CONTROLLERTOUCHPADDOWN`
+const SDL_CONTROLLERTOUCHPADDOWN : u32 = 0x656;
+/// `SDL_CONTROLLERTOUCHPADUP`
+const SDL_CONTROLLERTOUCHPADUP   : u32 = 0x658;
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Processing applied to the normalized values of a controller axis.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AxisFilter {
+  /// Magnitudes up to this value read as 0; the remaining range is rescaled
+  /// to start from 0
+  pub deadzone       : f32,
+  /// Magnitudes within this distance of 1 read as 1
+  pub outer_deadzone : f32,
+  pub curve          : ResponseCurve,
+  /// Changes of the filtered value smaller than this are not forwarded,
+  /// except for changes to 0 or full deflection
+  pub noise          : f32
+}
+
+/// Result of loading game controller mappings.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MappingReport {
+  /// Number of mappings for devices that had no mapping
+  pub added        : usize,
+  /// Number of mappings that replaced an existing mapping
+  pub updated      : usize,
+  /// Number of mappings for other platforms
+  pub skipped      : usize,
+  /// Line numbers and SDL error messages of invalid mappings
+  pub errors       : Vec <(usize, String)>,
+  /// Connected joysticks that are still not recognized as game controllers
+  pub unrecognized : Vec <UnrecognizedDevice>
+}
+
+/// A connected joystick without a game controller mapping.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnrecognizedDevice {
+  /// Device index
+  pub index : u32,
+  pub name  : String,
+  /// GUID in the form used by mappings
+  pub guid  : String
+}
+
+/// Motor strengths of a rumble effect, from 0 to 1.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Rumble {
+  /// Low frequency (left) motor
+  pub low           : f32,
+  /// High frequency (right) motor
+  pub high          : f32,
+  /// Left trigger motor (Xbox One and later controllers)
+  pub left_trigger  : f32,
+  /// Right trigger motor
+  pub right_trigger : f32
+}
+
+/// Strength of a rumble effect over time: ramping up from 0 over the attack,
+/// holding full strength for the sustain, and ramping down to 0 over the
+/// release.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Envelope {
+  pub attack  : std::time::Duration,
+  pub sustain : std::time::Duration,
+  pub release : std::time::Duration
+}
+
+/// Handle for sending commands to the game controllers on the main thread.
+#[derive(Clone)]
+pub struct GamepadSender {
+  wake_event : u32,
+  sender     : std::sync::mpsc::Sender <GamepadCommand>
+}
+
+/// Description of a connected joystick.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JoystickInfo {
+  /// Joystick instance ID
+  pub which              : i32,
+  pub name               : String,
+  /// GUID in the form used by mappings
+  pub guid               : String,
+  pub axes               : u32,
+  pub buttons            : u32,
+  pub hats               : u32,
+  pub balls              : u32,
+  /// The device is also opened as a game controller if gamepads are enabled
+  pub is_game_controller : bool
+}
+
+/// Main thread owner of the opened joysticks.
+pub(crate) struct Joysticks {
+  subsystem : sdl2::JoystickSubsystem,
+  /// Opened joysticks in order of connection
+  joysticks : Vec <sdl2::joystick::Joystick>,
+  /// Descriptions of the opened joysticks
+  infos     : Vec <JoystickInfo>
+}
+
+/// Main thread owner of the opened game controllers.
+pub(crate) struct Gamepads {
+  subsystem   : sdl2::GameControllerSubsystem,
+  /// Opened controllers by joystick instance ID
+  controllers : std::collections::HashMap <i32,
+    sdl2::controller::GameController>,
+  filters     : std::collections::HashMap <sdl2::controller::Axis, AxisFilter>,
+  /// Last forwarded value of each axis
+  axes        : std::collections::HashMap <(i32, sdl2::controller::Axis), f32>,
+  wake_event  : u32,
+  sender      : std::sync::mpsc::Sender <GamepadCommand>,
+  receiver    : std::sync::mpsc::Receiver <GamepadCommand>,
+  functions   : ControllerFunctions,
+  /// Playing rumble effects by instance ID
+  rumbling    : std::collections::HashMap <i32, PlayingRumble>,
+  /// Position and pressure of the touchpad fingers that are down, by
+  /// instance ID, touchpad and finger
+  fingers     : std::collections::HashMap <(i32, i32, i32), (f32, f32, f32)>
+}
+
+//
+// private
+//
+
+/// Game controller functions of newer SDL versions, if provided by the SDL
+/// library.
+struct ControllerFunctions {
+  rumble          : Option <unsafe extern "C" fn (
+    *mut sdl2_sys::SDL_GameController, u16, u16, u32) -> c_int>,
+  rumble_triggers : Option <unsafe extern "C" fn (
+    *mut sdl2_sys::SDL_GameController, u16, u16, u32) -> c_int>,
+  set_led         : Option <unsafe extern "C" fn (
+    *mut sdl2_sys::SDL_GameController, u8, u8, u8) -> c_int>,
+  num_touchpads   : Option <unsafe extern "C" fn (
+    *mut sdl2_sys::SDL_GameController) -> c_int>,
+  num_fingers     : Option <unsafe extern "C" fn (
+    *mut sdl2_sys::SDL_GameController, c_int) -> c_int>,
+  touchpad_finger : Option <unsafe extern "C" fn (
+    *mut sdl2_sys::SDL_GameController, c_int, c_int,
+    *mut u8, *mut f32, *mut f32, *mut f32) -> c_int>
+}
+
+struct PlayingRumble {
+  rumble   : Rumble,
+  envelope : Envelope,
+  start    : std::time::Instant
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  enums                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Mapping of the magnitude of an axis after the deadzones are applied.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ResponseCurve {
+  Linear,
+  /// Raise the magnitude to the given power, e.g. 2.0 for finer control
+  /// near the center
+  Power (f32)
+}
+
+/// A request for the game controllers on the main thread.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GamepadCommand {
+  /// Play a rumble effect on the controller with the given instance ID,
+  /// replacing any effect playing on it
+  Rumble { which : i32, rumble : Rumble, envelope : Envelope },
+  StopRumble { which : i32 },
+  /// Set the LED color of a controller
+  SetLed { which : i32, red : u8, green : u8, blue : u8 }
+}
+
+/// Phase of a controller touchpad contact.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TouchPhase {
+  Down,
+  Motion,
+  Up
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+impl Default for AxisFilter {
+  /// No deadzones, a linear response and no noise filtering.
+  fn default() -> Self {
+    AxisFilter {
+      deadzone:       0.0,
+      outer_deadzone: 0.0,
+      curve:          ResponseCurve::Linear,
+      noise:          0.0
+    }
+  }
+}
+
+impl AxisFilter {
+  /// Apply the deadzones and response curve to a normalized axis value.
+  pub fn apply (&self, value : f32) -> f32 {
+    let magnitude = value.abs();
+    let live = 1.0 - self.outer_deadzone - self.deadzone;
+    let magnitude = if magnitude <= self.deadzone {
+      0.0
+    } else if magnitude >= 1.0 - self.outer_deadzone || live <= 0.0 {
+      1.0
+    } else {
+      (magnitude - self.deadzone) / live
+    };
+    let magnitude = match self.curve {
+      ResponseCurve::Linear => magnitude,
+      ResponseCurve::Power (exponent) => magnitude.powf (exponent)
+    };
+    magnitude * value.signum()
+  }
+
+  /// Returns true if a change from the last forwarded value is large enough
+  /// to be forwarded.
+  fn passes (&self, last : f32, value : f32) -> bool {
+    value != last && ((value - last).abs() >= self.noise ||
+      value == 0.0 || value.abs() == 1.0)
+  }
+}
+
+impl Envelope {
+  /// Full strength for the given duration.
+  pub fn constant (duration : std::time::Duration) -> Self {
+    Envelope {
+      attack:  std::time::Duration::from_secs (0),
+      sustain: duration,
+      release: std::time::Duration::from_secs (0)
+    }
+  }
+
+  /// Strength from 0 to 1 at the given time since the start, or `None` once
+  /// the envelope has ended.
+  pub fn scale (&self, elapsed : std::time::Duration) -> Option <f32> {
+    let elapsed = seconds (elapsed);
+    let (attack, sustain, release)
+      = (seconds (self.attack), seconds (self.sustain), seconds (self.release));
+    if elapsed < attack {
+      Some (elapsed / attack)
+    } else if elapsed < attack + sustain {
+      Some (1.0)
+    } else if elapsed < attack + sustain + release {
+      Some (1.0 - (elapsed - attack - sustain) / release)
+    } else {
+      None
+    }
+  }
+}
+
+impl GamepadSender {
+  /// Queue a command for the forwarder on the main thread.
+  ///
+  /// Returns false if the forwarder has been dropped.
+  pub fn send (&self, command : GamepadCommand) -> bool {
+    if self.sender.send (command).is_err() {
+      return false
+    }
+    command::push_user_event (self.wake_event, 0);
+    true
+  }
+
+  /// Play a rumble effect on a controller.
+  pub fn rumble (&self, which : i32, rumble : Rumble, envelope : Envelope)
+    -> bool
+  {
+    self.send (GamepadCommand::Rumble { which, rumble, envelope })
+  }
+
+  pub fn stop_rumble (&self, which : i32) -> bool {
+    self.send (GamepadCommand::StopRumble { which })
+  }
+
+  /// Set the LED color of a controller.
+  pub fn set_led (&self, which : i32, red : u8, green : u8, blue : u8)
+    -> bool
+  {
+    self.send (GamepadCommand::SetLed { which, red, green, blue })
+  }
+}
+
+impl Gamepads {
+  pub(crate) fn new (subsystem : sdl2::GameControllerSubsystem) -> Self {
+    let wake_event = unsafe { sdl2_sys::SDL_RegisterEvents (1) };
+    let (sender, receiver) = std::sync::mpsc::channel();
+    Gamepads {
+      subsystem, wake_event, sender, receiver,
+      controllers: std::collections::HashMap::new(),
+      filters:     std::collections::HashMap::new(),
+      axes:        std::collections::HashMap::new(),
+      functions:   ControllerFunctions::load(),
+      rumbling:    std::collections::HashMap::new(),
+      fingers:     std::collections::HashMap::new()
+    }
+  }
+
+  pub(crate) fn sender (&self) -> GamepadSender {
+    GamepadSender {
+      wake_event: self.wake_event,
+      sender:     self.sender.clone()
+    }
+  }
+
+  /// Returns true if the event was pushed by a `GamepadSender`.
+  pub(crate) fn is_wake_event (&self, event : &sdl2::event::Event) -> bool {
+    match *event {
+      sdl2::event::Event::User { type_, .. } => type_ == self.wake_event,
+      _ => false
+    }
+  }
+
+  /// Execute the queued commands.
+  pub(crate) fn execute_pending (&mut self) {
+    while let Ok (command) = self.receiver.try_recv() {
+      match command {
+        GamepadCommand::Rumble { which, rumble, envelope } => {
+          if self.controllers.contains_key (&which) {
+            self.rumbling.insert (which, PlayingRumble {
+              rumble, envelope, start: std::time::Instant::now()
+            });
+          }
+        }
+        GamepadCommand::StopRumble { which } => {
+          if self.rumbling.remove (&which).is_some() {
+            self.play (which, Rumble::default(), 0);
+          }
+        }
+        GamepadCommand::SetLed { which, red, green, blue } => {
+          let set_led = match self.functions.set_led {
+            Some (set_led) => set_led,
+            None => continue
+          };
+          if self.controllers.contains_key (&which) {
+            unsafe {
+              let controller
+                = sdl2_sys::SDL_GameControllerFromInstanceID (which);
+              if !controller.is_null() {
+                set_led (controller, red, green, blue);
+              }
+            }
+          }
+        }
+      }
+    }
+    self.update_rumble();
+  }
+
+  /// Update the motor strengths of the playing rumble effects.
+  pub(crate) fn update_rumble (&mut self) {
+    let now = std::time::Instant::now();
+    let mut strengths = Vec::with_capacity (self.rumbling.len());
+    for (which, playing) in self.rumbling.iter() {
+      let scale = playing.envelope.scale (now.duration_since (playing.start));
+      strengths.push ((*which, scale.map (|scale| Rumble {
+        low:           playing.rumble.low * scale,
+        high:          playing.rumble.high * scale,
+        left_trigger:  playing.rumble.left_trigger * scale,
+        right_trigger: playing.rumble.right_trigger * scale
+      })));
+    }
+    for (which, rumble) in strengths {
+      match rumble {
+        Some (rumble) => self.play (which, rumble, RUMBLE_DURATION_MS),
+        None => {
+          self.rumbling.remove (&which);
+          self.play (which, Rumble::default(), 0);
+        }
+      }
+    }
+  }
+
+  pub(crate) fn is_rumbling (&self) -> bool {
+    !self.rumbling.is_empty()
+  }
+
+  fn play (&self, which : i32, rumble : Rumble, duration_ms : u32) {
+    if !self.controllers.contains_key (&which) {
+      return
+    }
+    let controller = unsafe {
+      sdl2_sys::SDL_GameControllerFromInstanceID (which)
+    };
+    if controller.is_null() {
+      return
+    }
+    let strength = |value : f32| (value.clamp (0.0, 1.0) * 65535.0) as u16;
+    let (mut low, mut high) = (rumble.low, rumble.high);
+    unsafe {
+      let triggers = self.functions.rumble_triggers.is_some_and (
+        |rumble_triggers| rumble_triggers (controller,
+          strength (rumble.left_trigger), strength (rumble.right_trigger),
+          duration_ms) == 0);
+      if !triggers {
+        low  = low.max (rumble.left_trigger);
+        high = high.max (rumble.right_trigger);
+      }
+      if let Some (rumble) = self.functions.rumble {
+        rumble (controller, strength (low), strength (high), duration_ms);
+      }
+    }
+  }
+
+  /// On a touchpad event, query the touchpad fingers of all controllers and
+  /// return the changes as `Event::ControllerTouchpad`s.
+  pub(crate) fn touchpad_events (&mut self, event : &sdl2::event::Event)
+    -> Vec <Event>
+  {
+    match *event {
+      sdl2::event::Event::Unknown { type_, .. }
+        if (SDL_CONTROLLERTOUCHPADDOWN..=SDL_CONTROLLERTOUCHPADUP)
+          .contains (&type_) => {}
+      _ => return vec![]
+    }
+    let functions = &self.functions;
+    let (num_touchpads, num_fingers, touchpad_finger) = match (
+      functions.num_touchpads, functions.num_fingers, functions.touchpad_finger
+    ) {
+      (Some (a), Some (b), Some (c)) => (a, b, c),
+      _ => return vec![]
+    };
+    let mut events = vec![];
+    for which in self.controllers.keys() {
+      let raw = unsafe { sdl2_sys::SDL_GameControllerFromInstanceID (*which) };
+      if raw.is_null() {
+        continue
+      }
+      for touchpad in 0..unsafe { num_touchpads (raw) } {
+        for finger in 0..unsafe { num_fingers (raw, touchpad) } {
+          let (mut down, mut x, mut y, mut pressure) = (0, 0.0, 0.0, 0.0);
+          unsafe {
+            touchpad_finger (raw, touchpad, finger,
+              &mut down, &mut x, &mut y, &mut pressure);
+          }
+          let key = (*which, touchpad, finger);
+          let current = if down != 0 { Some ((x, y, pressure)) } else { None };
+          let previous = match current {
+            Some (current) => self.fingers.insert (key, current),
+            None => self.fingers.remove (&key)
+          };
+          if let Some (phase) = touch_phase (previous, current) {
+            let (x, y, pressure) = current.or (previous).unwrap();
+            events.push (Event::ControllerTouchpad {
+              which: *which, touchpad, finger, phase, x, y, pressure
+            });
+          }
+        }
+      }
+    }
+    events
+  }
+
+  pub(crate) fn set_filter (&mut self,
+    axis : sdl2::controller::Axis, filter : Option <AxisFilter>
+  ) {
+    match filter {
+      Some (filter) => { self.filters.insert (axis, filter); }
+      None => { self.filters.remove (&axis); }
+    }
+  }
+
+  /// Process a controller event, returning the event to forward if any.
+  pub(crate) fn handle (&mut self, event : &sdl2::event::Event)
+    -> Option <Event>
+  {
+    match *event {
+      sdl2::event::Event::ControllerDeviceAdded { which, .. } => {
+        // SDL reports the device index here but the instance ID in all other
+        // controller events
+        let controller = try_opt!(self.subsystem.open (which).ok());
+        let which = controller.instance_id();
+        let name = controller.name();
+        self.controllers.insert (which, controller);
+        Some (Event::ControllerAdded { which, name })
+      }
+      sdl2::event::Event::ControllerDeviceRemoved { which, .. } => {
+        try_opt!(self.controllers.remove (&which));
+        self.rumbling.remove (&which);
+        self.fingers.retain (|&(id, _, _), _| id != which);
+        self.axes.retain (|&(id, _), _| id != which);
+        Some (Event::ControllerRemoved { which })
+      }
+      sdl2::event::Event::ControllerAxisMotion { which, axis, value, .. } => {
+        let normalized = (value as f32 / 32767.0).max (-1.0);
+        let filter = self.filters.get (&axis).cloned().unwrap_or_default();
+        let filtered = filter.apply (normalized);
+        let last = self.axes.entry ((which, axis)).or_insert (0.0);
+        if !filter.passes (*last, filtered) {
+          return None
+        }
+        *last = filtered;
+        Some (Event::ControllerAxis {
+          which, axis, value: filtered, raw: value
+        })
+      }
+      sdl2::event::Event::ControllerButtonDown { which, button, .. } =>
+        Some (Event::ControllerButtonDown { which, button }),
+      sdl2::event::Event::ControllerButtonUp { which, button, .. } =>
+        Some (Event::ControllerButtonUp { which, button }),
+      _ => None
+    }
+  }
+}
+
+impl Joysticks {
+  pub(crate) fn new (subsystem : sdl2::JoystickSubsystem) -> Self {
+    Joysticks { subsystem, joysticks: Vec::new(), infos: Vec::new() }
+  }
+
+  pub(crate) fn infos (&self) -> &[JoystickInfo] {
+    &self.infos
+  }
+
+  /// Process a joystick event, returning the event to forward if any.
+  pub(crate) fn handle (&mut self, event : &sdl2::event::Event)
+    -> Option <Event>
+  {
+    match *event {
+      sdl2::event::Event::JoyDeviceAdded { which, .. } => {
+        let is_game_controller = unsafe {
+          sdl2_sys::SDL_IsGameController (which as c_int) ==
+            sdl2_sys::SDL_bool::SDL_TRUE
+        };
+        let joystick = try_opt!(self.subsystem.open (which).ok());
+        let info = JoystickInfo {
+          which:   joystick.instance_id(),
+          name:    joystick.name(),
+          guid:    joystick.guid().string(),
+          axes:    joystick.num_axes(),
+          buttons: joystick.num_buttons(),
+          hats:    joystick.num_hats(),
+          balls:   joystick.num_balls(),
+          is_game_controller
+        };
+        self.joysticks.push (joystick);
+        self.infos.push (info.clone());
+        Some (Event::JoystickAdded (info))
+      }
+      sdl2::event::Event::JoyDeviceRemoved { which, .. } => {
+        let index = try_opt!(self.infos.iter()
+          .position (|info| info.which == which));
+        self.joysticks.remove (index);
+        self.infos.remove (index);
+        Some (Event::JoystickRemoved { which })
+      }
+      sdl2::event::Event::JoyAxisMotion { which, axis_idx, value, .. } =>
+        Some (Event::JoystickAxis { which, axis: axis_idx, value }),
+      sdl2::event::Event::JoyBallMotion {
+        which, ball_idx, xrel, yrel, ..
+      } => Some (Event::JoystickBall { which, ball: ball_idx, xrel, yrel }),
+      sdl2::event::Event::JoyHatMotion { which, hat_idx, state, .. } =>
+        Some (Event::JoystickHat { which, hat: hat_idx, state }),
+      sdl2::event::Event::JoyButtonDown { which, button_idx, .. } =>
+        Some (Event::JoystickButtonDown { which, button: button_idx }),
+      sdl2::event::Event::JoyButtonUp { which, button_idx, .. } =>
+        Some (Event::JoystickButtonUp { which, button: button_idx }),
+      _ => None
+    }
+  }
+}
+
+impl ControllerFunctions {
+  fn load() -> Self {
+    unsafe {
+      // the SDL library is already loaded, so on unix platforms the symbols
+      // can be looked up in the global namespace
+      let library_name = if cfg!(windows) {
+        Some (std::ffi::CString::new ("SDL2.dll").unwrap())
+      } else {
+        None
+      };
+      let library = sdl2_sys::SDL_LoadObject (library_name.as_ref()
+        .map_or (std::ptr::null(), |name| name.as_ptr()));
+      if library.is_null() {
+        return ControllerFunctions {
+          rumble: None, rumble_triggers: None, set_led: None,
+          num_touchpads: None, num_fingers: None, touchpad_finger: None
+        }
+      }
+      ControllerFunctions {
+        rumble:          sdl_function (library, "SDL_GameControllerRumble"),
+        rumble_triggers:
+          sdl_function (library, "SDL_GameControllerRumbleTriggers"),
+        set_led:         sdl_function (library, "SDL_GameControllerSetLED"),
+        num_touchpads:
+          sdl_function (library, "SDL_GameControllerGetNumTouchpads"),
+        num_fingers:
+          sdl_function (library, "SDL_GameControllerGetNumTouchpadFingers"),
+        touchpad_finger:
+          sdl_function (library, "SDL_GameControllerGetTouchpadFinger")
+      }
+    }
+  }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  functions                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Add game controller mappings given in the SDL mapping database format,
+/// one per line.
+///
+/// Comments, empty lines and mappings for other platforms are ignored. This
+/// must be called on the main thread; controllers already opened keep their
+/// previous mapping until reconnected.
+pub fn load_mappings (subsystem : &sdl2::GameControllerSubsystem, text : &str)
+  -> MappingReport
+{
+  use sdl2::controller::MappingStatus;
+  let platform = unsafe {
+    std::ffi::CStr::from_ptr (sdl2_sys::SDL_GetPlatform()).to_string_lossy()
+      .into_owned()
+  };
+  let mut report = MappingReport::default();
+  for (number, line) in text.lines().enumerate() {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with ('#') {
+      continue
+    }
+    match mapping_platform (line) {
+      Some (other) if other != platform => {
+        report.skipped += 1;
+        continue
+      }
+      _ => {}
+    }
+    match subsystem.add_mapping (line) {
+      Ok (MappingStatus::Added)   => report.added += 1,
+      Ok (MappingStatus::Updated) => report.updated += 1,
+      Err (err) => report.errors.push ((number + 1, format!("{:?}", err)))
+    }
+  }
+  report.unrecognized = unrecognized_devices (subsystem);
+  report
+}
+
+/// Add game controller mappings from a database file; see `load_mappings`.
+pub fn load_mappings_file <P : AsRef <std::path::Path>> (
+  subsystem : &sdl2::GameControllerSubsystem, path : P
+) -> std::io::Result <MappingReport> {
+  let mut text = String::new();
+  {
+    use std::io::Read;
+    try!{ try!{ std::fs::File::open (path) }.read_to_string (&mut text) };
+  }
+  Ok (load_mappings (subsystem, &text))
+}
+
+/// Connected joysticks that SDL does not recognize as game controllers.
+pub fn unrecognized_devices (subsystem : &sdl2::GameControllerSubsystem)
+  -> Vec <UnrecognizedDevice>
+{
+  let count = subsystem.num_joysticks().unwrap_or (0);
+  (0..count).filter (|index| !subsystem.is_game_controller (*index))
+    .map (|index| unsafe {
+      let name_raw = sdl2_sys::SDL_JoystickNameForIndex (index as i32);
+      let name = if name_raw.is_null() {
+        String::new()
+      } else {
+        std::ffi::CStr::from_ptr (name_raw).to_string_lossy().into_owned()
+      };
+      let mut guid = [0 as c_char; 33];
+      sdl2_sys::SDL_JoystickGetGUIDString (
+        sdl2_sys::SDL_JoystickGetDeviceGUID (index as i32),
+        guid.as_mut_ptr(), guid.len() as i32);
+      let guid = std::ffi::CStr::from_ptr (guid.as_ptr()).to_string_lossy()
+        .into_owned();
+      UnrecognizedDevice { index, name, guid }
+    }).collect()
+}
+
+/// Look up a function in a library opened with `SDL_LoadObject`.
+///
+/// `F` must be an `unsafe extern "C" fn` type matching the function.
+unsafe fn sdl_function <F : Copy> (library : *mut c_void, name : &str)
+  -> Option <F>
+{
+  debug_assert_eq!(
+    std::mem::size_of::<F>(), std::mem::size_of::<*mut c_void>());
+  let name = std::ffi::CString::new (name).unwrap();
+  let address = sdl2_sys::SDL_LoadFunction (library, name.as_ptr());
+  if address.is_null() {
+    None
+  } else {
+    Some (std::mem::transmute_copy (&address))
+  }
+}
+
+/// The phase of a touchpad contact given its previous and current position
+/// and pressure, or `None` if it did not change.
+fn touch_phase (
+  previous : Option <(f32, f32, f32)>, current : Option <(f32, f32, f32)>
+) -> Option <TouchPhase> {
+  match (previous, current) {
+    (None, Some (_)) => Some (TouchPhase::Down),
+    (Some (_), None) => Some (TouchPhase::Up),
+    (Some (previous), Some (current)) if previous != current =>
+      Some (TouchPhase::Motion),
+    _ => None
+  }
+}
+
+fn seconds (duration : std::time::Duration) -> f32 {
+  duration.as_secs() as f32 + duration.subsec_nanos() as f32 * 1e-9
+}
+
+/// The value of the `platform` field of a mapping, if present.
+fn mapping_platform (mapping : &str) -> Option <&str> {
+  mapping.split (',').filter_map (|field| {
+    let mut parts = field.splitn (2, ':');
+    match (parts.next(), parts.next()) {
+      (Some ("platform"), Some (value)) => Some (value.trim()),
+      _ => None
+    }
+  }).next()
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  #[test]
+  fn test_axis_filter() {
+    let identity = AxisFilter::default();
+    assert_eq!(identity.apply (-0.5), -0.5);
+    assert_eq!(identity.apply (0.0), 0.0);
+    let filter = AxisFilter {
+      deadzone:       0.2,
+      outer_deadzone: 0.1,
+      curve:          ResponseCurve::Power (2.0),
+      noise:          0.1
+    };
+    assert_eq!(filter.apply (0.15), 0.0);
+    assert_eq!(filter.apply (-0.95), -1.0);
+    assert!((filter.apply (-0.55) + 0.25).abs() < 1e-6);
+    assert!(!filter.passes (0.5, 0.55));
+    assert!(filter.passes (0.5, 0.6));
+    assert!(filter.passes (0.05, 0.0));
+    assert!(filter.passes (0.95, 1.0));
+    assert!(!filter.passes (0.0, 0.0));
+  }
+  #[test]
+  fn test_mapping_platform() {
+    assert_eq!(mapping_platform (
+      "030000005e0400008e02000014010000,X360 Controller,a:b0,b:b1,\
+      platform:Linux,"), Some ("Linux"));
+    assert_eq!(mapping_platform (
+      "03000000,Mac OS X Pad,a:b0,platform:Mac OS X"), Some ("Mac OS X"));
+    assert_eq!(mapping_platform ("03000000,Pad,a:b0,b:b1"), None);
+  }
+  #[test]
+  fn test_envelope() {
+    let ms = std::time::Duration::from_millis;
+    let envelope = Envelope {
+      attack: ms (100), sustain: ms (200), release: ms (100)
+    };
+    assert_eq!(envelope.scale (ms (0)), Some (0.0));
+    assert!((envelope.scale (ms (50)).unwrap() - 0.5).abs() < 1e-3);
+    assert_eq!(envelope.scale (ms (100)), Some (1.0));
+    assert_eq!(envelope.scale (ms (299)), Some (1.0));
+    assert!((envelope.scale (ms (375)).unwrap() - 0.25).abs() < 1e-3);
+    assert_eq!(envelope.scale (ms (400)), None);
+    assert_eq!(Envelope::constant (ms (10)).scale (ms (0)), Some (1.0));
+    assert_eq!(Envelope::constant (ms (10)).scale (ms (10)), None);
+  }
+  #[test]
+  fn test_touch_phase() {
+    let a = Some ((0.5, 0.5, 1.0));
+    let b = Some ((0.6, 0.5, 1.0));
+    assert_eq!(touch_phase (None, a), Some (TouchPhase::Down));
+    assert_eq!(touch_phase (a, b), Some (TouchPhase::Motion));
+    assert_eq!(touch_phase (b, b), None);
+    assert_eq!(touch_phase (b, None), Some (TouchPhase::Up));
+    assert_eq!(touch_phase (None, None), None);
+  }
+}
@@ -0,0 +1,416 @@
+//! Golden image tests for downstream crates.
+//!
+//! `GoldenTest::run` renders a single frame to a hidden window through the
+//! same backend and present path as an application, reads it back before the
+//! buffer swap, and compares it to a reference image stored in the golden
+//! directory:
+//!
+//! ```ignore
+//! #[test]
+//! fn test_clear() {
+//!   GoldenTest::run ("clear", |_display, frame| {
+//!     frame.clear_color (0.0, 0.0, 1.0, 1.0);
+//!   });
+//! }
+//! ```
+//!
+//! Reference images are binary PAM files (`<name>.pam`). When a reference is
+//! missing or the environment variable `GOLDEN_UPDATE` is set, the rendered
+//! image is written as the new reference. On a mismatch the rendered image
+//! (`<name>.actual.pam`) and a difference image (`<name>.diff.pam`) are
+//! written next to the reference.
+//!
+//! Some platforms only allow windows to be created on the main thread; on
+//! these the tests should be run with `harness = false`.
+
+use glium;
+use sdl2;
+
+use {allow_multiple_windows, SdlGliumDisplayFacade, SdlGlWindowBuilder,
+  BackendBuildError};
+use readback;
+
+///////////////////////////////////////////////////////////////////////////////
+//  statics                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Only one SDL context can be alive at a time, so tests are serialized
+static GOLDEN_TEST_LOCK : std::sync::Mutex <()> = std::sync::Mutex::new (());
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Configuration of a golden image test.
+#[derive(Clone, Debug)]
+pub struct GoldenTest {
+  /// Directory containing the reference images; defaults to `tests/golden`
+  /// in the directory of the crate under test
+  pub directory  : std::path::PathBuf,
+  pub dimensions : (u32, u32),
+  pub tolerance  : ImageTolerance
+}
+
+/// Perceptual tolerance of an image comparison.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ImageTolerance {
+  /// Color difference in the range 0.0 to 1.0 above which a pixel counts as
+  /// different, measured in the YIQ color space
+  pub threshold      : f32,
+  /// Fraction of pixels that may differ
+  pub max_difference : f32
+}
+
+/// Result of comparing a rendered image to its reference.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GoldenReport {
+  pub differing_pixels : usize,
+  pub total_pixels     : usize,
+  /// The largest color difference of a pixel, in the range 0.0 to 1.0
+  pub max_delta        : f32
+}
+
+/// An RGBA8 image with rows from top to bottom.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Image {
+  pub width  : u32,
+  pub height : u32,
+  pub data   : Vec <u8>
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  enums                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug)]
+pub enum GoldenError {
+  SdlError (String),
+  BackendBuildError (BackendBuildError),
+  IncompatibleOpenGl (glium::IncompatibleOpenGl),
+  IoError (std::io::Error),
+  /// The reference image could not be decoded
+  InvalidReference (std::path::PathBuf),
+  /// The rendered image has different dimensions than the reference
+  DimensionMismatch {
+    expected : (u32, u32),
+    actual   : (u32, u32)
+  },
+  /// The rendered image differs from the reference by more than the
+  /// tolerance
+  Mismatch (GoldenReport)
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+impl GoldenTest {
+  /// A test rendering 256x256 frames with the default tolerance.
+  pub fn new() -> Self {
+    let root = std::env::var_os ("CARGO_MANIFEST_DIR")
+      .map (std::path::PathBuf::from).unwrap_or_default();
+    GoldenTest {
+      directory:  root.join ("tests").join ("golden"),
+      dimensions: (256, 256),
+      tolerance:  ImageTolerance::default()
+    }
+  }
+
+  /// Render and check a frame with the default configuration, panicking with
+  /// a report if it does not match the reference.
+  pub fn run <F> (name : &str, render : F) where
+    F : FnOnce (&SdlGliumDisplayFacade, &mut glium::Frame)
+  {
+    if let Err (err) = GoldenTest::new().check (name, render) {
+      panic!("golden test {:?} failed: {:?}", name, err)
+    }
+  }
+
+  /// Render a frame with the given function and compare it to the reference
+  /// image of the given name.
+  pub fn check <F> (&self, name : &str, render : F)
+    -> Result <GoldenReport, GoldenError>
+  where
+    F : FnOnce (&SdlGliumDisplayFacade, &mut glium::Frame)
+  {
+    let actual = try!{ self.render (name, render) };
+    let reference_path = self.directory.join (format!("{}.pam", name));
+    let update = std::env::var_os ("GOLDEN_UPDATE").is_some();
+    if update || !reference_path.exists() {
+      try!{ std::fs::create_dir_all (&self.directory) };
+      try!{ write_image (&reference_path, &actual) };
+      return Ok (GoldenReport {
+        differing_pixels: 0,
+        total_pixels:     actual.width as usize * actual.height as usize,
+        max_delta:        0.0
+      })
+    }
+    let reference = try!{
+      read_image (&reference_path)
+        .map_err (|_| GoldenError::InvalidReference (reference_path.clone()))
+    };
+    let actual_path = self.directory.join (format!("{}.actual.pam", name));
+    let diff_path = self.directory.join (format!("{}.diff.pam", name));
+    let result = compare_images (&reference, &actual, self.tolerance);
+    match result {
+      Ok (ref report) => {
+        // remove outputs of a previous failure
+        let _ = std::fs::remove_file (&actual_path);
+        let _ = std::fs::remove_file (&diff_path);
+        return Ok (report.clone())
+      }
+      Err (GoldenError::Mismatch (_)) => {
+        try!{ write_image (&actual_path, &actual) };
+        let diff = diff_image (&reference, &actual, self.tolerance);
+        try!{ write_image (&diff_path, &diff) };
+      }
+      Err (_) => try!{ write_image (&actual_path, &actual) }
+    }
+    result
+  }
+
+  fn render <F> (&self, name : &str, render : F) -> Result <Image, GoldenError>
+  where
+    F : FnOnce (&SdlGliumDisplayFacade, &mut glium::Frame)
+  {
+    let _lock = GOLDEN_TEST_LOCK.lock().unwrap_or_else (|err| err.into_inner());
+    let sdl = try!{ sdl2::init() };
+    let video = try!{ sdl.video() };
+    let (width, height) = self.dimensions;
+    // each test builds a window of its own
+    allow_multiple_windows();
+    let window_backend = try!{
+      video.window (name, width, height).hidden().build_backend()
+    };
+    let display = try!{ window_backend.build_glium() };
+    let mut frame = display.draw();
+    render (&display, &mut frame);
+    let dimensions = display.framebuffer_dimensions();
+    let pixels = readback::read_back_buffer (&display.state.gl, dimensions);
+    // the frame has been captured, so a failed swap is not an error
+    let _ = frame.finish();
+    Ok (Image::from_bottom_up (dimensions.0, dimensions.1, pixels))
+  }
+}
+
+impl Default for GoldenTest {
+  fn default() -> Self {
+    GoldenTest::new()
+  }
+}
+
+impl Default for ImageTolerance {
+  /// A threshold of 0.1 with no differing pixels allowed.
+  fn default() -> Self {
+    ImageTolerance { threshold: 0.1, max_difference: 0.0 }
+  }
+}
+
+impl Image {
+  /// Create from RGBA8 pixels with rows from bottom to top, as read back from
+  /// GL.
+  pub fn from_bottom_up (width : u32, height : u32, pixels : Vec <u8>) -> Self {
+    let row = 4 * width as usize;
+    let mut data = Vec::with_capacity (pixels.len());
+    for y in (0..height as usize).rev() {
+      data.extend_from_slice (&pixels[y * row..(y + 1) * row]);
+    }
+    Image { width, height, data }
+  }
+
+  /// Encode as a binary PAM file.
+  pub fn to_pam (&self) -> Vec <u8> {
+    let mut pam = format!(
+      "P7\nWIDTH {}\nHEIGHT {}\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\n\
+      ENDHDR\n", self.width, self.height).into_bytes();
+    pam.extend_from_slice (&self.data);
+    pam
+  }
+
+  /// Decode a binary PAM file with 8-bit RGBA samples.
+  pub fn from_pam (pam : &[u8]) -> Option <Self> {
+    const ENDHDR : &[u8] = b"ENDHDR\n";
+    let end = try_opt!(
+      pam.windows (ENDHDR.len()).position (|window| window == ENDHDR));
+    let header = try_opt!(std::str::from_utf8 (&pam[..end]).ok());
+    let (mut width, mut height) = (None, None);
+    let mut lines = header.lines();
+    if lines.next() != Some ("P7") {
+      return None
+    }
+    for line in lines {
+      let mut fields = line.split_whitespace();
+      match (fields.next(), fields.next()) {
+        (Some ("WIDTH"),  Some (value)) => width  = value.parse().ok(),
+        (Some ("HEIGHT"), Some (value)) => height = value.parse().ok(),
+        (Some ("DEPTH"),  Some ("4"))   |
+        (Some ("MAXVAL"), Some ("255")) |
+        (Some ("TUPLTYPE"), _)          => {}
+        _ => return None
+      }
+    }
+    let (width, height) : (u32, u32) = (try_opt!(width), try_opt!(height));
+    let data = pam[end + ENDHDR.len()..].to_vec();
+    if data.len() != 4 * width as usize * height as usize {
+      return None
+    }
+    Some (Image { width, height, data })
+  }
+}
+
+impl From <String> for GoldenError {
+  fn from (err : String) -> Self {
+    GoldenError::SdlError (err)
+  }
+}
+
+impl From <BackendBuildError> for GoldenError {
+  fn from (err : BackendBuildError) -> Self {
+    GoldenError::BackendBuildError (err)
+  }
+}
+
+impl From <glium::IncompatibleOpenGl> for GoldenError {
+  fn from (err : glium::IncompatibleOpenGl) -> Self {
+    GoldenError::IncompatibleOpenGl (err)
+  }
+}
+
+impl From <std::io::Error> for GoldenError {
+  fn from (err : std::io::Error) -> Self {
+    GoldenError::IoError (err)
+  }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  functions                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Compare two images, returning a report if they match within the given
+/// tolerance.
+pub fn compare_images (
+  reference : &Image, actual : &Image, tolerance : ImageTolerance
+) -> Result <GoldenReport, GoldenError> {
+  if (reference.width, reference.height) != (actual.width, actual.height) {
+    return Err (GoldenError::DimensionMismatch {
+      expected: (reference.width, reference.height),
+      actual:   (actual.width, actual.height)
+    })
+  }
+  let mut report = GoldenReport {
+    differing_pixels: 0,
+    total_pixels:     reference.data.len() / 4,
+    max_delta:        0.0
+  };
+  for (a, b) in reference.data.chunks (4).zip (actual.data.chunks (4)) {
+    let delta = color_delta (a, b);
+    if delta > tolerance.threshold {
+      report.differing_pixels += 1;
+    }
+    if delta > report.max_delta {
+      report.max_delta = delta;
+    }
+  }
+  let allowed
+    = (tolerance.max_difference * report.total_pixels as f32) as usize;
+  if report.differing_pixels > allowed {
+    Err (GoldenError::Mismatch (report))
+  } else {
+    Ok (report)
+  }
+}
+
+/// An image highlighting differing pixels in red over a faded copy of the
+/// reference.
+fn diff_image (reference : &Image, actual : &Image, tolerance : ImageTolerance)
+  -> Image
+{
+  let mut data = Vec::with_capacity (reference.data.len());
+  for (a, b) in reference.data.chunks (4).zip (actual.data.chunks (4)) {
+    if color_delta (a, b) > tolerance.threshold {
+      data.extend_from_slice (&[255, 0, 0, 255]);
+    } else {
+      let gray = (luma (a) * 0.25 + 191.0) as u8;
+      data.extend_from_slice (&[gray, gray, gray, 255]);
+    }
+  }
+  Image { width: reference.width, height: reference.height, data }
+}
+
+/// Perceptual difference of two RGBA8 pixels in the range 0.0 to 1.0.
+///
+/// Colors are blended over white and compared in the YIQ color space, which
+/// weighs differences in brightness more than differences in hue.
+fn color_delta (a : &[u8], b : &[u8]) -> f32 {
+  let blend = |pixel : &[u8], channel : usize| {
+    let alpha = pixel[3] as f32 / 255.0;
+    255.0 + (pixel[channel] as f32 - 255.0) * alpha
+  };
+  let yiq = |pixel : &[u8]| {
+    let (r, g, b) = (blend (pixel, 0), blend (pixel, 1), blend (pixel, 2));
+    ( r * 0.2988953 + g * 0.5866225 + b * 0.1144822,
+      r * 0.595978  - g * 0.2741761 - b * 0.3218019,
+      r * 0.2114702 - g * 0.5226171 + b * 0.3111469 )
+  };
+  let (y0, i0, q0) = yiq (a);
+  let (y1, i1, q1) = yiq (b);
+  let (y, i, q) = (y0 - y1, i0 - i1, q0 - q1);
+  // the maximum of the weighted squared difference is 35215
+  ((0.5053 * y * y + 0.299 * i * i + 0.1957 * q * q) / 35215.0).sqrt()
+}
+
+fn luma (pixel : &[u8]) -> f32 {
+  0.299 * pixel[0] as f32 + 0.587 * pixel[1] as f32 + 0.114 * pixel[2] as f32
+}
+
+fn read_image (path : &std::path::Path) -> Result <Image, GoldenError> {
+  use std::io::Read;
+  let mut pam = Vec::new();
+  try!{
+    std::fs::File::open (path).and_then (|mut file| file.read_to_end (&mut pam))
+  };
+  Image::from_pam (&pam).ok_or (GoldenError::InvalidReference (path.into()))
+}
+
+fn write_image (path : &std::path::Path, image : &Image)
+  -> Result <(), GoldenError>
+{
+  use std::io::Write;
+  let mut file = try!{ std::fs::File::create (path) };
+  try!{ file.write_all (&image.to_pam()) };
+  Ok (())
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  #[test]
+  fn test_pam() {
+    let image = Image::from_bottom_up (2, 2, (0..16).collect());
+    assert_eq!(&image.data[..4], &[8, 9, 10, 11]);
+    assert_eq!(Image::from_pam (&image.to_pam()), Some (image.clone()));
+    assert_eq!(Image::from_pam (b"P6\n2 2\n255\n"), None);
+  }
+  #[test]
+  fn test_compare_images() {
+    let reference = Image { width: 2, height: 1, data: vec![
+      0, 0, 0, 255, 255, 255, 255, 255 ] };
+    let mut actual = reference.clone();
+    actual.data[0] = 2;
+    let report = compare_images (
+      &reference, &actual, ImageTolerance::default()).unwrap();
+    assert_eq!(report.differing_pixels, 0);
+    actual.data[4] = 0;
+    match compare_images (&reference, &actual, ImageTolerance::default()) {
+      Err (GoldenError::Mismatch (report)) =>
+        assert_eq!(report.differing_pixels, 1),
+      result => panic!("unexpected result: {:?}", result)
+    }
+    let tolerance = ImageTolerance { threshold: 0.1, max_difference: 0.5 };
+    assert!(compare_images (&reference, &actual, tolerance).is_ok());
+    let black = Image { width: 1, height: 1, data: vec![0, 0, 0, 255] };
+    let white = Image { width: 1, height: 1, data: vec![255; 4] };
+    let delta = color_delta (&black.data, &white.data);
+    assert!(0.9 < delta && delta <= 1.0);
+  }
+}
@@ -0,0 +1,224 @@
+//! Native window and display handles through the `raw-window-handle` traits.
+//!
+//! Enabled by the `window-handle` feature. The window backend and the display
+//! facade implement `HasRawWindowHandle` and `HasRawDisplayHandle`, so the
+//! window rendered to by glium can also be given to libraries that draw
+//! overlays or embed native UI toolkits:
+//!
+//! ```ignore
+//! use raw_window_handle::HasRawWindowHandle;
+//! let overlay = Overlay::new (display.raw_window_handle());
+//! ```
+//!
+//! The handles are queried from SDL with `SDL_GetWindowWMInfo` and are
+//! supported for X11, Wayland, Windows and Cocoa windows. Only the `NSWindow`
+//! of a Cocoa window is known, so its `ns_view` is null. The trait methods
+//! panic for other window systems and for mock backends; `window_handles`
+//! reports these cases as errors instead.
+//!
+//! The handles are only valid until the window backend is dropped.
+
+use sdl2;
+use sdl2_sys;
+
+use std::os::raw::{c_int, c_ulong, c_void};
+
+use raw_window_handle::{HasRawDisplayHandle, HasRawWindowHandle,
+  RawDisplayHandle, RawWindowHandle};
+
+use SdlGlWindowBackend;
+#[cfg(feature = "glium")]
+use SdlGliumDisplayFacade;
+
+///////////////////////////////////////////////////////////////////////////////
+//  constants                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+/// `SDL_SYSWM_TYPE` values
+const SYSWM_WINDOWS : c_int = 1;
+const SYSWM_X11     : c_int = 2;
+const SYSWM_COCOA   : c_int = 4;
+const SYSWM_WAYLAND : c_int = 6;
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+//
+// private
+//
+
+/// `SDL_SysWMinfo`
+#[repr(C)]
+struct SysWmInfo {
+  version   : sdl2_sys::SDL_version,
+  subsystem : c_int,
+  info      : SysWmInfoUnion
+}
+
+/// The window system specific part of `SDL_SysWMinfo`, padded by SDL to 64
+/// bytes.
+#[repr(C)]
+#[derive(Clone, Copy)]
+union SysWmInfoUnion {
+  windows : WindowsInfo,
+  x11     : X11Info,
+  cocoa   : CocoaInfo,
+  wayland : WaylandInfo,
+  dummy   : [u8; 64]
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct WindowsInfo {
+  window    : *mut c_void,
+  hdc       : *mut c_void,
+  hinstance : *mut c_void
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct X11Info {
+  display : *mut c_void,
+  window  : c_ulong
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct CocoaInfo {
+  window : *mut c_void
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct WaylandInfo {
+  display       : *mut c_void,
+  surface       : *mut c_void,
+  shell_surface : *mut c_void
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+unsafe impl HasRawWindowHandle for SdlGlWindowBackend {
+  /// Panics for a mock or an unsupported window system.
+  fn raw_window_handle (&self) -> RawWindowHandle {
+    window_handles (self).unwrap().0
+  }
+}
+
+unsafe impl HasRawDisplayHandle for SdlGlWindowBackend {
+  /// Panics for a mock or an unsupported window system.
+  fn raw_display_handle (&self) -> RawDisplayHandle {
+    window_handles (self).unwrap().1
+  }
+}
+
+#[cfg(feature = "glium")]
+unsafe impl HasRawWindowHandle for SdlGliumDisplayFacade {
+  /// Panics for a mock or an unsupported window system.
+  fn raw_window_handle (&self) -> RawWindowHandle {
+    self.window_backend.raw_window_handle()
+  }
+}
+
+#[cfg(feature = "glium")]
+unsafe impl HasRawDisplayHandle for SdlGliumDisplayFacade {
+  /// Panics for a mock or an unsupported window system.
+  fn raw_display_handle (&self) -> RawDisplayHandle {
+    self.window_backend.raw_display_handle()
+  }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  functions                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Native window and display handles of the window of the backend.
+pub fn window_handles (window_backend : &SdlGlWindowBackend)
+  -> Result <(RawWindowHandle, RawDisplayHandle), String>
+{
+  use raw_window_handle::*;
+  if_mock!(window_backend,
+    |_| Err ("mock backends have no window".to_string()));
+  let wm_info = try!{ wm_info (window_backend.window_raw.as_ptr()) };
+  let handles = unsafe {
+    match wm_info.subsystem {
+      SYSWM_X11 => {
+        let mut window = XlibWindowHandle::empty();
+        window.window = wm_info.info.x11.window;
+        let mut display = XlibDisplayHandle::empty();
+        display.display = wm_info.info.x11.display;
+        (RawWindowHandle::Xlib (window), RawDisplayHandle::Xlib (display))
+      }
+      SYSWM_WAYLAND => {
+        let mut window = WaylandWindowHandle::empty();
+        window.surface = wm_info.info.wayland.surface;
+        let mut display = WaylandDisplayHandle::empty();
+        display.display = wm_info.info.wayland.display;
+        (RawWindowHandle::Wayland (window),
+          RawDisplayHandle::Wayland (display))
+      }
+      SYSWM_WINDOWS => {
+        let mut window = Win32WindowHandle::empty();
+        window.hwnd = wm_info.info.windows.window;
+        window.hinstance = wm_info.info.windows.hinstance;
+        (RawWindowHandle::Win32 (window),
+          RawDisplayHandle::Windows (WindowsDisplayHandle::empty()))
+      }
+      SYSWM_COCOA => {
+        let mut window = AppKitWindowHandle::empty();
+        window.ns_window = wm_info.info.cocoa.window;
+        (RawWindowHandle::AppKit (window),
+          RawDisplayHandle::AppKit (AppKitDisplayHandle::empty()))
+      }
+      subsystem => return Err (
+        format!("unsupported window system (SDL_SYSWM_TYPE {})", subsystem))
+    }
+  };
+  Ok (handles)
+}
+
+//
+// private
+//
+
+extern "C" {
+  fn SDL_GetWindowWMInfo (window : *mut sdl2_sys::SDL_Window,
+    info : *mut SysWmInfo) -> sdl2_sys::SDL_bool;
+}
+
+/// Query the window system information of a window.
+fn wm_info (window_raw : *mut sdl2_sys::SDL_Window)
+  -> Result <SysWmInfo, String>
+{
+  let mut wm_info = SysWmInfo {
+    version:   sdl2_sys::SDL_version { major: 0, minor: 0, patch: 0 },
+    subsystem: 0,
+    info:      SysWmInfoUnion { dummy: [0; 64] }
+  };
+  unsafe {
+    // SDL fills in the fields known to the version given
+    sdl2_sys::SDL_GetVersion (&mut wm_info.version);
+    if SDL_GetWindowWMInfo (window_raw, &mut wm_info)
+      == sdl2_sys::SDL_bool::SDL_TRUE
+    {
+      Ok (wm_info)
+    } else {
+      Err (sdl2::get_error())
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  #[test]
+  fn test_sys_wm_info_layout() {
+    // SDL pads the union to 64 bytes so that the structure has the same
+    // size for every window system, after the version and subsystem
+    assert_eq!(std::mem::size_of::<SysWmInfoUnion>(), 64);
+    assert_eq!(std::mem::size_of::<SysWmInfo>(), 72);
+  }
+}
@@ -0,0 +1,117 @@
+//! Running the backend without a display server, e.g. in CI.
+//!
+//! SDL's `offscreen` video driver (SDL 2.0.12 and later) creates windows
+//! that are never shown, with GL contexts on EGL pbuffers, so the backend and
+//! facade work as they do with a real window. The framebuffer of a headless
+//! window has the size it was created with. The `dummy` driver has no GL
+//! support and cannot be used.
+//!
+//! ```ignore
+//! let sdl_context = headless::init_sdl().unwrap();
+//! let video_subsystem = sdl_context.video().unwrap();
+//! let window_backend = HeadlessBackend::new (320, 240)
+//!   .build_backend_or_mock (Some (&video_subsystem));
+//! let display = window_backend.build_glium().unwrap();
+//! ```
+//!
+//! Where EGL is not available either, `build_backend_or_mock` falls back to a
+//! `MockGlBackend` of the same size, so tests of the render thread plumbing
+//! still run.
+
+use sdl2;
+
+use {BackendBuildError, SdlGlWindowBackend, SdlGlWindowBuilder};
+use mock::MockGlBackend;
+
+///////////////////////////////////////////////////////////////////////////////
+//  constants                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Environment variable read by SDL to select the video driver
+const VIDEODRIVER : &str = "SDL_VIDEODRIVER";
+const OFFSCREEN_DRIVER : &str = "offscreen";
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Options for building a window backend that is never shown.
+#[derive(Clone, Debug)]
+pub struct HeadlessBackend {
+  /// Framebuffer width in pixels
+  pub width  : u32,
+  /// Framebuffer height in pixels
+  pub height : u32,
+  pub title  : String
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+impl HeadlessBackend {
+  pub fn new (width : u32, height : u32) -> Self {
+    HeadlessBackend { width, height, .. HeadlessBackend::default() }
+  }
+
+  /// Build a hidden window backend.
+  ///
+  /// The window is only headless if the video subsystem was initialized with
+  /// the offscreen driver, see `init_sdl`.
+  pub fn build_backend (&self, video_subsystem : &sdl2::VideoSubsystem)
+    -> Result <SdlGlWindowBackend, BackendBuildError>
+  {
+    video_subsystem.window (&self.title, self.width, self.height)
+      .hidden()
+      .build_backend()
+  }
+
+  /// Build a hidden window backend, or a mock backend of the same size if
+  /// there is no video subsystem or no window or context can be created.
+  pub fn build_backend_or_mock (&self,
+    video_subsystem : Option <&sdl2::VideoSubsystem>
+  ) -> SdlGlWindowBackend {
+    video_subsystem
+      .and_then (|video_subsystem| self.build_backend (video_subsystem).ok())
+      .unwrap_or_else (|| SdlGlWindowBackend::mock (
+        MockGlBackend::new (self.width, self.height)))
+  }
+}
+
+impl Default for HeadlessBackend {
+  /// A 640x480 window titled "headless".
+  fn default() -> Self {
+    HeadlessBackend {
+      width:  640,
+      height: 480,
+      title:  "headless".to_string()
+    }
+  }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  functions                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Select the offscreen video driver and initialize SDL with the video
+/// subsystem.
+///
+/// This must be called on the main thread before SDL is initialized, and
+/// overrides any driver selected by the environment.
+pub fn init_sdl() -> Result <sdl2::Sdl, String> {
+  std::env::set_var (VIDEODRIVER, OFFSCREEN_DRIVER);
+  let sdl_context = try!{ sdl2::init() };
+  try!{ sdl_context.video() };
+  Ok (sdl_context)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  #[test]
+  fn test_headless_mock_fallback() {
+    let window_backend
+      = HeadlessBackend::new (64, 32).build_backend_or_mock (None);
+    assert_eq!(window_backend.drawable_size(), (64, 32));
+  }
+}
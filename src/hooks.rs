@@ -0,0 +1,174 @@
+//! Callbacks run around buffer swaps.
+//!
+//! Hooks registered on a display run on the render thread each time a frame
+//! is finished: pre-swap hooks immediately before the buffers are swapped and
+//! post-swap hooks immediately after, e.g. to record latency timestamps:
+//!
+//! ```ignore
+//! display.add_post_swap_hook (|info : &SwapInfo| {
+//!   println!("frame {} swapped in {:?}", info.frame, info.swap_time.unwrap());
+//! });
+//! ```
+//!
+//! Hooks run in the order they were added, and may add or remove hooks.
+//!
+//! Glium is in the middle of swapping when hooks run, so they must not use
+//! the glium context, e.g. by drawing with the display: doing so panics.
+//! Hooks using raw GL calls must restore any state they change, since glium
+//! caches it.
+
+///////////////////////////////////////////////////////////////////////////////
+//  typedefs                                                                 //
+///////////////////////////////////////////////////////////////////////////////
+
+type SwapHook = Box <dyn FnMut (&SwapInfo)>;
+/// A hook is taken out of its slot while it runs
+type SwapHookSlot = (SwapHookId, Option <SwapHook>);
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Identifies a hook added to a display, for removing it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SwapHookId (u64);
+
+/// Passed to swap hooks.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SwapInfo {
+  /// Number of the frame being swapped, counting buffer swaps since the
+  /// display was built
+  pub frame      : u64,
+  pub dimensions : (u32, u32),
+  /// Time spent in the buffer swap; `None` before the swap
+  pub swap_time  : Option <std::time::Duration>
+}
+
+/// Swap hooks of a display.
+#[derive(Default)]
+pub(crate) struct SwapHooks {
+  next_id : u64,
+  frame   : u64,
+  pre     : Vec <SwapHookSlot>,
+  post    : Vec <SwapHookSlot>
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  enums                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum SwapStage {
+  Pre,
+  Post
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+impl SwapHooks {
+  pub(crate) fn add (
+    &mut self,
+    stage : SwapStage,
+    hook  : SwapHook
+  ) -> SwapHookId {
+    let id = SwapHookId (self.next_id);
+    self.next_id += 1;
+    self.hooks (stage).push ((id, Some (hook)));
+    id
+  }
+
+  /// Returns false if there is no hook with the id.
+  pub(crate) fn remove (&mut self, id : SwapHookId) -> bool {
+    for stage in [SwapStage::Pre, SwapStage::Post].iter() {
+      let hooks = self.hooks (*stage);
+      if let Some (i) = hooks.iter().position (|&(hook_id, _)| hook_id == id) {
+        hooks.remove (i);
+        return true
+      }
+    }
+    false
+  }
+
+  /// Run the hooks of a stage. Post-swap hooks finish the frame.
+  ///
+  /// Each hook is taken out while it runs so that it can add or remove
+  /// hooks; hooks added while running are first run on the next frame.
+  pub(crate) fn run (
+    hooks      : &std::cell::RefCell <SwapHooks>,
+    stage      : SwapStage,
+    dimensions : (u32, u32),
+    swap_time  : Option <std::time::Duration>
+  ) {
+    let (ids, info) = {
+      let mut hooks = hooks.borrow_mut();
+      let info = SwapInfo { frame: hooks.frame, dimensions, swap_time };
+      if stage == SwapStage::Post {
+        hooks.frame += 1;
+      }
+      let ids = hooks.hooks (stage).iter().map (|&(id, _)| id)
+        .collect::<Vec <_>>();
+      (ids, info)
+    };
+    for id in ids {
+      let hook = hooks.borrow_mut().hooks (stage).iter_mut()
+        .find (|&&mut (hook_id, _)| hook_id == id)
+        .and_then (|&mut (_, ref mut hook)| hook.take());
+      if let Some (mut hook) = hook {
+        hook (&info);
+        // the hook is dropped if it was removed while running
+        let mut hooks = hooks.borrow_mut();
+        if let Some (&mut (_, ref mut slot)) = hooks.hooks (stage).iter_mut()
+          .find (|&&mut (hook_id, _)| hook_id == id)
+        {
+          *slot = Some (hook);
+        }
+      }
+    }
+  }
+
+  fn hooks (&mut self, stage : SwapStage) -> &mut Vec <SwapHookSlot> {
+    match stage {
+      SwapStage::Pre  => &mut self.pre,
+      SwapStage::Post => &mut self.post
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  #[test]
+  fn test_swap_hooks() {
+    let hooks
+      = std::rc::Rc::new (std::cell::RefCell::new (SwapHooks::default()));
+    let log = std::rc::Rc::new (std::cell::RefCell::new (Vec::new()));
+    let hook = |name : &'static str| {
+      let log = log.clone();
+      Box::new (move |info : &SwapInfo| {
+        log.borrow_mut().push ((name, info.frame))
+      })
+    };
+    let first = hooks.borrow_mut().add (SwapStage::Pre, hook ("first"));
+    // a hook removing the first hook and adding another
+    let remover = {
+      let (hooks, added) = (hooks.clone(), hook ("added"));
+      let mut added = Some (added);
+      Box::new (move |_ : &SwapInfo| {
+        if let Some (added) = added.take() {
+          assert!(hooks.borrow_mut().remove (first));
+          hooks.borrow_mut().add (SwapStage::Pre, added);
+        }
+      })
+    };
+    hooks.borrow_mut().add (SwapStage::Pre, remover);
+    hooks.borrow_mut().add (SwapStage::Post, hook ("post"));
+    SwapHooks::run (&hooks, SwapStage::Pre, (1, 1), None);
+    SwapHooks::run (&hooks, SwapStage::Post, (1, 1), None);
+    SwapHooks::run (&hooks, SwapStage::Pre, (1, 1), None);
+    assert_eq!(*log.borrow(),
+      vec![("first", 0), ("post", 0), ("added", 1)]);
+    assert!(!hooks.borrow_mut().remove (first));
+  }
+}
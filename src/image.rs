@@ -0,0 +1,72 @@
+//! Loading image files into glium textures with SDL_image.
+//!
+//! With the `image` feature, image files in any format supported by
+//! SDL_image are decoded into RGBA pixels. Decoding does not need a GL
+//! context and can be done on any thread; uploading is done either by the
+//! display on the render thread through a texture upload queue, see `upload`:
+//!
+//! ```ignore
+//! let queue = display.texture_upload_queue();
+//! std::thread::spawn (move || {
+//!   let handle = queue.enqueue (decode_image ("sprite.png").unwrap());
+//!   // ... send the handle to the render thread
+//! });
+//! ```
+//!
+//! or on the calling thread, with the display on the render thread or with a
+//! `LoaderFacade` on a loader thread, see `loader`:
+//!
+//! ```ignore
+//! let texture = display.load_texture ("sprite.png")?;
+//! let texture = image::load_texture (&loader, "sprite.png")?;
+//! ```
+//!
+//! SDL_image initializes the decoder for a format when first loading an image
+//! in that format, which is not thread-safe, so applications decoding on
+//! several threads should initialize the formats they use on the main thread
+//! first with `sdl2::image::init`.
+
+use glium;
+use sdl2;
+
+use glium::texture::Texture2d;
+use surface::{self, SurfaceError};
+use upload::{TextureUpload, UploadFormat};
+
+///////////////////////////////////////////////////////////////////////////////
+//  functions                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Decode an image file into RGBA pixels for uploading.
+pub fn decode_image <P : AsRef <std::path::Path>> (path : P)
+  -> Result <TextureUpload, String>
+{
+  let surface = try!{ load_surface (path) };
+  let pixels  = try!{ surface::rgba_pixels (&surface) };
+  Ok (TextureUpload::new (
+    pixels, (surface.width(), surface.height()), UploadFormat::Rgba8))
+}
+
+/// Decode an image file and create a texture with its pixels.
+pub fn load_texture <F, P> (facade : &F, path : P)
+  -> Result <Texture2d, SurfaceError>
+where
+  F : glium::backend::Facade + ?Sized,
+  P : AsRef <std::path::Path>
+{
+  let surface = try!{ load_surface (path) };
+  surface::texture_from_surface (facade, &surface)
+}
+
+//
+// private
+//
+
+fn load_surface <P : AsRef <std::path::Path>> (path : P)
+  -> Result <sdl2::surface::Surface <'static>, String>
+{
+  use sdl2::image::LoadSurface;
+  let path = path.as_ref();
+  sdl2::surface::Surface::from_file (path)
+    .map_err (|err| format!("failed to load {}: {}", path.display(), err))
+}
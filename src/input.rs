@@ -0,0 +1,280 @@
+//! Polled input state shared with the render thread.
+//!
+//! Games that poll input once per frame do not need every event. An
+//! `InputWriter` on the main thread applies each pumped event to an
+//! `InputState` and publishes it once per iteration of the event loop; the
+//! `InputReader` on the render thread reads the latest published state
+//! without waiting. The state is passed through a triple buffer, so neither
+//! side ever blocks the other and no events are queued.
+//!
+//! ```ignore
+//! let (mut input_writer, mut input_reader) = InputWriter::new();
+//! // on the main thread
+//! bridge.pump (|event| input_writer.update (event));
+//! input_writer.publish();
+//! // on the render thread
+//! if input_reader.read().is_key_down (Scancode::Space) { /* ... */ }
+//! ```
+//!
+//! When a window loses focus, the keys and mouse buttons held are released
+//! since their release will not be reported to the window.
+
+use sdl2;
+
+///////////////////////////////////////////////////////////////////////////////
+//  constants                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+/// `SDL_NUM_SCANCODES`
+const SCANCODES : usize = 512;
+/// Set in the middle index of the triple buffer when it holds a state that
+/// has not been read
+const FRESH : usize = 4;
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Keyboard, mouse and focus state at the end of an event loop iteration.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InputState {
+  /// Keys held, as a bit set indexed by scancode
+  keys               : [u64; SCANCODES / 64],
+  /// Last mouse position in window coordinates of `mouse_window`
+  pub mouse_position : (i32, i32),
+  /// Window the mouse was last reported in
+  pub mouse_window   : Option <u32>,
+  /// Mouse buttons held, as an SDL button mask
+  pub mouse_buttons  : u32,
+  /// Window with keyboard focus
+  pub focused_window : Option <u32>,
+  /// Number of times the state has been published
+  pub sequence       : u64
+}
+
+/// Main thread end of the input state buffer.
+pub struct InputWriter {
+  state  : InputState,
+  shared : std::sync::Arc <TripleBuffer>,
+  back   : usize
+}
+
+/// Render thread end of the input state buffer.
+pub struct InputReader {
+  shared : std::sync::Arc <TripleBuffer>,
+  front  : usize
+}
+
+//
+// private
+//
+
+/// Three states, each owned by either the writer (back), the reader (front)
+/// or neither (middle). Publishing and reading exchange the owned state with
+/// the middle one.
+struct TripleBuffer {
+  slots  : [std::cell::UnsafeCell <InputState>; 3],
+  /// Index of the middle slot, with `FRESH` set if it was published after the
+  /// last read
+  middle : std::sync::atomic::AtomicUsize
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+impl InputState {
+  pub fn is_key_down (&self, scancode : sdl2::keyboard::Scancode) -> bool {
+    let index = scancode as usize;
+    index < SCANCODES && self.keys[index / 64] & 1 << (index % 64) != 0
+  }
+
+  /// Returns true if the given button is held.
+  pub fn is_button_down (&self, button : sdl2::mouse::MouseButton) -> bool {
+    self.mouse_buttons & button_mask (button) != 0
+  }
+
+  /// Apply an event pumped on the main thread.
+  pub fn update (&mut self, event : &sdl2::event::Event) {
+    use sdl2::event::{Event, WindowEvent};
+    match *event {
+      Event::KeyDown { scancode: Some (scancode), .. } =>
+        self.set_key (scancode, true),
+      Event::KeyUp { scancode: Some (scancode), .. } =>
+        self.set_key (scancode, false),
+      Event::MouseMotion { window_id, x, y, .. } => {
+        self.mouse_position = (x, y);
+        self.mouse_window   = Some (window_id);
+      }
+      Event::MouseButtonDown { window_id, mouse_btn, x, y, .. } => {
+        self.mouse_buttons |= button_mask (mouse_btn);
+        self.mouse_position = (x, y);
+        self.mouse_window   = Some (window_id);
+      }
+      Event::MouseButtonUp { window_id, mouse_btn, x, y, .. } => {
+        self.mouse_buttons &= !button_mask (mouse_btn);
+        self.mouse_position = (x, y);
+        self.mouse_window   = Some (window_id);
+      }
+      Event::Window { window_id, win_event, .. } => match win_event {
+        WindowEvent::FocusGained => self.focused_window = Some (window_id),
+        WindowEvent::FocusLost if self.focused_window == Some (window_id) => {
+          self.focused_window = None;
+          self.keys           = [0; SCANCODES / 64];
+          self.mouse_buttons  = 0;
+        }
+        WindowEvent::Leave if self.mouse_window == Some (window_id) =>
+          self.mouse_window = None,
+        _ => {}
+      },
+      _ => {}
+    }
+  }
+
+  fn set_key (&mut self, scancode : sdl2::keyboard::Scancode, down : bool) {
+    let index = scancode as usize;
+    if SCANCODES <= index {
+      return
+    }
+    if down {
+      self.keys[index / 64] |= 1 << (index % 64);
+    } else {
+      self.keys[index / 64] &= !(1 << (index % 64));
+    }
+  }
+}
+
+impl Default for InputState {
+  fn default() -> Self {
+    InputState {
+      keys:           [0; SCANCODES / 64],
+      mouse_position: (0, 0),
+      mouse_window:   None,
+      mouse_buttons:  0,
+      focused_window: None,
+      sequence:       0
+    }
+  }
+}
+
+impl InputWriter {
+  /// Create a writer and the reader of the states it publishes.
+  pub fn new() -> (InputWriter, InputReader) {
+    let shared = std::sync::Arc::new (TripleBuffer {
+      slots:  [
+        std::cell::UnsafeCell::new (InputState::default()),
+        std::cell::UnsafeCell::new (InputState::default()),
+        std::cell::UnsafeCell::new (InputState::default())
+      ],
+      middle: std::sync::atomic::AtomicUsize::new (1)
+    });
+    let writer = InputWriter {
+      state:  InputState::default(),
+      shared: shared.clone(),
+      back:   2
+    };
+    (writer, InputReader { shared, front: 0 })
+  }
+
+  /// Apply an event pumped on the main thread; see `InputState::update`.
+  pub fn update (&mut self, event : &sdl2::event::Event) {
+    self.state.update (event)
+  }
+
+  /// The state as updated so far, including changes not yet published.
+  pub fn state (&self) -> &InputState {
+    &self.state
+  }
+
+  /// Make the current state available to the reader.
+  ///
+  /// This should be called once per iteration of the event loop, after the
+  /// events have been pumped. It never blocks.
+  pub fn publish (&mut self) {
+    use std::sync::atomic::Ordering;
+    self.state.sequence += 1;
+    // the back slot is only accessed by the writer
+    unsafe { *self.shared.slots[self.back].get() = self.state };
+    let previous
+      = self.shared.middle.swap (self.back | FRESH, Ordering::AcqRel);
+    self.back = previous & !FRESH;
+  }
+}
+
+impl InputReader {
+  /// The most recently published state.
+  ///
+  /// This never blocks; if nothing was published since the last read, the
+  /// same state is returned again.
+  pub fn read (&mut self) -> &InputState {
+    use std::sync::atomic::Ordering;
+    if self.shared.middle.load (Ordering::Acquire) & FRESH != 0 {
+      let previous = self.shared.middle.swap (self.front, Ordering::AcqRel);
+      self.front = previous & !FRESH;
+    }
+    // the front slot is only accessed by the reader
+    unsafe { &*self.shared.slots[self.front].get() }
+  }
+}
+
+/// Each slot is only accessed by the side that owns its index.
+unsafe impl Sync for TripleBuffer { }
+
+///////////////////////////////////////////////////////////////////////////////
+//  functions                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+fn button_mask (button : sdl2::mouse::MouseButton) -> u32 {
+  match button as u32 {
+    0 => 0,
+    index => 1 << (index - 1)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  #[test]
+  fn test_input_state() {
+    use sdl2::event::{Event, WindowEvent};
+    use sdl2::keyboard::{Mod, Scancode};
+    let mut state = InputState::default();
+    state.update (&Event::Window {
+      timestamp: 0, window_id: 1, win_event: WindowEvent::FocusGained });
+    state.update (&Event::KeyDown {
+      timestamp: 0, window_id: 1, keycode: None, scancode: Some (Scancode::Q),
+      keymod: Mod::empty(), repeat: false });
+    state.mouse_buttons = button_mask (sdl2::mouse::MouseButton::Right);
+    assert!(state.is_key_down (Scancode::Q));
+    assert!(!state.is_key_down (Scancode::A));
+    assert!(state.is_button_down (sdl2::mouse::MouseButton::Right));
+    assert!(!state.is_button_down (sdl2::mouse::MouseButton::Left));
+    assert_eq!(state.focused_window, Some (1));
+    state.update (&Event::Window {
+      timestamp: 0, window_id: 1, win_event: WindowEvent::FocusLost });
+    assert!(!state.is_key_down (Scancode::Q));
+    assert_eq!(state.mouse_buttons, 0);
+    assert_eq!(state.focused_window, None);
+  }
+  #[test]
+  fn test_triple_buffer() {
+    let (mut writer, mut reader) = InputWriter::new();
+    assert_eq!(reader.read().sequence, 0);
+    writer.publish();
+    writer.publish();
+    assert_eq!(reader.read().sequence, 2);
+    assert_eq!(reader.read().sequence, 2);
+    let reader = std::thread::spawn (move || {
+      let mut last = 2;
+      while last < 1000 {
+        let sequence = reader.read().sequence;
+        assert!(last <= sequence);
+        last = sequence;
+      }
+    });
+    for _ in 0..998 {
+      writer.publish();
+    }
+    reader.join().unwrap();
+  }
+}
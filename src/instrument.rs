@@ -0,0 +1,45 @@
+//! Spans for the `tracing` crate.
+//!
+//! With the `instrument` feature, the crate enters `tracing` spans with the
+//! target `glium_sdl2_hack` around building a window backend, building glium,
+//! each buffer swap and the execution of each window command. Every span
+//! records the thread it was entered on, so that a subscriber shows which
+//! thread each operation ran on, e.g. when diagnosing a backend used from the
+//! wrong thread:
+//!
+//! ```ignore
+//! tracing_subscriber::fmt()
+//!   .with_max_level (tracing::Level::INFO)
+//!   .with_span_events (tracing_subscriber::fmt::format::FmtSpan::CLOSE)
+//!   .init();
+//! ```
+//!
+//! Buffer swaps are traced at the trace level, the other spans at the info
+//! level. Without the feature the spans compile to nothing.
+
+#[cfg(feature = "instrument")]
+use tracing;
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// An entered span, exited when dropped; created with the `trace_span!`
+/// macro.
+#[derive(Debug, Default)]
+#[must_use = "the span is exited when the guard is dropped"]
+pub(crate) struct SpanGuard {
+  #[cfg(feature = "instrument")]
+  _entered : Option <tracing::span::EnteredSpan>
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(feature = "instrument")]
+impl SpanGuard {
+  pub(crate) fn enter (span : tracing::Span) -> Self {
+    SpanGuard { _entered: Some (span.entered()) }
+  }
+}
@@ -0,0 +1,112 @@
+//! Bounding the number of frames queued by the driver.
+//!
+//! Swapping buffers returns once the swap is queued, so a render thread that
+//! is faster than the GPU runs ahead until the driver stops accepting
+//! frames, which may be several frames later. Input is read when a frame is
+//! started, so every queued frame adds a frame of input latency.
+//!
+//! With a maximum set by `GliumBuildConfig::max_frames_in_flight` or
+//! `SdlGliumDisplayFacade::set_max_frames_in_flight`, the facade inserts a
+//! fence after each buffer swap, and `draw` waits for the fence of the frame
+//! that many frames ago before starting the next frame:
+//!
+//! ```ignore
+//! // at most one frame queued while the next is drawn
+//! display.set_max_frames_in_flight (Some (2));
+//! ```
+//!
+//! A maximum of 1 waits for the GPU to finish each frame before the next is
+//! started, giving the lowest latency at the cost of throughput.
+
+use gl;
+
+///////////////////////////////////////////////////////////////////////////////
+//  constants                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Time waited on a fence before giving up, in nanoseconds, so that a hung
+/// GPU does not hang the render thread
+const FENCE_TIMEOUT_NS : u64 = 1_000_000_000;
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Fences of the frames in flight, oldest first.
+#[derive(Debug)]
+pub(crate) struct FrameFences {
+  max    : usize,
+  fences : std::collections::VecDeque <gl::types::GLsync>
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+impl FrameFences {
+  /// A maximum of 0 is treated as 1.
+  pub(crate) fn new (max : usize) -> Self {
+    FrameFences {
+      max:    std::cmp::max (1, max),
+      fences: std::collections::VecDeque::new()
+    }
+  }
+
+  pub(crate) fn max (&self) -> usize {
+    self.max
+  }
+
+  /// Insert a fence for the frame just swapped.
+  pub(crate) fn after_swap (&mut self, gl : &gl::Gl) {
+    let fence = unsafe { gl.FenceSync (gl::SYNC_GPU_COMMANDS_COMPLETE, 0) };
+    if !fence.is_null() {
+      self.fences.push_back (fence);
+    }
+  }
+
+  /// Wait until fewer than the maximum number of frames are in flight.
+  ///
+  /// Called before starting a frame with the context current.
+  pub(crate) fn before_frame (&mut self, gl : &gl::Gl) {
+    self.retire (|fence| unsafe {
+      // a timeout or failure, e.g. on context loss, is not waited for again
+      gl.ClientWaitSync (fence, gl::SYNC_FLUSH_COMMANDS_BIT, FENCE_TIMEOUT_NS);
+      gl.DeleteSync (fence);
+    });
+  }
+
+  /// Delete the remaining fences.
+  ///
+  /// The context must be current.
+  pub(crate) fn delete (mut self, gl : &gl::Gl) {
+    for fence in self.fences.drain (..) {
+      unsafe { gl.DeleteSync (fence) };
+    }
+  }
+
+  /// Pass the oldest fences to `wait` until fewer than the maximum remain.
+  fn retire <W : FnMut (gl::types::GLsync)> (&mut self, mut wait : W) {
+    while self.fences.len() >= self.max {
+      let fence = self.fences.pop_front().unwrap();
+      wait (fence);
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  #[test]
+  fn test_retire_fences() {
+    let mut frame_fences = FrameFences::new (2);
+    let mut waited = Vec::new();
+    for frame in 1..5 {
+      frame_fences.retire (|fence| waited.push (fence as usize));
+      frame_fences.fences.push_back (frame as gl::types::GLsync);
+    }
+    // each frame waits for the fence of the frame two frames before
+    assert_eq!(waited, vec![1, 2]);
+    assert_eq!(frame_fences.fences.len(), 2);
+    assert_eq!(FrameFences::new (0).max(), 1);
+  }
+}
@@ -0,0 +1,187 @@
+//! Verification that every handle created by the crate is released.
+//!
+//! A clone of the display facade that escapes into a global or a leaked GL
+//! object keeps the GL context and the window alive past the end of the render
+//! thread, so that the runner cannot tear them down in order (see `runner`).
+//! With the `leak-check` feature, every display facade clone, shared window
+//! impostor, window backend and glium backend is registered while alive
+//! together with a backtrace of where it was created.
+//!
+//! Create a `LeakCheck` at the start of `main`; when it is dropped at the end
+//! of `main` any handles still alive are reported to stderr:
+//!
+//! ```ignore
+//! fn main() {
+//!   let _leak_check = glium_sdl2_hack::leak::LeakCheck::new();
+//!   // ...
+//! }
+//! ```
+//!
+//! Without the feature the registration compiles to nothing.
+
+#[cfg(feature = "leak-check")]
+use backtrace;
+
+///////////////////////////////////////////////////////////////////////////////
+//  statics                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(feature = "leak-check")]
+static NEXT_HANDLE_ID : std::sync::atomic::AtomicUsize =
+  std::sync::atomic::AtomicUsize::new (0);
+/// Handles currently alive, by ID
+#[cfg(feature = "leak-check")]
+static LIVE_HANDLES : std::sync::Mutex <
+  std::collections::BTreeMap <usize, LiveHandle>
+> = std::sync::Mutex::new (std::collections::BTreeMap::new());
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// A registered handle that is still alive.
+#[cfg(feature = "leak-check")]
+#[derive(Clone, Debug)]
+pub struct LiveHandle {
+  pub kind      : HandleKind,
+  /// Name of the thread that created the handle
+  pub thread    : Option <String>,
+  /// Where the handle was created; resolved by `report`
+  pub backtrace : backtrace::Backtrace
+}
+
+/// Reports the handles still alive when dropped.
+#[cfg(feature = "leak-check")]
+#[derive(Debug, Default)]
+pub struct LeakCheck {
+  _private : ()
+}
+
+/// Registers its owner as a live handle for as long as it exists.
+#[derive(Debug)]
+pub(crate) struct LeakToken {
+  #[cfg(feature = "leak-check")]
+  id : usize
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  enums                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HandleKind {
+  /// A clone of the display facade
+  Display,
+  /// The window impostor shared by clones of a display facade
+  WindowImpostor,
+  /// The window backend owning the window and GL context
+  WindowBackend,
+  /// The backend owned by the glium context, alive as long as the context or
+  /// any GL object
+  GliumBackend
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(feature = "leak-check")]
+impl LeakCheck {
+  pub fn new() -> Self {
+    LeakCheck::default()
+  }
+}
+
+#[cfg(feature = "leak-check")]
+impl Drop for LeakCheck {
+  fn drop (&mut self) {
+    if let Err (mut live) = check() {
+      eprintln!("{}", report (&mut live));
+    }
+  }
+}
+
+impl LeakToken {
+  #[cfg(feature = "leak-check")]
+  pub(crate) fn new (kind : HandleKind) -> Self {
+    let id = NEXT_HANDLE_ID.fetch_add (1, std::sync::atomic::Ordering::SeqCst);
+    let handle = LiveHandle {
+      kind,
+      thread:    std::thread::current().name().map (str::to_string),
+      backtrace: backtrace::Backtrace::new_unresolved()
+    };
+    LIVE_HANDLES.lock().unwrap().insert (id, handle);
+    LeakToken { id }
+  }
+
+  #[cfg(not(feature = "leak-check"))]
+  pub(crate) fn new (_kind : HandleKind) -> Self {
+    LeakToken { }
+  }
+}
+
+#[cfg(feature = "leak-check")]
+impl Drop for LeakToken {
+  fn drop (&mut self) {
+    // the lock is poisoned only if a panic happened while it was held, in
+    // which case the registry is still consistent
+    let mut live = match LIVE_HANDLES.lock() {
+      Ok (live) => live,
+      Err (poisoned) => poisoned.into_inner()
+    };
+    live.remove (&self.id);
+  }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  functions                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Handles currently alive, in order of creation.
+#[cfg(feature = "leak-check")]
+pub fn live_handles() -> Vec <LiveHandle> {
+  LIVE_HANDLES.lock().unwrap().values().cloned().collect()
+}
+
+/// Returns the handles still alive, if any.
+///
+/// This should be called after the render thread has exited and the window
+/// has been destroyed, e.g. at the end of `main`.
+#[cfg(feature = "leak-check")]
+pub fn check() -> Result <(), Vec <LiveHandle>> {
+  let live = live_handles();
+  if live.is_empty() {
+    Ok (())
+  } else {
+    Err (live)
+  }
+}
+
+/// Describe live handles with the locations where they were created.
+#[cfg(feature = "leak-check")]
+pub fn report (live : &mut [LiveHandle]) -> String {
+  let mut text = format!("{} handle(s) still alive:\n", live.len());
+  for handle in live.iter_mut() {
+    handle.backtrace.resolve();
+    text.push_str (&format!("{:?} created on thread {} at:\n{:?}\n",
+      handle.kind,
+      handle.thread.as_ref().map_or ("<unnamed>", |name| name.as_str()),
+      handle.backtrace));
+  }
+  text
+}
+
+#[cfg(all(test, feature = "leak-check"))]
+mod test {
+  use super::*;
+  #[test]
+  fn test_leak_token() {
+    let token = LeakToken::new (HandleKind::Display);
+    assert!(live_handles().iter().any (|handle|
+      handle.kind == HandleKind::Display &&
+      handle.thread.as_ref().is_some_and (|name| name.contains ("leak"))));
+    drop (token);
+    assert!(live_handles().iter().all (|handle|
+      handle.kind != HandleKind::Display));
+  }
+}
@@ -0,0 +1,202 @@
+//! Lending the GL context of a display to another thread.
+//!
+//! The main thread sometimes needs to run a little GL itself, e.g. to show a
+//! splash screen while the render thread loads resources. The render thread
+//! releases its context with `SdlGliumDisplayFacade::lend_context` and sends
+//! the returned `ContextLoan` to the borrower, which makes the context
+//! current for the lifetime of a `LentContext` guard:
+//!
+//! ```ignore
+//! // on the render thread
+//! loan_sender.send (display.lend_context()).unwrap();
+//! // on the main thread
+//! let loan = loan_receiver.recv().unwrap();
+//! {
+//!   let context = loan.make_current().unwrap();
+//!   gl::load_with (|symbol| context.gl_get_proc_address (symbol));
+//!   // ... draw the splash screen
+//!   context.swap_window();
+//! }
+//! drop (loan);
+//! ```
+//!
+//! The context is returned when the loan is dropped. Until then the render
+//! thread must not use the display other than to call `draw` or
+//! `reclaim_context`, which block until the context is returned and make it
+//! current again; `reclaim_context` returns the error if the context cannot
+//! be made current.
+
+use sdl2;
+use sdl2_sys;
+
+use SdlGlWindowBackend;
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// The GL context of a display lent to another thread; dropping the loan
+/// returns the context to the render thread.
+pub struct ContextLoan {
+  window_raw     : *mut sdl2_sys::SDL_Window,
+  gl_context_raw : sdl2_sys::SDL_GLContext,
+  /// Set for a mock backend, which has no context to lend
+  mock           : bool,
+  shared         : std::sync::Arc <LoanShared>
+}
+
+/// Guard keeping a lent context current on the borrowing thread.
+pub struct LentContext <'a> {
+  loan : &'a ContextLoan
+}
+
+/// Render thread end of a loan, waiting for the context to be returned.
+pub(crate) struct LoanReturn {
+  shared : std::sync::Arc <LoanShared>
+}
+
+//
+// private
+//
+
+struct LoanShared {
+  returned : std::sync::Mutex <bool>,
+  condvar  : std::sync::Condvar
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+impl ContextLoan {
+  /// Release the context of the backend from the calling (render) thread and
+  /// lend it out.
+  pub(crate) fn new (window_backend : &SdlGlWindowBackend)
+    -> (ContextLoan, LoanReturn)
+  {
+    window_backend.gl_release_current();
+    let shared = std::sync::Arc::new (LoanShared {
+      returned: std::sync::Mutex::new (false),
+      condvar:  std::sync::Condvar::new()
+    });
+    let loan = ContextLoan {
+      window_raw:     window_backend.window_raw.as_ptr(),
+      gl_context_raw: window_backend.gl_context_raw.as_ptr(),
+      mock:           window_backend.mock.is_some(),
+      shared:         shared.clone()
+    };
+    (loan, LoanReturn { shared })
+  }
+
+  /// Make the context current on the calling thread until the returned guard
+  /// is dropped.
+  pub fn make_current (&self) -> Result <LentContext <'_>, String> {
+    if self.mock {
+      return Err ("mock backends have no context to lend".to_string())
+    }
+    if 0 == unsafe {
+      sdl2_sys::SDL_GL_MakeCurrent (self.window_raw, self.gl_context_raw)
+    } {
+      Ok (LentContext { loan: self })
+    } else {
+      Err (sdl2::get_error())
+    }
+  }
+}
+
+/// The context is only made current by one thread at a time: the render
+/// thread waits for the loan to be dropped.
+unsafe impl Send for ContextLoan { }
+
+impl Drop for ContextLoan {
+  fn drop (&mut self) {
+    *self.shared.lock() = true;
+    self.shared.condvar.notify_all();
+  }
+}
+
+impl <'a> LentContext <'a> {
+  /// Address of a GL function, or null if the name is not valid; see
+  /// `SdlGlWindowBackend::gl_get_proc_address`.
+  pub fn gl_get_proc_address (&self, symbol : &str)
+    -> *const std::os::raw::c_void
+  {
+    match std::ffi::CString::new (symbol) {
+      Ok (symbol) => unsafe {
+        sdl2_sys::SDL_GL_GetProcAddress (
+          symbol.as_ptr() as *const std::os::raw::c_char
+        ) as *const std::os::raw::c_void
+      },
+      Err (_) => std::ptr::null()
+    }
+  }
+
+  /// Size of the drawable area of the window in pixels.
+  pub fn drawable_size (&self) -> (u32, u32) {
+    let (mut width, mut height) = (0, 0);
+    unsafe {
+      sdl2_sys::SDL_GL_GetDrawableSize (
+        self.loan.window_raw, &mut width, &mut height)
+    };
+    (width as u32, height as u32)
+  }
+
+  pub fn swap_window (&self) {
+    unsafe { sdl2_sys::SDL_GL_SwapWindow (self.loan.window_raw) }
+  }
+}
+
+impl <'a> Drop for LentContext <'a> {
+  fn drop (&mut self) {
+    unsafe {
+      sdl2_sys::SDL_GL_MakeCurrent (self.loan.window_raw, std::ptr::null_mut())
+    };
+  }
+}
+
+impl LoanReturn {
+  /// Block until the loan has been dropped, then make the context current
+  /// on the calling (render) thread again, returning the error if it could
+  /// not be made current.
+  pub(crate) fn reclaim (self, window_backend : &SdlGlWindowBackend)
+    -> Result <(), String>
+  {
+    self.wait();
+    // the borrower has released the context
+    unsafe { window_backend.gl_make_current_retry() }
+  }
+
+  /// Block until the loan has been dropped.
+  pub(crate) fn wait (self) {
+    let mut returned = self.shared.lock();
+    while !*returned {
+      returned = self.shared.condvar.wait (returned)
+        .unwrap_or_else (|err| err.into_inner());
+    }
+  }
+}
+
+impl LoanShared {
+  fn lock (&self) -> std::sync::MutexGuard <'_, bool> {
+    // the flag is always valid so a poisoned lock can be recovered
+    self.returned.lock().unwrap_or_else (|err| err.into_inner())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use MockGlBackend;
+  #[test]
+  fn test_context_loan() {
+    let window_backend
+      = SdlGlWindowBackend::mock (MockGlBackend::new (320, 240));
+    let (loan, loan_return) = ContextLoan::new (&window_backend);
+    assert!(!window_backend.gl_is_current());
+    assert!(loan.make_current().is_err());
+    let borrower = std::thread::spawn (move || drop (loan));
+    loan_return.reclaim (&window_backend).unwrap();
+    assert!(window_backend.gl_is_current());
+    borrower.join().unwrap();
+  }
+}
@@ -2,17 +2,387 @@
 //! events on the main thread while rendering on a child thread. See
 //! `./README.md` for more details and `./example/example.rs` for a usage
 //! example.
+//!
+//! The `glium` feature (enabled by default) provides the glium display facade
+//! and the modules built on it. Without it only the SDL side is built: the
+//! window backend, which can be sent to the render thread and used there
+//! with any GL loader through `SdlGlWindowBackend::gl_get_proc_address`, and
+//! the event and command channels.
+//!
+//! Glium is built on the render thread through the public
+//! `glium::backend::Context::new`, which loads the GL functions there once
+//! the context has been made current. The `glium-hack` feature instead loads
+//! them on the main thread when the window backend is built and passes them
+//! to `Context::new_hack`, which requires the forked glium. Similarly windows
+//! are built with upstream sdl2 unless the `sdl2-hack` feature is enabled.
+//!
+//! The `sdl2::video::Window` given out by the display facade is built by the
+//! sdl2 crate and never dropped. The `window-impostor` feature instead
+//! transmutes it from an impostor type, which relies on the layout of the
+//! sdl2 types.
+//!
+//! The `tracy` feature emits zones for the Tracy profiler, see `tracy`.
+//!
+//! The `logging` feature emits `log` records with the target
+//! `glium_sdl2_hack` for window and context creation, the handoff of the
+//! backend to the render thread, failures to make the context current and
+//! teardown.
+//!
+//! The `instrument` feature enters `tracing` spans around context
+//! operations and window commands, see `instrument`.
 
-#![feature(unique)]
-
+#[cfg(feature = "leak-check")]
+extern crate backtrace;
+#[cfg(feature = "signals")]
+extern crate ctrlc;
+#[cfg(feature = "glium")]
 extern crate glium;
+#[cfg(feature = "logging")]
+extern crate log;
+#[cfg(feature = "instrument")]
+extern crate tracing;
+#[cfg(feature = "window-handle")]
+extern crate raw_window_handle;
 extern crate sdl2;
 extern crate sdl2_sys;
+#[cfg(feature = "tracy")]
+extern crate tracy_client;
+
+///////////////////////////////////////////////////////////////////////////////
+//  macros                                                                   //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Return `None` from the enclosing function if the option is `None`.
+macro_rules! try_opt {
+  ($option:expr) => {
+    match $option {
+      Some (value) => value,
+      None => return None
+    }
+  }
+}
+
+/// Fail to compile unless the constant condition holds: the array length is
+/// 0 otherwise, which is reported as a mismatched type for the named
+/// constant.
+#[cfg(feature = "window-impostor")]
+macro_rules! const_assert {
+  ($name:ident, $condition:expr) => {
+    #[allow(dead_code)]
+    const $name : [(); 1] = [(); ($condition) as usize];
+  }
+}
+
+/// Begin a Tracy CPU zone ending when the returned value is dropped; see
+/// `tracy`.
+macro_rules! tracy_zone {
+  ($name:expr) => {
+    tracy::Zone::new ($name, file!(), line!())
+  }
+}
+
+/// Emit a `log` record with the target `glium_sdl2_hack` at the given level
+/// (`Error`, `Warn`, `Info`, `Debug` or `Trace`) with the `logging` feature.
+/// Without it the arguments are only type checked.
+macro_rules! log_record {
+  ($level:ident, $($arg:tt)+) => {
+    #[cfg(feature = "logging")]
+    {
+      ::log::log!(target: "glium_sdl2_hack", ::log::Level::$level, $($arg)+);
+    }
+    #[cfg(not(feature = "logging"))]
+    {
+      let _ = format_args!($($arg)+);
+    }
+  }
+}
+
+/// Enter a `tracing` span with the target `glium_sdl2_hack` at the given
+/// level (`INFO` or `TRACE`), recording the calling thread and any given
+/// fields, with the `instrument` feature; the span is exited when the
+/// returned guard is dropped. Without the feature the fields are not
+/// evaluated.
+macro_rules! trace_span {
+  ($level:ident, $name:expr) => {
+    trace_span!($level, $name, )
+  };
+  ($level:ident, $name:expr, $($field:tt)*) => {{
+    #[cfg(feature = "instrument")]
+    let guard = ::instrument::SpanGuard::enter (::tracing::span!(
+      target: "glium_sdl2_hack", ::tracing::Level::$level, $name,
+      thread = ?::std::thread::current(), $($field)*));
+    #[cfg(not(feature = "instrument"))]
+    let guard = ::instrument::SpanGuard::default();
+    guard
+  }}
+}
+
+/// Return the given result, or nothing, from the enclosing method of the
+/// window backend if it is a mock. Mocks are only available with glium.
+macro_rules! if_mock {
+  ($backend:expr) => {
+    #[cfg(feature = "glium")]
+    {
+      if $backend.mock.is_some() {
+        return
+      }
+    }
+  };
+  ($backend:expr, |$mock:pat| $result:expr) => {
+    #[cfg(feature = "glium")]
+    {
+      if let Some ($mock) = $backend.mock.as_ref() {
+        return $result
+      }
+    }
+  }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  modules                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+pub mod affinity;
+pub mod analysis;
+pub mod attr;
+pub mod bridge;
+pub mod command;
+#[cfg(feature = "glium")]
+pub mod damage;
+pub mod debug;
+#[cfg(feature = "glium")]
+pub mod diagnostics;
+pub mod event;
+pub mod focus;
+pub mod gamepad;
+#[cfg(feature = "glium")]
+pub mod golden;
+#[cfg(feature = "window-handle")]
+pub mod handle;
+#[cfg(feature = "glium")]
+pub mod headless;
+#[cfg(feature = "image")]
+pub mod image;
+#[cfg(feature = "glium")]
+pub mod hooks;
+pub mod input;
+pub mod instrument;
+#[cfg(feature = "glium")]
+pub mod latency;
+pub mod leak;
+#[cfg(feature = "glium")]
+pub mod lend;
+#[cfg(feature = "glium")]
+pub mod loader;
+#[cfg(feature = "glium")]
+pub mod manager;
+#[cfg(feature = "glium")]
+pub mod mock;
+pub mod native;
+pub mod pacer;
+pub mod pause;
+pub mod pointer;
+#[cfg(feature = "glium")]
+pub mod present;
+#[cfg(feature = "glium")]
+pub mod profiler;
+pub mod proxy;
+#[cfg(feature = "glium")]
+pub mod readback;
+pub mod reaper;
+#[cfg(feature = "glium")]
+pub mod recorder;
+pub mod redraw;
+pub mod registry;
+pub mod renderdoc;
+pub mod resize;
+#[cfg(feature = "glium")]
+pub mod runner;
+#[cfg(feature = "glium")]
+pub mod shaders;
+pub mod shutdown;
+#[cfg(feature = "glium")]
+pub mod surface;
+#[cfg(feature = "glium")]
+pub mod stats;
+pub mod system;
+pub mod timer;
+pub mod tracy;
+#[cfg(feature = "glium")]
+pub mod upload;
+
+/// Bindings for the GL state that glium does not manage, generated by
+/// `build.rs`
+#[cfg(feature = "glium")]
+mod gl {
+  #![allow(bare_trait_objects, dead_code, non_snake_case,
+    non_upper_case_globals, unused_imports)]
+  #![allow(clippy::all)]
+  include!(concat!(env!("OUT_DIR"), "/gl_bindings.rs"));
+}
+
+pub use affinity::MainThreadToken;
+#[cfg(feature = "glium")]
+pub use affinity::RenderThreadToken;
+pub use analysis::{FrameAnalysis, FrameSignature, Tolerance};
+pub use attr::{ContextVersion, GlAttr};
+pub use bridge::EventBridge;
+pub use command::{CommandError, CommandExecutor, CommandReply, CommandResponse,
+  CommandSender, GlobalMouseState, WindowCommand, WindowState};
+#[cfg(feature = "glium")]
+pub use damage::{DamageTracker, Repaint};
+pub use debug::GlDebugMessage;
+#[cfg(feature = "glium")]
+pub use debug::GliumDebugMessage;
+#[cfg(feature = "glium")]
+pub use diagnostics::FrameDiagnostics;
+pub use event::{DisplayPolicy, Event, EventForwarder, EventReceiver,
+  EventRouter, FrameEvents, KeyRepeatMode};
+pub use focus::{FocusChange, FocusPolicy};
+pub use gamepad::{AxisFilter, Envelope, GamepadCommand, GamepadSender,
+  JoystickInfo, MappingReport, ResponseCurve, Rumble, TouchPhase,
+  UnrecognizedDevice};
+#[cfg(feature = "glium")]
+pub use golden::{GoldenTest, Image, ImageTolerance};
+#[cfg(feature = "glium")]
+pub use headless::HeadlessBackend;
+#[cfg(feature = "glium")]
+pub use hooks::{SwapHookId, SwapInfo};
+pub use input::{InputReader, InputState, InputWriter};
+#[cfg(feature = "glium")]
+pub use lend::{ContextLoan, LentContext};
+#[cfg(feature = "glium")]
+pub use loader::{LoaderFacade, SharedLoaderContext};
+#[cfg(feature = "glium")]
+pub use manager::{DisplayManager, ManagedWindow};
+#[cfg(feature = "glium")]
+pub use mock::MockGlBackend;
+pub use native::{Compositing, PresentStats};
+pub use pacer::FramePacer;
+pub use pause::{PauseHandle, VisibilityPolicy};
+#[cfg(feature = "glium")]
+pub use present::{PresentContext, PresentFrame, Presenter};
+pub use pointer::{ClickThresholds, Scroll, ScrollDirection};
+#[cfg(feature = "glium")]
+pub use profiler::{GpuTimer, GpuTiming};
+pub use proxy::WindowProxy;
+#[cfg(feature = "glium")]
+pub use readback::{PendingCapture, ReadbackFrame, ReadbackReceiver};
+pub use reaper::WindowReaper;
+#[cfg(feature = "glium")]
+pub use recorder::{FrameRecorder, RecordingFormat, RecordingSink,
+  RecordingStats};
+pub use redraw::RedrawHandle;
+pub use registry::{WindowController, WindowHandle, WindowRegistry};
+pub use renderdoc::RenderDoc;
+#[cfg(feature = "glium")]
+pub use shaders::ShaderCache;
+pub use shutdown::ShutdownToken;
+#[cfg(feature = "glium")]
+pub use surface::SurfaceError;
+#[cfg(feature = "glium")]
+pub use stats::FrameStats;
+#[cfg(feature = "glium")]
+pub use runner::{RenderThread, RenderThreadError, RenderThreadHandle, RunLoop};
+pub use system::{DisplayInfo, DisplayOrientation, KeyboardLayout, SystemTheme,
+  WindowPlacement};
+pub use timer::{TimerId, TimerResolution};
+#[cfg(feature = "glium")]
+pub use upload::{TextureHandle, TextureUpload, TextureUploadQueue,
+  UploadFormat};
+
+///////////////////////////////////////////////////////////////////////////////
+//  constants                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+/// `SDL_GL_CONTEXT_ROBUST_ACCESS_FLAG`
+const GL_CONTEXT_ROBUST_ACCESS_FLAG : std::os::raw::c_int = 0x0004;
+/// `SDL_GL_CONTEXT_DEBUG_FLAG`
+const GL_CONTEXT_DEBUG_FLAG         : std::os::raw::c_int = 0x0001;
+/// `SDL_GL_CONTEXT_RESET_LOSE_CONTEXT`
+const GL_CONTEXT_RESET_LOSE_CONTEXT : std::os::raw::c_int = 0x0001;
+const GL_VERSION                    : u32 = 0x1F02;
+const GL_EXTENSIONS                 : u32 = 0x1F03;
+const GL_CONTEXT_PROFILE_MASK       : u32 = 0x9126;
+const GL_CONTEXT_CORE_PROFILE_BIT   : i32 = 0x0001;
+/// GL functions that must be provided for a window backend to be built: the
+/// core of OpenGL 2.0 and OpenGL ES 2.0 needed to draw anything, with the
+/// OpenGL version they are core in and the extension providing them in
+/// earlier versions
+const REQUIRED_GL_FUNCTIONS : &[(&str, (u8, u8), Option <&str>)] = &[
+  ("glGetString",               (1, 1), None),
+  ("glGetIntegerv",             (1, 1), None),
+  ("glGetError",                (1, 1), None),
+  ("glEnable",                  (1, 1), None),
+  ("glDisable",                 (1, 1), None),
+  ("glViewport",                (1, 1), None),
+  ("glScissor",                 (1, 1), None),
+  ("glClear",                   (1, 1), None),
+  ("glClearColor",              (1, 1), None),
+  ("glFlush",                   (1, 1), None),
+  ("glFinish",                  (1, 1), None),
+  ("glGenTextures",             (1, 1), None),
+  ("glBindTexture",             (1, 1), None),
+  ("glTexImage2D",              (1, 1), None),
+  ("glTexParameteri",           (1, 1), None),
+  ("glDeleteTextures",          (1, 1), None),
+  ("glDrawArrays",              (1, 1), None),
+  ("glDrawElements",            (1, 1), None),
+  ("glGenBuffers",              (1, 5), Some ("GL_ARB_vertex_buffer_object")),
+  ("glBindBuffer",              (1, 5), Some ("GL_ARB_vertex_buffer_object")),
+  ("glBufferData",              (1, 5), Some ("GL_ARB_vertex_buffer_object")),
+  ("glDeleteBuffers",           (1, 5), Some ("GL_ARB_vertex_buffer_object")),
+  ("glCreateShader",            (2, 0), Some ("GL_ARB_shader_objects")),
+  ("glShaderSource",            (2, 0), Some ("GL_ARB_shader_objects")),
+  ("glCompileShader",           (2, 0), Some ("GL_ARB_shader_objects")),
+  ("glCreateProgram",           (2, 0), Some ("GL_ARB_shader_objects")),
+  ("glAttachShader",            (2, 0), Some ("GL_ARB_shader_objects")),
+  ("glLinkProgram",             (2, 0), Some ("GL_ARB_shader_objects")),
+  ("glUseProgram",              (2, 0), Some ("GL_ARB_shader_objects")),
+  ("glVertexAttribPointer",     (2, 0), Some ("GL_ARB_vertex_program")),
+  ("glEnableVertexAttribArray", (2, 0), Some ("GL_ARB_vertex_program"))
+];
+/// Attempts at making the context current before giving up
+const MAKE_CURRENT_ATTEMPTS : u32 = 4;
+/// Delay after the first failed attempt, doubled after each further attempt
+const MAKE_CURRENT_BACKOFF_MS : u64 = 1;
+
+// the impostor types are transmuted into the sdl2 types, whose size and
+// alignment they must match; layout drift in the sdl2 crate fails the build
+#[cfg(feature = "window-impostor")]
+const_assert!(WINDOW_IMPOSTOR_SIZE,
+  std::mem::size_of::<sdl2::video::Window>()
+    == std::mem::size_of::<SdlWindowImpostor>());
+#[cfg(feature = "window-impostor")]
+const_assert!(WINDOW_IMPOSTOR_ALIGN,
+  std::mem::align_of::<sdl2::video::Window>()
+    == std::mem::align_of::<SdlWindowImpostor>());
+#[cfg(feature = "window-impostor")]
+const_assert!(WINDOW_CONTEXT_IMPOSTOR_SIZE,
+  std::mem::size_of::<sdl2::video::WindowContext>()
+    == std::mem::size_of::<SdlWindowContextImpostor>());
+#[cfg(feature = "window-impostor")]
+const_assert!(WINDOW_CONTEXT_IMPOSTOR_ALIGN,
+  std::mem::align_of::<sdl2::video::WindowContext>()
+    == std::mem::align_of::<SdlWindowContextImpostor>());
+
+///////////////////////////////////////////////////////////////////////////////
+//  statics                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Set while a window backend exists; checked by
+/// `SdlGlWindowBuilder::build_backend_with_attr`
+static WINDOW_BUILT : std::sync::atomic::AtomicBool =
+  std::sync::atomic::AtomicBool::new (false);
+/// Set by `allow_multiple_windows`
+static MULTIPLE_WINDOWS : std::sync::atomic::AtomicBool =
+  std::sync::atomic::AtomicBool::new (false);
 
 ///////////////////////////////////////////////////////////////////////////////
 //  typedefs                                                                 //
 ///////////////////////////////////////////////////////////////////////////////
 
+#[cfg(feature = "glium")]
 pub type Display = SdlGliumDisplayFacade;
 pub type Window  = SdlGlWindowBackend;
 
@@ -34,39 +404,243 @@ pub type Window  = SdlGlWindowBackend;
 /// to create a window on a thread other than the main thread, so the
 /// `VideoSubsystem::window` function **must not be called**.
 ///
-/// TODO: since we already fork sdl2, could we add a global atomic flag to
-/// prevent ever trying to build another window after the first ?
-#[derive(Clone)]
+/// Only one window backend can exist at a time unless
+/// `allow_multiple_windows` has been called, so a second window backend
+/// accidentally built with `SdlGlWindowBuilder::build_backend*` fails with
+/// `BackendBuildError::WindowAlreadyExists`. The guard only covers these
+/// functions: a window built directly with `WindowBuilder::build` through
+/// the subsystem of the window is not detected, since it is created by sdl2.
+///
+/// NB: fields are dropped in declaration order, and everything that refers to
+/// the window backend is declared before it. This is checked in debug builds
+/// when the window backend is dropped.
+#[cfg(feature = "glium")]
 pub struct SdlGliumDisplayFacade {
+  /// NB: holds a reference to the window backend
   glium_context       : std::rc::Rc <glium::backend::Context>,
+  /// NB: must be dropped before the window backend since it may hold GL
+  /// objects
+  state               : std::rc::Rc <FacadeState>,
+  /// NB: refers to the raw window
+  #[cfg(feature = "window-impostor")]
+  sdl_window_impostor : std::rc::Rc <SharedImpostor>,
   window_backend      : std::rc::Rc <SdlGlWindowBackend>,
-  sdl_window_impostor : std::rc::Rc <std::cell::UnsafeCell <SdlWindowImpostor>>
+  pause               : PauseHandle,
+  redraw              : RedrawHandle,
+  /// The thread glium was built on, checked by every method in debug builds
+  render_thread       : affinity::RenderThreadToken,
+  _leak_token         : leak::LeakToken
 }
 
 /// This type is transferrable to another thread.
 ///
 /// When acquired the context will already be released so all you can do with
-/// it is build Glium (which will automatically re-acquire the context).
+/// it is build Glium (which will automatically re-acquire the context), or
+/// make it current with `gl_make_current` to use another GL wrapper.
 pub struct SdlGlWindowBackend {
-  window_raw     : std::ptr::Unique <sdl2_sys::SDL_Window>,
+  window_raw     : std::ptr::NonNull <sdl2_sys::SDL_Window>,
   /// The intended type is:
   /// ```ignore
-  /// gl_context_raw : std::ptr::Unique <sdl2_sys::SDL_GLContext>
+  /// gl_context_raw : std::ptr::NonNull <sdl2_sys::SDL_GLContext>
   /// ```
-  /// but this gives a `std::ptr::Unique <*mut std::os::raw::c_void>`
+  /// but this gives a `std::ptr::NonNull <*mut std::os::raw::c_void>`
   /// which is not what we want.
-  gl_context_raw : std::ptr::Unique <std::os::raw::c_void>,
-  gl_funs        : Option <Box <glium::gl::Gl>>
+  gl_context_raw : std::ptr::NonNull <std::os::raw::c_void>,
+  /// False if the window is destroyed elsewhere when the backend is dropped,
+  /// see `runner`
+  owns_window    : bool,
+  /// The thread the backend was built on
+  main_thread    : affinity::MainThreadToken,
+  /// Set if the context was created robust, see `BackendConfig`
+  robust         : bool,
+  /// Samples per pixel negotiated for the window, see `BackendConfig`
+  multisample    : Option <u8>,
+  /// Set if an sRGB-capable framebuffer was requested, see `BackendConfig`
+  srgb           : bool,
+  /// The version the context was created for, see `BackendConfig`
+  version        : Option <ContextVersion>,
+  /// Sends debug messages if built with `BackendConfig::debug`
+  debug_output   : Option <debug::DebugOutput>,
+  /// Receives debug messages until taken with `take_debug_receiver`
+  debug_receiver : Option <std::sync::mpsc::Receiver <GlDebugMessage>>,
+  /// Destroys the window on the main thread if set, see `reaper`
+  reaper         : Option <reaper::ReaperSender>,
+  /// Queues window commands to the main thread if set, see `proxy`
+  proxy          : Option <WindowProxy>,
+  /// GL functions loaded on the main thread, see the `glium-hack` feature
+  #[cfg(feature = "glium-hack")]
+  gl_funs        : Option <Box <glium::gl::Gl>>,
+  native         : native::NativeGl,
+  /// Drawable size of the window, updated on resize; `None` for a mock
+  size_cache     : Option <resize::SizeCache>,
+  /// The `sdl2` window, which is never dropped since the window is destroyed
+  /// by the backend; `None` for a mock
+  #[cfg(all(feature = "glium", not(feature = "window-impostor")))]
+  sdl_window     : Option <
+    std::cell::UnsafeCell <std::mem::ManuallyDrop <sdl2::video::Window>>>,
+  /// NB: must be dropped after the GL context has been deleted
+  exit_signal    : shutdown::ExitSignal,
+  /// Damage regions for the next swap, set with
+  /// `SdlGliumDisplayFacade::set_swap_damage`
+  #[cfg(feature = "glium")]
+  swap_damage    : std::cell::RefCell <Option <Vec <glium::Rect>>>,
+  /// Set when the context could not be made current for glium, which is
+  /// then told that the context is lost on the next buffer swap
+  #[cfg(feature = "glium")]
+  context_lost   : std::cell::Cell <bool>,
+  /// The glium context built on this backend, which must be dropped first
+  #[cfg(feature = "glium")]
+  context_probe  : DropProbe <glium::backend::Context>,
+  /// The facade state built on this backend, which must be dropped first
+  #[cfg(feature = "glium")]
+  state_probe    : DropProbe <FacadeState>,
+  /// Set for a mock backend, in which case there is no window or context and
+  /// the raw pointers are dangling
+  #[cfg(feature = "glium")]
+  mock           : Option <MockGlBackend>,
+  _leak_token    : leak::LeakToken
+}
+
+/// Options for building a window backend, see
+/// `SdlGlWindowBuilder::build_backend_with`.
+#[derive(Clone, Debug)]
+pub struct BackendConfig {
+  /// Create a robust context (`SDL_GL_CONTEXT_ROBUST_ACCESS_FLAG`) that is
+  /// lost on a GPU reset, so that resets can be detected with
+  /// `SdlGlWindowBackend::gl_context_reset` and recovered from with
+  /// `SdlGliumDisplayFacade::rebuild`
+  pub robust           : bool,
+  /// Request a multisampled default framebuffer with this many samples per
+  /// pixel, falling back to each lower power of two and then to no
+  /// multisampling if the window or context cannot be created; see
+  /// `SdlGlWindowBackend::multisample`
+  pub multisample      : Option <u8>,
+  /// Request an sRGB-capable default framebuffer
+  /// (`SDL_GL_FRAMEBUFFER_SRGB_CAPABLE`); glium then enables sRGB encoding
+  /// when drawing to it unless `GliumBuildConfig::framebuffer_srgb` says
+  /// otherwise
+  pub srgb             : bool,
+  /// GL versions to try creating the context for in order, e.g.
+  /// `ContextVersion::FALLBACK`; the version set by attributes is used if
+  /// empty. If all are OpenGL ES versions the native OpenGL ES driver is
+  /// selected, see `attr`. See `SdlGlWindowBackend::context_version`
+  pub context_versions : Vec <ContextVersion>,
+  /// Create a debug context (`SDL_GL_CONTEXT_DEBUG_FLAG`) whose debug output
+  /// is sent to a channel, see `debug`
+  pub debug            : bool
+}
+
+/// The attributes of a GL context as obtained, which may differ from those
+/// requested; see `SdlGlWindowBackend::context_info`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContextInfo {
+  pub major_version : u8,
+  pub minor_version : u8,
+  pub profile       : sdl2::video::GLProfile,
+  pub depth_bits    : u8,
+  pub stencil_bits  : u8,
+  /// Samples per pixel of the default framebuffer, 0 if not multisampled
+  pub samples       : u8,
+  pub srgb_capable  : bool
+}
+
+/// Options for building glium on a window backend, see
+/// `SdlGlWindowBackend::build_glium_with`.
+#[cfg(feature = "glium")]
+pub struct GliumBuildConfig {
+  /// Check that the context is current before each use, making it current
+  /// if needed
+  pub checked              : bool,
+  pub debug                : glium::debug::DebugCallbackBehavior,
+  /// Initial setting of `SdlGliumDisplayFacade::set_framebuffer_srgb`; `None`
+  /// enables sRGB encoding for a backend built with `BackendConfig::srgb`
+  pub framebuffer_srgb     : Option <bool>,
+  /// Wait for vertical retrace when swapping buffers, or `None` to keep the
+  /// current swap interval; see `SdlGliumDisplayFacade::set_swap_interval`
+  /// to change it later
+  pub vsync                : Option <bool>,
+  /// Initial setting of `SdlGliumDisplayFacade::set_max_frames_in_flight`
+  pub max_frames_in_flight : Option <usize>
 }
 
 //
 // private
 //
 
+/// Settings shared by clones of a display facade.
+#[cfg(feature = "glium")]
+struct FacadeState {
+  /// GL functions for state that glium does not manage
+  gl               : gl::Gl,
+  /// `GL_FRAMEBUFFER_SRGB` setting applied by `draw`
+  framebuffer_srgb : std::cell::Cell <Option <bool>>,
+  inspection       : FrameInspection,
+  timing           : FrameTiming,
+  resources        : FacadeResources,
+  swap_hooks       : std::cell::RefCell <hooks::SwapHooks>,
+  /// Set while the context is lent to another thread
+  loan             : std::cell::RefCell <Option <lend::LoanReturn>>
+}
+
+/// Checks and copies of the back buffer made before each buffer swap.
+#[cfg(feature = "glium")]
+#[derive(Default)]
+struct FrameInspection {
+  diagnostics : std::cell::RefCell <Option <FrameDiagnostics>>,
+  readback    : std::cell::RefCell <Option <readback::ReadbackRing>>,
+  captures    : std::cell::RefCell <readback::CaptureQueue>,
+  analysis    : std::cell::RefCell <Option <FrameAnalysis>>
+}
+
+/// Measurement and pacing of frames.
+#[cfg(feature = "glium")]
+struct FrameTiming {
+  stats        : std::cell::RefCell <stats::StatsCollector>,
+  profiler     : std::cell::RefCell <profiler::GpuProfiler>,
+  /// Waits after each buffer swap
+  pacer        : std::cell::RefCell <Option <FramePacer>>,
+  frame_fences : std::cell::RefCell <Option <latency::FrameFences>>,
+  /// Tracy zone from `draw` until the buffer swap
+  frame_zone   : std::cell::RefCell <Option <tracy::Zone>>
+}
+
+/// Programs and textures created through the facade.
+#[cfg(feature = "glium")]
+#[derive(Default)]
+struct FacadeResources {
+  shader_cache : std::cell::RefCell <Option <ShaderCache>>,
+  uploads      : std::cell::RefCell <upload::TextureUploads>
+}
+
+/// The backend given to glium, running the per-frame work of the facade
+/// around buffer swaps.
+#[cfg(feature = "glium")]
+struct RenderBackend {
+  /// NB: must be dropped before the window backend
+  state          : std::rc::Rc <FacadeState>,
+  window_backend : std::rc::Rc <SdlGlWindowBackend>,
+  _leak_token    : leak::LeakToken
+}
+
+/// Weak reference to a value that must be dropped before the owner of the
+/// probe.
+#[cfg(feature = "glium")]
+struct DropProbe <T> {
+  target : std::cell::RefCell <std::rc::Weak <T>>
+}
+
+/// The window impostor shared by clones of a display facade.
+#[cfg(feature = "window-impostor")]
+struct SharedImpostor {
+  window      : std::cell::UnsafeCell <SdlWindowImpostor>,
+  _leak_token : leak::LeakToken
+}
+
 /// Type used to transmute into an `sdl2::video::Window`.
 ///
 /// It is important that only references to the transmuted value are given out
 /// so that resources are not freed when dropped.
+#[cfg(feature = "window-impostor")]
 #[derive(Clone)]
 struct SdlWindowImpostor {
   window_context_impostor : std::rc::Rc <SdlWindowContextImpostor>
@@ -77,6 +651,7 @@ struct SdlWindowImpostor {
 /// This will not be accessible directly, but any functions on the referring
 /// window that attempt to *clone* the video subsystem **should not be called**
 /// as it will contain a NULL `Rc` pointer.
+#[cfg(feature = "window-impostor")]
 struct SdlWindowContextImpostor {
   /// `VideoSubsystem` is a single (unused) `Rc` drop token.
   _video_subsystem : std::rc::Rc <()>,
@@ -90,7 +665,36 @@ struct SdlWindowContextImpostor {
 #[derive(Debug)]
 pub enum BackendBuildError {
   WindowBuildError     (sdl2::video::WindowBuildError),
-  ContextCreationError (String)
+  ContextCreationError (String),
+  /// Another window backend exists, see `allow_multiple_windows`
+  WindowAlreadyExists,
+  /// The closure given to `SdlGlWindowBuilder::build_backend_with_attr`
+  /// failed to set an attribute
+  GlAttrError          (String),
+  /// GL functions that the new context does not provide
+  GlLoadError          (Vec <String>),
+  /// A field of an sdl2 type is not at the offset of the corresponding field
+  /// of its impostor, i.e. the version of sdl2 is not compatible with the
+  /// `window-impostor` feature; see `verify_layout`
+  #[cfg(feature = "window-impostor")]
+  LayoutMismatch {
+    type_name : &'static str,
+    /// Offset of the field in the impostor in bytes
+    expected  : usize,
+    /// Offset of the field in the sdl2 type in bytes, or the size of the
+    /// type if the field was not found
+    actual    : usize
+  }
+}
+
+/// Error rebuilding a display, see `SdlGliumDisplayFacade::rebuild`.
+#[cfg(feature = "glium")]
+#[derive(Debug)]
+pub enum RebuildError {
+  /// Other references to the display or its GL objects remain
+  DisplayInUse,
+  ContextCreationError (String),
+  IncompatibleOpenGl   (glium::IncompatibleOpenGl)
 }
 
 ///////////////////////////////////////////////////////////////////////////////
@@ -102,279 +706,2275 @@ pub enum BackendBuildError {
 pub trait SdlGlWindowBuilder {
   /// Builds a window backend and releases the context.
   fn build_backend (&mut self) -> Result <SdlGlWindowBackend, BackendBuildError>;
+  /// Builds a window backend with the given configuration and releases the
+  /// context.
+  fn build_backend_with (&mut self, config : BackendConfig)
+    -> Result <SdlGlWindowBackend, BackendBuildError>;
+  /// Builds a window backend with the given configuration after setting GL
+  /// attributes with `f`, and releases the context; see `attr`.
+  fn build_backend_with_attr <F> (&mut self, config : BackendConfig, f : F)
+    -> Result <SdlGlWindowBackend, BackendBuildError>
+  where F : FnOnce (&GlAttr) -> Result <(), String>;
 }
 
 ///////////////////////////////////////////////////////////////////////////////
 //  impls                                                                    //
 ///////////////////////////////////////////////////////////////////////////////
 
+#[cfg(feature = "glium")]
 impl SdlGliumDisplayFacade {
-  /// &#9888; **Warning**: the returned window reference does *not* contain a
-  /// "real" reference to the `sdl2::VideoSubsystem`. While most methods should
-  /// work, it is not possible to create a window from a thread other than the
-  /// main thread, so the `sdl2::VideoSubsystem::window` function to build a
-  /// new window **should not be called**.
+  /// &#9888; **Warning**: the `sdl2::VideoSubsystem` of the returned window
+  /// belongs to the main thread. While most methods should work, it is not
+  /// possible to create a window from a thread other than the main thread,
+  /// so the subsystem **should not be cloned or used**, e.g. with the
+  /// `sdl2::VideoSubsystem::window` function to build a new window. With the
+  /// `window-impostor` feature the window is transmuted from an impostor
+  /// that does not contain a "real" reference to the subsystem at all.
+  ///
+  /// Prefer `window_proxy`, which is safe to use from the render thread.
+  ///
+  /// # Safety
+  ///
+  /// The video subsystem of the returned window must not be used.
   pub unsafe fn window (&self) -> &sdl2::video::Window {
-    let ptr = self.sdl_window_impostor.get();
-    let window : &sdl2::video::Window = std::mem::transmute (ptr);
-    window
+    self.render_thread.debug_assert_current();
+    &*self.sdl_window()
   }
 
-  /// &#9888; **Warning**: the returned window reference does *not* contain a
-  /// "real" reference to the `sdl2::VideoSubsystem`. While most methods should
-  /// work, it is not possible to create a window from a thread other than the
-  /// main thread, so the `sdl2::VideoSubsystem::window` function to build a
-  /// new window **should not be called**.
+  /// &#9888; **Warning**: see `window`.
+  ///
+  /// # Safety
+  ///
+  /// See `window`.
   pub unsafe fn window_mut (&mut self) -> &mut sdl2::video::Window {
-    let ptr = self.sdl_window_impostor.get();
-    let window : &mut sdl2::video::Window = std::mem::transmute (ptr);
-    window
+    self.render_thread.debug_assert_current();
+    &mut *self.sdl_window()
   }
 
-  /// Start drawing on the backbuffer.
-  ///
-  /// This function returns a `Frame`, which can be used to draw on it.  When
-  /// the `Frame` is destroyed, the buffers are swapped.
-  ///
-  /// Note that destroying a `Frame` is immediate, even if vsync is enabled.
-  pub fn draw (&self) -> glium::Frame {
-    use glium::backend::Backend;
-    glium::Frame::new (
-      self.glium_context.clone(),
-      self.window_backend.get_framebuffer_dimensions())
+  /// SDL window ID of the underlying window.
+  pub fn window_id (&self) -> u32 {
+    self.render_thread.debug_assert_current();
+    self.window_backend.window_id()
   }
-}
 
-impl SdlGlWindowBackend {
-  /// Build Glium with current context checks and with default debug callback
-  /// behavior.
-  pub fn build_glium (self)
-    -> Result <SdlGliumDisplayFacade, glium::IncompatibleOpenGl>
-  {
-    self.build_glium_debug (Default::default())
+  /// The thread glium was built on, where the display must be used.
+  pub fn render_thread (&self) -> RenderThreadToken {
+    self.render_thread
   }
 
-  /// Build Glium without current context checks and with default debug
-  /// callback behavior.
-  pub fn build_glium_unchecked (self)
-    -> Result <SdlGliumDisplayFacade, glium::IncompatibleOpenGl>
-  {
-    self.build_glium_unchecked_debug (Default::default())
+  /// Safe queries and changes on the underlying window, if a proxy has been
+  /// attached to the window backend with `CommandExecutor::attach`.
+  pub fn window_proxy (&self) -> Option <&WindowProxy> {
+    self.render_thread.debug_assert_current();
+    self.window_backend.window_proxy()
   }
 
-  /// Build Glium with current context checks and with the given debug callback
-  /// behavior.
-  pub fn build_glium_debug (mut self,
-    debug : glium::debug::DebugCallbackBehavior
-  ) -> Result <SdlGliumDisplayFacade, glium::IncompatibleOpenGl> {
-    let gl_funs = self.gl_funs.take().unwrap();
-    let sdl_window_context_impostor
-      = SdlWindowContextImpostor::new (self.window_raw.as_ptr());
-    let sdl_window_impostor = std::rc::Rc::new (std::cell::UnsafeCell::new (
-      SdlWindowImpostor::new (sdl_window_context_impostor)));
-    let window_backend = std::rc::Rc::new (self);
-    let glium_context = try!{
-      unsafe {
-        glium::backend::Context::new_hack (
-          window_backend.clone(),
-          *gl_funs,
-          true,
-          debug
-        )
-      }
-    };
-    Ok (SdlGliumDisplayFacade {
-      glium_context,
-      window_backend,
-      sdl_window_impostor
-    })
+  /// Token for shutting down the render loop of this display, shared with
+  /// the token taken from the window backend.
+  pub fn shutdown_handle (&self) -> ShutdownToken {
+    self.render_thread.debug_assert_current();
+    self.window_backend.shutdown_handle()
   }
 
-  /// Build Glium without current context checks and with the given debug
-  /// callback behavior.
-  pub fn build_glium_unchecked_debug (mut self,
-    debug : glium::debug::DebugCallbackBehavior
-  ) -> Result <SdlGliumDisplayFacade, glium::IncompatibleOpenGl> {
-    let gl_funs = self.gl_funs.take().unwrap();
-    let sdl_window_context_impostor
-      = SdlWindowContextImpostor::new (self.window_raw.as_ptr());
-    let sdl_window_impostor = std::rc::Rc::new (std::cell::UnsafeCell::new (
-      SdlWindowImpostor::new (sdl_window_context_impostor)));
-    let window_backend = std::rc::Rc::new (self);
-    let glium_context = try!{
-      unsafe {
-        glium::backend::Context::new_hack (
-          window_backend.clone(),
-          *gl_funs,
-          false,
-          debug
-        )
-      }
-    };
-    Ok (SdlGliumDisplayFacade {
-      glium_context,
-      window_backend,
-      sdl_window_impostor
-    })
+  /// Returns true once shutdown of this display or the process has been
+  /// requested; see `ShutdownToken::is_requested`.
+  pub fn shutdown_requested (&self) -> bool {
+    self.render_thread.debug_assert_current();
+    self.shutdown_handle().is_requested()
   }
 
-} // end impl SdlGlWindowBackend
+  /// Handle for pausing and resuming this display from another thread.
+  pub fn pause_handle (&self) -> PauseHandle {
+    self.render_thread.debug_assert_current();
+    self.pause.clone()
+  }
 
-/// Implementation of drop will destroy the window and delete the OpenGL
-/// context.
-///
-/// NB: Because the Glium backend context holds a reference to this structure,
-/// it should be guaranteed not to drop while a reference to the Glium context
-/// exists (such as in a `glium::Frame` object). Also, because the lifetime of
-/// any `&sdl2::video::Window` reference returned from the display is tied to
-/// the display itself, it is not possible for this to drop while any window
-/// references are in scope.
-impl Drop for SdlGlWindowBackend {
-  fn drop (&mut self) {
-    unsafe { sdl2_sys::SDL_DestroyWindow (self.window_raw.as_ptr()) };
-    unsafe { sdl2_sys::SDL_GL_DeleteContext (self.gl_context_raw.as_ptr()) };
+  /// Stop issuing frames; see `wait_while_paused`.
+  pub fn pause (&self) {
+    self.render_thread.debug_assert_current();
+    self.pause.pause()
   }
-}
 
-/// Backend implementation basically follows that of the `glium-sdl2` crate,
-/// except with raw `SDL_GL_*` calls.
-unsafe impl glium::backend::Backend for SdlGlWindowBackend {
-  fn swap_buffers (&self) -> Result<(), glium::SwapBuffersError> {
-    // TODO: is context loss is possible?
-    unsafe { sdl2_sys::SDL_GL_SwapWindow (self.window_raw.as_ptr()) }
-    Ok(())
+  /// Stop issuing frames and release the GL context while paused.
+  pub fn pause_and_release_context (&self) {
+    self.render_thread.debug_assert_current();
+    self.pause.pause_and_release_context()
   }
 
-  unsafe fn get_proc_address (&self, symbol : &str)
-    -> *const std::os::raw::c_void
-  {
-    match std::ffi::CString::new (symbol) {
-      Ok (symbol) => {
-        sdl2_sys::SDL_GL_GetProcAddress (
-          symbol.as_ptr() as *const std::os::raw::c_char
-        ) as *const std::os::raw::c_void
-      }
-      Err (_) => std::ptr::null()
-    }
+  pub fn resume (&self) {
+    self.render_thread.debug_assert_current();
+    self.pause.resume()
   }
 
-  fn get_framebuffer_dimensions (&self) -> (u32, u32) {
-    let mut width  : std::os::raw::c_int = 0;
-    let mut height : std::os::raw::c_int = 0;
-    unsafe {
-      sdl2_sys::SDL_GL_GetDrawableSize (
-        self.window_raw.as_ptr(), &mut width, &mut height) };
-    (width as u32, height as u32)
+  pub fn is_paused (&self) -> bool {
+    self.render_thread.debug_assert_current();
+    self.pause.is_paused()
   }
 
-  fn is_current (&self) -> bool {
-    let current_raw = unsafe { sdl2_sys::SDL_GL_GetCurrentContext() };
-    self.gl_context_raw.as_ptr() == current_raw
+  /// Returns true while the window is throttled or suspended because it is
+  /// hidden; see `pause`.
+  pub fn is_hidden (&self) -> bool {
+    self.render_thread.debug_assert_current();
+    self.pause.is_hidden()
   }
 
-  unsafe fn make_current (&self) {
-    let result = if 0 == sdl2_sys::SDL_GL_MakeCurrent (
-      self.window_raw.as_ptr(), self.gl_context_raw.as_ptr()
-    ) {
-      Ok (())
-    } else {
-      Err (sdl2::get_error())
-    };
-    result.unwrap();
+  /// Block the calling (render) thread while the display is paused or
+  /// suspended, or until the next frame is due while throttled, returning
+  /// true if it blocked.
+  ///
+  /// This should be called once per iteration of the frame loop before
+  /// `draw`. If the display was paused with `pause_and_release_context` the
+  /// context is released before blocking and made current again before
+  /// returning; if it cannot be made current the error is returned, and the
+  /// display must not be used until it has been made current.
+  pub fn wait_while_paused (&self) -> Result <bool, String> {
+    self.render_thread.debug_assert_current();
+    self.pause.wait_while_paused (&self.window_backend)
   }
-}
 
-impl SdlGlWindowBuilder for sdl2::video::WindowBuilder {
-  /// Builds a raw window backend and releases the context.
+  /// Handle for requesting redraws of this display from another thread.
+  pub fn redraw_handle (&self) -> RedrawHandle {
+    self.render_thread.debug_assert_current();
+    self.redraw.clone()
+  }
+
+  /// Enable or disable rendering on demand; see `redraw`.
+  pub fn set_render_on_demand (&self, on_demand : bool) {
+    self.render_thread.debug_assert_current();
+    self.redraw.set_on_demand (on_demand)
+  }
+
+  pub fn is_render_on_demand (&self) -> bool {
+    self.render_thread.debug_assert_current();
+    self.redraw.is_on_demand()
+  }
+
+  /// Request a frame when rendering on demand.
+  pub fn request_redraw (&self) {
+    self.render_thread.debug_assert_current();
+    self.redraw.request_redraw()
+  }
+
+  /// When rendering on demand, block the calling (render) thread until a
+  /// redraw is requested, returning true if it blocked.
   ///
-  /// # Panics
+  /// This should be called once per iteration of the frame loop before
+  /// `draw`, after `wait_while_paused`.
+  pub fn wait_for_redraw (&self) -> bool {
+    self.render_thread.debug_assert_current();
+    self.redraw.wait_for_redraw()
+  }
+
+  /// Presentation counters of the window reported by the window system.
   ///
-  /// Call will panic if the size of the `sdl2::video::Window` type does not
-  /// match the size of the internal `SdlWindowImpostor` type, or if the
-  /// `sdl2::video::WindowContext` type does not match the size of the internal
-  /// `SdlWindowContextImpostor` type.
+  /// Unlike CPU timestamps taken around `swap_buffers` these reflect when
+  /// frames were actually presented, e.g. comparing `msc` before and after a
+  /// swap detects missed vertical retraces. Returns `None` unless running on
+  /// GLX with `GLX_OML_sync_control` or EGL with `EGL_CHROMIUM_sync_control`.
+  pub fn present_stats (&self) -> Option <PresentStats> {
+    self.render_thread.debug_assert_current();
+    self.window_backend.present_stats()
+  }
+
+  /// The attributes of the context as obtained, see
+  /// `SdlGlWindowBackend::context_info`.
+  pub fn context_info (&self) -> Result <ContextInfo, String> {
+    self.render_thread.debug_assert_current();
+    self.window_backend.context_info()
+  }
+
+  /// Samples per pixel of the default framebuffer negotiated when the window
+  /// backend was built, see `SdlGlWindowBackend::multisample`.
+  pub fn multisample (&self) -> Option <u8> {
+    self.render_thread.debug_assert_current();
+    self.window_backend.multisample()
+  }
+
+  /// Whether the frames of the window pass through a compositing manager.
   ///
-  /// TODO: can this be made a compile time check when compile-time assertions
-  /// are allowed ?
-  fn build_backend (&mut self) -> Result <SdlGlWindowBackend, BackendBuildError> {
-    assert_eq!(
-      std::mem::size_of::<sdl2::video::Window>(),
-      std::mem::size_of::<SdlWindowImpostor>());
-    assert_eq!(
-      std::mem::size_of::<sdl2::video::WindowContext>(),
-      std::mem::size_of::<SdlWindowContextImpostor>());
+  /// A window is expected to be unredirected when it is fullscreen and
+  /// compositor bypass is enabled (see `system::set_compositor_bypass`),
+  /// though compositors may ignore the request. Frame pacing should account
+  /// for composited presentation. Returns `None` unless running on GLX.
+  ///
+  /// This queries the X server and so should not be called every frame.
+  pub fn compositing (&self) -> Option <Compositing> {
+    self.render_thread.debug_assert_current();
+    self.window_backend.compositing()
+  }
 
-    use glium::backend::Backend;
+  /// Age of the back buffer contents in frames, for partial redraws.
+  ///
+  /// An age of `n` means the back buffer holds the frame presented `n` swaps
+  /// ago, so only the regions damaged in the last `n` frames need to be
+  /// redrawn; an age of 0 means the contents are undefined and the whole
+  /// frame must be redrawn. Returns `None`, meaning the age is unknown and
+  /// the whole frame must be redrawn, when the context is neither GLX with
+  /// `GLX_EXT_buffer_age` nor EGL with `EGL_EXT_buffer_age`, or when the EGL
+  /// query fails.
+  ///
+  /// This should be queried before drawing each frame.
+  pub fn buffer_age (&self) -> Option <u32> {
+    self.render_thread.debug_assert_current();
+    self.window_backend.buffer_age()
+  }
 
-    // opengl must be requested
-    self.opengl();
-    // create window from self
-    let (window_raw, video_subsystem) = unsafe {
-      let (window_raw, video_subsystem) = try!{ self.build_hack() };
-      (std::ptr::Unique::new_unchecked (window_raw), video_subsystem)
-    };
-    // create gl context
-    let gl_context_raw = unsafe {
-      let gl_context_raw : sdl2_sys::SDL_GLContext
-        = sdl2_sys::SDL_GL_CreateContext (window_raw.as_ptr());
-      if gl_context_raw.is_null() {
-        return Err (BackendBuildError::ContextCreationError (sdl2::get_error()))
-      }
-      std::ptr::Unique::new_unchecked (gl_context_raw)
-    };
-    let mut window_backend
-      = SdlGlWindowBackend { window_raw, gl_context_raw, gl_funs: None };
-    // load gl function pointers
-    window_backend.gl_funs = Some (Box::new (glium::gl::Gl::load_with (
-      |symbol| unsafe { window_backend.get_proc_address (symbol) as *const _ }
-    )));
+  /// Dimensions of the default framebuffer in pixels.
+  pub fn framebuffer_dimensions (&self) -> (u32, u32) {
+    self.render_thread.debug_assert_current();
+    use glium::backend::Backend;
+    self.window_backend.get_framebuffer_dimensions()
+  }
 
-    video_subsystem.gl_release_current_context().unwrap();
+  /// Set the regions of the frame that changed, to be passed to the window
+  /// system with the next buffer swap.
+  ///
+  /// Rectangles are in pixels with the origin at the bottom left, as used by
+  /// GL. Compositors can then recompose only the damaged regions. This is
+  /// only effective on EGL with `EGL_KHR_swap_buffers_with_damage` or
+  /// `EGL_EXT_swap_buffers_with_damage` (e.g. on Wayland); otherwise the
+  /// whole frame is swapped as usual. Note that on Wayland the swap then
+  /// bypasses SDL's own swap interval handling. See `DamageTracker`.
+  pub fn set_swap_damage (&self, rects : &[glium::Rect]) {
+    self.render_thread.debug_assert_current();
+    *self.window_backend.swap_damage.borrow_mut() = Some (rects.to_vec());
+  }
 
-    Ok (window_backend)
+  /// Set whether `GL_FRAMEBUFFER_SRGB` is enabled when drawing to the
+  /// default framebuffer.
+  ///
+  /// When enabled, linear color values output by shaders are gamma-encoded
+  /// when written to an sRGB-capable framebuffer; disable it when drawing
+  /// content that is already gamma-encoded. The setting is applied by each
+  /// call to `draw`, so it can be changed between frames. `None` (the
+  /// default) leaves the state to glium.
+  ///
+  /// Note that glium does not track this state, and may still change it for
+  /// draw calls depending on the sRGB outputs declared by programs.
+  pub fn set_framebuffer_srgb (&self, enabled : Option <bool>) {
+    self.render_thread.debug_assert_current();
+    self.state.framebuffer_srgb.set (enabled)
   }
-}
 
-impl From <sdl2::video::WindowBuildError> for BackendBuildError {
-  fn from (err : sdl2::video::WindowBuildError) -> Self {
-    BackendBuildError::WindowBuildError (err)
+  pub fn framebuffer_srgb (&self) -> Option <bool> {
+    self.render_thread.debug_assert_current();
+    self.state.framebuffer_srgb.get()
   }
-}
 
-impl From <String> for BackendBuildError {
-  fn from (err : String) -> Self {
-    BackendBuildError::ContextCreationError (err)
+  /// Set the swap interval of the context, e.g. to toggle vsync from a
+  /// settings menu.
+  ///
+  /// Fails with the SDL error if the driver does not support the interval,
+  /// which is common for `LateSwapTearing` (adaptive vsync); the interval is
+  /// then left unchanged.
+  pub fn set_swap_interval (&self, interval : sdl2::video::SwapInterval)
+    -> Result <(), String>
+  {
+    self.render_thread.debug_assert_current();
+    self.window_backend.gl_set_swap_interval (interval as i32)
   }
-}
 
-impl SdlWindowImpostor {
-  fn new (window_context_impostor : SdlWindowContextImpostor) -> Self {
-    SdlWindowImpostor {
-      window_context_impostor: std::rc::Rc::new (window_context_impostor)
+  /// The swap interval of the context.
+  ///
+  /// Intervals greater than one, which some drivers report, are returned as
+  /// `VSync`.
+  pub fn get_swap_interval (&self) -> sdl2::video::SwapInterval {
+    use sdl2::video::SwapInterval;
+    self.render_thread.debug_assert_current();
+    match self.window_backend.gl_get_swap_interval() {
+      0 => SwapInterval::Immediate,
+      interval if interval < 0 => SwapInterval::LateSwapTearing,
+      _ => SwapInterval::VSync
     }
   }
-}
 
-impl SdlWindowContextImpostor {
-  fn new (window_raw : *mut sdl2_sys::SDL_Window) -> Self {
-    SdlWindowContextImpostor {
-      _video_subsystem: std::rc::Rc::new (()),
-      _window_raw:      window_raw
+  /// Start reading back every presented frame into a ring of `buffers`
+  /// pixel buffers, delivering them to the returned receiver.
+  ///
+  /// Frame dimensions are divided by `downscale` (1 for full size). Reading
+  /// back never blocks the render thread; frames are dropped instead when the
+  /// GPU or the consumer falls behind. Any previous readback is stopped.
+  pub fn start_readback (&self, buffers : usize, downscale : u32)
+    -> ReadbackReceiver
+  {
+    self.render_thread.debug_assert_current();
+    self.stop_readback();
+    let (ring, receiver)
+      = readback::ReadbackRing::new (&self.state.gl, buffers, downscale);
+    *self.state.inspection.readback.borrow_mut() = Some (ring);
+    receiver
+  }
+
+  /// Stop reading back frames; the receiver is disconnected.
+  ///
+  /// Readback is also stopped automatically when the receiver is dropped.
+  pub fn stop_readback (&self) {
+    self.render_thread.debug_assert_current();
+    if let Some (ring) = self.state.inspection.readback.borrow_mut().take() {
+      ring.delete (&self.state.gl);
     }
   }
-}
 
-#[cfg(test)]
-mod test {
-  use super::*;
-  /// TODO: check offset of transmuted values ?
-  #[test]
-  fn test() {
-    assert_eq!(
-      std::mem::size_of::<sdl2::video::Window>(),
-      std::mem::size_of::<SdlWindowImpostor>());
-    assert_eq!(
-      std::mem::size_of::<sdl2::video::WindowContext>(),
+  /// Start recording every presented frame to `sink`, reading frames back
+  /// through a ring of `buffers` pixel buffers; see `recorder`.
+  ///
+  /// Recording uses the readback ring of the display, so it stops any
+  /// readback started with `start_readback` and is stopped by
+  /// `stop_readback`.
+  pub fn start_recording (
+    &self,
+    sink    : RecordingSink,
+    format  : RecordingFormat,
+    buffers : usize
+  ) -> std::io::Result <FrameRecorder> {
+    self.render_thread.debug_assert_current();
+    let receiver = self.start_readback (buffers, 1);
+    FrameRecorder::new (receiver, sink, format)
+  }
+
+  /// Stop recording and wait for the recorder to close its sink.
+  pub fn stop_recording (&self, recorder : FrameRecorder)
+    -> std::io::Result <RecordingStats>
+  {
+    self.render_thread.debug_assert_current();
+    self.stop_readback();
+    recorder.finish()
+  }
+
+  /// Read the frame last presented as an image, e.g. for a screenshot.
+  ///
+  /// This is to be called after the frame is finished: it reads the front
+  /// buffer synchronously, stalling until rendering has completed, and
+  /// flips the rows so that the image is top to bottom. The image can be
+  /// saved with `Image::to_pam`. Where the window system does not keep the
+  /// front buffer, such as with some compositors, the image may be blank;
+  /// `start_readback` reads frames before they are swapped instead.
+  pub fn capture_frame (&self) -> Image {
+    self.render_thread.debug_assert_current();
+    let (width, height) = self.framebuffer_dimensions();
+    let pixels = readback::read_front_buffer (&self.state.gl, (width, height));
+    Image::from_bottom_up (width, height, pixels)
+  }
+
+  /// Start reading back the frame presented by the next buffer swap,
+  /// returning a handle resolved once the copy has completed.
+  ///
+  /// Called while drawing a frame, this captures that frame. Unlike
+  /// `capture_frame` this never stalls the render thread: the back buffer is
+  /// copied into a pixel buffer before the swap and the pixels are mapped
+  /// once the GPU has finished the copy, typically a few frames later.
+  /// Screenshots are only delivered while frames are being swapped.
+  pub fn capture_frame_async (&self) -> PendingCapture {
+    self.render_thread.debug_assert_current();
+    self.state.inspection.captures.borrow_mut().request()
+  }
+
+  /// Capture the next frame presented with RenderDoc, returning false if
+  /// the application is not running under RenderDoc; see `renderdoc`.
+  pub fn trigger_capture (&self) -> bool {
+    self.render_thread.debug_assert_current();
+    renderdoc::trigger_capture()
+  }
+
+  /// Install a frame analysis that checks the back buffer before each
+  /// buffer swap, replacing any previous analysis.
+  ///
+  /// Reading back the frame stalls the render thread, so this is intended for
+  /// tests. Passing `None` removes the analysis.
+  pub fn set_frame_analysis (&self, analysis : Option <FrameAnalysis>) {
+    self.render_thread.debug_assert_current();
+    *self.state.inspection.analysis.borrow_mut() = analysis;
+  }
+
+  /// Remove the frame analysis, returning it with its results.
+  pub fn take_frame_analysis (&self) -> Option <FrameAnalysis> {
+    self.render_thread.debug_assert_current();
+    self.state.inspection.analysis.borrow_mut().take()
+  }
+
+  /// Frame rate and frame time statistics over the recent frames, see
+  /// `stats`.
+  pub fn stats (&self) -> FrameStats {
+    self.render_thread.debug_assert_current();
+    self.state.timing.stats.borrow().stats()
+  }
+
+  /// Cap the frame rate by waiting after each buffer swap, replacing any
+  /// previous pacer; see `pacer`. Passing `None` removes the pacer.
+  pub fn set_frame_pacer (&self, pacer : Option <FramePacer>) {
+    self.render_thread.debug_assert_current();
+    *self.state.timing.pacer.borrow_mut() = pacer;
+  }
+
+  /// Remove the frame pacer, returning it.
+  pub fn take_frame_pacer (&self) -> Option <FramePacer> {
+    self.render_thread.debug_assert_current();
+    self.state.timing.pacer.borrow_mut().take()
+  }
+
+  /// Load the programs built with `build_program` from the binaries in a
+  /// shader cache, storing the binaries of programs compiled from source,
+  /// replacing any previous cache; see `shaders`. Passing `None` removes the
+  /// cache.
+  pub fn set_shader_cache (&self, cache : Option <ShaderCache>) {
+    self.render_thread.debug_assert_current();
+    *self.state.resources.shader_cache.borrow_mut() = cache;
+  }
+
+  /// Remove the shader cache, returning it.
+  pub fn take_shader_cache (&self) -> Option <ShaderCache> {
+    self.render_thread.debug_assert_current();
+    self.state.resources.shader_cache.borrow_mut().take()
+  }
+
+  /// Build a program from the shader cache if one is set, or compile it from
+  /// source.
+  pub fn build_program (
+    &self,
+    vertex   : &str,
+    fragment : &str,
+    geometry : Option <&str>
+  ) -> Result <glium::Program, glium::ProgramCreationError> {
+    self.render_thread.debug_assert_current();
+    match *self.state.resources.shader_cache.borrow_mut() {
+      Some (ref mut cache) =>
+        cache.program (&self.glium_context, vertex, fragment, geometry),
+      None => glium::Program::from_source (
+        &self.glium_context, vertex, fragment, geometry)
+    }
+  }
+
+  /// A queue for uploading textures decoded on other threads; see `upload`.
+  pub fn texture_upload_queue (&self) -> TextureUploadQueue {
+    self.render_thread.debug_assert_current();
+    self.state.resources.uploads.borrow().queue()
+  }
+
+  /// Bound the time spent uploading queued textures in each `draw`. Passing
+  /// `None` uploads all queued textures.
+  pub fn set_texture_upload_budget (&self,
+    budget : Option <std::time::Duration>
+  ) {
+    self.render_thread.debug_assert_current();
+    self.state.resources.uploads.borrow_mut().set_budget (budget)
+  }
+
+  pub fn texture_upload_budget (&self) -> Option <std::time::Duration> {
+    self.render_thread.debug_assert_current();
+    self.state.resources.uploads.borrow().budget()
+  }
+
+  /// Take the texture of an upload handle, or `None` if the texture has not
+  /// been uploaded yet or was already taken.
+  pub fn take_uploaded_texture (&self, handle : &TextureHandle) -> Option <
+    Result <glium::texture::Texture2d, glium::texture::TextureCreationError>
+  > {
+    self.render_thread.debug_assert_current();
+    self.state.resources.uploads.borrow_mut().take (handle)
+  }
+
+  /// Create a texture with the pixels of an SDL surface; see `surface`.
+  pub fn texture_from_surface (&self, surface : &sdl2::surface::Surface)
+    -> Result <glium::texture::Texture2d, SurfaceError>
+  {
+    self.render_thread.debug_assert_current();
+    surface::texture_from_surface (&self.glium_context, surface)
+  }
+
+  /// Decode an image file with SDL_image and create a texture with its
+  /// pixels; see `image`.
+  #[cfg(feature = "image")]
+  pub fn load_texture <P : AsRef <std::path::Path>> (&self, path : P)
+    -> Result <glium::texture::Texture2d, SurfaceError>
+  {
+    self.render_thread.debug_assert_current();
+    image::load_texture (&self.glium_context, path)
+  }
+
+  /// Read back the pixels of a texture into a new SDL surface; see
+  /// `surface`.
+  pub fn surface_from_texture (&self, texture : &glium::texture::Texture2d)
+    -> Result <sdl2::surface::Surface <'static>, SurfaceError>
+  {
+    self.render_thread.debug_assert_current();
+    surface::surface_from_texture (texture)
+  }
+
+  /// Bound the number of frames queued by the driver, with `draw` waiting
+  /// for the GPU as needed; see `latency`. Passing `None` removes the bound.
+  pub fn set_max_frames_in_flight (&self, max : Option <usize>) {
+    self.render_thread.debug_assert_current();
+    self.state.timing.set_max_frames_in_flight (&self.state.gl, max);
+  }
+
+  pub fn max_frames_in_flight (&self) -> Option <usize> {
+    self.render_thread.debug_assert_current();
+    self.state.timing.frame_fences.borrow().as_ref()
+      .map (|fences| fences.max())
+  }
+
+  /// Start a present thread presenting frames drawn with the returned
+  /// presenter into a ring of `buffers` textures, at least 2; see `present`.
+  pub fn start_present_thread (&self, present_context : PresentContext,
+    buffers : usize
+  ) -> Result <Presenter, String> {
+    self.render_thread.debug_assert_current();
+    Presenter::new (self, present_context, buffers)
+  }
+
+  /// Start measuring the GPU time of a scope, which ends when the returned
+  /// timer is dropped; see `profiler`.
+  pub fn gpu_timer (&self, name : &str) -> GpuTimer <'_> {
+    self.render_thread.debug_assert_current();
+    GpuTimer::new (&self.state.timing.profiler, &self.state.gl, name)
+  }
+
+  /// Add a hook run on the render thread immediately before each buffer
+  /// swap; see `hooks`.
+  pub fn add_pre_swap_hook <F> (&self, hook : F) -> SwapHookId where
+    F : FnMut (&SwapInfo) + 'static
+  {
+    self.render_thread.debug_assert_current();
+    self.state.swap_hooks.borrow_mut()
+      .add (hooks::SwapStage::Pre, Box::new (hook))
+  }
+
+  /// Add a hook run on the render thread immediately after each buffer
+  /// swap; see `hooks`.
+  pub fn add_post_swap_hook <F> (&self, hook : F) -> SwapHookId where
+    F : FnMut (&SwapInfo) + 'static
+  {
+    self.render_thread.debug_assert_current();
+    self.state.swap_hooks.borrow_mut()
+      .add (hooks::SwapStage::Post, Box::new (hook))
+  }
+
+  /// Remove a swap hook, returning false if it was already removed.
+  ///
+  /// A hook may remove itself while running.
+  pub fn remove_swap_hook (&self, id : SwapHookId) -> bool {
+    self.render_thread.debug_assert_current();
+    self.state.swap_hooks.borrow_mut().remove (id)
+  }
+
+  /// Take the GPU timings collected so far, oldest first.
+  pub fn take_gpu_timings (&self) -> Vec <GpuTiming> {
+    self.render_thread.debug_assert_current();
+    self.state.timing.profiler.borrow_mut().take_timings()
+  }
+
+  /// Returns false if timer queries are not supported, in which case GPU
+  /// timers record nothing.
+  pub fn gpu_timers_supported (&self) -> bool {
+    self.render_thread.debug_assert_current();
+    self.state.timing.profiler.borrow().is_supported()
+  }
+
+  /// Enable or disable checking for GL errors when each frame is finished,
+  /// see `diagnostics`. Enabling resets the totals.
+  pub fn set_frame_diagnostics (&self, enabled : bool) {
+    self.render_thread.debug_assert_current();
+    *self.state.inspection.diagnostics.borrow_mut() = if enabled {
+      Some (FrameDiagnostics::default())
+    } else {
+      None
+    };
+  }
+
+  /// GL errors raised by the last finished frame, or `None` if frame
+  /// diagnostics are disabled.
+  pub fn frame_diagnostics (&self) -> Option <FrameDiagnostics> {
+    self.render_thread.debug_assert_current();
+    self.state.inspection.diagnostics.borrow().clone()
+  }
+
+  /// Release the context from the calling (render) thread and lend it to
+  /// another thread until the returned loan is dropped; see `lend`.
+  ///
+  /// If the context is already lent, this first waits for it to be returned.
+  pub fn lend_context (&self) -> ContextLoan {
+    self.render_thread.debug_assert_current();
+    // the context is lent again at once, so it need not be made current
+    if let Some (loan_return) = self.state.loan.borrow_mut().take() {
+      loan_return.wait();
+    }
+    let (loan, loan_return) = ContextLoan::new (&self.window_backend);
+    *self.state.loan.borrow_mut() = Some (loan_return);
+    loan
+  }
+
+  /// If the context is lent, block until it is returned and make it current
+  /// again, returning true if it was lent, or the error if it could not be
+  /// made current.
+  ///
+  /// This is called by `draw`.
+  pub fn reclaim_context (&self) -> Result <bool, String> {
+    self.render_thread.debug_assert_current();
+    match self.state.loan.borrow_mut().take() {
+      Some (loan_return) => {
+        try!{ loan_return.reclaim (&self.window_backend) };
+        Ok (true)
+      }
+      None => Ok (false)
+    }
+  }
+
+  /// Returns true if the context has been reset, e.g. after a GPU hang or
+  /// driver update, or could not be made current, in which case the display
+  /// must be rebuilt with `rebuild`.
+  ///
+  /// Resets are only reported for contexts built with `BackendConfig::robust`
+  /// where a robustness extension is supported. Both are also reported to
+  /// glium as context loss when swapping buffers.
+  pub fn check_reset (&self) -> bool {
+    self.render_thread.debug_assert_current();
+    self.window_backend.context_lost.get()
+      || self.window_backend.gl_context_reset()
+  }
+
+  /// Replace the GL context with a new one on the same window and build glium
+  /// on it again, e.g. after a reset.
+  ///
+  /// All GL objects are lost and must be created again; they must have been
+  /// dropped along with every other clone of the display before calling this.
+  /// The pause and redraw handles of the display are kept. Note that creating
+  /// the context on the render thread is not supported by all platforms
+  /// (notably macOS).
+  pub fn rebuild (self, config : GliumBuildConfig)
+    -> Result <SdlGliumDisplayFacade, RebuildError>
+  {
+    self.render_thread.debug_assert_current();
+    let pause  = self.pause.clone();
+    let redraw = self.redraw.clone();
+    let mut window_backend = try!{
+      self.into_window_backend().ok_or (RebuildError::DisplayInUse)
+    };
+    try!{
+      window_backend.recreate_context()
+        .map_err (RebuildError::ContextCreationError)
+    };
+    let mut display = try!{
+      window_backend.build_glium_with (config)
+        .map_err (RebuildError::IncompatibleOpenGl)
+    };
+    display.pause  = pause;
+    display.redraw = redraw;
+    Ok (display)
+  }
+
+  /// Start drawing on the backbuffer.
+  ///
+  /// This function returns a `Frame`, which can be used to draw on it.  When
+  /// the `Frame` is destroyed, the buffers are swapped.
+  ///
+  /// Note that destroying a `Frame` is immediate, even if vsync is enabled.
+  ///
+  /// If the context was lent and cannot be made current again, it is marked
+  /// as lost: drawing has no effect and finishing the frame fails with
+  /// `SwapBuffersError::ContextLost`. Call `reclaim_context` first to get the
+  /// error.
+  pub fn draw (&self) -> glium::Frame {
+    self.render_thread.debug_assert_current();
+    use glium::backend::Backend;
+    if self.reclaim_context().is_err() {
+      self.window_backend.context_lost.set (true);
+    }
+    self.state.before_frame (&self.glium_context);
+    glium::Frame::new (
+      self.glium_context.clone(),
+      self.window_backend.get_framebuffer_dimensions())
+  }
+
+  /// Drop the glium context and return the window backend, or `None` if
+  /// other references to the display or its GL objects remain.
+  ///
+  /// GL objects held by the facade are deleted, so this should be called on
+  /// the thread where the context is current.
+  pub(crate) fn into_window_backend (self) -> Option <SdlGlWindowBackend> {
+    let SdlGliumDisplayFacade { glium_context, state, window_backend, .. }
+      = self;
+    // the glium context holds a reference to the window backend
+    drop (glium_context);
+    drop (state);
+    std::rc::Rc::try_unwrap (window_backend).ok()
+  }
+
+  /// The window transmuted from the shared impostor.
+  #[cfg(feature = "window-impostor")]
+  fn sdl_window (&self) -> *mut sdl2::video::Window {
+    self.sdl_window_impostor.window.get() as *mut sdl2::video::Window
+  }
+
+  /// The window of the backend.
+  ///
+  /// Panics for a mock.
+  #[cfg(not(feature = "window-impostor"))]
+  fn sdl_window (&self) -> *mut sdl2::video::Window {
+    let sdl_window = self.window_backend.sdl_window.as_ref()
+      .expect ("mock backends have no window");
+    unsafe { &mut **sdl_window.get() }
+  }
+}
+
+#[cfg(feature = "glium")]
+impl Clone for SdlGliumDisplayFacade {
+  fn clone (&self) -> Self {
+    SdlGliumDisplayFacade {
+      glium_context:       self.glium_context.clone(),
+      state:               self.state.clone(),
+      #[cfg(feature = "window-impostor")]
+      sdl_window_impostor: self.sdl_window_impostor.clone(),
+      window_backend:      self.window_backend.clone(),
+      pause:               self.pause.clone(),
+      redraw:              self.redraw.clone(),
+      render_thread:       self.render_thread,
+      _leak_token:         leak::LeakToken::new (leak::HandleKind::Display)
+    }
+  }
+}
+
+impl SdlGlWindowBackend {
+  /// SDL window ID of the underlying window.
+  ///
+  /// This can be used to route commands and events for this window on the
+  /// main thread after the backend has been sent to another thread.
+  pub fn window_id (&self) -> u32 {
+    if_mock!(self, |_| 0);
+    unsafe { sdl2_sys::SDL_GetWindowID (self.window_raw.as_ptr()) }
+  }
+
+  /// The thread the backend was built on.
+  pub fn main_thread (&self) -> MainThreadToken {
+    self.main_thread
+  }
+
+  /// Samples per pixel the window and context were created with after
+  /// falling back from the count requested in `BackendConfig`, where 0 means
+  /// no multisampling; `None` if multisampling was not requested.
+  pub fn multisample (&self) -> Option <u8> {
+    self.multisample
+  }
+
+  /// The attributes of the context as obtained.
+  ///
+  /// The version and profile are queried from GL, since SDL reports those
+  /// that were requested; the other attributes are read back with
+  /// `SDL_GL_GetAttribute`. The context must be current on the calling
+  /// thread. For a mock backend the framebuffer attributes are zero.
+  pub fn context_info (&self) -> Result <ContextInfo, String> {
+    use sdl2_sys::SDL_GLattr::{SDL_GL_DEPTH_SIZE, SDL_GL_STENCIL_SIZE,
+      SDL_GL_MULTISAMPLESAMPLES, SDL_GL_FRAMEBUFFER_SRGB_CAPABLE};
+    type GetIntegerv = unsafe extern "system" fn (u32, *mut i32);
+    let get_integer_v = self.gl_get_proc_address ("glGetIntegerv");
+    if get_integer_v.is_null() {
+      return Err ("failed to load glGetIntegerv".to_string())
+    }
+    let get_integer_v : GetIntegerv
+      = unsafe { std::mem::transmute (get_integer_v) };
+    let version = try!{ self.gl_string (GL_VERSION) };
+    let (major_version, minor_version, es) = try!{
+      parse_gl_version (&version)
+        .ok_or_else (|| format!("invalid GL version: {}", version))
+    };
+    let profile = if es {
+      sdl2::video::GLProfile::GLES
+    } else if (major_version, minor_version) >= (3, 2) {
+      let mut mask = 0;
+      unsafe { get_integer_v (GL_CONTEXT_PROFILE_MASK, &mut mask) };
+      if mask & GL_CONTEXT_CORE_PROFILE_BIT != 0 {
+        sdl2::video::GLProfile::Core
+      } else {
+        sdl2::video::GLProfile::Compatibility
+      }
+    } else {
+      sdl2::video::GLProfile::Compatibility
+    };
+    Ok (ContextInfo {
+      major_version,
+      minor_version,
+      profile,
+      depth_bits:   try!{ self.gl_attribute (SDL_GL_DEPTH_SIZE) } as u8,
+      stencil_bits: try!{ self.gl_attribute (SDL_GL_STENCIL_SIZE) } as u8,
+      samples:      try!{ self.gl_attribute (SDL_GL_MULTISAMPLESAMPLES) } as u8,
+      srgb_capable:
+        try!{ self.gl_attribute (SDL_GL_FRAMEBUFFER_SRGB_CAPABLE) } != 0
+    })
+  }
+
+  /// The version the context was created for, the first one that succeeded
+  /// of `BackendConfig::context_versions`; `None` if no versions were given.
+  ///
+  /// See `context_info` for the version actually obtained, which may be
+  /// higher.
+  pub fn context_version (&self) -> Option <ContextVersion> {
+    self.version
+  }
+
+  /// Take the receiver of the debug output of a backend built with
+  /// `BackendConfig::debug`; see `debug`.
+  pub fn take_debug_receiver (&mut self)
+    -> Option <std::sync::mpsc::Receiver <GlDebugMessage>>
+  {
+    self.debug_receiver.take()
+  }
+
+  /// Whether an sRGB-capable framebuffer was requested in `BackendConfig`.
+  ///
+  /// Drivers may ignore the request. Encoding is enabled by glium as set by
+  /// `GliumBuildConfig::framebuffer_srgb`.
+  pub fn srgb (&self) -> bool {
+    self.srgb
+  }
+
+  /// Safe queries and changes on the window, e.g. from the render thread,
+  /// if attached with `CommandExecutor::attach`; see `proxy`.
+  pub fn window_proxy (&self) -> Option <&WindowProxy> {
+    self.proxy.as_ref()
+  }
+
+  /// Whether the context was created robust, see `BackendConfig`.
+  pub fn is_robust (&self) -> bool {
+    self.robust
+  }
+
+  /// Token for shutting down the render loop of this window, to be taken on
+  /// the main thread before sending the backend to the render thread.
+  pub fn shutdown_handle (&self) -> ShutdownToken {
+    self.exit_signal.token()
+  }
+
+  /// Create a window backend around a mock backend, for testing without SDL
+  /// or GL.
+  ///
+  /// The window ID of a mock is 0, which is never a valid SDL window ID. See
+  /// `mock` for the functions that can be used with the resulting display.
+  #[cfg(feature = "glium")]
+  pub fn mock (mock : MockGlBackend) -> Self {
+    #[cfg(feature = "glium-hack")]
+    let gl_funs = {
+      use glium::backend::Backend;
+      glium::gl::Gl::load_with (|symbol| unsafe {
+        mock.get_proc_address (symbol) as *const _
+      })
+    };
+    let (window_raw, gl_context_raw)
+      = (std::ptr::NonNull::dangling(), std::ptr::NonNull::dangling());
+    SdlGlWindowBackend {
+      window_raw,
+      gl_context_raw,
+      owns_window:    false,
+      main_thread:    affinity::MainThreadToken::capture(),
+      robust:         false,
+      multisample:    None,
+      srgb:           false,
+      version:        None,
+      debug_output:   None,
+      debug_receiver: None,
+      reaper:         None,
+      proxy:          None,
+      #[cfg(feature = "glium-hack")]
+      gl_funs:        Some (Box::new (gl_funs)),
+      native:         native::NativeGl::unsupported(),
+      size_cache:     None,
+      #[cfg(not(feature = "window-impostor"))]
+      sdl_window:     None,
+      exit_signal:    shutdown::ExitSignal::new(),
+      swap_damage:    std::cell::RefCell::new (None),
+      context_lost:   std::cell::Cell::new (false),
+      context_probe:  DropProbe::new(),
+      state_probe:    DropProbe::new(),
+      mock:           Some (mock),
+      _leak_token:    leak::LeakToken::new (leak::HandleKind::WindowBackend)
+    }
+  }
+
+  /// Make the context current on the calling thread.
+  ///
+  /// This is only needed when not using glium, which makes the context
+  /// current itself.
+  ///
+  /// # Safety
+  ///
+  /// The context must not be current on any other thread.
+  pub unsafe fn gl_make_current (&self) -> Result <(), String> {
+    if_mock!(self, |mock| {
+      glium::backend::Backend::make_current (mock);
+      Ok (())
+    });
+    if 0 == sdl2_sys::SDL_GL_MakeCurrent (
+      self.window_raw.as_ptr(), self.gl_context_raw.as_ptr()
+    ) {
+      Ok (())
+    } else {
+      Err (sdl2::get_error())
+    }
+  }
+
+  /// Make the context current on the calling thread, retrying with
+  /// exponential backoff if it fails, e.g. while the window system is
+  /// briefly unavailable. Returns the last error if every attempt failed.
+  ///
+  /// # Safety
+  ///
+  /// The context must not be current on any other thread.
+  pub unsafe fn gl_make_current_retry (&self) -> Result <(), String> {
+    let _zone = tracy_zone!("make_current");
+    let result = retry_with_backoff (|| {
+      let result = self.gl_make_current();
+      if let Err (ref err) = result {
+        log_record!(Warn, "making the context of window {} current failed: {}",
+          self.window_id(), err);
+      }
+      result
+    });
+    if let Err (ref err) = result {
+      log_record!(Error,
+        "making the context of window {} current failed {} times: {}",
+        self.window_id(), MAKE_CURRENT_ATTEMPTS, err);
+    }
+    result
+  }
+
+  /// Release the context from the calling thread.
+  pub fn gl_release_current (&self) {
+    if_mock!(self, |mock| mock.release_current());
+    unsafe {
+      sdl2_sys::SDL_GL_MakeCurrent (
+        self.window_raw.as_ptr(), std::ptr::null_mut());
+    }
+  }
+
+  /// Whether the context is current on the calling thread with the window of
+  /// the backend.
+  ///
+  /// The window is compared as well as the context, since a context shared
+  /// between windows may be current with another window.
+  pub fn gl_is_current (&self) -> bool {
+    if_mock!(self, |mock| glium::backend::Backend::is_current (mock));
+    unsafe {
+      self.gl_context_raw.as_ptr() == sdl2_sys::SDL_GL_GetCurrentContext()
+        && self.window_raw.as_ptr() == sdl2_sys::SDL_GL_GetCurrentWindow()
+    }
+  }
+
+  /// Swap the buffers of the window, returning the SDL error if the swap
+  /// failed.
+  pub fn gl_swap_window (&self) -> Result <(), String> {
+    if_mock!(self, |mock| glium::backend::Backend::swap_buffers (mock)
+      .map_err (|err| format!("{:?}", err)));
+    // the error message is thread-local
+    unsafe {
+      sdl2_sys::SDL_ClearError();
+      sdl2_sys::SDL_GL_SwapWindow (self.window_raw.as_ptr());
+    }
+    let error = sdl2::get_error();
+    if error.is_empty() {
+      Ok (())
+    } else {
+      Err (error)
+    }
+  }
+
+  /// Whether the context has been reset, e.g. after a GPU hang, since it was
+  /// created.
+  ///
+  /// Returns false if `GL_KHR_robustness`, `GL_ARB_robustness` or
+  /// `GL_EXT_robustness` is not supported. The context must be current on
+  /// the calling thread.
+  pub fn gl_context_reset (&self) -> bool {
+    self.native.context_reset()
+  }
+
+  /// Address of a GL function, or null if the name is not valid.
+  ///
+  /// Function pointers are only valid while the context is current. This can
+  /// be used to load any GL wrapper on the render thread, e.g. with the `gl`
+  /// crate:
+  ///
+  /// ```ignore
+  /// unsafe { window_backend.gl_make_current().unwrap() };
+  /// gl::load_with (|symbol| window_backend.gl_get_proc_address (symbol));
+  /// ```
+  pub fn gl_get_proc_address (&self, symbol : &str)
+    -> *const std::os::raw::c_void
+  {
+    if_mock!(self, |mock| unsafe {
+      glium::backend::Backend::get_proc_address (mock, symbol)
+    });
+    match std::ffi::CString::new (symbol) {
+      Ok (symbol) => unsafe {
+        sdl2_sys::SDL_GL_GetProcAddress (
+          symbol.as_ptr() as *const std::os::raw::c_char
+        ) as *const std::os::raw::c_void
+      },
+      Err (_) => std::ptr::null()
+    }
+  }
+
+  /// Create a GL context sharing objects with the context of this backend,
+  /// for uploading resources on a loader thread; see `loader`.
+  ///
+  /// The context of this backend is made current on the calling thread while
+  /// the loader context is created, then both are released, so this should
+  /// be called on the main thread before the backend is sent to the render
+  /// thread.
+  ///
+  /// # Safety
+  ///
+  /// The context of this backend must not be current on any other thread, and
+  /// the loader context must be dropped before the window is destroyed.
+  #[cfg(feature = "glium")]
+  pub unsafe fn build_shared_loader_context (&self)
+    -> Result <SharedLoaderContext, String>
+  {
+    if_mock!(self, |_| Err ("loader contexts need a window".to_string()));
+    SharedLoaderContext::new (self)
+  }
+
+  /// Create a GL context sharing objects with the context of this backend,
+  /// for presenting frames from a present thread; see `present`.
+  ///
+  /// As with `build_shared_loader_context`, this should be called on the
+  /// main thread before the backend is sent to the render thread.
+  ///
+  /// # Safety
+  ///
+  /// The context of this backend must not be current on any other thread, and
+  /// the present context must be dropped before the window is destroyed.
+  #[cfg(feature = "glium")]
+  pub unsafe fn build_present_context (&self)
+    -> Result <PresentContext, String>
+  {
+    if_mock!(self, |_| Err ("present contexts need a window".to_string()));
+    PresentContext::new (self)
+  }
+
+  /// Swap the buffers of the window, passing the regions that changed to the
+  /// window system.
+  ///
+  /// Rectangles are `[left, bottom, width, height]` in pixels with the origin
+  /// at the bottom left. Falls back to a full swap where this is not
+  /// supported; see `SdlGliumDisplayFacade::set_swap_damage`.
+  pub fn gl_swap_window_with_damage (&self, rects : &[[i32; 4]])
+    -> Result <(), String>
+  {
+    if self.native.swap_buffers_with_damage (rects) {
+      Ok (())
+    } else {
+      self.gl_swap_window()
+    }
+  }
+
+  /// Set the swap interval of the context: 0 for immediate swaps, 1 to wait
+  /// for vertical retrace, or -1 for adaptive vsync.
+  ///
+  /// The context must be current on the calling thread.
+  pub fn gl_set_swap_interval (&self, interval : i32) -> Result <(), String> {
+    if_mock!(self, |_| Ok (()));
+    if 0 == unsafe { sdl2_sys::SDL_GL_SetSwapInterval (interval) } {
+      Ok (())
+    } else {
+      Err (sdl2::get_error())
+    }
+  }
+
+  /// The swap interval of the context, see `gl_set_swap_interval`.
+  ///
+  /// The context must be current on the calling thread.
+  pub fn gl_get_swap_interval (&self) -> i32 {
+    if_mock!(self, |_| 0);
+    unsafe { sdl2_sys::SDL_GL_GetSwapInterval() }
+  }
+
+  /// Size of the drawable area of the window in pixels.
+  ///
+  /// The size is cached and updated when the main thread handles a size
+  /// change of the window, see `resize`.
+  pub fn drawable_size (&self) -> (u32, u32) {
+    if_mock!(self, |mock|
+      glium::backend::Backend::get_framebuffer_dimensions (mock));
+    if let Some (size_cache) = self.size_cache.as_ref() {
+      return size_cache.get()
+    }
+    let mut width  : std::os::raw::c_int = 0;
+    let mut height : std::os::raw::c_int = 0;
+    unsafe {
+      sdl2_sys::SDL_GL_GetDrawableSize (
+        self.window_raw.as_ptr(), &mut width, &mut height) };
+    (width as u32, height as u32)
+  }
+
+  /// See `SdlGliumDisplayFacade::present_stats`.
+  pub fn present_stats (&self) -> Option <PresentStats> {
+    self.native.present_stats()
+  }
+
+  /// See `SdlGliumDisplayFacade::compositing`.
+  pub fn compositing (&self) -> Option <Compositing> {
+    if !try_opt!(self.native.compositor_running()) {
+      return Some (Compositing::NoCompositor)
+    }
+    let fullscreen = unsafe {
+      sdl2_sys::SDL_GetWindowFlags (self.window_raw.as_ptr())
+    } & sdl2_sys::SDL_WindowFlags::SDL_WINDOW_FULLSCREEN as u32 != 0;
+    if fullscreen && system::compositor_bypass() {
+      Some (Compositing::Bypassed)
+    } else {
+      Some (Compositing::Composited)
+    }
+  }
+
+  /// See `SdlGliumDisplayFacade::buffer_age`.
+  pub fn buffer_age (&self) -> Option <u32> {
+    self.native.buffer_age()
+  }
+
+  /// Build Glium with current context checks and with default debug callback
+  /// behavior.
+  #[cfg(feature = "glium")]
+  pub fn build_glium (self)
+    -> Result <SdlGliumDisplayFacade, glium::IncompatibleOpenGl>
+  {
+    self.build_glium_with (GliumBuildConfig::default())
+  }
+
+  /// Build Glium without current context checks and with default debug
+  /// callback behavior.
+  #[cfg(feature = "glium")]
+  pub fn build_glium_unchecked (self)
+    -> Result <SdlGliumDisplayFacade, glium::IncompatibleOpenGl>
+  {
+    self.build_glium_with (GliumBuildConfig {
+      checked: false,
+      .. GliumBuildConfig::default()
+    })
+  }
+
+  /// Build Glium with current context checks and with the given debug callback
+  /// behavior.
+  #[cfg(feature = "glium")]
+  pub fn build_glium_debug (self, debug : glium::debug::DebugCallbackBehavior)
+    -> Result <SdlGliumDisplayFacade, glium::IncompatibleOpenGl>
+  {
+    self.build_glium_with (GliumBuildConfig {
+      debug,
+      .. GliumBuildConfig::default()
+    })
+  }
+
+  /// Build Glium without current context checks and with the given debug
+  /// callback behavior.
+  #[cfg(feature = "glium")]
+  pub fn build_glium_unchecked_debug (self,
+    debug : glium::debug::DebugCallbackBehavior
+  ) -> Result <SdlGliumDisplayFacade, glium::IncompatibleOpenGl> {
+    self.build_glium_with (GliumBuildConfig {
+      checked: false,
+      debug,
+      .. GliumBuildConfig::default()
+    })
+  }
+
+  /// Build Glium with the given configuration.
+  #[cfg(feature = "glium")]
+  #[cfg_attr(not(feature = "glium-hack"), allow(unused_mut))]
+  pub fn build_glium_with (mut self, config : GliumBuildConfig)
+    -> Result <SdlGliumDisplayFacade, glium::IncompatibleOpenGl>
+  {
+    let _span = trace_span!(INFO, "build_glium", window_id = self.window_id());
+    #[cfg(feature = "glium-hack")]
+    let gl_funs = self.gl_funs.take().unwrap();
+    #[cfg(feature = "window-impostor")]
+    let sdl_window_impostor = std::rc::Rc::new (SharedImpostor::new (
+      SdlWindowContextImpostor::new (self.window_raw.as_ptr())));
+    let window_backend = std::rc::Rc::new (self);
+    let state = std::rc::Rc::new (FacadeState::new (&window_backend));
+    let render_backend = RenderBackend {
+      state:          state.clone(),
+      window_backend: window_backend.clone(),
+      _leak_token:    leak::LeakToken::new (leak::HandleKind::GliumBackend)
+    };
+    #[cfg(feature = "glium-hack")]
+    let glium_context = try!{
+      unsafe {
+        glium::backend::Context::new_hack (
+          render_backend, *gl_funs, config.checked, config.debug)
+      }
+    };
+    // glium makes the context current on this thread before loading the GL
+    // functions
+    #[cfg(not(feature = "glium-hack"))]
+    let glium_context = try!{
+      unsafe {
+        glium::backend::Context::new (
+          render_backend, config.checked, config.debug)
+      }
+    };
+    log_record!(Info, "glium built for window {} on thread {}",
+      window_backend.window_id(),
+      std::thread::current().name().unwrap_or ("<unnamed>"));
+    window_backend.context_probe.set (&glium_context);
+    window_backend.state_probe.set (&state);
+    // replace the debug callback installed by glium
+    let _ = window_backend.install_debug_output();
+    // the context is current after building glium; drivers may not support
+    // changing the swap interval, in which case it is left as is
+    if let Some (vsync) = config.vsync {
+      let _ = window_backend.gl_set_swap_interval (vsync as i32);
+    }
+    state.framebuffer_srgb.set (config.framebuffer_srgb
+      .or (if window_backend.srgb { Some (true) } else { None }));
+    state.timing.set_max_frames_in_flight (
+      &state.gl, config.max_frames_in_flight);
+    Ok (SdlGliumDisplayFacade {
+      glium_context,
+      state,
+      #[cfg(feature = "window-impostor")]
+      sdl_window_impostor,
+      window_backend,
+      pause:         PauseHandle::new(),
+      redraw:        RedrawHandle::new(),
+      render_thread: affinity::RenderThreadToken::capture(),
+      _leak_token:   leak::LeakToken::new (leak::HandleKind::Display)
+    })
+  }
+
+  /// Create a window from the builder, to be destroyed by the backend.
+  ///
+  /// With the `sdl2-hack` feature the window is created by the forked
+  /// `WindowBuilder::build_hack`, which returns the raw window, and wrapped
+  /// with `Window::from_ll`. Otherwise it is created by upstream
+  /// `WindowBuilder::build`. The returned `Window` must not be dropped since
+  /// it would destroy the window; the video subsystem reference it holds is
+  /// leaked with it, so the video subsystem stays initialized until SDL is
+  /// quit.
+  fn create_window (builder : &sdl2::video::WindowBuilder) -> Result <
+    std::mem::ManuallyDrop <sdl2::video::Window>,
+    sdl2::video::WindowBuildError
+  > {
+    #[cfg(feature = "sdl2-hack")]
+    let window = unsafe {
+      let (window_raw, video_subsystem) = try!{ builder.build_hack() };
+      sdl2::video::Window::from_ll (video_subsystem, window_raw)
+    };
+    #[cfg(not(feature = "sdl2-hack"))]
+    let window = try!{ builder.build() };
+    Ok (std::mem::ManuallyDrop::new (window))
+  }
+
+  /// A string of the current context queried with `glGetString`.
+  fn gl_string (&self, name : u32) -> Result <String, String> {
+    type GetString = unsafe extern "system" fn (u32) -> *const u8;
+    let get_string = self.gl_get_proc_address ("glGetString");
+    if get_string.is_null() {
+      return Err ("failed to load glGetString".to_string())
+    }
+    let get_string : GetString = unsafe { std::mem::transmute (get_string) };
+    let string = unsafe { get_string (name) };
+    if string.is_null() {
+      return Err ("no GL context is current".to_string())
+    }
+    Ok (unsafe {
+      std::ffi::CStr::from_ptr (string as *const std::os::raw::c_char)
+    }.to_string_lossy().into_owned())
+  }
+
+  /// Read back an attribute of the current context.
+  fn gl_attribute (&self, attribute : sdl2_sys::SDL_GLattr)
+    -> Result <i32, String>
+  {
+    if_mock!(self, |_| Ok (0));
+    let mut value = 0;
+    if 0 == unsafe { sdl2_sys::SDL_GL_GetAttribute (attribute, &mut value) } {
+      Ok (value)
+    } else {
+      Err (sdl2::get_error())
+    }
+  }
+
+  /// Create a window with `create_window` and a GL context for it, which is
+  /// left current. The window is destroyed if the context cannot be created.
+  fn create_window_with_context (
+    builder : &sdl2::video::WindowBuilder, robust : bool, debug : bool
+  ) -> Result <(
+    std::mem::ManuallyDrop <sdl2::video::Window>,
+    std::ptr::NonNull <std::os::raw::c_void>
+  ), BackendBuildError> {
+    let sdl_window = try!{ SdlGlWindowBackend::create_window (builder) };
+    #[cfg(feature = "window-impostor")]
+    {
+      if let Err (err) = verify_layout (sdl_window.subsystem()) {
+        unsafe { sdl2_sys::SDL_DestroyWindow (sdl_window.raw()) };
+        return Err (err)
+      }
+    }
+    match unsafe {
+      SdlGlWindowBackend::create_context (sdl_window.raw(), robust, debug)
+    } {
+      Ok (gl_context_raw) => Ok ((sdl_window, gl_context_raw)),
+      Err (err) => {
+        log_record!(Debug, "creating a GL context failed: {}", err);
+        unsafe { sdl2_sys::SDL_DestroyWindow (sdl_window.raw()) };
+        Err (BackendBuildError::ContextCreationError (err))
+      }
+    }
+  }
+
+  /// Install the debug output callback on the current context, if the
+  /// backend was built with `BackendConfig::debug`.
+  fn install_debug_output (&self) -> Result <(), String> {
+    match self.debug_output.as_ref() {
+      // the context is deleted before the debug output is dropped
+      Some (debug_output) => unsafe {
+        debug_output.install (|symbol| self.gl_get_proc_address (symbol))
+      },
+      None => Ok (())
+    }
+  }
+
+  /// Check that the context provides the functions in
+  /// `REQUIRED_GL_FUNCTIONS` by its version or extensions, failing with
+  /// `BackendBuildError::GlLoadError` naming those that it does not.
+  ///
+  /// Function addresses are not checked, since on GLX
+  /// `SDL_GL_GetProcAddress` does not return null for missing functions (see
+  /// `native`).
+  fn check_gl_functions (&self) -> Result <(), BackendBuildError> {
+    let version = try!{
+      self.gl_string (GL_VERSION)
+        .map_err (BackendBuildError::ContextCreationError)
+    };
+    let version = try!{
+      parse_gl_version (&version).ok_or_else (||
+        BackendBuildError::ContextCreationError (
+          format!("invalid GL version: {}", version)))
+    };
+    let (major_version, minor_version, es) = version;
+    // extensions are only needed before OpenGL 2.0, which still has the
+    // GL_EXTENSIONS string
+    let extensions = if es || (major_version, minor_version) >= (2, 0) {
+      String::new()
+    } else {
+      self.gl_string (GL_EXTENSIONS).unwrap_or_default()
+    };
+    let missing = missing_gl_functions (version, &extensions);
+    if missing.is_empty() {
+      Ok (())
+    } else {
+      Err (BackendBuildError::GlLoadError (missing))
+    }
+  }
+
+  /// Create a GL context for the window, with robustness and debugging if
+  /// requested. The context is current on the calling thread on success.
+  unsafe fn create_context (
+    window_raw : *mut sdl2_sys::SDL_Window, robust : bool, debug : bool
+  ) -> Result <std::ptr::NonNull <std::os::raw::c_void>, String> {
+    use sdl2_sys::SDL_GLattr::{SDL_GL_CONTEXT_FLAGS,
+      SDL_GL_CONTEXT_RESET_NOTIFICATION};
+    let mut extra_flags = 0;
+    if robust {
+      extra_flags |= GL_CONTEXT_ROBUST_ACCESS_FLAG;
+    }
+    if debug {
+      extra_flags |= GL_CONTEXT_DEBUG_FLAG;
+    }
+    let (mut flags, mut notification) = (0, 0);
+    if extra_flags != 0 {
+      sdl2_sys::SDL_GL_GetAttribute (SDL_GL_CONTEXT_FLAGS, &mut flags);
+      sdl2_sys::SDL_GL_SetAttribute (SDL_GL_CONTEXT_FLAGS, flags | extra_flags);
+    }
+    if robust {
+      sdl2_sys::SDL_GL_GetAttribute (
+        SDL_GL_CONTEXT_RESET_NOTIFICATION, &mut notification);
+      sdl2_sys::SDL_GL_SetAttribute (
+        SDL_GL_CONTEXT_RESET_NOTIFICATION, GL_CONTEXT_RESET_LOSE_CONTEXT);
+    }
+    let gl_context_raw : sdl2_sys::SDL_GLContext
+      = sdl2_sys::SDL_GL_CreateContext (window_raw);
+    let error = sdl2::get_error();
+    if extra_flags != 0 {
+      sdl2_sys::SDL_GL_SetAttribute (SDL_GL_CONTEXT_FLAGS, flags);
+    }
+    if robust {
+      sdl2_sys::SDL_GL_SetAttribute (
+        SDL_GL_CONTEXT_RESET_NOTIFICATION, notification);
+    }
+    if gl_context_raw.is_null() {
+      Err (error)
+    } else {
+      Ok (std::ptr::NonNull::new_unchecked (gl_context_raw))
+    }
+  }
+
+  /// Replace the GL context with a new one created with the same
+  /// configuration, on the calling thread, leaving it released.
+  ///
+  /// The old context is only deleted if the new one could be created.
+  #[cfg(feature = "glium")]
+  fn recreate_context (&mut self) -> Result <(), String> {
+    if_mock!(self, |_| Err ("mock backends have no context".to_string()));
+    // the context must match the pixel format of the window
+    let gl_context_raw = try!{
+      with_context_attributes (self.version, self.multisample, self.srgb,
+        || unsafe {
+          SdlGlWindowBackend::create_context (self.window_raw.as_ptr(),
+            self.robust, self.debug_output.is_some())
+        })
+    };
+    unsafe { sdl2_sys::SDL_GL_DeleteContext (self.gl_context_raw.as_ptr()) };
+    self.gl_context_raw = gl_context_raw;
+    #[cfg(feature = "glium")]
+    self.context_lost.set (false);
+    log_record!(Info, "GL context of window {} recreated", self.window_id());
+    self.native = native::NativeGl::load();
+    // supported since it was installed on the previous context
+    let _ = self.install_debug_output();
+    #[cfg(feature = "glium-hack")]
+    {
+      self.gl_funs = Some (Box::new (glium::gl::Gl::load_with (
+        |symbol| self.gl_get_proc_address (symbol) as *const _
+      )));
+    }
+    self.gl_release_current();
+    Ok (())
+  }
+
+} // end impl SdlGlWindowBackend
+
+/// Implementation of drop will delete the OpenGL context and destroy the
+/// window, unless the window has been detached by the render thread runner to
+/// be destroyed on the main thread.
+///
+/// NB: Because the Glium backend context holds a reference to this structure,
+/// it should be guaranteed not to drop while a reference to the Glium context
+/// exists (such as in a `glium::Frame` object). Also, because the lifetime of
+/// any `&sdl2::video::Window` reference returned from the display is tied to
+/// the display itself, it is not possible for this to drop while any window
+/// references are in scope.
+impl Drop for SdlGlWindowBackend {
+  fn drop (&mut self) {
+    #[cfg(feature = "glium")]
+    {
+      self.context_probe.assert_dropped (
+        "window backend dropped before its glium context");
+      self.state_probe.assert_dropped (
+        "window backend dropped before its facade state");
+    }
+    if_mock!(self);
+    let window_id = self.window_id();
+    // stop watching for size changes before the window is destroyed
+    self.size_cache = None;
+    unsafe { sdl2_sys::SDL_GL_DeleteContext (self.gl_context_raw.as_ptr()) };
+    log_record!(Info, "GL context of window {} deleted on thread {}",
+      window_id, std::thread::current().name().unwrap_or ("<unnamed>"));
+    if self.owns_window {
+      let window
+        = reaper::DetachedWindow { window_raw: self.window_raw.as_ptr() };
+      match self.reaper.take() {
+        Some (reaper) => if let Err (window) = reaper.send (window) {
+          window.destroy();
+        },
+        None => window.destroy()
+      }
+    }
+    WINDOW_BUILT.store (false, std::sync::atomic::Ordering::SeqCst);
+  }
+}
+
+/// The backend uniquely owns its window and context and is only used by one
+/// thread at a time: the context is released before the backend is sent to
+/// the render thread, and window operations that SDL only supports on the
+/// main thread are sent back there as commands.
+unsafe impl Send for SdlGlWindowBackend { }
+
+/// Backend implementation basically follows that of the `glium-sdl2` crate,
+/// except with raw `SDL_GL_*` calls.
+#[cfg(feature = "glium")]
+unsafe impl glium::backend::Backend for SdlGlWindowBackend {
+  /// A failed swap or a context reset is reported as context loss, after
+  /// which the display must be rebuilt. A context that could not be made
+  /// current is also reported as lost, without swapping.
+  fn swap_buffers (&self) -> Result<(), glium::SwapBuffersError> {
+    if self.context_lost.get() {
+      return Err (glium::SwapBuffersError::ContextLost)
+    }
+    let swapped = match self.swap_damage.borrow_mut().take() {
+      Some (damage) => {
+        let rects = damage.iter().map (|rect| [
+          rect.left as i32, rect.bottom as i32,
+          rect.width as i32, rect.height as i32
+        ]).collect::<Vec <_>>();
+        self.gl_swap_window_with_damage (&rects)
+      }
+      None => self.gl_swap_window()
+    };
+    if let Err (err) = swapped {
+      log_record!(Warn, "swapping the buffers of window {} failed: {}",
+        self.window_id(), err);
+      return Err (glium::SwapBuffersError::ContextLost)
+    }
+    if self.gl_context_reset() {
+      return Err (glium::SwapBuffersError::ContextLost)
+    }
+    Ok(())
+  }
+
+  unsafe fn get_proc_address (&self, symbol : &str)
+    -> *const std::os::raw::c_void
+  {
+    self.gl_get_proc_address (symbol)
+  }
+
+  fn get_framebuffer_dimensions (&self) -> (u32, u32) {
+    self.drawable_size()
+  }
+
+  fn is_current (&self) -> bool {
+    self.gl_is_current()
+  }
+
+  /// glium calls this when the context is not current and cannot be told
+  /// that it failed, so failures are retried with backoff, and if every
+  /// attempt fails the context is marked as lost: glium's GL calls have no
+  /// effect until the next buffer swap reports `SwapBuffersError::ContextLost`
+  /// and the display is rebuilt. A lost context is not made current again.
+  unsafe fn make_current (&self) {
+    if !self.context_lost.get() && self.gl_make_current_retry().is_err() {
+      self.context_lost.set (true);
+    }
+  }
+}
+
+impl SdlGlWindowBuilder for sdl2::video::WindowBuilder {
+  /// Builds a raw window backend with the default configuration.
+  fn build_backend (&mut self) -> Result <SdlGlWindowBackend, BackendBuildError> {
+    self.build_backend_with (BackendConfig::default())
+  }
+
+  /// Builds a raw window backend and releases the context.
+  ///
+  /// Fails with `WindowAlreadyExists` if another window backend exists,
+  /// unless `allow_multiple_windows` has been called.
+  fn build_backend_with (&mut self, config : BackendConfig)
+    -> Result <SdlGlWindowBackend, BackendBuildError>
+  {
+    self.build_backend_with_attr (config, |_| Ok (()))
+  }
+
+  /// Builds a raw window backend after setting GL attributes, and releases
+  /// the context.
+  ///
+  /// The attributes are set after checking that no window backend exists, and
+  /// are left set, as are those requested by the configuration.
+  fn build_backend_with_attr <F> (&mut self, config : BackendConfig, f : F)
+    -> Result <SdlGlWindowBackend, BackendBuildError>
+  where F : FnOnce (&GlAttr) -> Result <(), String>
+  {
+    use std::sync::atomic::Ordering;
+    let _span = trace_span!(INFO, "build_backend");
+    if WINDOW_BUILT.load (Ordering::SeqCst)
+      && !MULTIPLE_WINDOWS.load (Ordering::SeqCst)
+    {
+      return Err (BackendBuildError::WindowAlreadyExists)
+    }
+    // attributes must be set before the window is created, since they may
+    // determine its pixel format
+    try!{ f (&GlAttr::new()).map_err (BackendBuildError::GlAttrError) };
+    // opengl must be requested
+    self.opengl();
+    // create window from self with a gl context, falling back to lower
+    // versions and for each version to fewer samples if the requested ones
+    // are not supported
+    attr::select_es_driver (&config.context_versions);
+    let versions = if config.context_versions.is_empty() {
+      vec![None]
+    } else {
+      config.context_versions.iter().cloned().map (Some).collect()
+    };
+    let sample_counts = match config.multisample {
+      Some (samples) => multisample_fallback (samples).into_iter()
+        .map (Some).collect(),
+      None           => vec![None]
+    };
+    let mut created    = None;
+    let mut last_error = None;
+    'versions: for &version in versions.iter() {
+      for &samples in sample_counts.iter() {
+        match with_context_attributes (version, samples, config.srgb,
+          || SdlGlWindowBackend::create_window_with_context (
+            self, config.robust, config.debug))
+        {
+          Ok (window) => {
+            created = Some ((window, version, samples));
+            break 'versions
+          }
+          #[cfg(feature = "window-impostor")]
+          Err (err @ BackendBuildError::LayoutMismatch { .. }) =>
+            return Err (err),
+          Err (err) => last_error = Some (err)
+        }
+      }
+    }
+    let ((sdl_window, gl_context_raw), context_version, multisample)
+      = match created {
+        Some (created) => created,
+        None           => return Err (last_error.unwrap())
+      };
+    let video_subsystem = sdl_window.subsystem().clone();
+    // SDL only returns null on failure, which is reported by the builder
+    let window_raw
+      = unsafe { std::ptr::NonNull::new_unchecked (sdl_window.raw()) };
+    let (debug_output, debug_receiver) = if config.debug {
+      let (debug_output, debug_receiver) = debug::DebugOutput::new();
+      (Some (debug_output), Some (debug_receiver))
+    } else {
+      (None, None)
+    };
+    #[cfg_attr(not(feature = "glium-hack"), allow(unused_mut))]
+    let mut window_backend = SdlGlWindowBackend {
+      window_raw,
+      gl_context_raw,
+      owns_window: true,
+      main_thread: affinity::MainThreadToken::capture(),
+      robust:      config.robust,
+      multisample,
+      srgb:        config.srgb,
+      version:     context_version,
+      debug_output,
+      debug_receiver,
+      reaper:      None,
+      proxy:       None,
+      #[cfg(feature = "glium-hack")]
+      gl_funs:     None,
+      native:      native::NativeGl::load(),
+      size_cache:  Some (resize::SizeCache::new (window_raw.as_ptr())),
+      #[cfg(all(feature = "glium", not(feature = "window-impostor")))]
+      sdl_window:  Some (std::cell::UnsafeCell::new (sdl_window)),
+      exit_signal: shutdown::ExitSignal::new(),
+      #[cfg(feature = "glium")]
+      swap_damage:   std::cell::RefCell::new (None),
+      #[cfg(feature = "glium")]
+      context_lost:  std::cell::Cell::new (false),
+      #[cfg(feature = "glium")]
+      context_probe: DropProbe::new(),
+      #[cfg(feature = "glium")]
+      state_probe:   DropProbe::new(),
+      #[cfg(feature = "glium")]
+      mock:          None,
+      _leak_token:   leak::LeakToken::new (leak::HandleKind::WindowBackend)
+    };
+    log_record!(Info,
+      "window {} created with a GL context of version {:?}, {:?} samples",
+      window_backend.window_id(), context_version, multisample);
+    // the context is current, so missing functions can be detected
+    try!{ window_backend.check_gl_functions() };
+    try!{
+      window_backend.install_debug_output()
+        .map_err (BackendBuildError::ContextCreationError)
+    };
+    // load gl function pointers
+    #[cfg(feature = "glium-hack")]
+    {
+      window_backend.gl_funs = Some (Box::new (glium::gl::Gl::load_with (
+        |symbol| window_backend.gl_get_proc_address (symbol) as *const _
+      )));
+    }
+
+    video_subsystem.gl_release_current_context().unwrap();
+    WINDOW_BUILT.store (true, Ordering::SeqCst);
+
+    Ok (window_backend)
+  }
+}
+
+#[cfg(feature = "glium")]
+impl Default for GliumBuildConfig {
+  /// Current context checks, default debug callback behavior, framebuffer
+  /// sRGB and swap interval left as they are, and no bound on frames in
+  /// flight.
+  fn default() -> Self {
+    GliumBuildConfig {
+      checked:              true,
+      debug:                glium::debug::DebugCallbackBehavior::default(),
+      framebuffer_srgb:     None,
+      vsync:                None,
+      max_frames_in_flight: None
+    }
+  }
+}
+
+impl BackendConfig {
+  /// The default configuration for an OpenGL ES context, trying
+  /// `ContextVersion::ES_FALLBACK`; see `attr` for using OpenGL ES.
+  pub fn gles() -> Self {
+    BackendConfig {
+      context_versions: ContextVersion::ES_FALLBACK.to_vec(),
+      .. BackendConfig::default()
+    }
+  }
+}
+
+impl Default for BackendConfig {
+  /// A context of the version set by attributes, without robustness,
+  /// multisampling, sRGB framebuffer or debug output.
+  fn default() -> Self {
+    BackendConfig {
+      robust:           false,
+      multisample:      None,
+      srgb:             false,
+      context_versions: Vec::new(),
+      debug:            false
+    }
+  }
+}
+
+impl From <sdl2::video::WindowBuildError> for BackendBuildError {
+  fn from (err : sdl2::video::WindowBuildError) -> Self {
+    BackendBuildError::WindowBuildError (err)
+  }
+}
+
+impl From <String> for BackendBuildError {
+  fn from (err : String) -> Self {
+    BackendBuildError::ContextCreationError (err)
+  }
+}
+
+#[cfg(feature = "glium")]
+impl FacadeState {
+  fn new (window_backend : &SdlGlWindowBackend) -> Self {
+    use glium::backend::Backend;
+    let gl = gl::Gl::load_with (|symbol| unsafe {
+      window_backend.get_proc_address (symbol) as *const _
+    });
+    let timer_queries = unsafe {
+      !window_backend.get_proc_address ("glQueryCounter").is_null()
+    };
+    FacadeState {
+      gl,
+      framebuffer_srgb: std::cell::Cell::new (None),
+      inspection:       FrameInspection::default(),
+      timing:           FrameTiming::new (timer_queries),
+      resources:        FacadeResources::default(),
+      swap_hooks:       std::cell::RefCell::new (Default::default()),
+      loan:             std::cell::RefCell::new (None)
+    }
+  }
+
+  /// Work done by `draw` before starting a frame.
+  fn before_frame (&self, context : &std::rc::Rc <glium::backend::Context>) {
+    self.apply_framebuffer_srgb();
+    self.timing.wait_for_gpu (&self.gl);
+    self.resources.uploads.borrow_mut().upload (context);
+    self.timing.frame_started();
+  }
+
+  /// Work done before each buffer swap.
+  fn before_swap (&self, dimensions : (u32, u32)) {
+    self.inspection.before_swap (&self.gl, dimensions);
+    self.timing.before_swap (&self.gl);
+  }
+
+  fn apply_framebuffer_srgb (&self) {
+    match self.framebuffer_srgb.get() {
+      Some (true)  => unsafe { self.gl.Enable (gl::FRAMEBUFFER_SRGB) },
+      Some (false) => unsafe { self.gl.Disable (gl::FRAMEBUFFER_SRGB) },
+      None         => {}
+    }
+  }
+}
+
+/// GL objects are deleted while the window backend is still alive.
+#[cfg(feature = "glium")]
+impl Drop for FacadeState {
+  fn drop (&mut self) {
+    self.inspection.delete (&self.gl);
+    self.timing.delete (&self.gl);
+  }
+}
+
+#[cfg(feature = "glium")]
+impl FrameInspection {
+  fn before_swap (&self, gl : &gl::Gl, dimensions : (u32, u32)) {
+    // checked first so that only errors raised by the frame are recorded
+    if let Some (ref mut diagnostics) = *self.diagnostics.borrow_mut() {
+      diagnostics.check (gl);
+    }
+    let mut readback = self.readback.borrow_mut();
+    let disconnected = match *readback {
+      Some (ref mut ring) => {
+        ring.before_swap (gl, dimensions);
+        ring.is_disconnected()
+      }
+      None => false
+    };
+    if disconnected {
+      readback.take().unwrap().delete (gl);
+    }
+    self.captures.borrow_mut().before_swap (gl, dimensions);
+    if let Some (ref mut analysis) = *self.analysis.borrow_mut() {
+      let pixels = readback::read_back_buffer (gl, dimensions);
+      analysis.analyze (dimensions.0, dimensions.1, &pixels);
+    }
+  }
+
+  fn delete (&self, gl : &gl::Gl) {
+    if let Some (ring) = self.readback.borrow_mut().take() {
+      ring.delete (gl);
+    }
+    self.captures.borrow_mut().delete (gl);
+  }
+}
+
+#[cfg(feature = "glium")]
+impl FrameTiming {
+  fn new (timer_queries : bool) -> Self {
+    FrameTiming {
+      stats:        std::cell::RefCell::new (Default::default()),
+      profiler:     std::cell::RefCell::new (
+        profiler::GpuProfiler::new (timer_queries)),
+      pacer:        std::cell::RefCell::new (None),
+      frame_fences: std::cell::RefCell::new (None),
+      frame_zone:   std::cell::RefCell::new (None)
+    }
+  }
+
+  fn set_max_frames_in_flight (&self, gl : &gl::Gl, max : Option <usize>) {
+    let mut frame_fences = self.frame_fences.borrow_mut();
+    if let Some (old) = frame_fences.take() {
+      old.delete (gl);
+    }
+    *frame_fences = max.map (latency::FrameFences::new);
+  }
+
+  /// Wait until the number of frames in flight is within the bound.
+  fn wait_for_gpu (&self, gl : &gl::Gl) {
+    if let Some (ref mut frame_fences) = *self.frame_fences.borrow_mut() {
+      frame_fences.before_frame (gl);
+    }
+  }
+
+  fn frame_started (&self) {
+    self.stats.borrow_mut().frame_started (std::time::Instant::now());
+    // a frame that was not finished ends before the next begins
+    self.frame_zone.borrow_mut().take();
+    *self.frame_zone.borrow_mut() = Some (tracy_zone!("draw"));
+  }
+
+  fn before_swap (&self, gl : &gl::Gl) {
+    self.profiler.borrow_mut().before_swap (gl);
+  }
+
+  fn end_frame_zone (&self) {
+    self.frame_zone.borrow_mut().take();
+  }
+
+  /// Record the swap and wait for the GPU or the pacer as needed.
+  fn after_swap (&self, gl : &gl::Gl, swap_time : std::time::Duration) {
+    self.stats.borrow_mut().swapped (swap_time);
+    if let Some (ref mut frame_fences) = *self.frame_fences.borrow_mut() {
+      frame_fences.after_swap (gl);
+    }
+    if let Some (ref mut pacer) = *self.pacer.borrow_mut() {
+      pacer.wait();
+    }
+  }
+
+  fn delete (&self, gl : &gl::Gl) {
+    self.profiler.borrow_mut().delete (gl);
+    if let Some (frame_fences) = self.frame_fences.borrow_mut().take() {
+      frame_fences.delete (gl);
+    }
+  }
+}
+
+#[cfg(feature = "glium")]
+unsafe impl glium::backend::Backend for RenderBackend {
+  fn swap_buffers (&self) -> Result<(), glium::SwapBuffersError> {
+    let _span = trace_span!(TRACE, "swap_buffers",
+      window_id = self.window_backend.window_id());
+    let dimensions = self.window_backend.get_framebuffer_dimensions();
+    self.state.before_swap (dimensions);
+    hooks::SwapHooks::run (
+      &self.state.swap_hooks, hooks::SwapStage::Pre, dimensions, None);
+    let swap_start = std::time::Instant::now();
+    let result = {
+      let _zone = tracy_zone!("swap_buffers");
+      self.window_backend.swap_buffers()
+    };
+    let swap_time = swap_start.elapsed();
+    self.state.timing.end_frame_zone();
+    hooks::SwapHooks::run (
+      &self.state.swap_hooks, hooks::SwapStage::Post, dimensions,
+      Some (swap_time));
+    self.state.timing.after_swap (&self.state.gl, swap_time);
+    tracy::frame_mark();
+    result
+  }
+
+  unsafe fn get_proc_address (&self, symbol : &str)
+    -> *const std::os::raw::c_void
+  {
+    self.window_backend.get_proc_address (symbol)
+  }
+
+  fn get_framebuffer_dimensions (&self) -> (u32, u32) {
+    self.window_backend.get_framebuffer_dimensions()
+  }
+
+  fn is_current (&self) -> bool {
+    self.window_backend.is_current()
+  }
+
+  unsafe fn make_current (&self) {
+    self.window_backend.make_current()
+  }
+}
+
+#[cfg(feature = "glium")]
+impl <T> DropProbe <T> {
+  fn new() -> Self {
+    DropProbe { target: std::cell::RefCell::new (std::rc::Weak::new()) }
+  }
+
+  fn set (&self, target : &std::rc::Rc <T>) {
+    *self.target.borrow_mut() = std::rc::Rc::downgrade (target);
+  }
+
+  fn is_alive (&self) -> bool {
+    self.target.borrow().upgrade().is_some()
+  }
+
+  /// Panic in debug builds if the target is still alive.
+  fn assert_dropped (&self, message : &str) {
+    debug_assert!(!self.is_alive(), "{}", message);
+  }
+}
+
+/// Probes are only set when their owner is already shared by an `Rc` on the
+/// render thread, so an unset probe can be sent with the window backend.
+#[cfg(feature = "glium")]
+unsafe impl <T> Send for DropProbe <T> { }
+
+#[cfg(feature = "window-impostor")]
+impl SharedImpostor {
+  fn new (window_context_impostor : SdlWindowContextImpostor) -> Self {
+    SharedImpostor {
+      window:      std::cell::UnsafeCell::new (
+        SdlWindowImpostor::new (window_context_impostor)),
+      _leak_token: leak::LeakToken::new (leak::HandleKind::WindowImpostor)
+    }
+  }
+}
+
+#[cfg(feature = "window-impostor")]
+impl SdlWindowImpostor {
+  fn new (window_context_impostor : SdlWindowContextImpostor) -> Self {
+    SdlWindowImpostor {
+      window_context_impostor: std::rc::Rc::new (window_context_impostor)
+    }
+  }
+}
+
+#[cfg(feature = "window-impostor")]
+impl SdlWindowContextImpostor {
+  fn new (window_raw : *mut sdl2_sys::SDL_Window) -> Self {
+    SdlWindowContextImpostor {
+      _video_subsystem: std::rc::Rc::new (()),
+      _window_raw:      window_raw
+    }
+  }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  functions                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Allow more than one window backend to be built, for applications that
+/// manage several windows; called by `DisplayManager::new`.
+///
+/// Windows must still only be built on the main thread.
+pub fn allow_multiple_windows() {
+  MULTIPLE_WINDOWS.store (true, std::sync::atomic::Ordering::SeqCst);
+}
+
+/// Verify that the fields of the impostor types are at the offsets of the
+/// fields of the sdl2 types they are transmuted into, returning
+/// `BackendBuildError::LayoutMismatch` otherwise. Sizes and alignments are
+/// checked at compile time.
+///
+/// A window is built around a sentinel pointer with `Window::from_ll` and
+/// its fields are located by their values. The window is never dropped, so
+/// the reference to the video subsystem it holds is leaked. This is called
+/// by `SdlGlWindowBuilder::build_backend_with`.
+#[cfg(feature = "window-impostor")]
+pub fn verify_layout (video_subsystem : &sdl2::VideoSubsystem)
+  -> Result <(), BackendBuildError>
+{
+  const WORD : usize = std::mem::size_of::<usize>();
+  let context_size = std::mem::size_of::<SdlWindowContextImpostor>();
+  let mismatch = |expected, actual| Err (BackendBuildError::LayoutMismatch {
+    type_name: "sdl2::video::WindowContext", expected, actual
+  });
+  // SDL never sees the sentinel
+  let window_raw
+    = std::ptr::NonNull::<sdl2_sys::SDL_Window>::dangling().as_ptr();
+  let window = std::mem::ManuallyDrop::new (unsafe {
+    sdl2::video::Window::from_ll (video_subsystem.clone(), window_raw)
+  });
+  let window : &sdl2::video::Window = &window;
+  // the window is a single `Rc` of its context, as the sizes match
+  let impostor : &SdlWindowImpostor = unsafe { std::mem::transmute (window) };
+  let context = &*impostor.window_context_impostor;
+  let context_addr = context as *const _ as usize;
+  // the subsystem is located by the reference the window gives out
+  let expected = &context._video_subsystem as *const _ as usize - context_addr;
+  let actual = (window.subsystem() as *const _ as usize)
+    .wrapping_sub (context_addr);
+  if actual != expected {
+    return mismatch (expected, std::cmp::min (actual, context_size))
+  }
+  // the raw window is located by the sentinel
+  let expected = &context._window_raw as *const _ as usize - context_addr;
+  let actual = (0..context_size / WORD).map (|i| i * WORD)
+    .find (|&offset| window_raw as usize
+      == unsafe { *((context_addr + offset) as *const usize) })
+    .unwrap_or (context_size);
+  if actual != expected {
+    return mismatch (expected, actual)
+  }
+  Ok (())
+}
+
+//
+// private
+//
+
+/// Call `f` until it succeeds, sleeping between attempts with exponential
+/// backoff, and return the last error if all `MAKE_CURRENT_ATTEMPTS` fail.
+fn retry_with_backoff <F> (mut f : F) -> Result <(), String> where
+  F : FnMut() -> Result <(), String>
+{
+  let mut backoff = std::time::Duration::from_millis (MAKE_CURRENT_BACKOFF_MS);
+  let mut attempt = 1;
+  loop {
+    match f() {
+      Ok (()) => return Ok (()),
+      Err (err) => if attempt == MAKE_CURRENT_ATTEMPTS {
+        return Err (err)
+      }
+    }
+    std::thread::sleep (backoff);
+    backoff *= 2;
+    attempt += 1;
+  }
+}
+
+/// Major and minor version in a `GL_VERSION` string, and whether it is an
+/// OpenGL ES version.
+fn parse_gl_version (version : &str) -> Option <(u8, u8, bool)> {
+  let es = version.starts_with ("OpenGL ES");
+  // skip an "OpenGL ES " or "OpenGL ES-CM " prefix
+  let version = version.trim_start_matches (|c : char| !c.is_ascii_digit());
+  let mut numbers = version.split (|c : char| !c.is_ascii_digit());
+  let major = try_opt!(numbers.next().and_then (|major| major.parse().ok()));
+  let minor = try_opt!(numbers.next().and_then (|minor| minor.parse().ok()));
+  Some ((major, minor, es))
+}
+
+/// The functions of `REQUIRED_GL_FUNCTIONS` not provided by a context of the
+/// given version, as returned by `parse_gl_version`, and extensions.
+fn missing_gl_functions (
+  (major_version, minor_version, es) : (u8, u8, bool),
+  extensions                         : &str
+) -> Vec <String> {
+  REQUIRED_GL_FUNCTIONS.iter().filter (|&&(_, core, extension)| if es {
+    major_version < 2
+  } else {
+    (major_version, minor_version) < core && extension.is_none_or (
+      |extension| !extensions.split_whitespace().any (|name| name == extension))
+  }).map (|&(symbol, _, _)| symbol.to_string()).collect()
+}
+
+/// Sample counts to try for a multisampling request: the requested count,
+/// each lower power of two down to 2, and no multisampling.
+fn multisample_fallback (samples : u8) -> Vec <u8> {
+  let mut counts = vec![samples];
+  counts.extend ([128, 64, 32, 16, 8, 4, 2, 0].iter().cloned()
+    .filter (|count| *count < samples));
+  counts
+}
+
+/// Call `f` with the attributes set for the given context version and
+/// number of multisampling samples, if any, and for an sRGB-capable
+/// framebuffer if `srgb` is set.
+///
+/// The attributes are left set: without a current context SDL does not
+/// report the values of some of them (e.g. `SDL_GL_MULTISAMPLESAMPLES`), so
+/// values set by the application cannot be read back to be restored.
+fn with_context_attributes <T, F> (
+  version : Option <ContextVersion>, samples : Option <u8>, srgb : bool, f : F
+) -> T where F : FnOnce() -> T {
+  use sdl2_sys::SDL_GLattr::{SDL_GL_FRAMEBUFFER_SRGB_CAPABLE,
+    SDL_GL_MULTISAMPLEBUFFERS, SDL_GL_MULTISAMPLESAMPLES};
+  let mut attributes = Vec::new();
+  if let Some (version) = version {
+    attributes.extend_from_slice (&version.attributes());
+  }
+  if let Some (samples) = samples {
+    attributes.push ((SDL_GL_MULTISAMPLEBUFFERS, (samples > 0) as i32));
+    attributes.push ((SDL_GL_MULTISAMPLESAMPLES, samples as i32));
+  }
+  if srgb {
+    attributes.push ((SDL_GL_FRAMEBUFFER_SRGB_CAPABLE, 1));
+  }
+  for (attribute, value) in attributes {
+    unsafe { sdl2_sys::SDL_GL_SetAttribute (attribute, value) };
+  }
+  f()
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  /// Field offsets are checked at runtime by `verify_layout`.
+  #[cfg(feature = "window-impostor")]
+  #[test]
+  fn test() {
+    assert_eq!(
+      std::mem::size_of::<sdl2::video::Window>(),
+      std::mem::size_of::<SdlWindowImpostor>());
+    assert_eq!(
+      std::mem::size_of::<sdl2::video::WindowContext>(),
       std::mem::size_of::<SdlWindowContextImpostor>());
   }
+  #[test]
+  fn test_missing_gl_functions() {
+    assert!(missing_gl_functions ((2, 0, false), "").is_empty());
+    assert!(missing_gl_functions ((2, 0, true), "").is_empty());
+    assert_eq!(missing_gl_functions ((1, 1, true), "").len(),
+      REQUIRED_GL_FUNCTIONS.len());
+    let missing = missing_gl_functions ((1, 5, false),
+      "GL_ARB_multitexture GL_ARB_shader_objects");
+    assert!(missing.contains (&"glVertexAttribPointer".to_string()));
+    assert!(!missing.contains (&"glCreateShader".to_string()));
+    assert!(!missing.contains (&"glGenBuffers".to_string()));
+    let missing = missing_gl_functions ((1, 4, false), "");
+    assert!(missing.contains (&"glGenBuffers".to_string()));
+    assert!(!missing.contains (&"glDrawArrays".to_string()));
+  }
+  #[test]
+  fn test_parse_gl_version() {
+    assert_eq!(parse_gl_version ("4.6.0 NVIDIA 535.54"), Some ((4, 6, false)));
+    assert_eq!(parse_gl_version ("3.3 (Core Profile) Mesa 23.0"),
+      Some ((3, 3, false)));
+    assert_eq!(parse_gl_version ("OpenGL ES 3.2 Mesa 23.0"),
+      Some ((3, 2, true)));
+    assert_eq!(parse_gl_version ("OpenGL ES-CM 1.1"), Some ((1, 1, true)));
+    assert_eq!(parse_gl_version ("invalid"), None);
+  }
+  #[cfg(feature = "glium")]
+  #[test]
+  fn test_frame_swap() {
+    use std::sync::atomic::Ordering;
+    let mock = MockGlBackend::new (320, 240);
+    let (swaps, dimensions, dropped)
+      = (mock.swaps(), mock.dimensions(), mock.dropped());
+    let display = SdlGlWindowBackend::mock (mock).build_glium().unwrap();
+    let log = std::rc::Rc::new (std::cell::RefCell::new (Vec::new()));
+    let hook = |stage : &'static str| {
+      let log = log.clone();
+      move |info : &SwapInfo| log.borrow_mut().push (
+        (stage, info.frame, info.dimensions, info.swap_time.is_some()))
+    };
+    display.add_pre_swap_hook (hook ("pre"));
+    display.add_post_swap_hook (hook ("post"));
+    display.draw().finish().unwrap();
+    // a resize is seen by the next frame
+    *dimensions.lock().unwrap() = (640, 480);
+    display.draw().finish().unwrap();
+    assert_eq!(swaps.load (Ordering::SeqCst), 2);
+    assert_eq!(*log.borrow(), vec![
+      ("pre",  0, (320, 240), false),
+      ("post", 0, (320, 240), true),
+      ("pre",  1, (640, 480), false),
+      ("post", 1, (640, 480), true)
+    ]);
+    assert_eq!(display.stats().frames, 2);
+    // the mock has no timer queries
+    assert!(!display.gpu_timers_supported());
+    assert!(display.take_gpu_timings().is_empty());
+    drop (display);
+    assert!(dropped.load (Ordering::SeqCst));
+  }
+  #[cfg(feature = "glium")]
+  #[test]
+  fn test_context_lost() {
+    let mock = MockGlBackend::new (320, 240);
+    let swaps = mock.swaps();
+    let display = SdlGlWindowBackend::mock (mock).build_glium().unwrap();
+    assert!(!display.check_reset());
+    // as if the context could not be made current
+    display.window_backend.context_lost.set (true);
+    assert!(display.check_reset());
+    assert_eq!(display.draw().finish(),
+      Err (glium::SwapBuffersError::ContextLost));
+    assert_eq!(swaps.load (std::sync::atomic::Ordering::SeqCst), 0);
+  }
+  #[test]
+  fn test_multisample_fallback() {
+    assert_eq!(multisample_fallback (8), vec![8, 4, 2, 0]);
+    assert_eq!(multisample_fallback (6), vec![6, 4, 2, 0]);
+    assert_eq!(multisample_fallback (2), vec![2, 0]);
+    assert_eq!(multisample_fallback (0), vec![0]);
+  }
+  #[test]
+  fn test_retry_with_backoff() {
+    let mut attempts = 0;
+    assert!(retry_with_backoff (|| {
+      attempts += 1;
+      if attempts < 3 { Err ("busy".to_string()) } else { Ok (()) }
+    }).is_ok());
+    assert_eq!(attempts, 3);
+    attempts = 0;
+    assert_eq!(retry_with_backoff (|| {
+      attempts += 1;
+      Err (format!("attempt {}", attempts))
+    }), Err (format!("attempt {}", MAKE_CURRENT_ATTEMPTS)));
+    assert_eq!(attempts, MAKE_CURRENT_ATTEMPTS);
+  }
+  #[cfg(feature = "glium")]
+  #[test]
+  fn test_drop_probe() {
+    // mirrors the facade: the probed value is declared, and so dropped,
+    // before the owner of the probe
+    struct Backend {
+      probe : DropProbe <u32>
+    }
+    impl Drop for Backend {
+      fn drop (&mut self) {
+        self.probe.assert_dropped ("dropped out of order");
+      }
+    }
+    struct Facade {
+      _context : std::rc::Rc <u32>,
+      _backend : std::rc::Rc <Backend>
+    }
+    let context = std::rc::Rc::new (0);
+    let backend = std::rc::Rc::new (Backend { probe: DropProbe::new() });
+    assert!(!backend.probe.is_alive());
+    backend.probe.set (&context);
+    assert!(backend.probe.is_alive());
+    let _facade = Facade { _context: context, _backend: backend };
+  }
+  #[cfg(all(feature = "glium", debug_assertions))]
+  #[test]
+  #[should_panic(expected = "dropped out of order")]
+  fn test_drop_probe_order() {
+    let target = std::rc::Rc::new (0);
+    let probe  = DropProbe::new();
+    probe.set (&target);
+    probe.assert_dropped ("dropped out of order");
+  }
 }
@@ -0,0 +1,185 @@
+//! A second GL context for uploading resources on a loader thread.
+//!
+//! A `SharedLoaderContext` is created on the main thread from a window
+//! backend, before the backend is sent to the render thread. It shares
+//! objects (textures, buffers, programs) with the context of the backend and
+//! can be sent to a loader thread, where glium is built on it:
+//!
+//! ```ignore
+//! let loader_context
+//!   = unsafe { window_backend.build_shared_loader_context() }.unwrap();
+//! std::thread::spawn (move || {
+//!   let loader = loader_context.build_glium().unwrap();
+//!   let texture = glium::texture::Texture2d::new (&loader, image).unwrap();
+//!   // make the upload visible to the render context before handing over
+//!   loader.finish();
+//!   // ... send texture.get_id() to the render thread
+//! });
+//! ```
+//!
+//! Glium objects are tied to the context that created them and cannot be sent
+//! to the render thread; the render thread should instead use the GL names of
+//! the objects, which are valid in both contexts, while the loader keeps the
+//! objects alive. Only objects are shared: container objects such as vertex
+//! arrays and framebuffers are not.
+//!
+//! The loader context is made current with the window of the backend, but
+//! never draws to or swaps the window.
+
+use glium;
+use sdl2;
+use sdl2_sys;
+
+use SdlGlWindowBackend;
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// A GL context sharing objects with the context of a window backend, to be
+/// sent to a loader thread.
+pub struct SharedLoaderContext {
+  backend : LoaderBackend
+}
+
+/// Glium facade of a loader context.
+pub struct LoaderFacade {
+  glium_context : std::rc::Rc <glium::backend::Context>
+}
+
+//
+// private
+//
+
+/// The backend given to glium for a loader context.
+struct LoaderBackend {
+  window_raw     : *mut sdl2_sys::SDL_Window,
+  gl_context_raw : sdl2_sys::SDL_GLContext
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+impl SharedLoaderContext {
+  /// See `SdlGlWindowBackend::build_shared_loader_context`.
+  pub(crate) unsafe fn new (window_backend : &SdlGlWindowBackend)
+    -> Result <Self, String>
+  {
+    let gl_context_raw = try!{ create_shared_context (window_backend) };
+    Ok (SharedLoaderContext {
+      backend: LoaderBackend {
+        window_raw: window_backend.window_raw.as_ptr(),
+        gl_context_raw
+      }
+    })
+  }
+
+  /// Build glium on the calling (loader) thread, making the context current
+  /// on it.
+  pub fn build_glium (self)
+    -> Result <LoaderFacade, glium::IncompatibleOpenGl>
+  {
+    let glium_context = try!{
+      unsafe {
+        glium::backend::Context::new (self.backend, false,
+          glium::debug::DebugCallbackBehavior::default())
+      }
+    };
+    Ok (LoaderFacade { glium_context })
+  }
+}
+
+/// The context is only made current on the thread it is sent to.
+unsafe impl Send for SharedLoaderContext { }
+
+impl LoaderFacade {
+  /// Block until the commands issued so far have completed, so that the
+  /// objects they uploaded can be used by the render context.
+  pub fn finish (&self) {
+    self.glium_context.finish()
+  }
+}
+
+impl glium::backend::Facade for LoaderFacade {
+  fn get_context (&self) -> &std::rc::Rc <glium::backend::Context> {
+    &self.glium_context
+  }
+}
+
+unsafe impl glium::backend::Backend for LoaderBackend {
+  /// The loader context never presents frames.
+  fn swap_buffers (&self) -> Result <(), glium::SwapBuffersError> {
+    Ok (())
+  }
+
+  unsafe fn get_proc_address (&self, symbol : &str)
+    -> *const std::os::raw::c_void
+  {
+    match std::ffi::CString::new (symbol) {
+      Ok (symbol) => sdl2_sys::SDL_GL_GetProcAddress (
+        symbol.as_ptr() as *const std::os::raw::c_char
+      ) as *const std::os::raw::c_void,
+      Err (_) => std::ptr::null()
+    }
+  }
+
+  fn get_framebuffer_dimensions (&self) -> (u32, u32) {
+    let (mut width, mut height) = (0, 0);
+    unsafe {
+      sdl2_sys::SDL_GL_GetDrawableSize (
+        self.window_raw, &mut width, &mut height)
+    };
+    (width as u32, height as u32)
+  }
+
+  fn is_current (&self) -> bool {
+    unsafe {
+      self.gl_context_raw == sdl2_sys::SDL_GL_GetCurrentContext()
+        && self.window_raw == sdl2_sys::SDL_GL_GetCurrentWindow()
+    }
+  }
+
+  unsafe fn make_current (&self) {
+    if 0 != sdl2_sys::SDL_GL_MakeCurrent (
+      self.window_raw, self.gl_context_raw
+    ) {
+      panic!("failed to make loader context current: {}", sdl2::get_error())
+    }
+  }
+}
+
+impl Drop for LoaderBackend {
+  fn drop (&mut self) {
+    unsafe { sdl2_sys::SDL_GL_DeleteContext (self.gl_context_raw) };
+  }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  functions                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Create a context sharing objects with the context of a window backend.
+///
+/// The context of the backend is made current on the calling thread while
+/// the context is created, then both are released.
+pub(crate) unsafe fn create_shared_context (
+  window_backend : &SdlGlWindowBackend
+) -> Result <sdl2_sys::SDL_GLContext, String> {
+  use sdl2_sys::SDL_GLattr::SDL_GL_SHARE_WITH_CURRENT_CONTEXT as SHARE;
+  let window_raw = window_backend.window_raw.as_ptr();
+  try!{ window_backend.gl_make_current() };
+  let mut share = 0;
+  sdl2_sys::SDL_GL_GetAttribute (SHARE, &mut share);
+  sdl2_sys::SDL_GL_SetAttribute (SHARE, 1);
+  // the new context is made current
+  let gl_context_raw = sdl2_sys::SDL_GL_CreateContext (window_raw);
+  let result = if gl_context_raw.is_null() {
+    Err (sdl2::get_error())
+  } else {
+    Ok (gl_context_raw)
+  };
+  sdl2_sys::SDL_GL_SetAttribute (SHARE, share);
+  sdl2_sys::SDL_GL_MakeCurrent (window_raw, std::ptr::null_mut());
+  result
+}
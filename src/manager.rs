@@ -0,0 +1,233 @@
+//! Several windows, each with its own display and render loop.
+//!
+//! A `DisplayManager` builds window backends on the main thread and routes
+//! the events of each window to a receiver subscribed for it. A window can
+//! be handed to a render thread of its own with `spawn`:
+//!
+//! ```ignore
+//! let (mut manager, receiver) = DisplayManager::new();
+//! for title in &["scene", "inspector"] {
+//!   let window = manager.build_window (
+//!     &mut video_subsystem.window (title, 640, 480)).unwrap();
+//!   manager.spawn (window, RenderThread::new(), |display, events| {
+//!     while !display.shutdown_requested() {
+//!       for event in events.drain_frame() { /* ... */ }
+//!       // ... render a frame ...
+//!     }
+//!   }).unwrap();
+//! }
+//! while !manager.is_empty() {
+//!   let event = event_pump.wait_event();
+//!   for (window_id, result) in manager.handle_event (&event) {
+//!     // ... the window has been closed and destroyed
+//!   }
+//! }
+//! ```
+//!
+//! Several windows can also share a render thread, by moving their
+//! `ManagedWindow`s to a thread spawned by the application and building
+//! glium for each of them there. The contexts of the windows are then made
+//! current in turn, which glium only does when built with
+//! `GliumBuildConfig::checked` set. Such windows are destroyed on the main
+//! thread by the `WindowReaper` of the manager once their backends have been
+//! dropped.
+//!
+//! Closing a window (`WindowEvent::Close`) requests shutdown of its render
+//! loop with its `ShutdownToken`, leaving the other windows running; a
+//! process-wide shutdown request stops all of them.
+
+use sdl2;
+
+use {allow_multiple_windows, BackendBuildError, SdlGlWindowBackend,
+  SdlGlWindowBuilder, SdlGliumDisplayFacade, ShutdownToken};
+use event::{EventForwarder, EventReceiver};
+use reaper::WindowReaper;
+use registry::{WindowHandle, WindowRegistry};
+use runner::{RenderThread, RenderThreadError, RenderThreadHandle};
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Main thread owner of several windows and their render threads.
+pub struct DisplayManager {
+  forwarder : EventForwarder,
+  reaper    : WindowReaper,
+  windows   : WindowRegistry <ManagedState>
+}
+
+/// A window built by a display manager, to be moved to its render thread.
+pub struct ManagedWindow {
+  pub window_backend : SdlGlWindowBackend,
+  /// Receiver of the events of the window
+  pub events         : EventReceiver,
+  pub handle         : WindowHandle
+}
+
+//
+// private
+//
+
+/// Main thread state of a managed window.
+struct ManagedState {
+  shutdown      : ShutdownToken,
+  /// Set if the window was spawned on its own render thread
+  render_thread : Option <RenderThreadHandle>
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+impl DisplayManager {
+  /// Create a manager with a new event forwarder, returning the broadcast
+  /// receiver for events not associated with a window.
+  ///
+  /// This should be called on the main thread after SDL has been initialized.
+  pub fn new() -> (DisplayManager, EventReceiver) {
+    let (forwarder, receiver) = EventForwarder::new();
+    (DisplayManager::with_forwarder (forwarder), receiver)
+  }
+
+  /// Create a manager with a forwarder that has already been configured.
+  ///
+  /// This allows more than one window backend to be built in the process,
+  /// see `allow_multiple_windows`.
+  pub fn with_forwarder (forwarder : EventForwarder) -> Self {
+    allow_multiple_windows();
+    DisplayManager {
+      forwarder,
+      reaper:  WindowReaper::new(),
+      windows: WindowRegistry::new()
+    }
+  }
+
+  /// The forwarder, e.g. to add timers or subscribe the audio thread.
+  pub fn forwarder (&mut self) -> &mut EventForwarder {
+    &mut self.forwarder
+  }
+
+  /// Build a window backend, register it and subscribe to its events.
+  pub fn build_window (&mut self, builder : &mut sdl2::video::WindowBuilder)
+    -> Result <ManagedWindow, BackendBuildError>
+  {
+    let mut window_backend = try!{ builder.build_backend() };
+    self.reaper.attach (&mut window_backend);
+    let window_id = window_backend.window_id();
+    let handle = self.windows.register (&window_backend, ManagedState {
+      shutdown:      window_backend.shutdown_handle(),
+      render_thread: None
+    });
+    self.forwarder.add_window (window_id);
+    let events = self.forwarder.subscribe (window_id);
+    Ok (ManagedWindow { window_backend, events, handle })
+  }
+
+  /// Spawn a render thread for the window, running the render function with
+  /// its display and event receiver; see `RenderThread::spawn`.
+  pub fn spawn <F> (&mut self,
+    window        : ManagedWindow,
+    render_thread : RenderThread,
+    render        : F
+  ) -> Result <WindowHandle, RenderThreadError> where
+    F : FnOnce (SdlGliumDisplayFacade, EventReceiver) + Send + 'static
+  {
+    let ManagedWindow { window_backend, events, handle } = window;
+    let spawned = render_thread.spawn (window_backend,
+      move |display| render (display, events));
+    match spawned {
+      Ok (thread) => {
+        if let Some (state) = self.windows.get_mut (handle.id()) {
+          state.render_thread = Some (thread);
+        }
+        Ok (handle)
+      }
+      Err (err) => {
+        // no render thread was started, so there is nothing to join
+        let _ = self.remove (handle.id());
+        Err (err)
+      }
+    }
+  }
+
+  /// Handle and forward an event pumped on the main thread, returning the
+  /// windows that have been closed and destroyed as a result, with the
+  /// result of their render thread.
+  ///
+  /// This must be called on the main thread with every event.
+  pub fn handle_event (&mut self, event : &sdl2::event::Event)
+    -> Vec <(u32, Result <(), RenderThreadError>)>
+  {
+    use sdl2::event::{Event, WindowEvent};
+    if let Event::Window { window_id, win_event: WindowEvent::Close, .. }
+      = *event
+    {
+      self.close (window_id);
+    }
+    if self.reaper.is_wake_event (event) {
+      self.reaper.reap();
+      return self.remove_exited()
+    }
+    let exited = self.windows.iter()
+      .filter (|&(_, state)| state.render_thread.as_ref()
+        .is_some_and (|thread| thread.is_exit_event (event)))
+      .map (|(&window_id, _)| window_id)
+      .next();
+    if let Some (window_id) = exited {
+      let result = self.remove (window_id);
+      return vec![(window_id, result)]
+    }
+    self.forwarder.forward (event);
+    Vec::new()
+  }
+
+  /// Request shutdown of the render loop of a window.
+  pub fn close (&self, window_id : u32) {
+    if let Some (state) = self.windows.get (window_id) {
+      state.shutdown.request();
+    }
+  }
+
+  /// Request shutdown of the render loops of all windows.
+  pub fn close_all (&self) {
+    for (_, state) in self.windows.iter() {
+      state.shutdown.request();
+    }
+  }
+
+  pub fn contains (&self, window_id : u32) -> bool {
+    self.windows.contains (window_id)
+  }
+
+  /// Number of windows that have not been destroyed.
+  pub fn len (&self) -> usize {
+    self.windows.len()
+  }
+
+  pub fn is_empty (&self) -> bool {
+    self.windows.is_empty()
+  }
+
+  /// Remove the windows not on their own render thread whose backends have
+  /// been dropped.
+  fn remove_exited (&mut self) -> Vec <(u32, Result <(), RenderThreadError>)> {
+    let exited = self.windows.iter()
+      .filter (|&(_, state)|
+        state.render_thread.is_none() && state.shutdown.has_render_exited())
+      .map (|(&window_id, _)| window_id)
+      .collect::<Vec <_>>();
+    exited.into_iter()
+      .map (|window_id| (window_id, self.remove (window_id)))
+      .collect()
+  }
+
+  /// Unregister a window, joining its render thread if it has one.
+  fn remove (&mut self, window_id : u32) -> Result <(), RenderThreadError> {
+    self.forwarder.unsubscribe (window_id);
+    self.forwarder.remove_window (window_id);
+    match self.windows.unregister (window_id) {
+      Some (ManagedState { render_thread: Some (thread), .. }) => thread.join(),
+      _ => Ok (())
+    }
+  }
+}
@@ -0,0 +1,323 @@
+//! A CPU-only backend for testing without SDL or a graphics stack.
+//!
+//! `MockGlBackend` implements `glium::backend::Backend` with a small table of
+//! GL functions written in Rust that report an OpenGL 3.3 core context with
+//! no extensions and otherwise do nothing. A window backend built with
+//! `SdlGlWindowBackend::mock` goes through the same facade construction,
+//! ownership and teardown as a real one, so logic built on the facade can be
+//! tested on machines without a display and under Miri:
+//!
+//! ```ignore
+//! let mock = MockGlBackend::new (320, 240);
+//! let swaps = mock.swaps();
+//! let display = SdlGlWindowBackend::mock (mock).build_glium().unwrap();
+//! display.draw().finish().unwrap();
+//! assert_eq!(swaps.load (std::sync::atomic::Ordering::SeqCst), 1);
+//! ```
+//!
+//! The handles returned by `swaps`, `dimensions` and `dropped` stay with the
+//! test when the backend is moved to a render thread, so that frames, resizes
+//! and the drop of the window backend can be observed and driven from there:
+//!
+//! ```ignore
+//! let mock = MockGlBackend::new (320, 240);
+//! let (dimensions, dropped) = (mock.dimensions(), mock.dropped());
+//! let handle = RenderThread::new()
+//!   .spawn (SdlGlWindowBackend::mock (mock), render).unwrap();
+//! *dimensions.lock().unwrap() = (640, 480);
+//! // ...
+//! handle.join().unwrap();
+//! assert!(dropped.load (std::sync::atomic::Ordering::SeqCst));
+//! ```
+//!
+//! Calling a GL function that is not part of the table panics with the name
+//! of the function, as glium does for any function it could not load. Window
+//! functions that require SDL, such as `SdlGliumDisplayFacade::window`, must
+//! not be used with a mock.
+//!
+//! The table is built on the bindings generated for this crate rather than
+//! on glium internals, so the mock does not need the `glium-hack` feature.
+//! Downstream crates can also give it to glium directly, without a window
+//! backend:
+//!
+//! ```ignore
+//! let context = unsafe {
+//!   glium::backend::Context::new (MockGlBackend::new (320, 240), false,
+//!     Default::default())
+//! }.unwrap();
+//! ```
+
+use glium;
+
+use gl;
+use gl::types::{GLboolean, GLdouble, GLenum, GLfloat, GLint, GLint64,
+  GLsizei, GLubyte, GLuint};
+
+///////////////////////////////////////////////////////////////////////////////
+//  constants                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+const VENDOR : &[u8] = b"glium-sdl2-hack\0";
+const RENDERER : &[u8] = b"MockGlBackend\0";
+const VERSION : &[u8] = b"3.3.0 Mock\0";
+const SHADING_LANGUAGE_VERSION : &[u8] = b"3.30\0";
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Backend that renders nothing and counts buffer swaps.
+#[derive(Debug)]
+pub struct MockGlBackend {
+  dimensions : std::sync::Arc <std::sync::Mutex <(u32, u32)>>,
+  current    : std::cell::Cell <bool>,
+  swaps      : std::sync::Arc <std::sync::atomic::AtomicUsize>,
+  dropped    : std::sync::Arc <std::sync::atomic::AtomicBool>
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+impl MockGlBackend {
+  /// Create a backend with the given framebuffer dimensions.
+  pub fn new (width : u32, height : u32) -> Self {
+    use std::sync::{atomic, Arc, Mutex};
+    MockGlBackend {
+      dimensions: Arc::new (Mutex::new ((width, height))),
+      current:    std::cell::Cell::new (false),
+      swaps:      Arc::new (atomic::AtomicUsize::new (0)),
+      dropped:    Arc::new (atomic::AtomicBool::new (false))
+    }
+  }
+
+  /// Shared counter of buffer swaps, which can be kept after the backend has
+  /// been moved into a display.
+  pub fn swaps (&self) -> std::sync::Arc <std::sync::atomic::AtomicUsize> {
+    self.swaps.clone()
+  }
+
+  /// Shared framebuffer dimensions, which can be changed to simulate a resize
+  /// of the window after the backend has been moved into a display.
+  pub fn dimensions (&self) -> std::sync::Arc <std::sync::Mutex <(u32, u32)>> {
+    self.dimensions.clone()
+  }
+
+  /// Shared flag set when the backend is dropped, i.e. after the context and
+  /// the window backend owning it have been torn down.
+  pub fn dropped (&self) -> std::sync::Arc <std::sync::atomic::AtomicBool> {
+    self.dropped.clone()
+  }
+
+  pub(crate) fn release_current (&self) {
+    self.current.set (false);
+  }
+}
+
+unsafe impl glium::backend::Backend for MockGlBackend {
+  fn swap_buffers (&self) -> Result <(), glium::SwapBuffersError> {
+    self.swaps.fetch_add (1, std::sync::atomic::Ordering::SeqCst);
+    Ok (())
+  }
+
+  unsafe fn get_proc_address (&self, symbol : &str)
+    -> *const std::os::raw::c_void
+  {
+    gl_function (symbol)
+  }
+
+  fn get_framebuffer_dimensions (&self) -> (u32, u32) {
+    // the dimensions are always valid so a poisoned lock can be recovered
+    *self.dimensions.lock().unwrap_or_else (|err| err.into_inner())
+  }
+
+  fn is_current (&self) -> bool {
+    self.current.get()
+  }
+
+  unsafe fn make_current (&self) {
+    self.current.set (true);
+  }
+}
+
+impl Drop for MockGlBackend {
+  fn drop (&mut self) {
+    self.dropped.store (true, std::sync::atomic::Ordering::SeqCst);
+  }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  functions                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Address of the mock implementation of a GL function, or null if there is
+/// none.
+fn gl_function (symbol : &str) -> *const std::os::raw::c_void {
+  use std::os::raw::c_void;
+  match symbol {
+    "glGetString" => get_string as *const c_void,
+    "glGetStringi" => get_string_i as *const c_void,
+    "glGetIntegerv" => get_integer_v as *const c_void,
+    "glGetInteger64v" => get_integer64_v as *const c_void,
+    "glGetIntegeri_v" => get_integer_i_v as *const c_void,
+    "glGetBooleanv" => get_boolean_v as *const c_void,
+    "glGetFloatv" => get_float_v as *const c_void,
+    "glGetError" => get_error as *const c_void,
+    "glGetFramebufferAttachmentParameteriv" =>
+      get_framebuffer_attachment_parameter_iv as *const c_void,
+    "glEnable" | "glDisable" | "glDrawBuffer" | "glReadBuffer" |
+    "glUseProgram" | "glBindVertexArray" | "glStencilMask" | "glClear"
+      => enum_noop as *const c_void,
+    "glBindFramebuffer" | "glBindBuffer" | "glHint" |
+    "glStencilMaskSeparate"
+      => enum_enum_noop as *const c_void,
+    "glPixelStorei" => enum_int_noop as *const c_void,
+    "glFlush" | "glFinish" => noop as *const c_void,
+    "glClearColor" => clear_color as *const c_void,
+    "glClearDepth" => clear_depth as *const c_void,
+    "glClearDepthf" => clear_depth_f as *const c_void,
+    "glClearStencil" => int_noop as *const c_void,
+    "glDepthMask" => boolean_noop as *const c_void,
+    "glColorMask" => color_mask as *const c_void,
+    "glScissor" | "glViewport" => rect_noop as *const c_void,
+    "glDrawBuffers" => draw_buffers as *const c_void,
+    _ => std::ptr::null()
+  }
+}
+
+//
+// private
+//
+
+extern "system" fn get_string (name : GLenum) -> *const GLubyte {
+  let string = match name {
+    gl::VENDOR => VENDOR,
+    gl::RENDERER => RENDERER,
+    gl::VERSION => VERSION,
+    gl::SHADING_LANGUAGE_VERSION => SHADING_LANGUAGE_VERSION,
+    gl::EXTENSIONS => b"\0",
+    _ => return std::ptr::null()
+  };
+  string.as_ptr()
+}
+
+extern "system" fn get_string_i (_name : GLenum, _index : GLuint)
+  -> *const GLubyte
+{
+  // there are no extensions
+  std::ptr::null()
+}
+
+extern "system" fn get_integer_v (pname : GLenum, data : *mut GLint) {
+  let values : &[GLint] = match pname {
+    gl::MAJOR_VERSION => &[3],
+    gl::MINOR_VERSION => &[3],
+    gl::CONTEXT_PROFILE_MASK =>
+      &[gl::CONTEXT_CORE_PROFILE_BIT as GLint],
+    gl::MAX_TEXTURE_SIZE | gl::MAX_CUBE_MAP_TEXTURE_SIZE |
+    gl::MAX_RENDERBUFFER_SIZE => &[4096],
+    gl::MAX_VIEWPORT_DIMS => &[4096, 4096],
+    gl::MAX_3D_TEXTURE_SIZE | gl::MAX_ARRAY_TEXTURE_LAYERS =>
+      &[256],
+    gl::MAX_DRAW_BUFFERS | gl::MAX_COLOR_ATTACHMENTS => &[8],
+    gl::MAX_COMBINED_TEXTURE_IMAGE_UNITS |
+    gl::MAX_VERTEX_ATTRIBS |
+    gl::MAX_UNIFORM_BUFFER_BINDINGS => &[16],
+    gl::MAX_SAMPLES => &[4],
+    gl::MAX_TEXTURE_BUFFER_SIZE => &[65536],
+    gl::VIEWPORT | gl::SCISSOR_BOX => &[0, 0, 0, 0],
+    _ => &[0]
+  };
+  unsafe {
+    std::ptr::copy_nonoverlapping (values.as_ptr(), data, values.len());
+  }
+}
+
+extern "system" fn get_integer64_v (_pname : GLenum, data : *mut GLint64) {
+  unsafe { *data = 0 };
+}
+
+extern "system" fn get_integer_i_v (
+  _target : GLenum, _index : GLuint, data : *mut GLint
+) {
+  unsafe { *data = 0 };
+}
+
+extern "system" fn get_boolean_v (_pname : GLenum, data : *mut GLboolean) {
+  unsafe { *data = gl::FALSE };
+}
+
+extern "system" fn get_float_v (_pname : GLenum, data : *mut GLfloat) {
+  unsafe { *data = 0.0 };
+}
+
+extern "system" fn get_error() -> GLenum {
+  gl::NO_ERROR
+}
+
+extern "system" fn get_framebuffer_attachment_parameter_iv (
+  _target : GLenum, _attachment : GLenum, pname : GLenum, data : *mut GLint
+) {
+  let value = match pname {
+    gl::FRAMEBUFFER_ATTACHMENT_COLOR_ENCODING =>
+      gl::LINEAR as GLint,
+    gl::FRAMEBUFFER_ATTACHMENT_DEPTH_SIZE => 24,
+    _ => 8
+  };
+  unsafe { *data = value };
+}
+
+extern "system" fn noop() { }
+extern "system" fn enum_noop (_ : GLenum) { }
+extern "system" fn enum_enum_noop (_ : GLenum, _ : GLenum) { }
+extern "system" fn enum_int_noop (_ : GLenum, _ : GLint) { }
+extern "system" fn int_noop (_ : GLint) { }
+extern "system" fn boolean_noop (_ : GLboolean) { }
+extern "system" fn clear_color (
+  _ : GLfloat, _ : GLfloat, _ : GLfloat, _ : GLfloat
+) { }
+extern "system" fn clear_depth (_ : GLdouble) { }
+extern "system" fn clear_depth_f (_ : GLfloat) { }
+extern "system" fn color_mask (
+  _ : GLboolean, _ : GLboolean, _ : GLboolean, _ : GLboolean
+) { }
+extern "system" fn rect_noop (
+  _ : GLint, _ : GLint, _ : GLsizei, _ : GLsizei
+) { }
+extern "system" fn draw_buffers (_ : GLsizei, _ : *const GLenum) { }
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  #[test]
+  fn test_mock_backend() {
+    use glium::backend::Backend;
+    let mock = MockGlBackend::new (320, 240);
+    let swaps = mock.swaps();
+    assert!(!mock.is_current());
+    unsafe { mock.make_current() };
+    assert!(mock.is_current());
+    assert_eq!(mock.get_framebuffer_dimensions(), (320, 240));
+    mock.swap_buffers().unwrap();
+    assert_eq!(swaps.load (std::sync::atomic::Ordering::SeqCst), 1);
+    let (dimensions, dropped) = (mock.dimensions(), mock.dropped());
+    *dimensions.lock().unwrap() = (640, 480);
+    assert_eq!(mock.get_framebuffer_dimensions(), (640, 480));
+    unsafe {
+      assert!(mock.get_proc_address ("glDrawArrays").is_null());
+      let get_string : extern "system" fn (GLenum) -> *const GLubyte
+        = std::mem::transmute (mock.get_proc_address ("glGetString"));
+      let version = std::ffi::CStr::from_ptr (
+        get_string (gl::VERSION) as *const std::os::raw::c_char);
+      assert_eq!(version.to_str().unwrap(), "3.3.0 Mock");
+      let get_integer_v : extern "system" fn (GLenum, *mut GLint)
+        = std::mem::transmute (mock.get_proc_address ("glGetIntegerv"));
+      let mut dims = [0; 2];
+      get_integer_v (gl::MAX_VIEWPORT_DIMS, dims.as_mut_ptr());
+      assert_eq!(dims, [4096, 4096]);
+    }
+    assert!(!dropped.load (std::sync::atomic::Ordering::SeqCst));
+    drop (mock);
+    assert!(dropped.load (std::sync::atomic::Ordering::SeqCst));
+  }
+}
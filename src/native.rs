@@ -0,0 +1,501 @@
+//! Native GLX and EGL interfaces underlying the SDL GL context, and GL
+//! context reset detection.
+//!
+//! SDL does not expose the GLX or EGL objects of the contexts it creates, but
+//! the current display and drawable can be queried through functions loaded
+//! with `SDL_GL_GetProcAddress`. These are used for window system extensions
+//! that glium does not know about.
+//!
+//! Note that on GLX `SDL_GL_GetProcAddress` returns a non-null pointer for
+//! *any* name, so functions are only loaded once the extensions providing
+//! them are known to be supported.
+//!
+//! Context resets (e.g. after a GPU hang or driver update) are detected with
+//! `glGetGraphicsResetStatus` where a robustness extension is supported.
+
+use sdl2_sys;
+
+use std::os::raw::{c_char, c_int, c_ulong, c_void};
+
+///////////////////////////////////////////////////////////////////////////////
+//  constants                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+const GLX_SCREEN              : c_int = 0x800C;
+const GLX_BACK_BUFFER_AGE_EXT : c_int = 0x20F4;
+const EGL_EXTENSIONS          : c_int = 0x3055;
+const EGL_DRAW                : c_int = 0x3059;
+const EGL_BUFFER_AGE_EXT      : c_int = 0x313D;
+const XLIB_LIBRARY            : &str  = "libX11.so.6";
+const GL_NO_ERROR             : u32   = 0;
+/// `SDL_GL_CONTEXT_PROFILE_ES`
+const GL_CONTEXT_PROFILE_ES   : c_int = 0x0004;
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Presentation counters reported by the window system
+/// (`GLX_OML_sync_control` or `EGL_CHROMIUM_sync_control`).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PresentStats {
+  /// Unadjusted system time of the most recent vertical retrace, in
+  /// microseconds
+  pub ust      : i64,
+  /// Media stream counter: the number of vertical retraces
+  pub msc      : i64,
+  /// Swap buffer counter: the number of completed swaps
+  pub sbc      : i64,
+  /// Vertical retraces per second, if known
+  pub msc_rate : Option <f64>
+}
+
+/// GLX or EGL functions for the context of a window backend.
+pub(crate) struct NativeGl {
+  api   : NativeApi,
+  reset : Option <ResetStatus>
+}
+
+//
+// private
+//
+
+struct Glx {
+  display         : *mut c_void,
+  get_drawable    : unsafe extern "C" fn() -> c_ulong,
+  get_sync_values : Option <unsafe extern "C" fn (
+    *mut c_void, c_ulong, *mut i64, *mut i64, *mut i64) -> c_int>,
+  get_msc_rate    : Option <unsafe extern "C" fn (
+    *mut c_void, c_ulong, *mut i32, *mut i32) -> c_int>,
+  /// `glXQueryDrawable`, loaded if `GLX_EXT_buffer_age` is supported
+  query_drawable  : Option <unsafe extern "C" fn (
+    *mut c_void, c_ulong, c_int, *mut u32)>,
+  compositor      : Option <XCompositor>
+}
+
+/// Xlib function and atom to query the owner of the `_NET_WM_CM_S<screen>`
+/// selection, which is held by the running compositing manager (ICCCM
+/// manager selection).
+struct XCompositor {
+  get_selection_owner : unsafe extern "C" fn (*mut c_void, c_ulong) -> c_ulong,
+  selection           : c_ulong
+}
+
+/// `glGetGraphicsResetStatus`, with whether a reset has been reported: the
+/// status returns to `GL_NO_ERROR` once the reset has completed.
+struct ResetStatus {
+  get_status : unsafe extern "system" fn() -> u32,
+  reset      : std::cell::Cell <bool>
+}
+
+struct Egl {
+  display         : *mut c_void,
+  get_surface     : unsafe extern "C" fn (c_int) -> *mut c_void,
+  get_sync_values : Option <unsafe extern "C" fn (
+    *mut c_void, *mut c_void, *mut i64, *mut i64, *mut i64) -> u32>,
+  /// `eglQuerySurface`, loaded if `EGL_EXT_buffer_age` is supported
+  query_surface   : Option <unsafe extern "C" fn (
+    *mut c_void, *mut c_void, c_int, *mut c_int) -> u32>,
+  /// `eglSwapBuffersWithDamageKHR` or `eglSwapBuffersWithDamageEXT`, loaded
+  /// only on drivers where `SDL_GL_SwapWindow` does nothing more than swap
+  swap_damage     : Option <unsafe extern "C" fn (
+    *mut c_void, *mut c_void, *const c_int, c_int) -> u32>
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  enums                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Whether the frames of a window pass through a compositing manager.
+///
+/// Composited frames are copied by the compositor on its own schedule, which
+/// typically adds a frame of latency and makes presentation timing less
+/// regular; unredirected fullscreen windows are flipped directly.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Compositing {
+  /// No compositing manager is running
+  NoCompositor,
+  /// A compositing manager is running and the window is fullscreen with
+  /// compositor bypass enabled, so it is expected to be unredirected
+  Bypassed,
+  /// The window is composited
+  Composited
+}
+
+enum NativeApi {
+  Glx (Glx),
+  Egl (Egl),
+  /// WGL, CGL, or a context that could not be identified
+  Unsupported
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+impl NativeGl {
+  /// Load the functions for the current context.
+  ///
+  /// The context of the window backend must be current. The loaded display is
+  /// the connection used by SDL for all windows, so this can be used from
+  /// any thread the context is made current on.
+  pub(crate) fn load() -> Self {
+    let driver = unsafe {
+      let driver = sdl2_sys::SDL_GetCurrentVideoDriver();
+      if driver.is_null() {
+        String::new()
+      } else {
+        std::ffi::CStr::from_ptr (driver).to_string_lossy().into_owned()
+      }
+    };
+    let api = match driver.as_str() {
+      "windows" | "cocoa" | "uikit" | "winrt" | "" => NativeApi::Unsupported,
+      "x11" => unsafe {
+        // SDL uses EGL on X11 for GLES contexts or when forced by a hint, in
+        // which case there is no current GLX context
+        Glx::load().map (NativeApi::Glx)
+          .or_else (|| Egl::load (true).map (NativeApi::Egl))
+          .unwrap_or (NativeApi::Unsupported)
+      },
+      _ => unsafe {
+        // other drivers (e.g. KMSDRM) present the frame themselves after the
+        // EGL swap, so it can only be replaced on Wayland
+        let swap_damage = driver == "wayland";
+        Egl::load (swap_damage).map (NativeApi::Egl)
+          .unwrap_or (NativeApi::Unsupported)
+      }
+    };
+    NativeGl { api, reset: unsafe { ResetStatus::load() } }
+  }
+
+  /// No native functions, e.g. for a mock backend.
+  #[cfg(feature = "glium")]
+  pub(crate) fn unsupported() -> Self {
+    NativeGl { api: NativeApi::Unsupported, reset: None }
+  }
+
+  /// Whether the context has been reset since it was created.
+  ///
+  /// Returns false if no robustness extension is supported. The context must
+  /// be current.
+  pub(crate) fn context_reset (&self) -> bool {
+    let reset = match self.reset {
+      Some (ref reset) => reset,
+      None => return false
+    };
+    if !reset.reset.get() && unsafe { (reset.get_status)() } != GL_NO_ERROR {
+      reset.reset.set (true);
+    }
+    reset.reset.get()
+  }
+
+  /// Query the presentation counters of the current drawable.
+  pub(crate) fn present_stats (&self) -> Option <PresentStats> {
+    let (mut ust, mut msc, mut sbc) = (0, 0, 0);
+    match self.api {
+      NativeApi::Glx (ref glx) => unsafe {
+        let get_sync_values = try_opt!(glx.get_sync_values);
+        let drawable = (glx.get_drawable)();
+        if get_sync_values (
+          glx.display, drawable, &mut ust, &mut msc, &mut sbc) == 0
+        {
+          return None
+        }
+        let msc_rate = glx.get_msc_rate.and_then (|get_msc_rate| {
+          let (mut numerator, mut denominator) = (0, 0);
+          if get_msc_rate (
+            glx.display, drawable, &mut numerator, &mut denominator) != 0 &&
+            denominator != 0
+          {
+            Some (numerator as f64 / denominator as f64)
+          } else {
+            None
+          }
+        });
+        Some (PresentStats { ust, msc, sbc, msc_rate })
+      },
+      NativeApi::Egl (ref egl) => unsafe {
+        let get_sync_values = try_opt!(egl.get_sync_values);
+        let surface = (egl.get_surface)(EGL_DRAW);
+        if get_sync_values (
+          egl.display, surface, &mut ust, &mut msc, &mut sbc) == 0
+        {
+          return None
+        }
+        Some (PresentStats { ust, msc, sbc, msc_rate: None })
+      },
+      NativeApi::Unsupported => None
+    }
+  }
+
+  /// Query the age of the back buffer of the current drawable.
+  pub(crate) fn buffer_age (&self) -> Option <u32> {
+    match self.api {
+      NativeApi::Glx (ref glx) => unsafe {
+        let query_drawable = try_opt!(glx.query_drawable);
+        let mut age = 0;
+        query_drawable (
+          glx.display, (glx.get_drawable)(), GLX_BACK_BUFFER_AGE_EXT, &mut age);
+        Some (age)
+      },
+      NativeApi::Egl (ref egl) => unsafe {
+        let query_surface = try_opt!(egl.query_surface);
+        let mut age = 0;
+        if query_surface (egl.display, (egl.get_surface)(EGL_DRAW),
+          EGL_BUFFER_AGE_EXT, &mut age) == 0
+        {
+          return None
+        }
+        Some (std::cmp::max (age, 0) as u32)
+      },
+      NativeApi::Unsupported => None
+    }
+  }
+
+  /// Whether a compositing manager is running on the screen of the context.
+  ///
+  /// Returns `None` unless running on GLX with Xlib available.
+  pub(crate) fn compositor_running (&self) -> Option <bool> {
+    match self.api {
+      NativeApi::Glx (ref glx) => unsafe {
+        let compositor = try_opt!(glx.compositor.as_ref());
+        Some ((compositor.get_selection_owner)(
+          glx.display, compositor.selection) != 0)
+      },
+      _ => None
+    }
+  }
+
+  /// Swap the buffers of the current surface passing the damaged regions to
+  /// the window system.
+  ///
+  /// Rectangles are `[left, bottom, width, height]`. Returns false without
+  /// swapping if this is not supported.
+  pub(crate) fn swap_buffers_with_damage (&self, rects : &[[c_int; 4]])
+    -> bool
+  {
+    match self.api {
+      NativeApi::Egl (ref egl) => unsafe {
+        let swap_damage = match egl.swap_damage {
+          Some (swap_damage) => swap_damage,
+          None => return false
+        };
+        let mut coords = Vec::with_capacity (4 * rects.len());
+        for rect in rects {
+          coords.extend_from_slice (rect);
+        }
+        swap_damage (egl.display, (egl.get_surface)(EGL_DRAW),
+          coords.as_ptr(), rects.len() as c_int) != 0
+      },
+      _ => false
+    }
+  }
+}
+
+/// The native display and function pointers are usable from any thread.
+unsafe impl Send for NativeGl { }
+
+impl Glx {
+  unsafe fn load() -> Option <Self> {
+    let get_display : unsafe extern "C" fn() -> *mut c_void
+      = try_opt!(proc_address ("glXGetCurrentDisplay"));
+    let get_context : unsafe extern "C" fn() -> *mut c_void
+      = try_opt!(proc_address ("glXGetCurrentContext"));
+    let get_drawable = try_opt!(proc_address ("glXGetCurrentDrawable"));
+    let query_context : unsafe extern "C" fn (
+      *mut c_void, *mut c_void, c_int, *mut c_int) -> c_int
+      = try_opt!(proc_address ("glXQueryContext"));
+    let query_extensions : unsafe extern "C" fn (*mut c_void, c_int)
+      -> *const c_char
+      = try_opt!(proc_address ("glXQueryExtensionsString"));
+    let display = get_display();
+    let context = get_context();
+    if display.is_null() || context.is_null() {
+      return None
+    }
+    let mut screen = 0;
+    query_context (display, context, GLX_SCREEN, &mut screen);
+    let extensions = c_string (query_extensions (display, screen));
+    let oml = has_extension (&extensions, "GLX_OML_sync_control");
+    let get_sync_values = if oml {
+      proc_address ("glXGetSyncValuesOML")
+    } else {
+      None
+    };
+    let get_msc_rate = if oml {
+      proc_address ("glXGetMscRateOML")
+    } else {
+      None
+    };
+    let query_drawable = if has_extension (&extensions, "GLX_EXT_buffer_age") {
+      proc_address ("glXQueryDrawable")
+    } else {
+      None
+    };
+    let compositor = XCompositor::load (display, screen);
+    Some (Glx {
+      display, get_drawable, get_sync_values, get_msc_rate, query_drawable,
+      compositor
+    })
+  }
+}
+
+impl XCompositor {
+  /// Load from Xlib, which is already loaded by the GLX implementation; the
+  /// library handle is deliberately never unloaded.
+  unsafe fn load (display : *mut c_void, screen : c_int) -> Option <Self> {
+    let library_name = std::ffi::CString::new (XLIB_LIBRARY).unwrap();
+    let library = sdl2_sys::SDL_LoadObject (library_name.as_ptr());
+    if library.is_null() {
+      return None
+    }
+    let intern_atom : unsafe extern "C" fn (
+      *mut c_void, *const c_char, c_int) -> c_ulong
+      = try_opt!(library_function (library, "XInternAtom"));
+    let get_selection_owner
+      = try_opt!(library_function (library, "XGetSelectionOwner"));
+    let selection_name
+      = std::ffi::CString::new (format!("_NET_WM_CM_S{}", screen)).unwrap();
+    let selection = intern_atom (display, selection_name.as_ptr(), 0);
+    if selection == 0 {
+      return None
+    }
+    Some (XCompositor { get_selection_owner, selection })
+  }
+}
+
+impl ResetStatus {
+  /// Load for the current context: `GL_KHR_robustness` (core in GL 4.5 and
+  /// GLES 3.2, suffixed `KHR` on GLES), `GL_ARB_robustness` or
+  /// `GL_EXT_robustness` (GLES).
+  unsafe fn load() -> Option <Self> {
+    let mut profile = 0;
+    sdl2_sys::SDL_GL_GetAttribute (
+      sdl2_sys::SDL_GLattr::SDL_GL_CONTEXT_PROFILE_MASK, &mut profile);
+    let es = profile & GL_CONTEXT_PROFILE_ES != 0;
+    let name = if gl_extension ("GL_KHR_robustness") {
+      if es {
+        "glGetGraphicsResetStatusKHR"
+      } else {
+        "glGetGraphicsResetStatus"
+      }
+    } else if gl_extension ("GL_ARB_robustness") {
+      "glGetGraphicsResetStatusARB"
+    } else if gl_extension ("GL_EXT_robustness") {
+      "glGetGraphicsResetStatusEXT"
+    } else {
+      return None
+    };
+    let get_status = try_opt!(proc_address (name));
+    Some (ResetStatus { get_status, reset: std::cell::Cell::new (false) })
+  }
+}
+
+impl Egl {
+  unsafe fn load (swap_damage : bool) -> Option <Self> {
+    let get_display : unsafe extern "C" fn() -> *mut c_void
+      = try_opt!(proc_address ("eglGetCurrentDisplay"));
+    let get_surface = try_opt!(proc_address ("eglGetCurrentSurface"));
+    let query_string : unsafe extern "C" fn (*mut c_void, c_int)
+      -> *const c_char
+      = try_opt!(proc_address ("eglQueryString"));
+    let display = get_display();
+    if display.is_null() {
+      return None
+    }
+    let extensions = c_string (query_string (display, EGL_EXTENSIONS));
+    let get_sync_values =
+      if has_extension (&extensions, "EGL_CHROMIUM_sync_control") {
+        proc_address ("eglGetSyncValuesCHROMIUM")
+      } else {
+        None
+      };
+    let query_surface = if has_extension (&extensions, "EGL_EXT_buffer_age") {
+      proc_address ("eglQuerySurface")
+    } else {
+      None
+    };
+    let swap_damage = if !swap_damage {
+      None
+    } else if has_extension (&extensions, "EGL_KHR_swap_buffers_with_damage") {
+      proc_address ("eglSwapBuffersWithDamageKHR")
+    } else if has_extension (&extensions, "EGL_EXT_swap_buffers_with_damage") {
+      proc_address ("eglSwapBuffersWithDamageEXT")
+    } else {
+      None
+    };
+    Some (Egl {
+      display, get_surface, get_sync_values, query_surface, swap_damage
+    })
+  }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  functions                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Load a function with `SDL_GL_GetProcAddress`.
+///
+/// `F` must be an `unsafe extern "C" fn` type matching the function, or
+/// `unsafe extern "system" fn` for GL functions.
+unsafe fn proc_address <F : Copy> (name : &str) -> Option <F> {
+  debug_assert_eq!(
+    std::mem::size_of::<F>(), std::mem::size_of::<*mut c_void>());
+  let name = std::ffi::CString::new (name).unwrap();
+  let address = sdl2_sys::SDL_GL_GetProcAddress (name.as_ptr());
+  if address.is_null() {
+    None
+  } else {
+    Some (std::mem::transmute_copy (&address))
+  }
+}
+
+/// Load a function from a library opened with `SDL_LoadObject`.
+///
+/// `F` must be an `unsafe extern "C" fn` type matching the function.
+unsafe fn library_function <F : Copy> (library : *mut c_void, name : &str)
+  -> Option <F>
+{
+  debug_assert_eq!(
+    std::mem::size_of::<F>(), std::mem::size_of::<*mut c_void>());
+  let name = std::ffi::CString::new (name).unwrap();
+  let address = sdl2_sys::SDL_LoadFunction (library, name.as_ptr());
+  if address.is_null() {
+    None
+  } else {
+    Some (std::mem::transmute_copy (&address))
+  }
+}
+
+/// Whether the current GL context supports the extension.
+unsafe fn gl_extension (name : &str) -> bool {
+  let name = std::ffi::CString::new (name).unwrap();
+  sdl2_sys::SDL_GL_ExtensionSupported (name.as_ptr())
+    == sdl2_sys::SDL_bool::SDL_TRUE
+}
+
+unsafe fn c_string (string : *const c_char) -> String {
+  if string.is_null() {
+    String::new()
+  } else {
+    std::ffi::CStr::from_ptr (string).to_string_lossy().into_owned()
+  }
+}
+
+fn has_extension (extensions : &str, extension : &str) -> bool {
+  extensions.split_whitespace().any (|name| name == extension)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  #[test]
+  fn test_has_extension() {
+    let extensions = "GLX_ARB_create_context GLX_OML_sync_control_ext \
+      GLX_OML_sync_control";
+    assert!(has_extension (extensions, "GLX_OML_sync_control"));
+    assert!(has_extension (extensions, "GLX_ARB_create_context"));
+    assert!(!has_extension (extensions, "GLX_EXT_buffer_age"));
+    assert!(!has_extension ("", "GLX_OML_sync_control"));
+  }
+}
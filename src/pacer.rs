@@ -0,0 +1,185 @@
+//! Capping the frame rate of the render thread.
+//!
+//! Without vsync, e.g. in menus or on machines where the driver forces it
+//! off, the render thread draws as fast as it can. A `FramePacer` spaces
+//! frames to a target rate by waiting after each buffer swap until the
+//! deadline of the next frame:
+//!
+//! ```ignore
+//! display.set_frame_pacer (Some (FramePacer::new (60.0)));
+//! loop {
+//!   let mut frame = display.draw();
+//!   // ... draw
+//!   frame.finish().unwrap();   // waits here after swapping
+//! }
+//! ```
+//!
+//! A pacer can also be used on its own by calling `wait` once per frame.
+//!
+//! The wait sleeps with `SDL_Delay` until `spin_threshold` before the
+//! deadline, then spins, since sleeps may overshoot by up to a scheduler
+//! tick. Deadlines follow a fixed cadence from the first frame, so time spent
+//! drawing and swapping, including swaps blocking on vsync, counts toward the
+//! frame: a frame that finishes late is not waited for, and a frame more than
+//! a whole frame late restarts the cadence rather than being followed by a
+//! burst of frames to catch up.
+
+use sdl2_sys;
+
+use timer::TimerResolution;
+
+///////////////////////////////////////////////////////////////////////////////
+//  constants                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Default time before a deadline spent spinning rather than sleeping, in
+/// microseconds
+pub const DEFAULT_SPIN_THRESHOLD_US : u64 = 2000;
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Waits between frames to cap the frame rate; see `pacer`.
+///
+/// The system timer resolution is raised while a pacer is alive.
+#[derive(Clone, Debug)]
+pub struct FramePacer {
+  frame_time     : std::time::Duration,
+  spin_threshold : std::time::Duration,
+  /// Deadline of the next frame, `None` before the first frame
+  deadline       : Option <std::time::Instant>,
+  late_frames    : u64,
+  _resolution    : TimerResolution
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+impl FramePacer {
+  /// Panics if `target_fps` is not positive.
+  pub fn new (target_fps : f64) -> Self {
+    FramePacer {
+      frame_time:     frame_time (target_fps),
+      spin_threshold:
+        std::time::Duration::from_micros (DEFAULT_SPIN_THRESHOLD_US),
+      deadline:       None,
+      late_frames:    0,
+      _resolution:    TimerResolution::acquire()
+    }
+  }
+
+  /// Panics if `target_fps` is not positive.
+  pub fn set_target_fps (&mut self, target_fps : f64) {
+    self.frame_time = frame_time (target_fps);
+    self.deadline   = None;
+  }
+
+  pub fn target_fps (&self) -> f64 {
+    1e9 / (self.frame_time.as_secs() as f64 * 1e9
+      + self.frame_time.subsec_nanos() as f64)
+  }
+
+  pub fn frame_time (&self) -> std::time::Duration {
+    self.frame_time
+  }
+
+  /// Time before each deadline spent spinning rather than sleeping.
+  ///
+  /// Larger values are more precise at the cost of CPU time.
+  pub fn set_spin_threshold (&mut self, spin_threshold : std::time::Duration) {
+    self.spin_threshold = spin_threshold;
+  }
+
+  /// Number of frames that finished after their deadline.
+  pub fn late_frames (&self) -> u64 {
+    self.late_frames
+  }
+
+  /// Wait until the deadline of the current frame, returning the time
+  /// waited; called once per frame after swapping buffers.
+  pub fn wait (&mut self) -> std::time::Duration {
+    let start = std::time::Instant::now();
+    let deadline = match self.deadline {
+      Some (deadline) => deadline,
+      None => {
+        self.deadline = Some (start + self.frame_time);
+        return std::time::Duration::default()
+      }
+    };
+    if start < deadline {
+      self.wait_until (deadline);
+    } else {
+      self.late_frames += 1;
+    }
+    let now = std::time::Instant::now();
+    self.deadline = Some (next_deadline (deadline, now, self.frame_time));
+    now - start
+  }
+
+  fn wait_until (&self, deadline : std::time::Instant) {
+    loop {
+      let now = std::time::Instant::now();
+      if now >= deadline {
+        break
+      }
+      let remaining = deadline - now;
+      if remaining > self.spin_threshold {
+        let sleep = remaining - self.spin_threshold;
+        let ms = sleep.as_secs().saturating_mul (1000)
+          + sleep.subsec_nanos() as u64 / 1_000_000;
+        if ms > 0 {
+          unsafe { sdl2_sys::SDL_Delay (ms.min (std::u32::MAX as u64) as u32) };
+          continue
+        }
+      }
+      std::thread::yield_now();
+    }
+  }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  functions                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+//
+// private
+//
+
+fn frame_time (target_fps : f64) -> std::time::Duration {
+  assert!(target_fps > 0.0, "target frame rate must be positive");
+  std::time::Duration::from_nanos ((1e9 / target_fps) as u64)
+}
+
+/// The deadline following `deadline` for a frame finished at `now`.
+fn next_deadline (
+  deadline   : std::time::Instant,
+  now        : std::time::Instant,
+  frame_time : std::time::Duration
+) -> std::time::Instant {
+  let next = deadline + frame_time;
+  if now < next {
+    next
+  } else {
+    // more than a frame late: restart the cadence
+    now + frame_time
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  #[test]
+  fn test_next_deadline() {
+    let ms = std::time::Duration::from_millis;
+    let start = std::time::Instant::now();
+    // on time or slightly late keeps the cadence
+    assert_eq!(next_deadline (start, start, ms (10)), start + ms (10));
+    assert_eq!(next_deadline (start, start + ms (4), ms (10)), start + ms (10));
+    // more than a frame late
+    assert_eq!(next_deadline (start, start + ms (25), ms (10)),
+      start + ms (35));
+    assert_eq!(frame_time (50.0), ms (20));
+  }
+}
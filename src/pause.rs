@@ -0,0 +1,241 @@
+//! Pausing and resuming the render loop from any thread.
+//!
+//! Besides explicit pauses, a window can be throttled or suspended while it
+//! is minimized or hidden by watching its visibility on the main thread with
+//! `EventForwarder::watch_visibility`:
+//!
+//! ```ignore
+//! bridge.forwarder().watch_visibility (window_id, display.pause_handle(),
+//!   VisibilityPolicy::Suspend);
+//! // on the render thread, once per frame
+//! display.wait_while_paused().unwrap();
+//! ```
+//!
+//! Full rate is restored as soon as the window is restored or shown again.
+//! Some platforms (macOS) also report windows covered by other windows as
+//! hidden.
+
+use SdlGlWindowBackend;
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Shared pause state for a render loop.
+///
+/// The render thread calls `SdlGliumDisplayFacade::wait_while_paused` (or
+/// `PauseHandle::wait_while_paused` when not using glium) once per frame;
+/// while paused this blocks until `resume` is called from any thread.
+#[derive(Clone)]
+pub struct PauseHandle {
+  shared : std::sync::Arc <(std::sync::Mutex <PauseState>, std::sync::Condvar)>
+}
+
+//
+// private
+//
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct PauseState {
+  paused          : bool,
+  release_context : bool,
+  /// Set while the window is hidden
+  hidden          : Option <VisibilityPolicy>,
+  /// Last frame issued while throttled
+  last_frame      : Option <std::time::Instant>
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  enums                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+/// What to do while a watched window is minimized or hidden.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VisibilityPolicy {
+  /// Stop issuing frames, keeping the GL context current
+  Suspend,
+  /// Issue frames at the given rate per second
+  Throttle (u32)
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+impl PauseHandle {
+  pub fn new() -> Self {
+    PauseHandle {
+      shared: std::sync::Arc::new ((
+        std::sync::Mutex::new (PauseState::default()),
+        std::sync::Condvar::new()))
+    }
+  }
+
+  /// Stop issuing frames, keeping the GL context current on the render
+  /// thread.
+  pub fn pause (&self) {
+    self.update (|state| {
+      state.paused          = true;
+      state.release_context = false;
+    });
+  }
+
+  /// Stop issuing frames and release the GL context while paused, e.g. so
+  /// that it can be used on another thread or torn down by the platform.
+  pub fn pause_and_release_context (&self) {
+    self.update (|state| {
+      state.paused          = true;
+      state.release_context = true;
+    });
+  }
+
+  /// Resume issuing frames.
+  ///
+  /// A hidden window remains throttled or suspended until it is shown.
+  pub fn resume (&self) {
+    self.update (|state| {
+      state.paused          = false;
+      state.release_context = false;
+    });
+  }
+
+  pub fn is_paused (&self) -> bool {
+    self.lock().paused
+  }
+
+  /// Returns true while the window is throttled or suspended because it is
+  /// hidden.
+  pub fn is_hidden (&self) -> bool {
+    self.lock().hidden.is_some()
+  }
+
+  /// Apply a visibility policy while the window is hidden, or restore the
+  /// full rate with `None`; called by the event forwarder for watched
+  /// windows.
+  pub fn set_hidden (&self, hidden : Option <VisibilityPolicy>) {
+    self.update (|state| state.hidden = hidden);
+  }
+
+  /// Block the calling (render) thread while paused, suspended or until the
+  /// next frame when throttled, returning true if it blocked.
+  ///
+  /// If paused with `pause_and_release_context` the context of the window
+  /// backend is released before blocking and made current again before
+  /// returning, and the error is returned if it could not be made current.
+  pub fn wait_while_paused (&self, window_backend : &SdlGlWindowBackend)
+    -> Result <bool, String>
+  {
+    let release_context = match self.paused() {
+      Some (release_context) => release_context,
+      None => return Ok (self.wait_throttled())
+    };
+    if release_context {
+      window_backend.gl_release_current();
+    }
+    self.wait_resumed();
+    if release_context {
+      try!{ unsafe { window_backend.gl_make_current_retry() } };
+    }
+    Ok (true)
+  }
+
+  /// If paused or suspended, returns whether the context should be released.
+  pub(crate) fn paused (&self) -> Option <bool> {
+    let state = self.lock();
+    if state.is_stopped() {
+      Some (state.release_context)
+    } else {
+      None
+    }
+  }
+
+  /// Block until resumed and shown.
+  pub(crate) fn wait_resumed (&self) {
+    let (_, condvar) = &*self.shared;
+    let mut state = self.lock();
+    while state.is_stopped() {
+      state = condvar.wait (state).unwrap_or_else (|err| err.into_inner());
+    }
+  }
+
+  /// While throttled, block until the next frame is due or the throttle is
+  /// lifted, returning true if it blocked.
+  pub(crate) fn wait_throttled (&self) -> bool {
+    let (_, condvar) = &*self.shared;
+    let mut state = self.lock();
+    let mut waited = false;
+    while let Some (VisibilityPolicy::Throttle (frame_rate)) = state.hidden {
+      let now = std::time::Instant::now();
+      let due = state.last_frame.map_or (now, |last_frame| last_frame
+        + std::time::Duration::from_secs (1) / std::cmp::max (1, frame_rate));
+      if due <= now {
+        state.last_frame = Some (now);
+        break
+      }
+      waited = true;
+      state = condvar.wait_timeout (state, due - now)
+        .unwrap_or_else (|err| err.into_inner()).0;
+    }
+    waited
+  }
+
+  fn update <F : FnOnce (&mut PauseState)> (&self, f : F) {
+    let (_, condvar) = &*self.shared;
+    f (&mut self.lock());
+    condvar.notify_all();
+  }
+
+  fn lock (&self) -> std::sync::MutexGuard <'_, PauseState> {
+    let (mutex, _) = &*self.shared;
+    // the state is always valid so a poisoned lock can be recovered
+    mutex.lock().unwrap_or_else (|err| err.into_inner())
+  }
+}
+
+impl PauseState {
+  fn is_stopped (&self) -> bool {
+    self.paused || self.hidden == Some (VisibilityPolicy::Suspend)
+  }
+}
+
+impl Default for PauseHandle {
+  fn default() -> Self {
+    PauseHandle::new()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  #[test]
+  fn test_pause_resume() {
+    let pause = PauseHandle::new();
+    assert_eq!(pause.paused(), None);
+    pause.pause_and_release_context();
+    assert_eq!(pause.paused(), Some (true));
+    let waiter = {
+      let pause = pause.clone();
+      std::thread::spawn (move || pause.wait_resumed())
+    };
+    pause.resume();
+    waiter.join().unwrap();
+    assert!(!pause.is_paused());
+  }
+  #[test]
+  fn test_hidden() {
+    let pause = PauseHandle::new();
+    pause.set_hidden (Some (VisibilityPolicy::Suspend));
+    assert_eq!(pause.paused(), Some (false));
+    // resuming an explicit pause does not show the window
+    pause.pause();
+    pause.resume();
+    assert!(pause.is_hidden());
+    pause.set_hidden (Some (VisibilityPolicy::Throttle (20)));
+    assert_eq!(pause.paused(), None);
+    // the first throttled frame is not delayed, the second is
+    assert!(!pause.wait_throttled());
+    assert!(pause.wait_throttled());
+    pause.set_hidden (None);
+    assert!(!pause.wait_throttled());
+  }
+}
@@ -0,0 +1,207 @@
+//! Processing of mouse events on the main thread before they are forwarded.
+//!
+//! Click counts are computed by the `EventForwarder` rather than taken from
+//! SDL, whose reporting of multiple clicks differs between platforms and
+//! versions. Mouse wheel events are normalized into `Scroll` values with a
+//! consistent direction.
+
+use sdl2;
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Limits within which consecutive presses of a button count as a multiple
+/// click.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClickThresholds {
+  /// Maximum time between presses
+  pub interval : std::time::Duration,
+  /// Maximum distance between presses in window coordinates, in each axis
+  pub distance : i32
+}
+
+/// Counts consecutive clicks.
+#[derive(Clone, Debug)]
+pub(crate) struct ClickCounter {
+  thresholds : ClickThresholds,
+  last_press : Option <Press>
+}
+
+/// A normalized mouse wheel or touchpad scroll.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Scroll {
+  /// Horizontal scroll amount, positive to the right
+  pub dx      : f32,
+  /// Vertical scroll amount, positive away from the user (up)
+  pub dy      : f32,
+  /// True if the amounts are pixel-precise deltas from a touchpad or other
+  /// high-resolution device rather than wheel notches.
+  ///
+  /// The `sdl2` crate only reports whole notches, so this is currently
+  /// always false.
+  pub precise : bool,
+  /// Horizontal amount as reported by SDL
+  pub raw_x   : i32,
+  /// Vertical amount as reported by SDL
+  pub raw_y   : i32,
+  /// SDL reported the amounts as flipped by "natural" scrolling
+  pub flipped : bool
+}
+
+//
+// private
+//
+
+#[derive(Clone, Copy, Debug)]
+struct Press {
+  window_id : u32,
+  button    : sdl2::mouse::MouseButton,
+  position  : (i32, i32),
+  /// SDL event timestamp in milliseconds
+  timestamp : u32,
+  clicks    : u32
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  enums                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Direction of normalized scroll amounts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScrollDirection {
+  /// Follow the user's "natural" scrolling preference, i.e. scrolling moves
+  /// content the way it does in other applications (the default)
+  User,
+  /// Undo natural scrolling so that the sign always follows the physical
+  /// direction of the wheel, e.g. for zooming.
+  ///
+  /// This is only possible where SDL reports natural scrolling (macOS);
+  /// elsewhere the setting is applied by the window system unreported.
+  Physical
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+impl Default for ClickThresholds {
+  /// 500 milliseconds and 4 units, the usual desktop defaults.
+  fn default() -> Self {
+    ClickThresholds {
+      interval: std::time::Duration::from_millis (500),
+      distance: 4
+    }
+  }
+}
+
+impl ClickCounter {
+  pub(crate) fn new (thresholds : ClickThresholds) -> Self {
+    ClickCounter { thresholds, last_press: None }
+  }
+
+  pub(crate) fn set_thresholds (&mut self, thresholds : ClickThresholds) {
+    self.thresholds = thresholds;
+  }
+
+  /// Register a button press, returning the click count: 1 for a single
+  /// click, 2 for a double click, and so on.
+  pub(crate) fn press (&mut self,
+    window_id : u32,
+    button    : sdl2::mouse::MouseButton,
+    position  : (i32, i32),
+    timestamp : u32
+  ) -> u32 {
+    let interval = self.thresholds.interval;
+    let interval_ms
+      = interval.as_secs() * 1000 + interval.subsec_nanos() as u64 / 1_000_000;
+    let distance = self.thresholds.distance;
+    let clicks = match self.last_press {
+      Some (last) if last.window_id == window_id && last.button == button &&
+        timestamp.wrapping_sub (last.timestamp) as u64 <= interval_ms &&
+        (position.0 - last.position.0).abs() <= distance &&
+        (position.1 - last.position.1).abs() <= distance
+        => last.clicks + 1,
+      _ => 1
+    };
+    self.last_press = Some (Press {
+      window_id, button, position, timestamp, clicks
+    });
+    clicks
+  }
+
+  /// Click count of the press that a button release ends.
+  pub(crate) fn release (&self,
+    window_id : u32, button : sdl2::mouse::MouseButton
+  ) -> u32 {
+    match self.last_press {
+      Some (last) if last.window_id == window_id && last.button == button =>
+        last.clicks,
+      _ => 1
+    }
+  }
+}
+
+impl Scroll {
+  /// Normalize the amounts of an SDL mouse wheel event.
+  pub fn from_wheel (
+    x         : i32,
+    y         : i32,
+    direction : sdl2::mouse::MouseWheelDirection,
+    mode      : ScrollDirection
+  ) -> Self {
+    let flipped = direction == sdl2::mouse::MouseWheelDirection::Flipped;
+    // flipped amounts are already negated by SDL
+    let sign = if flipped && mode == ScrollDirection::Physical {
+      -1.0
+    } else {
+      1.0
+    };
+    Scroll {
+      dx:      sign * x as f32,
+      dy:      sign * y as f32,
+      precise: false,
+      raw_x:   x,
+      raw_y:   y,
+      flipped
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  #[test]
+  fn test_click_counter() {
+    use sdl2::mouse::MouseButton;
+    let mut counter = ClickCounter::new (ClickThresholds::default());
+    assert_eq!(counter.press (1, MouseButton::Left, (10, 10), 1000), 1);
+    assert_eq!(counter.release (1, MouseButton::Left), 1);
+    assert_eq!(counter.press (1, MouseButton::Left, (12, 8), 1400), 2);
+    assert_eq!(counter.press (1, MouseButton::Left, (12, 8), 1800), 3);
+    assert_eq!(counter.release (1, MouseButton::Left), 3);
+    assert_eq!(counter.release (1, MouseButton::Right), 1);
+    // too late, too far, another button, another window
+    assert_eq!(counter.press (1, MouseButton::Left, (12, 8), 2301), 1);
+    assert_eq!(counter.press (1, MouseButton::Left, (20, 8), 2400), 1);
+    assert_eq!(counter.press (1, MouseButton::Right, (20, 8), 2500), 1);
+    assert_eq!(counter.press (2, MouseButton::Right, (20, 8), 2600), 1);
+    // the SDL timestamp wraps around after 49 days
+    counter.press (2, MouseButton::Right, (20, 8), std::u32::MAX - 100);
+    assert_eq!(counter.press (2, MouseButton::Right, (20, 8), 100), 2);
+  }
+  #[test]
+  fn test_scroll() {
+    use sdl2::mouse::MouseWheelDirection;
+    let scroll = Scroll::from_wheel (
+      1, -2, MouseWheelDirection::Flipped, ScrollDirection::User);
+    assert_eq!((scroll.dx, scroll.dy, scroll.flipped), (1.0, -2.0, true));
+    let scroll = Scroll::from_wheel (
+      1, -2, MouseWheelDirection::Flipped, ScrollDirection::Physical);
+    assert_eq!((scroll.dx, scroll.dy), (-1.0, 2.0));
+    assert_eq!((scroll.raw_x, scroll.raw_y), (1, -2));
+    let scroll = Scroll::from_wheel (
+      0, 3, MouseWheelDirection::Normal, ScrollDirection::Physical);
+    assert_eq!((scroll.dx, scroll.dy, scroll.flipped), (0.0, 3.0, false));
+  }
+}
@@ -0,0 +1,375 @@
+//! Presenting frames from a dedicated thread.
+//!
+//! With vsync, swapping buffers blocks until vertical retrace, stalling the
+//! render thread for up to a refresh interval. In present mode the render
+//! thread instead draws each frame into an offscreen texture, and a present
+//! thread with a context sharing objects with the render context blits the
+//! texture to the window and swaps, so that only the present thread waits
+//! for vsync.
+//!
+//! The `PresentContext` is created on the main thread, before the backend is
+//! sent to the render thread, and the present thread is started from the
+//! display:
+//!
+//! ```ignore
+//! let present_context
+//!   = unsafe { window_backend.build_present_context() }.unwrap();
+//! // ... on the render thread
+//! let mut presenter
+//!   = display.start_present_thread (present_context, 3).unwrap();
+//! loop {
+//!   let mut frame = presenter.draw (&display).unwrap();
+//!   frame.clear_color (0.0, 0.0, 0.0, 1.0);
+//!   // ... draw to the frame, which is a `SimpleFrameBuffer`
+//!   frame.finish();
+//! }
+//! ```
+//!
+//! Frames are handed over with fences, so neither thread waits for the other
+//! on the CPU. When the render thread is faster than the display, the present
+//! thread presents the latest finished frame and skips older ones; the render
+//! thread only blocks when every texture is in use. `SdlGliumDisplayFacade::
+//! draw` must not be used while presenting, since the render context would
+//! swap the window as well.
+//!
+//! Both contexts are current with the window at the same time on different
+//! threads, which is not supported where a window surface may only be
+//! current on one thread (EGL).
+
+use glium;
+use sdl2;
+use sdl2_sys;
+
+use gl;
+
+use {loader, SdlGlWindowBackend, SdlGliumDisplayFacade};
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// A GL context sharing objects with the context of a window backend, to be
+/// given to `SdlGliumDisplayFacade::start_present_thread`.
+pub struct PresentContext {
+  window_raw     : *mut sdl2_sys::SDL_Window,
+  gl_context_raw : sdl2_sys::SDL_GLContext
+}
+
+/// Render thread end of a present thread, see `present`.
+///
+/// The present thread is stopped when this is dropped.
+pub struct Presenter {
+  /// Functions of the render context
+  gl       : gl::Gl,
+  slots    : Vec <Slot>,
+  sender   : Option <std::sync::mpsc::Sender <PresentRequest>>,
+  recycled : std::sync::mpsc::Receiver <(usize, Fence)>,
+  thread   : Option <std::thread::JoinHandle <()>>
+}
+
+/// A frame drawn into an offscreen texture, presented when finished or
+/// dropped.
+pub struct PresentFrame <'a> {
+  framebuffer : glium::framebuffer::SimpleFrameBuffer <'a>,
+  gl          : &'a gl::Gl,
+  sender      : &'a std::sync::mpsc::Sender <PresentRequest>,
+  slot        : usize,
+  texture     : gl::types::GLuint,
+  dimensions  : (u32, u32)
+}
+
+//
+// private
+//
+
+struct Slot {
+  texture : glium::texture::Texture2d,
+  /// Set while the texture is owned by the present thread
+  in_use  : bool,
+  /// Signalled when the present thread has finished reading the texture
+  read    : Option <Fence>
+}
+
+/// A finished frame handed to the present thread.
+struct PresentRequest {
+  slot       : usize,
+  texture    : gl::types::GLuint,
+  dimensions : (u32, u32),
+  /// Signalled when the frame has been drawn
+  drawn      : Fence
+}
+
+/// A sync object passed between the contexts.
+struct Fence (gl::types::GLsync);
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+impl PresentContext {
+  /// See `SdlGlWindowBackend::build_present_context`.
+  pub(crate) unsafe fn new (window_backend : &SdlGlWindowBackend)
+    -> Result <Self, String>
+  {
+    let gl_context_raw = try!{ loader::create_shared_context (window_backend) };
+    Ok (PresentContext {
+      window_raw: window_backend.window_raw.as_ptr(),
+      gl_context_raw
+    })
+  }
+}
+
+/// The context is only made current on the thread it is sent to.
+unsafe impl Send for PresentContext { }
+
+impl Drop for PresentContext {
+  fn drop (&mut self) {
+    unsafe { sdl2_sys::SDL_GL_DeleteContext (self.gl_context_raw) };
+  }
+}
+
+impl Presenter {
+  /// See `SdlGliumDisplayFacade::start_present_thread`.
+  pub(crate) fn new (
+    display         : &SdlGliumDisplayFacade,
+    present_context : PresentContext,
+    buffers         : usize
+  ) -> Result <Self, String> {
+    use glium::backend::Backend;
+    let gl = gl::Gl::load_with (|symbol| unsafe {
+      display.window_backend.get_proc_address (symbol) as *const _
+    });
+    let dimensions = display.window_backend.get_framebuffer_dimensions();
+    let mut slots = Vec::new();
+    for _ in 0..std::cmp::max (2, buffers) {
+      slots.push (Slot {
+        texture: try!{ create_texture (display, dimensions) },
+        in_use:  false,
+        read:    None
+      });
+    }
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let (recycle, recycled) = std::sync::mpsc::channel();
+    let (started, start) = std::sync::mpsc::channel();
+    let thread = try!{
+      std::thread::Builder::new().name ("present".to_string())
+        .spawn (move ||
+          present_thread (present_context, started, receiver, recycle))
+        .map_err (|err| err.to_string())
+    };
+    // the present thread reports whether its context could be made current
+    // before receiving frames
+    let result = start.recv()
+      .unwrap_or_else (|_| Err ("the present thread has stopped".to_string()));
+    if let Err (err) = result {
+      let _ = thread.join();
+      return Err (err)
+    }
+    Ok (Presenter {
+      gl,
+      slots,
+      sender:   Some (sender),
+      recycled,
+      thread:   Some (thread)
+    })
+  }
+
+  /// Start drawing a frame into a free texture, blocking if all textures
+  /// are in use by the present thread.
+  ///
+  /// Textures are recreated when the size of the window changes. Fails if
+  /// the present thread has stopped.
+  pub fn draw (&mut self, display : &SdlGliumDisplayFacade)
+    -> Result <PresentFrame <'_>, String>
+  {
+    use glium::GlObject;
+    use glium::backend::Backend;
+    display.render_thread.debug_assert_current();
+    while let Ok ((slot, read)) = self.recycled.try_recv() {
+      self.recycle (slot, read);
+    }
+    let free = loop {
+      if let Some (free) = self.slots.iter().position (|slot| !slot.in_use) {
+        break free
+      }
+      match self.recycled.recv() {
+        Ok ((slot, read)) => self.recycle (slot, read),
+        Err (_) => return Err ("the present thread has stopped".to_string())
+      }
+    };
+    let dimensions = display.window_backend.get_framebuffer_dimensions();
+    let slot = &mut self.slots[free];
+    if let Some (read) = slot.read.take() {
+      // the present thread may still be reading the texture
+      unsafe {
+        self.gl.WaitSync (read.0, 0, gl::TIMEOUT_IGNORED);
+        self.gl.DeleteSync (read.0);
+      }
+    }
+    let dimensions = (std::cmp::max (1, dimensions.0),
+      std::cmp::max (1, dimensions.1));
+    if (slot.texture.width(), slot.texture.height()) != dimensions {
+      slot.texture = try!{ create_texture (display, dimensions) };
+    }
+    let framebuffer = try!{
+      glium::framebuffer::SimpleFrameBuffer::new (&display.glium_context,
+        &slot.texture)
+        .map_err (|err| format!("{:?}", err))
+    };
+    slot.in_use = true;
+    Ok (PresentFrame {
+      framebuffer,
+      gl:         &self.gl,
+      sender:     self.sender.as_ref().unwrap(),
+      slot:       free,
+      texture:    slot.texture.get_id(),
+      dimensions
+    })
+  }
+
+  fn recycle (&mut self, slot : usize, read : Fence) {
+    self.slots[slot].in_use = false;
+    self.slots[slot].read = Some (read);
+  }
+}
+
+impl Drop for Presenter {
+  fn drop (&mut self) {
+    // the present thread stops when the sender is dropped
+    drop (self.sender.take());
+    if let Some (thread) = self.thread.take() {
+      let _ = thread.join();
+    }
+    let mut fences = self.slots.iter_mut()
+      .filter_map (|slot| slot.read.take()).collect::<Vec <_>>();
+    fences.extend (self.recycled.try_iter().map (|(_, read)| read));
+    for fence in fences {
+      unsafe { self.gl.DeleteSync (fence.0) };
+    }
+  }
+}
+
+impl <'a> PresentFrame <'a> {
+  /// Hand the frame to the present thread.
+  pub fn finish (self) { }
+}
+
+impl <'a> std::ops::Deref for PresentFrame <'a> {
+  type Target = glium::framebuffer::SimpleFrameBuffer <'a>;
+  fn deref (&self) -> &Self::Target {
+    &self.framebuffer
+  }
+}
+
+impl <'a> std::ops::DerefMut for PresentFrame <'a> {
+  fn deref_mut (&mut self) -> &mut Self::Target {
+    &mut self.framebuffer
+  }
+}
+
+impl <'a> Drop for PresentFrame <'a> {
+  fn drop (&mut self) {
+    let drawn = unsafe {
+      let fence = self.gl.FenceSync (gl::SYNC_GPU_COMMANDS_COMPLETE, 0);
+      // the fence must reach the GPU before the present thread waits on it
+      self.gl.Flush();
+      Fence (fence)
+    };
+    let request = PresentRequest {
+      slot: self.slot, texture: self.texture, dimensions: self.dimensions, drawn
+    };
+    if let Err (err) = self.sender.send (request) {
+      // the present thread has stopped
+      unsafe { self.gl.DeleteSync ((err.0).drawn.0) };
+    }
+  }
+}
+
+/// Sync objects are shared between the contexts.
+unsafe impl Send for Fence { }
+
+///////////////////////////////////////////////////////////////////////////////
+//  functions                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+//
+// private
+//
+
+fn create_texture (
+  display         : &SdlGliumDisplayFacade,
+  (width, height) : (u32, u32)
+) -> Result <glium::texture::Texture2d, String> {
+  glium::texture::Texture2d::empty_with_format (&display.glium_context,
+    glium::texture::UncompressedFloatFormat::U8U8U8U8,
+    glium::texture::MipmapsOption::NoMipmap,
+    std::cmp::max (1, width), std::cmp::max (1, height)
+  ).map_err (|err| format!("{:?}", err))
+}
+
+/// Presents the latest frame received until the render thread drops the
+/// sender.
+///
+/// Whether the context could be made current is sent to `started` first.
+fn present_thread (
+  context  : PresentContext,
+  started  : std::sync::mpsc::Sender <Result <(), String>>,
+  receiver : std::sync::mpsc::Receiver <PresentRequest>,
+  recycle  : std::sync::mpsc::Sender <(usize, Fence)>
+) {
+  unsafe {
+    if 0 != sdl2_sys::SDL_GL_MakeCurrent (
+      context.window_raw, context.gl_context_raw
+    ) {
+      let _ = started.send (Err (format!(
+        "failed to make present context current: {}", sdl2::get_error())));
+      return
+    }
+  }
+  let _ = started.send (Ok (()));
+  let gl = gl::Gl::load_with (|symbol| match std::ffi::CString::new (symbol) {
+    Ok (symbol) => unsafe {
+      sdl2_sys::SDL_GL_GetProcAddress (symbol.as_ptr()) as *const _
+    },
+    Err (_) => std::ptr::null()
+  });
+  // framebuffers are not shared, so the present context has its own
+  let mut fbo = 0;
+  unsafe { gl.GenFramebuffers (1, &mut fbo) };
+  while let Ok (mut request) = receiver.recv() {
+    // skip to the latest frame, returning the others unread
+    while let Ok (newer) = receiver.try_recv() {
+      let _ = recycle.send ((request.slot, request.drawn));
+      request = newer;
+    }
+    let (mut width, mut height) = (0, 0);
+    unsafe {
+      gl.WaitSync (request.drawn.0, 0, gl::TIMEOUT_IGNORED);
+      gl.DeleteSync (request.drawn.0);
+      sdl2_sys::SDL_GL_GetDrawableSize (context.window_raw,
+        &mut width, &mut height);
+      gl.BindFramebuffer (gl::READ_FRAMEBUFFER, fbo);
+      gl.FramebufferTexture2D (gl::READ_FRAMEBUFFER, gl::COLOR_ATTACHMENT0,
+        gl::TEXTURE_2D, request.texture, 0);
+      gl.BindFramebuffer (gl::DRAW_FRAMEBUFFER, 0);
+      // the frame is scaled if the window has been resized since it was
+      // drawn
+      let (texture_width, texture_height) = request.dimensions;
+      gl.BlitFramebuffer (0, 0, texture_width as i32, texture_height as i32,
+        0, 0, width, height, gl::COLOR_BUFFER_BIT, gl::LINEAR);
+    }
+    let read = Fence (unsafe {
+      gl.FenceSync (gl::SYNC_GPU_COMMANDS_COMPLETE, 0)
+    });
+    unsafe { sdl2_sys::SDL_GL_SwapWindow (context.window_raw) };
+    if let Err (std::sync::mpsc::SendError ((_, read))) =
+      recycle.send ((request.slot, read))
+    {
+      unsafe { gl.DeleteSync (read.0) };
+    }
+  }
+  unsafe {
+    gl.DeleteFramebuffers (1, &fbo);
+    sdl2_sys::SDL_GL_MakeCurrent (context.window_raw, std::ptr::null_mut());
+  }
+}
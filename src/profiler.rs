@@ -0,0 +1,273 @@
+//! GPU time measurement with timer queries.
+//!
+//! `SdlGliumDisplayFacade::gpu_timer` returns a scope that records a
+//! `GL_TIMESTAMP` with `glQueryCounter` when created and another when
+//! dropped, so the GPU time of a pass is measured by keeping the scope alive
+//! while its commands are issued:
+//!
+//! ```ignore
+//! let mut frame = display.draw();
+//! {
+//!   let _timer = display.gpu_timer ("shadows");
+//!   draw_shadows (&mut frame);
+//! }
+//! {
+//!   let _timer = display.gpu_timer ("lighting");
+//!   draw_lighting (&mut frame);
+//! }
+//! frame.finish().unwrap();
+//! for timing in display.take_gpu_timings() {
+//!   println!("frame {} {}: {:?}", timing.frame, timing.name, timing.time);
+//! }
+//! ```
+//!
+//! Results are collected without waiting on the GPU: before each buffer swap
+//! the facade reads back the queries whose results are available, so timings
+//! typically arrive a few frames after they were recorded. Scopes may be
+//! nested or overlap. Query objects are reused once their results have been
+//! read.
+//!
+//! With the `tracy` feature the scopes are also reported to Tracy as GPU
+//! zones, see `tracy`.
+//!
+//! Timer queries require OpenGL 3.3 or `GL_ARB_timer_query`; where
+//! `glQueryCounter` cannot be loaded, e.g. on OpenGL ES, scopes record
+//! nothing.
+
+use gl;
+
+use tracy;
+
+///////////////////////////////////////////////////////////////////////////////
+//  constants                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Bound on scopes awaiting results; scopes beyond it record nothing, e.g.
+/// if the GPU has stopped making progress
+const MAX_PENDING : usize = 256;
+/// Bound on timings kept until taken, dropping the oldest
+const MAX_TIMINGS : usize = 1024;
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// GPU time of a scope, see `SdlGliumDisplayFacade::take_gpu_timings`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GpuTiming {
+  pub name  : String,
+  /// Number of the frame the scope was recorded in, counting buffer swaps
+  /// since the display was built
+  pub frame : u64,
+  /// Time between the GPU reaching the start and the end of the scope
+  pub time  : std::time::Duration
+}
+
+/// A scope being measured, created with `SdlGliumDisplayFacade::gpu_timer`.
+///
+/// The end timestamp is recorded when this is dropped.
+#[must_use = "the scope ends when the timer is dropped"]
+pub struct GpuTimer <'a> {
+  profiler : &'a std::cell::RefCell <GpuProfiler>,
+  gl       : &'a gl::Gl,
+  /// `None` if nothing is recorded
+  id       : Option <u64>
+}
+
+/// Render thread state of the GPU timers of a display.
+#[derive(Default)]
+pub(crate) struct GpuProfiler {
+  supported : bool,
+  frame     : u64,
+  next_id   : u64,
+  /// Query objects available for reuse
+  free      : Vec <gl::types::GLuint>,
+  /// Scopes in the order they were started
+  pending   : std::collections::VecDeque <PendingTimer>,
+  timings   : std::collections::VecDeque <GpuTiming>,
+  tracy     : tracy::GpuContext
+}
+
+//
+// private
+//
+
+struct PendingTimer {
+  id    : u64,
+  name  : String,
+  frame : u64,
+  start : gl::types::GLuint,
+  /// `None` while the scope is alive
+  end   : Option <gl::types::GLuint>,
+  zone  : tracy::GpuZone
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+impl <'a> GpuTimer <'a> {
+  pub(crate) fn new (
+    profiler : &'a std::cell::RefCell <GpuProfiler>,
+    gl       : &'a gl::Gl,
+    name     : &str
+  ) -> Self {
+    let id = profiler.borrow_mut().begin (gl, name);
+    GpuTimer { profiler, gl, id }
+  }
+}
+
+impl <'a> Drop for GpuTimer <'a> {
+  fn drop (&mut self) {
+    if let Some (id) = self.id {
+      self.profiler.borrow_mut().end (self.gl, id);
+    }
+  }
+}
+
+impl GpuProfiler {
+  /// Timer queries are used if `glQueryCounter` was loaded.
+  pub(crate) fn new (supported : bool) -> Self {
+    GpuProfiler { supported, .. GpuProfiler::default() }
+  }
+
+  pub(crate) fn is_supported (&self) -> bool {
+    self.supported
+  }
+
+  /// Collect the available results of the frame being finished and start
+  /// the next frame.
+  ///
+  /// Called before the buffer swap with the context current.
+  pub(crate) fn before_swap (&mut self, gl : &gl::Gl) {
+    self.collect (
+      |query| unsafe {
+        let mut available = 0;
+        gl.GetQueryObjectiv (query, gl::QUERY_RESULT_AVAILABLE, &mut available);
+        available != 0
+      },
+      |query| unsafe {
+        let mut time = 0;
+        gl.GetQueryObjectui64v (query, gl::QUERY_RESULT, &mut time);
+        time
+      });
+    self.frame += 1;
+  }
+
+  /// Take the timings collected so far, oldest first.
+  pub(crate) fn take_timings (&mut self) -> Vec <GpuTiming> {
+    self.timings.drain (..).collect()
+  }
+
+  /// Delete the query objects.
+  ///
+  /// The context must be current.
+  pub(crate) fn delete (&mut self, gl : &gl::Gl) {
+    let queries = self.free.drain (..).chain (
+      self.pending.drain (..).flat_map (|timer| {
+        std::iter::once (timer.start).chain (timer.end)
+      })).collect::<Vec <_>>();
+    if !queries.is_empty() {
+      unsafe {
+        gl.DeleteQueries (queries.len() as gl::types::GLsizei, queries.as_ptr())
+      };
+    }
+  }
+
+  fn begin (&mut self, gl : &gl::Gl, name : &str) -> Option <u64> {
+    if !self.supported || self.pending.len() >= MAX_PENDING {
+      return None
+    }
+    let zone = self.tracy.begin (gl, name);
+    let start = self.timestamp (gl);
+    let id = self.next_id;
+    self.next_id += 1;
+    self.pending.push_back (PendingTimer {
+      id, name: name.to_string(), frame: self.frame, start, end: None, zone
+    });
+    Some (id)
+  }
+
+  fn end (&mut self, gl : &gl::Gl, id : u64) {
+    let end = self.timestamp (gl);
+    // scopes usually end in reverse order of starting
+    if let Some (timer) = self.pending.iter_mut().rev()
+      .find (|timer| timer.id == id)
+    {
+      timer.end = Some (end);
+      timer.zone.end();
+    }
+  }
+
+  /// Record a timestamp with a new or reused query.
+  fn timestamp (&mut self, gl : &gl::Gl) -> gl::types::GLuint {
+    let query = self.free.pop().unwrap_or_else (|| {
+      let mut query = 0;
+      unsafe { gl.GenQueries (1, &mut query) };
+      query
+    });
+    unsafe { gl.QueryCounter (query, gl::TIMESTAMP) };
+    query
+  }
+
+  /// Read the results of finished scopes in order, stopping at the first
+  /// scope that is alive or whose results are not available yet.
+  fn collect <A, R> (&mut self, mut available : A, mut result : R) where
+    A : FnMut (gl::types::GLuint) -> bool,
+    R : FnMut (gl::types::GLuint) -> u64
+  {
+    loop {
+      let end = match self.pending.front() {
+        // results become available in the order queries were issued, so the
+        // start is available when the end is
+        Some (&PendingTimer { end: Some (end), .. }) if available (end) => end,
+        _ => break
+      };
+      let timer = self.pending.pop_front().unwrap();
+      let (start_time, end_time) = (result (timer.start), result (end));
+      let nanos = end_time.saturating_sub (start_time);
+      timer.zone.upload (start_time, end_time);
+      if self.timings.len() == MAX_TIMINGS {
+        self.timings.pop_front();
+      }
+      self.timings.push_back (GpuTiming {
+        name:  timer.name,
+        frame: timer.frame,
+        time:  std::time::Duration::from_nanos (nanos)
+      });
+      self.free.push (timer.start);
+      self.free.push (end);
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  #[test]
+  fn test_collect_timings() {
+    let mut profiler = GpuProfiler::new (true);
+    let timer = |id, name : &str, start, end| PendingTimer {
+      id, name: name.to_string(), frame: 0, start, end,
+      zone: tracy::GpuZone::default()
+    };
+    profiler.pending.push_back (timer (0, "outer", 1, Some (4)));
+    profiler.pending.push_back (timer (1, "inner", 2, Some (3)));
+    profiler.pending.push_back (timer (2, "open", 5, None));
+    profiler.pending.push_back (timer (3, "after", 6, Some (7)));
+    // timestamps in ns are 100 times the query name
+    profiler.collect (|_| true, |query| query as u64 * 100);
+    let timings = profiler.take_timings();
+    assert_eq!(timings.iter().map (|timing| timing.name.as_str())
+      .collect::<Vec <_>>(), vec!["outer", "inner"]);
+    assert_eq!(timings[0].time, std::time::Duration::from_nanos (300));
+    assert_eq!(timings[1].time, std::time::Duration::from_nanos (100));
+    assert_eq!(profiler.free, vec![1, 4, 2, 3]);
+    // the open scope holds back later results
+    assert_eq!(profiler.pending.len(), 2);
+    profiler.pending[0].end = Some (8);
+    profiler.collect (|query| query != 7, |query| query as u64 * 100);
+    assert_eq!(profiler.take_timings().len(), 1);
+    assert_eq!(profiler.pending.len(), 1);
+  }
+}
@@ -0,0 +1,107 @@
+//! Safe access to the window from the render thread.
+//!
+//! `SdlGliumDisplayFacade::window` gives access to the whole
+//! `sdl2::video::Window` API, most of which must only be called on the main
+//! thread, and so is unsafe. A `WindowProxy` queues its queries and changes
+//! to the main thread through the command queue, see `command`, and so can
+//! be used from any thread without `unsafe`.
+//!
+//! The proxy of a window backend is attached on the main thread, before the
+//! backend is sent to the render thread:
+//!
+//! ```ignore
+//! let mut executor = CommandExecutor::new().unwrap();
+//! executor.attach (&mut window_backend);
+//! // ... on the render thread
+//! let proxy = display.window_proxy().unwrap();
+//! proxy.set_title ("loading");
+//! let state = proxy.state().unwrap();
+//! ```
+//!
+//! Changes return a `CommandReply` without waiting, while queries block until
+//! the main thread has executed pending commands, so the main thread must not
+//! wait on the render thread while it is querying.
+
+use sdl2;
+
+use command::{CommandError, CommandReply, CommandResponse, CommandSender,
+  WindowCommand, WindowState};
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Thread-safe queries and changes on a window, executed on the main thread.
+#[derive(Clone)]
+pub struct WindowProxy {
+  sender : CommandSender
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+impl WindowProxy {
+  pub(crate) fn new (sender : CommandSender) -> Self {
+    WindowProxy { sender }
+  }
+
+  /// SDL window ID.
+  pub fn id (&self) -> u32 {
+    self.sender.window_id()
+  }
+
+  /// Query the title, size, position and flags of the window, blocking until
+  /// the main thread has serviced the request.
+  pub fn state (&self) -> Result <WindowState, CommandError> {
+    match try!{ self.sender.send (WindowCommand::QueryWindowState).wait() } {
+      CommandResponse::WindowState (state) => Ok (state),
+      response => Err (CommandError::UnexpectedResponse (response))
+    }
+  }
+
+  pub fn set_title (&self, title : &str) -> CommandReply {
+    self.sender.send (WindowCommand::SetTitle (title.to_string()))
+  }
+
+  pub fn set_fullscreen (&self, fullscreen : sdl2::video::FullscreenType)
+    -> CommandReply
+  {
+    self.sender.send (WindowCommand::SetFullscreen (fullscreen))
+  }
+
+  /// Resize the client area, in window coordinates.
+  pub fn resize (&self, width : u32, height : u32) -> CommandReply {
+    self.sender.send (WindowCommand::Resize { width, height })
+  }
+
+  /// Move the window to the given desktop position.
+  pub fn set_position (&self, x : i32, y : i32) -> CommandReply {
+    self.sender.send (WindowCommand::SetPosition { x, y })
+  }
+}
+
+#[cfg(all(test, feature = "glium"))]
+mod test {
+  use super::*;
+  use {CommandExecutor, MockGlBackend, SdlGlWindowBackend};
+  #[test]
+  fn test_window_proxy_commands() {
+    let mut executor = CommandExecutor::new().unwrap();
+    let mut window_backend
+      = SdlGlWindowBackend::mock (MockGlBackend::new (320, 240));
+    assert!(window_backend.window_proxy().is_none());
+    executor.attach (&mut window_backend);
+    let proxy = window_backend.window_proxy().unwrap().clone();
+    assert_eq!(proxy.id(), window_backend.window_id());
+    // changes are only executed when the main thread drains the queue
+    let reply = proxy.set_title ("title");
+    assert_eq!(reply.try_wait(), None);
+    assert_eq!(executor.execute_pending(), 1);
+    // the mock backend has no SDL window
+    assert_eq!(reply.wait(),
+      Err (CommandError::WindowNotFound (proxy.id())));
+    drop (executor);
+    assert_eq!(proxy.state(), Err (CommandError::Disconnected));
+  }
+}
@@ -0,0 +1,566 @@
+//! Continuous asynchronous readback of presented frames.
+//!
+//! Each frame is copied (optionally downscaled) into one of a ring of pixel
+//! pack buffers just before the buffer swap. Once the GPU has finished the
+//! copy, as signalled by a fence, the pixels are mapped and copied into a CPU
+//! buffer which is sent to a `ReadbackReceiver` on any thread.
+//!
+//! The render thread never waits on the GPU or on the consumer: frames are
+//! dropped when all pixel buffers are still in flight, when the consumer holds
+//! all of the CPU buffers, or when the delivery channel is full.
+//!
+//! Single screenshots are read back the same way with
+//! `SdlGliumDisplayFacade::capture_frame_async`, which returns a
+//! `PendingCapture` resolved once the copy of the next presented frame has
+//! completed, typically a few frames later:
+//!
+//! ```ignore
+//! let capture = display.capture_frame_async();
+//! frame.finish().unwrap();
+//! // ... later frames
+//! if let Some (image) = capture.try_recv() {
+//!   image.to_pam (&mut file).unwrap();
+//! }
+//! ```
+
+use gl;
+
+use golden::Image;
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// A frame read back from the default framebuffer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReadbackFrame {
+  /// Number of the frame since readback was started, counting dropped frames
+  pub index  : u64,
+  pub width  : u32,
+  pub height : u32,
+  /// RGBA8 pixels with rows from bottom to top
+  pub data   : Vec <u8>
+}
+
+/// Consumer end of a readback ring, created with
+/// `SdlGliumDisplayFacade::start_readback`.
+pub struct ReadbackReceiver {
+  receiver : std::sync::mpsc::Receiver <ReadbackFrame>,
+  recycle  : std::sync::mpsc::Sender <Vec <u8>>,
+  dropped  : std::sync::Arc <std::sync::atomic::AtomicUsize>
+}
+
+/// A screenshot being read back, created with
+/// `SdlGliumDisplayFacade::capture_frame_async`.
+pub struct PendingCapture {
+  receiver : std::sync::mpsc::Receiver <Image>
+}
+
+/// Render thread state of a readback ring.
+pub(crate) struct ReadbackRing {
+  downscale    : u32,
+  slots        : Vec <Slot>,
+  /// Framebuffer and renderbuffer for downscaling
+  fbo          : gl::types::GLuint,
+  rbo          : gl::types::GLuint,
+  rbo_size     : (u32, u32),
+  /// CPU buffers available for reuse
+  pool         : Vec <Vec <u8>>,
+  /// Number of CPU buffers allocated
+  allocated    : usize,
+  max_buffers  : usize,
+  frame        : u64,
+  sender       : std::sync::mpsc::SyncSender <ReadbackFrame>,
+  recycled     : std::sync::mpsc::Receiver <Vec <u8>>,
+  dropped      : std::sync::Arc <std::sync::atomic::AtomicUsize>,
+  disconnected : bool
+}
+
+/// Render thread state of the pending screenshots of a display.
+#[derive(Default)]
+pub(crate) struct CaptureQueue {
+  /// Screenshots of the frame being drawn
+  requested : Vec <std::sync::mpsc::Sender <Image>>,
+  /// Copies in flight, oldest first
+  copies    : std::collections::VecDeque <CaptureCopy>,
+  /// Pixel buffers available for reuse
+  free      : Vec <gl::types::GLuint>
+}
+
+//
+// private
+//
+
+struct CaptureCopy {
+  pbo        : gl::types::GLuint,
+  fence      : gl::types::GLsync,
+  dimensions : (u32, u32),
+  senders    : Vec <std::sync::mpsc::Sender <Image>>
+}
+
+struct Slot {
+  pbo        : gl::types::GLuint,
+  size       : usize,
+  /// Pending copy
+  fence      : Option <gl::types::GLsync>,
+  index      : u64,
+  dimensions : (u32, u32)
+}
+
+/// GL state modified by a readback, restored afterwards.
+struct SavedState {
+  read_framebuffer  : gl::types::GLint,
+  draw_framebuffer  : gl::types::GLint,
+  renderbuffer      : gl::types::GLint,
+  pixel_pack_buffer : gl::types::GLint,
+  pack_alignment    : gl::types::GLint,
+  read_buffer       : gl::types::GLint,
+  scissor_test      : bool
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+impl ReadbackReceiver {
+  /// Block until the next frame arrives.
+  ///
+  /// Returns `None` if readback has been stopped.
+  pub fn recv (&self) -> Option <ReadbackFrame> {
+    self.receiver.recv().ok()
+  }
+
+  /// Returns the next frame if one is ready, without blocking.
+  pub fn try_recv (&self) -> Option <ReadbackFrame> {
+    self.receiver.try_recv().ok()
+  }
+
+  /// Return the buffer of a processed frame for reuse.
+  ///
+  /// Frames that are not recycled are replaced by new allocations, up to the
+  /// number of buffers the ring was started with.
+  pub fn recycle (&self, frame : ReadbackFrame) {
+    // readback may have been stopped
+    let _ = self.recycle.send (frame.data);
+  }
+
+  /// Number of frames dropped so far because of backpressure.
+  pub fn dropped_frames (&self) -> usize {
+    self.dropped.load (std::sync::atomic::Ordering::SeqCst)
+  }
+}
+
+impl PendingCapture {
+  /// Block until the screenshot arrives.
+  ///
+  /// Returns `None` if the display was dropped before the frame was read
+  /// back. This waits on the render thread, so it must not be called there.
+  pub fn recv (self) -> Option <Image> {
+    self.receiver.recv().ok()
+  }
+
+  /// Returns the screenshot if it has arrived, without blocking.
+  ///
+  /// The screenshot is returned only once.
+  pub fn try_recv (&self) -> Option <Image> {
+    self.receiver.try_recv().ok()
+  }
+}
+
+impl ReadbackRing {
+  /// Create a ring of `buffers` pixel buffers and CPU buffers, dividing the
+  /// frame dimensions by `downscale`.
+  ///
+  /// The context must be current.
+  pub(crate) fn new (gl : &gl::Gl, buffers : usize, downscale : u32)
+    -> (ReadbackRing, ReadbackReceiver)
+  {
+    let buffers = std::cmp::max (1, buffers);
+    let (sender, receiver) = std::sync::mpsc::sync_channel (buffers);
+    let (recycle, recycled) = std::sync::mpsc::channel();
+    let dropped = std::sync::Arc::new (std::sync::atomic::AtomicUsize::new (0));
+    let mut pbos = vec![0; buffers];
+    let (mut fbo, mut rbo) = (0, 0);
+    unsafe {
+      gl.GenBuffers (buffers as gl::types::GLsizei, pbos.as_mut_ptr());
+      if downscale > 1 {
+        gl.GenFramebuffers (1, &mut fbo);
+        gl.GenRenderbuffers (1, &mut rbo);
+      }
+    }
+    let slots = pbos.into_iter().map (|pbo| Slot {
+      pbo, size: 0, fence: None, index: 0, dimensions: (0, 0)
+    }).collect();
+    let ring = ReadbackRing {
+      downscale:    std::cmp::max (1, downscale),
+      slots,
+      fbo,
+      rbo,
+      rbo_size:     (0, 0),
+      pool:         Vec::new(),
+      allocated:    0,
+      max_buffers:  buffers,
+      frame:        0,
+      sender,
+      recycled,
+      dropped:      dropped.clone(),
+      disconnected: false
+    };
+    (ring, ReadbackReceiver { receiver, recycle, dropped })
+  }
+
+  /// Returns true if the receiver has been dropped.
+  pub(crate) fn is_disconnected (&self) -> bool {
+    self.disconnected
+  }
+
+  /// Deliver completed readbacks and start a readback of the back buffer.
+  ///
+  /// Called before the buffer swap with the context current.
+  pub(crate) fn before_swap (&mut self, gl : &gl::Gl, dimensions : (u32, u32)) {
+    let saved = unsafe { SavedState::save (gl) };
+    self.deliver (gl);
+    self.capture (gl, dimensions);
+    unsafe { saved.restore (gl) };
+  }
+
+  /// Delete the GL objects of the ring.
+  ///
+  /// The context must be current.
+  pub(crate) fn delete (self, gl : &gl::Gl) {
+    unsafe {
+      for slot in self.slots.iter() {
+        if let Some (fence) = slot.fence {
+          gl.DeleteSync (fence);
+        }
+        gl.DeleteBuffers (1, &slot.pbo);
+      }
+      if self.downscale > 1 {
+        gl.DeleteFramebuffers (1, &self.fbo);
+        gl.DeleteRenderbuffers (1, &self.rbo);
+      }
+    }
+  }
+
+  /// Send the pixels of all pixel buffers whose copies have completed, in
+  /// frame order.
+  fn deliver (&mut self, gl : &gl::Gl) {
+    while let Ok (buffer) = self.recycled.try_recv() {
+      self.pool.push (buffer);
+    }
+    let mut pending = (0..self.slots.len())
+      .filter (|i| self.slots[*i].fence.is_some())
+      .collect::<Vec <usize>>();
+    pending.sort_by_key (|i| self.slots[*i].index);
+    for i in pending {
+      let fence = self.slots[i].fence.unwrap();
+      let status = unsafe { gl.ClientWaitSync (fence, 0, 0) };
+      if status != gl::ALREADY_SIGNALED && status != gl::CONDITION_SATISFIED {
+        // later copies cannot have completed either
+        break
+      }
+      unsafe { gl.DeleteSync (fence) };
+      self.slots[i].fence = None;
+      let mut data = match self.pool.pop() {
+        Some (data) => data,
+        None if self.allocated < self.max_buffers => {
+          self.allocated += 1;
+          Vec::new()
+        }
+        None => {
+          // the consumer holds all of the buffers
+          self.drop_frame();
+          continue
+        }
+      };
+      let slot = &self.slots[i];
+      data.clear();
+      unsafe {
+        gl.BindBuffer (gl::PIXEL_PACK_BUFFER, slot.pbo);
+        let pixels = gl.MapBuffer (gl::PIXEL_PACK_BUFFER, gl::READ_ONLY);
+        if !pixels.is_null() {
+          data.extend_from_slice (
+            std::slice::from_raw_parts (pixels as *const u8, slot.size));
+          gl.UnmapBuffer (gl::PIXEL_PACK_BUFFER);
+        }
+      }
+      if data.is_empty() {
+        self.pool.push (data);
+        self.drop_frame();
+        continue
+      }
+      let frame = ReadbackFrame {
+        index:  slot.index,
+        width:  slot.dimensions.0,
+        height: slot.dimensions.1,
+        data
+      };
+      match self.sender.try_send (frame) {
+        Ok (()) => {}
+        Err (std::sync::mpsc::TrySendError::Full (frame)) => {
+          self.pool.push (frame.data);
+          self.drop_frame();
+        }
+        Err (std::sync::mpsc::TrySendError::Disconnected (_)) => {
+          self.disconnected = true;
+        }
+      }
+    }
+  }
+
+  /// Start copying the back buffer into the next pixel buffer.
+  fn capture (&mut self, gl : &gl::Gl, dimensions : (u32, u32)) {
+    let index = self.frame;
+    self.frame += 1;
+    let slot_index = (index % self.slots.len() as u64) as usize;
+    if self.slots[slot_index].fence.is_some() {
+      // the GPU has not finished the copy from `slots.len()` frames ago
+      self.drop_frame();
+      return
+    }
+    let (width, height) = (
+      std::cmp::max (1, dimensions.0 / self.downscale),
+      std::cmp::max (1, dimensions.1 / self.downscale));
+    let size = 4 * width as usize * height as usize;
+    unsafe {
+      gl.Disable (gl::SCISSOR_TEST);
+      gl.BindFramebuffer (gl::READ_FRAMEBUFFER, 0);
+      gl.ReadBuffer (gl::BACK);
+      if self.downscale > 1 {
+        if self.rbo_size != (width, height) {
+          gl.BindRenderbuffer (gl::RENDERBUFFER, self.rbo);
+          gl.RenderbufferStorage (gl::RENDERBUFFER, gl::RGBA8,
+            width as gl::types::GLsizei, height as gl::types::GLsizei);
+          gl.BindFramebuffer (gl::DRAW_FRAMEBUFFER, self.fbo);
+          gl.FramebufferRenderbuffer (gl::DRAW_FRAMEBUFFER,
+            gl::COLOR_ATTACHMENT0, gl::RENDERBUFFER, self.rbo);
+          self.rbo_size = (width, height);
+        }
+        gl.BindFramebuffer (gl::DRAW_FRAMEBUFFER, self.fbo);
+        gl.BlitFramebuffer (
+          0, 0, dimensions.0 as gl::types::GLint,
+          dimensions.1 as gl::types::GLint,
+          0, 0, width as gl::types::GLint, height as gl::types::GLint,
+          gl::COLOR_BUFFER_BIT, gl::LINEAR);
+        gl.BindFramebuffer (gl::READ_FRAMEBUFFER, self.fbo);
+      }
+      let slot = &mut self.slots[slot_index];
+      gl.BindBuffer (gl::PIXEL_PACK_BUFFER, slot.pbo);
+      if slot.size != size {
+        gl.BufferData (gl::PIXEL_PACK_BUFFER, size as gl::types::GLsizeiptr,
+          std::ptr::null(), gl::STREAM_READ);
+        slot.size = size;
+      }
+      gl.PixelStorei (gl::PACK_ALIGNMENT, 1);
+      gl.ReadPixels (0, 0,
+        width as gl::types::GLsizei, height as gl::types::GLsizei,
+        gl::RGBA, gl::UNSIGNED_BYTE, std::ptr::null_mut());
+      slot.fence = Some (gl.FenceSync (gl::SYNC_GPU_COMMANDS_COMPLETE, 0));
+      slot.index = index;
+      slot.dimensions = (width, height);
+    }
+  }
+
+  fn drop_frame (&self) {
+    self.dropped.fetch_add (1, std::sync::atomic::Ordering::SeqCst);
+  }
+}
+
+impl CaptureQueue {
+  /// Request a screenshot of the frame presented by the next buffer swap.
+  pub(crate) fn request (&mut self) -> PendingCapture {
+    let (sender, receiver) = std::sync::mpsc::channel();
+    self.requested.push (sender);
+    PendingCapture { receiver }
+  }
+
+  /// Deliver completed screenshots and start copying the back buffer if a
+  /// screenshot was requested.
+  ///
+  /// Called before the buffer swap with the context current.
+  pub(crate) fn before_swap (&mut self, gl : &gl::Gl, dimensions : (u32, u32)) {
+    if self.requested.is_empty() && self.copies.is_empty() {
+      return
+    }
+    let saved = unsafe { SavedState::save (gl) };
+    self.deliver (gl);
+    if !self.requested.is_empty() {
+      self.capture (gl, dimensions);
+    }
+    unsafe { saved.restore (gl) };
+  }
+
+  /// Delete the GL objects of the queue; pending screenshots are
+  /// disconnected.
+  ///
+  /// The context must be current.
+  pub(crate) fn delete (&mut self, gl : &gl::Gl) {
+    self.requested.clear();
+    for copy in self.copies.drain (..) {
+      unsafe {
+        gl.DeleteSync (copy.fence);
+        gl.DeleteBuffers (1, &copy.pbo);
+      }
+    }
+    for pbo in self.free.drain (..) {
+      unsafe { gl.DeleteBuffers (1, &pbo) };
+    }
+  }
+
+  /// Send the screenshots whose copies have completed.
+  fn deliver (&mut self, gl : &gl::Gl) {
+    while let Some (copy) = self.copies.front() {
+      let status = unsafe { gl.ClientWaitSync (copy.fence, 0, 0) };
+      if status != gl::ALREADY_SIGNALED && status != gl::CONDITION_SATISFIED {
+        // later copies cannot have completed either
+        break
+      }
+      let copy = self.copies.pop_front().unwrap();
+      let (width, height) = copy.dimensions;
+      let size = 4 * width as usize * height as usize;
+      let mut data = Vec::with_capacity (size);
+      unsafe {
+        gl.DeleteSync (copy.fence);
+        gl.BindBuffer (gl::PIXEL_PACK_BUFFER, copy.pbo);
+        let pixels = gl.MapBuffer (gl::PIXEL_PACK_BUFFER, gl::READ_ONLY);
+        if !pixels.is_null() {
+          data.extend_from_slice (
+            std::slice::from_raw_parts (pixels as *const u8, size));
+          gl.UnmapBuffer (gl::PIXEL_PACK_BUFFER);
+        }
+      }
+      self.free.push (copy.pbo);
+      if data.is_empty() {
+        // mapping failed: the screenshots are disconnected
+        continue
+      }
+      let image = Image::from_bottom_up (width, height, data);
+      let mut senders = copy.senders;
+      let last = senders.pop().unwrap();
+      for sender in senders {
+        // the capture may have been dropped
+        let _ = sender.send (image.clone());
+      }
+      let _ = last.send (image);
+    }
+  }
+
+  /// Start copying the back buffer into a pixel buffer for the requested
+  /// screenshots.
+  fn capture (&mut self, gl : &gl::Gl, dimensions : (u32, u32)) {
+    let (width, height) = dimensions;
+    let size = 4 * width as usize * height as usize;
+    let pbo = self.free.pop().unwrap_or_else (|| {
+      let mut pbo = 0;
+      unsafe { gl.GenBuffers (1, &mut pbo) };
+      pbo
+    });
+    let fence = unsafe {
+      gl.Disable (gl::SCISSOR_TEST);
+      gl.BindFramebuffer (gl::READ_FRAMEBUFFER, 0);
+      gl.ReadBuffer (gl::BACK);
+      gl.BindBuffer (gl::PIXEL_PACK_BUFFER, pbo);
+      gl.BufferData (gl::PIXEL_PACK_BUFFER, size as gl::types::GLsizeiptr,
+        std::ptr::null(), gl::STREAM_READ);
+      gl.PixelStorei (gl::PACK_ALIGNMENT, 1);
+      gl.ReadPixels (0, 0,
+        width as gl::types::GLsizei, height as gl::types::GLsizei,
+        gl::RGBA, gl::UNSIGNED_BYTE, std::ptr::null_mut());
+      gl.FenceSync (gl::SYNC_GPU_COMMANDS_COMPLETE, 0)
+    };
+    self.copies.push_back (CaptureCopy {
+      pbo,
+      fence,
+      dimensions,
+      senders: self.requested.drain (..).collect()
+    });
+  }
+}
+
+impl SavedState {
+  unsafe fn save (gl : &gl::Gl) -> Self {
+    let get = |pname| {
+      let mut value = 0;
+      gl.GetIntegerv (pname, &mut value);
+      value
+    };
+    let read_framebuffer = get (gl::READ_FRAMEBUFFER_BINDING);
+    // the read buffer is state of the default framebuffer
+    gl.BindFramebuffer (gl::READ_FRAMEBUFFER, 0);
+    let read_buffer = get (gl::READ_BUFFER);
+    gl.BindFramebuffer (gl::READ_FRAMEBUFFER, read_framebuffer as u32);
+    SavedState {
+      read_framebuffer,
+      draw_framebuffer:  get (gl::DRAW_FRAMEBUFFER_BINDING),
+      renderbuffer:      get (gl::RENDERBUFFER_BINDING),
+      pixel_pack_buffer: get (gl::PIXEL_PACK_BUFFER_BINDING),
+      pack_alignment:    get (gl::PACK_ALIGNMENT),
+      read_buffer,
+      scissor_test:      gl.IsEnabled (gl::SCISSOR_TEST) != 0
+    }
+  }
+
+  unsafe fn restore (&self, gl : &gl::Gl) {
+    gl.BindFramebuffer (gl::READ_FRAMEBUFFER, 0);
+    gl.ReadBuffer (self.read_buffer as gl::types::GLenum);
+    gl.BindFramebuffer (gl::READ_FRAMEBUFFER, self.read_framebuffer as u32);
+    gl.BindFramebuffer (gl::DRAW_FRAMEBUFFER, self.draw_framebuffer as u32);
+    gl.BindRenderbuffer (gl::RENDERBUFFER, self.renderbuffer as u32);
+    gl.BindBuffer (gl::PIXEL_PACK_BUFFER, self.pixel_pack_buffer as u32);
+    gl.PixelStorei (gl::PACK_ALIGNMENT, self.pack_alignment);
+    if self.scissor_test {
+      gl.Enable (gl::SCISSOR_TEST);
+    }
+  }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  functions                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Read the back buffer of the default framebuffer synchronously, returning
+/// RGBA8 pixels with rows from bottom to top.
+///
+/// The context must be current. This stalls until all pending rendering has
+/// completed.
+pub(crate) fn read_back_buffer (gl : &gl::Gl, dimensions : (u32, u32))
+  -> Vec <u8>
+{
+  read_default_framebuffer (gl, dimensions, gl::BACK)
+}
+
+/// Read the front buffer of the default framebuffer synchronously, i.e. the
+/// frame last swapped, returning RGBA8 pixels with rows from bottom to top.
+///
+/// The context must be current.
+pub(crate) fn read_front_buffer (gl : &gl::Gl, dimensions : (u32, u32))
+  -> Vec <u8>
+{
+  read_default_framebuffer (gl, dimensions, gl::FRONT)
+}
+
+//
+// private
+//
+
+fn read_default_framebuffer (
+  gl         : &gl::Gl,
+  dimensions : (u32, u32),
+  buffer     : gl::types::GLenum
+) -> Vec <u8> {
+  let (width, height) = dimensions;
+  let mut data = vec![0u8; 4 * width as usize * height as usize];
+  unsafe {
+    let saved = SavedState::save (gl);
+    gl.Disable (gl::SCISSOR_TEST);
+    gl.BindFramebuffer (gl::READ_FRAMEBUFFER, 0);
+    gl.ReadBuffer (buffer);
+    gl.BindBuffer (gl::PIXEL_PACK_BUFFER, 0);
+    gl.PixelStorei (gl::PACK_ALIGNMENT, 1);
+    gl.ReadPixels (0, 0,
+      width as gl::types::GLsizei, height as gl::types::GLsizei,
+      gl::RGBA, gl::UNSIGNED_BYTE, data.as_mut_ptr() as *mut _);
+    saved.restore (gl);
+  }
+  data
+}
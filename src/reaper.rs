@@ -0,0 +1,141 @@
+//! Destruction of windows on the main thread.
+//!
+//! A window backend destroys its window when dropped, which usually happens
+//! on the render thread, but some platforms (notably macOS and X11 with
+//! several threads) only support destroying windows on the main thread. A
+//! backend attached to a `WindowReaper` still deletes its GL context on the
+//! thread where it is dropped, but sends the window to the reaper instead of
+//! destroying it, and wakes the main thread with an SDL user event:
+//!
+//! ```ignore
+//! let mut reaper = WindowReaper::new();
+//! reaper.attach (&mut window_backend);
+//! // ... send the window backend to the render thread
+//! loop {
+//!   let event = event_pump.wait_event();
+//!   if reaper.is_wake_event (&event) {
+//!     reaper.reap();
+//!   }
+//!   // ...
+//! }
+//! ```
+//!
+//! Windows that have not been reaped when the reaper is dropped are destroyed
+//! then. If the reaper is dropped before the backend, the window is destroyed
+//! on the dropping thread as if it had not been attached.
+//!
+//! A `runner::RenderThread` sends its window back to the main thread itself,
+//! so its window backend does not need to be attached.
+
+use sdl2;
+use sdl2_sys;
+
+use SdlGlWindowBackend;
+use command;
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Main thread end of the window destruction channel.
+pub struct WindowReaper {
+  wake_event : u32,
+  sender     : std::sync::mpsc::Sender <DetachedWindow>,
+  receiver   : std::sync::mpsc::Receiver <DetachedWindow>
+}
+
+/// Held by an attached window backend to send its window to the reaper.
+pub(crate) struct ReaperSender {
+  wake_event : u32,
+  sender     : std::sync::mpsc::Sender <DetachedWindow>
+}
+
+/// A window whose GL context has been deleted, to be destroyed on the main
+/// thread.
+pub(crate) struct DetachedWindow {
+  pub(crate) window_raw : *mut sdl2_sys::SDL_Window
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+impl WindowReaper {
+  /// Create a new reaper.
+  ///
+  /// This registers a custom SDL event type used to wake the main thread and
+  /// so should be called after SDL has been initialized, which is why there
+  /// is no `Default` implementation.
+  #[allow(clippy::new_without_default)]
+  pub fn new() -> Self {
+    let wake_event = unsafe { sdl2_sys::SDL_RegisterEvents (1) };
+    let (sender, receiver) = std::sync::mpsc::channel();
+    WindowReaper { wake_event, sender, receiver }
+  }
+
+  /// Send the window of the backend to this reaper when the backend is
+  /// dropped, replacing any previous reaper.
+  ///
+  /// This has no effect on a backend whose window is destroyed elsewhere.
+  pub fn attach (&self, window_backend : &mut SdlGlWindowBackend) {
+    window_backend.reaper = Some (ReaperSender {
+      wake_event: self.wake_event,
+      sender:     self.sender.clone()
+    });
+  }
+
+  /// Returns true if the event was pushed to wake the main thread.
+  ///
+  /// Such events carry no other information and can be ignored after calling
+  /// `reap`.
+  pub fn is_wake_event (&self, event : &sdl2::event::Event) -> bool {
+    match *event {
+      sdl2::event::Event::User { type_, .. } => type_ == self.wake_event,
+      _ => false
+    }
+  }
+
+  /// Destroy the windows received, returning the number destroyed.
+  ///
+  /// This must be called on the main thread.
+  pub fn reap (&mut self) -> usize {
+    let mut count = 0;
+    while let Ok (window) = self.receiver.try_recv() {
+      window.destroy();
+      count += 1;
+    }
+    count
+  }
+}
+
+impl Drop for WindowReaper {
+  fn drop (&mut self) {
+    self.reap();
+  }
+}
+
+impl ReaperSender {
+  /// Send the window to the reaper, or give it back if the reaper has been
+  /// dropped.
+  pub(crate) fn send (self, window : DetachedWindow)
+    -> Result <(), DetachedWindow>
+  {
+    try!{ self.sender.send (window).map_err (|err| err.0) };
+    command::push_user_event (self.wake_event, 0);
+    Ok (())
+  }
+}
+
+impl DetachedWindow {
+  /// Destroy the window.
+  ///
+  /// This should be called on the main thread.
+  pub(crate) fn destroy (self) {
+    log_record!(Info, "window {} destroyed",
+      unsafe { sdl2_sys::SDL_GetWindowID (self.window_raw) });
+    unsafe { sdl2_sys::SDL_DestroyWindow (self.window_raw) };
+  }
+}
+
+/// The window pointer is only used on the main thread after it is sent.
+unsafe impl Send for DetachedWindow { }
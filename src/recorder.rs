@@ -0,0 +1,283 @@
+//! Recording presented frames to a file or an encoder.
+//!
+//! A `FrameRecorder` consumes the frames of a readback ring (see `readback`)
+//! on a writer thread, converting them to top to bottom rows and streaming
+//! them to a raw video file or to the standard input of an `ffmpeg` process:
+//!
+//! ```ignore
+//! let recorder = display.start_recording (
+//!   RecordingSink::Ffmpeg {
+//!     fps:    60.0,
+//!     args:   vec!["-c:v".to_string(), "libx264".to_string()],
+//!     output: "repro.mp4".into()
+//!   },
+//!   RecordingFormat::Rgb24, 4
+//! ).unwrap();
+//! // ... frames
+//! let stats = display.stop_recording (recorder).unwrap();
+//! println!("{} frames recorded", stats.frames);
+//! ```
+//!
+//! The render thread never waits for the writer: frames are dropped when the
+//! writer falls behind, as counted by `RecordingStats::dropped`. The video
+//! has the dimensions of the first frame recorded, and frames of other
+//! dimensions, e.g. after the window is resized, are skipped.
+//!
+//! A raw file holds the frames one after another with no header; it can be
+//! encoded later with e.g. `ffmpeg -f rawvideo -pixel_format rgb24
+//! -video_size 640x480 -framerate 60 -i frames.raw out.mp4`.
+
+use readback::{ReadbackFrame, ReadbackReceiver};
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Writes frames of a readback ring from a writer thread, see `recorder`.
+pub struct FrameRecorder {
+  thread : std::thread::JoinHandle <std::io::Result <RecordingStats>>
+}
+
+/// Frame counts of a finished recording.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RecordingStats {
+  /// Frames written
+  pub frames  : usize,
+  /// Frames of other dimensions than the first frame
+  pub skipped : usize,
+  /// Frames dropped by the readback ring because the writer fell behind
+  pub dropped : usize
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  enums                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Where recorded frames are written.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RecordingSink {
+  /// Raw frames, created or truncated
+  File (std::path::PathBuf),
+  /// An `ffmpeg` process found in `PATH`, reading raw frames at `fps` frames
+  /// per second and writing `output` with the output options `args`
+  Ffmpeg {
+    fps    : f64,
+    args   : Vec <String>,
+    output : std::path::PathBuf
+  }
+}
+
+/// Pixel format of recorded frames.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordingFormat {
+  /// 3 bytes per pixel, dropping alpha
+  Rgb24,
+  /// 4 bytes per pixel
+  Rgba
+}
+
+//
+// private
+//
+
+enum Writer {
+  File (std::io::BufWriter <std::fs::File>),
+  Ffmpeg (std::process::Child)
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+impl FrameRecorder {
+  /// Start a writer thread recording the frames of `receiver`.
+  ///
+  /// The sink is opened when the first frame arrives, since `ffmpeg` needs
+  /// the frame dimensions; errors opening or writing it are returned by
+  /// `finish`, and stop the recording, which also stops the readback.
+  pub fn new (
+    receiver : ReadbackReceiver,
+    sink     : RecordingSink,
+    format   : RecordingFormat
+  ) -> std::io::Result <Self> {
+    let thread = try!{
+      std::thread::Builder::new().name ("frame-recorder".to_string())
+        .spawn (move || record (receiver, sink, format))
+    };
+    Ok (FrameRecorder { thread })
+  }
+
+  /// Wait for the recording to end and close the sink.
+  ///
+  /// The recording ends when readback is stopped, e.g. with
+  /// `SdlGliumDisplayFacade::stop_readback`; otherwise this blocks forever.
+  /// An `ffmpeg` process is waited for, and exiting unsuccessfully is an
+  /// error.
+  pub fn finish (self) -> std::io::Result <RecordingStats> {
+    match self.thread.join() {
+      Ok (result) => result,
+      Err (_) => Err (std::io::Error::other ("frame recorder thread panicked"))
+    }
+  }
+}
+
+impl RecordingFormat {
+  pub fn bytes_per_pixel (&self) -> usize {
+    match *self {
+      RecordingFormat::Rgb24 => 3,
+      RecordingFormat::Rgba  => 4
+    }
+  }
+
+  /// Name of the format for `ffmpeg -pixel_format`.
+  pub fn ffmpeg_name (&self) -> &'static str {
+    match *self {
+      RecordingFormat::Rgb24 => "rgb24",
+      RecordingFormat::Rgba  => "rgba"
+    }
+  }
+}
+
+impl Writer {
+  fn open (
+    sink       : &RecordingSink,
+    format     : RecordingFormat,
+    dimensions : (u32, u32)
+  ) -> std::io::Result <Self> {
+    match *sink {
+      RecordingSink::File (ref path) => {
+        let file = try!{ std::fs::File::create (path) };
+        Ok (Writer::File (std::io::BufWriter::new (file)))
+      }
+      RecordingSink::Ffmpeg { fps, ref args, ref output } => {
+        let child = try!{
+          std::process::Command::new ("ffmpeg")
+            .args (["-loglevel", "error", "-y", "-f", "rawvideo"])
+            .arg ("-pixel_format").arg (format.ffmpeg_name())
+            .arg ("-video_size")
+            .arg (format!("{}x{}", dimensions.0, dimensions.1))
+            .arg ("-framerate").arg (fps.to_string())
+            .args (["-i", "-"])
+            .args (args)
+            .arg (output)
+            .stdin (std::process::Stdio::piped())
+            .spawn()
+        };
+        Ok (Writer::Ffmpeg (child))
+      }
+    }
+  }
+
+  fn write (&mut self, data : &[u8]) -> std::io::Result <()> {
+    use std::io::Write;
+    match *self {
+      Writer::File (ref mut file)    => file.write_all (data),
+      Writer::Ffmpeg (ref mut child) =>
+        child.stdin.as_mut().unwrap().write_all (data)
+    }
+  }
+
+  fn close (self) -> std::io::Result <()> {
+    use std::io::Write;
+    match self {
+      Writer::File (mut file) => file.flush(),
+      Writer::Ffmpeg (mut child) => {
+        // closing standard input ends the stream
+        drop (child.stdin.take());
+        let status = try!{ child.wait() };
+        if status.success() {
+          Ok (())
+        } else {
+          Err (std::io::Error::other (format!("ffmpeg exited with {}", status)))
+        }
+      }
+    }
+  }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  functions                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+//
+// private
+//
+
+/// Body of the writer thread.
+fn record (
+  receiver : ReadbackReceiver,
+  sink     : RecordingSink,
+  format   : RecordingFormat
+) -> std::io::Result <RecordingStats> {
+  let mut stats = RecordingStats::default();
+  // opened with the dimensions of the first frame
+  let mut opened : Option <(Writer, (u32, u32))> = None;
+  let mut converted = Vec::new();
+  while let Some (frame) = receiver.recv() {
+    let dimensions = (frame.width, frame.height);
+    if opened.is_none() {
+      opened = Some ((try!{ Writer::open (&sink, format, dimensions) },
+        dimensions));
+    }
+    let (ref mut writer, expected) = *opened.as_mut().unwrap();
+    if dimensions != expected {
+      stats.skipped += 1;
+      receiver.recycle (frame);
+      continue
+    }
+    convert (&frame, format, &mut converted);
+    receiver.recycle (frame);
+    // on errors the receiver is dropped, stopping the readback
+    try!{ writer.write (&converted) };
+    stats.frames += 1;
+  }
+  stats.dropped = receiver.dropped_frames();
+  if let Some ((writer, _)) = opened {
+    try!{ writer.close() };
+  }
+  Ok (stats)
+}
+
+/// Convert the bottom to top RGBA rows of a frame to top to bottom rows of
+/// `format`.
+fn convert (
+  frame  : &ReadbackFrame,
+  format : RecordingFormat,
+  out    : &mut Vec <u8>
+) {
+  let row_len = 4 * frame.width as usize;
+  out.clear();
+  if row_len == 0 {
+    return
+  }
+  for row in frame.data.chunks (row_len).rev() {
+    match format {
+      RecordingFormat::Rgba  => out.extend_from_slice (row),
+      RecordingFormat::Rgb24 => for pixel in row.chunks (4) {
+        out.extend_from_slice (&pixel[..3]);
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  #[test]
+  fn test_convert() {
+    let frame = ReadbackFrame {
+      index:  0,
+      width:  2,
+      height: 2,
+      // bottom row first
+      data:   vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16]
+    };
+    let mut out = Vec::new();
+    convert (&frame, RecordingFormat::Rgba, &mut out);
+    assert_eq!(out,
+      vec![9, 10, 11, 12, 13, 14, 15, 16, 1, 2, 3, 4, 5, 6, 7, 8]);
+    convert (&frame, RecordingFormat::Rgb24, &mut out);
+    assert_eq!(out, vec![9, 10, 11, 13, 14, 15, 1, 2, 3, 5, 6, 7]);
+    assert_eq!(out.len(), 4 * RecordingFormat::Rgb24.bytes_per_pixel());
+  }
+}
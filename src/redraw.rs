@@ -0,0 +1,133 @@
+//! Rendering frames on demand.
+//!
+//! Tools and editors only need a new frame when something changed. In
+//! on-demand mode the render thread blocks in `wait_for_redraw` until a
+//! redraw is requested from any thread, then draws one frame:
+//!
+//! ```ignore
+//! let redraw = display.redraw_handle();
+//! display.set_render_on_demand (true);
+//! main_sender.send (redraw).unwrap();
+//! while !display.shutdown_requested() {
+//!   display.wait_for_redraw();
+//!   let mut frame = display.draw();
+//!   // ...
+//!   frame.finish().unwrap();
+//! }
+//! // on the main thread, e.g. after forwarding an event
+//! redraw.request_redraw();
+//! ```
+//!
+//! Requests made before the render thread wakes are coalesced into a single
+//! frame. A request made while a frame is being drawn causes one more frame,
+//! so no change is missed. Requesting shutdown does not wake the render
+//! thread, so a redraw should be requested afterwards for it to exit.
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Shared redraw state of a render loop, see `redraw`.
+#[derive(Clone)]
+pub struct RedrawHandle {
+  shared : std::sync::Arc <(std::sync::Mutex <RedrawState>, std::sync::Condvar)>
+}
+
+//
+// private
+//
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct RedrawState {
+  on_demand : bool,
+  requested : bool
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+impl RedrawHandle {
+  pub fn new() -> Self {
+    RedrawHandle {
+      shared: std::sync::Arc::new ((
+        std::sync::Mutex::new (RedrawState::default()),
+        std::sync::Condvar::new()))
+    }
+  }
+
+  /// Enable or disable on-demand mode; disabling wakes a waiting render
+  /// thread.
+  pub fn set_on_demand (&self, on_demand : bool) {
+    let (_, condvar) = &*self.shared;
+    self.lock().on_demand = on_demand;
+    condvar.notify_all();
+  }
+
+  pub fn is_on_demand (&self) -> bool {
+    self.lock().on_demand
+  }
+
+  /// Request a frame, waking the render thread if it is waiting.
+  pub fn request_redraw (&self) {
+    let (_, condvar) = &*self.shared;
+    self.lock().requested = true;
+    condvar.notify_all();
+  }
+
+  /// Returns true if a redraw has been requested and not yet waited for.
+  pub fn is_redraw_requested (&self) -> bool {
+    self.lock().requested
+  }
+
+  /// In on-demand mode, block the calling (render) thread until a redraw is
+  /// requested, returning true if it blocked. The request is consumed.
+  ///
+  /// Returns immediately if not in on-demand mode.
+  pub fn wait_for_redraw (&self) -> bool {
+    let (_, condvar) = &*self.shared;
+    let mut state = self.lock();
+    let mut waited = false;
+    while state.on_demand && !state.requested {
+      waited = true;
+      state = condvar.wait (state).unwrap_or_else (|err| err.into_inner());
+    }
+    state.requested = false;
+    waited
+  }
+
+  fn lock (&self) -> std::sync::MutexGuard <'_, RedrawState> {
+    let (mutex, _) = &*self.shared;
+    // the state is always valid so a poisoned lock can be recovered
+    mutex.lock().unwrap_or_else (|err| err.into_inner())
+  }
+}
+
+impl Default for RedrawHandle {
+  fn default() -> Self {
+    RedrawHandle::new()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  #[test]
+  fn test_redraw_coalescing() {
+    let redraw = RedrawHandle::new();
+    // not on demand
+    assert!(!redraw.wait_for_redraw());
+    redraw.set_on_demand (true);
+    redraw.request_redraw();
+    redraw.request_redraw();
+    assert!(!redraw.wait_for_redraw());
+    assert!(!redraw.is_redraw_requested());
+    let waiter = {
+      let redraw = redraw.clone();
+      std::thread::spawn (move || redraw.wait_for_redraw())
+    };
+    redraw.request_redraw();
+    waiter.join().unwrap();
+    assert!(!redraw.is_redraw_requested());
+  }
+}
@@ -0,0 +1,202 @@
+//! Main thread registry of windows by SDL window ID.
+//!
+//! When more than one window is in use, events and commands are associated
+//! with windows by their SDL window ID. The `WindowRegistry` maps those IDs to
+//! application data on the main thread, while the render thread refers to
+//! windows through `Send`able `WindowHandle`s.
+
+use sdl2_sys;
+
+use SdlGlWindowBackend;
+use system::{self, WindowPlacement};
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Maps SDL window IDs to per-window data of type `T`.
+pub struct WindowRegistry <T = ()> {
+  windows : std::collections::BTreeMap <u32, T>
+}
+
+/// A `Send`able reference to a registered window, resolved to a controller or
+/// the registered data by the registry on the main thread.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct WindowHandle {
+  window_id : u32
+}
+
+/// Main thread access to a live window.
+///
+/// Controllers are resolved by window ID each time they are needed, so they
+/// should not be held across iterations of the event loop.
+pub struct WindowController {
+  window_id  : u32,
+  window_raw : *mut sdl2_sys::SDL_Window
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+impl <T> WindowRegistry <T> {
+  pub fn new() -> Self {
+    WindowRegistry { windows: std::collections::BTreeMap::new() }
+  }
+
+  /// Register a window backend with associated data, returning a handle that
+  /// can be sent along with the backend to the render thread.
+  ///
+  /// Registering the same window again replaces its data.
+  pub fn register (&mut self, window_backend : &SdlGlWindowBackend, data : T)
+    -> WindowHandle
+  {
+    self.register_id (window_backend.window_id(), data)
+  }
+
+  /// Register a window by SDL window ID.
+  pub fn register_id (&mut self, window_id : u32, data : T) -> WindowHandle {
+    self.windows.insert (window_id, data);
+    WindowHandle { window_id }
+  }
+
+  /// Remove a window, returning its data.
+  pub fn unregister (&mut self, window_id : u32) -> Option <T> {
+    self.windows.remove (&window_id)
+  }
+
+  pub fn contains (&self, window_id : u32) -> bool {
+    self.windows.contains_key (&window_id)
+  }
+
+  pub fn len (&self) -> usize {
+    self.windows.len()
+  }
+
+  pub fn is_empty (&self) -> bool {
+    self.windows.is_empty()
+  }
+
+  /// Handle for a registered window ID.
+  pub fn handle (&self, window_id : u32) -> Option <WindowHandle> {
+    if self.contains (window_id) {
+      Some (WindowHandle { window_id })
+    } else {
+      None
+    }
+  }
+
+  pub fn get (&self, window_id : u32) -> Option <&T> {
+    self.windows.get (&window_id)
+  }
+
+  pub fn get_mut (&mut self, window_id : u32) -> Option <&mut T> {
+    self.windows.get_mut (&window_id)
+  }
+
+  /// Data registered for the window referred to by the handle.
+  pub fn resolve (&self, handle : WindowHandle) -> Option <&T> {
+    self.get (handle.window_id)
+  }
+
+  /// Controller for a registered window.
+  ///
+  /// Returns `None` if the window is not registered or has been destroyed.
+  pub fn controller (&self, window_id : u32) -> Option <WindowController> {
+    if self.contains (window_id) {
+      WindowController::from_id (window_id)
+    } else {
+      None
+    }
+  }
+
+  /// Iterate over the registered window IDs and data in ID order.
+  pub fn iter (&self) -> std::collections::btree_map::Iter <'_, u32, T> {
+    self.windows.iter()
+  }
+}
+
+impl <T> Default for WindowRegistry <T> {
+  fn default() -> Self {
+    WindowRegistry::new()
+  }
+}
+
+impl WindowHandle {
+  /// SDL window ID
+  pub fn id (&self) -> u32 {
+    self.window_id
+  }
+}
+
+impl WindowController {
+  /// Look up a live window by SDL window ID.
+  ///
+  /// This must be called on the main thread.
+  pub fn from_id (window_id : u32) -> Option <Self> {
+    let window_raw = unsafe { sdl2_sys::SDL_GetWindowFromID (window_id) };
+    if window_raw.is_null() {
+      None
+    } else {
+      Some (WindowController { window_id, window_raw })
+    }
+  }
+
+  pub fn id (&self) -> u32 {
+    self.window_id
+  }
+
+  pub fn raw (&self) -> *mut sdl2_sys::SDL_Window {
+    self.window_raw
+  }
+
+  pub fn handle (&self) -> WindowHandle {
+    WindowHandle { window_id: self.window_id }
+  }
+
+  /// Window size in window coordinates.
+  pub fn size (&self) -> (u32, u32) {
+    let (mut width, mut height) = (0, 0);
+    unsafe {
+      sdl2_sys::SDL_GetWindowSize (self.window_raw, &mut width, &mut height)
+    };
+    (width as u32, height as u32)
+  }
+
+  /// Window size in drawable pixels.
+  pub fn drawable_size (&self) -> (u32, u32) {
+    let (mut width, mut height) = (0, 0);
+    unsafe {
+      sdl2_sys::SDL_GL_GetDrawableSize (
+        self.window_raw, &mut width, &mut height)
+    };
+    (width as u32, height as u32)
+  }
+
+  /// Desktop position of the window.
+  pub fn position (&self) -> (i32, i32) {
+    let (mut x, mut y) = (0, 0);
+    unsafe {
+      sdl2_sys::SDL_GetWindowPosition (self.window_raw, &mut x, &mut y)
+    };
+    (x, y)
+  }
+
+  /// Current display, drawable size and scale of the window.
+  pub fn placement (&self) -> WindowPlacement {
+    system::window_placement (self.window_raw)
+  }
+
+  pub fn title (&self) -> String {
+    unsafe {
+      let title = sdl2_sys::SDL_GetWindowTitle (self.window_raw);
+      std::ffi::CStr::from_ptr (title).to_string_lossy().into_owned()
+    }
+  }
+
+  pub fn set_title (&self, title : &str) -> Result <(), std::ffi::NulError> {
+    let title = try!{ std::ffi::CString::new (title) };
+    unsafe { sdl2_sys::SDL_SetWindowTitle (self.window_raw, title.as_ptr()) };
+    Ok (())
+  }
+}
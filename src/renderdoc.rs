@@ -0,0 +1,179 @@
+//! Triggering RenderDoc captures from the application.
+//!
+//! When the application is launched from RenderDoc, or RenderDoc is injected
+//! into it, the in-application API is used to capture frames on request, e.g.
+//! after a key press, rather than through the RenderDoc overlay:
+//!
+//! ```ignore
+//! // on the render thread
+//! if capture_requested {
+//!   display.trigger_capture();
+//! }
+//! let mut frame = display.draw();
+//! ```
+//!
+//! From the main thread, e.g. in the input handler, the capture is requested
+//! with `WindowCommand::TriggerCapture` through a command sender created by
+//! the `CommandExecutor`, and happens once the main thread executes pending
+//! commands.
+//!
+//! RenderDoc captures the next frame presented by its active window, which is
+//! the window that last presented a frame. The API is only looked up if
+//! RenderDoc is already loaded; this never loads RenderDoc itself.
+
+use sdl2_sys;
+
+use std::os::raw::{c_int, c_void};
+
+///////////////////////////////////////////////////////////////////////////////
+//  constants                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+/// `eRENDERDOC_API_Version_1_0_0`
+const RENDERDOC_API_VERSION_1_0_0 : c_int = 10000;
+#[cfg(windows)]
+const RENDERDOC_LIBRARY : &[u8] = b"renderdoc.dll\0";
+
+///////////////////////////////////////////////////////////////////////////////
+//  statics                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Address of the API, or 0 if RenderDoc is not loaded; looked up on first
+/// use
+static RENDERDOC_API : std::sync::atomic::AtomicUsize =
+  std::sync::atomic::AtomicUsize::new (0);
+static RENDERDOC_API_LOOKUP : std::sync::Once = std::sync::Once::new();
+
+///////////////////////////////////////////////////////////////////////////////
+//  typedefs                                                                 //
+///////////////////////////////////////////////////////////////////////////////
+
+type GetApi = unsafe extern "C" fn (c_int, *mut *mut c_void) -> c_int;
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// The in-application API of a loaded RenderDoc.
+#[derive(Clone, Copy)]
+pub struct RenderDoc {
+  api : &'static RenderDocApi
+}
+
+//
+// private
+//
+
+/// The leading functions of `RENDERDOC_API_1_0_0`, which later versions
+/// extend.
+#[repr(C)]
+struct RenderDocApi {
+  get_api_version  : unsafe extern "C" fn (*mut c_int, *mut c_int, *mut c_int),
+  /// Capture options, keys, overlay, hooks and capture file path functions
+  _settings        : [*const c_void; 12],
+  get_num_captures : unsafe extern "C" fn() -> u32,
+  _get_capture     : *const c_void,
+  trigger_capture  : unsafe extern "C" fn()
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+impl RenderDoc {
+  /// The API of RenderDoc, or `None` if the application is not running
+  /// under RenderDoc.
+  pub fn get() -> Option <Self> {
+    use std::sync::atomic::Ordering;
+    RENDERDOC_API_LOOKUP.call_once (|| {
+      let api = unsafe { lookup_api() };
+      RENDERDOC_API.store (api as usize, Ordering::SeqCst);
+    });
+    let api = RENDERDOC_API.load (Ordering::SeqCst) as *const RenderDocApi;
+    if api.is_null() {
+      None
+    } else {
+      // the API is valid for as long as RenderDoc is loaded, which is until
+      // the process exits
+      Some (RenderDoc { api: unsafe { &*api } })
+    }
+  }
+
+  /// Major, minor and patch version of the API.
+  pub fn api_version (&self) -> (i32, i32, i32) {
+    let (mut major, mut minor, mut patch) = (0, 0, 0);
+    unsafe { (self.api.get_api_version) (&mut major, &mut minor, &mut patch) };
+    (major, minor, patch)
+  }
+
+  /// Capture the next frame presented by the active window.
+  pub fn trigger_capture (&self) {
+    unsafe { (self.api.trigger_capture)() }
+  }
+
+  /// Number of captures made so far.
+  pub fn num_captures (&self) -> u32 {
+    unsafe { (self.api.get_num_captures)() }
+  }
+}
+
+/// The functions of the API may be called from any thread.
+unsafe impl Send for RenderDoc { }
+unsafe impl Sync for RenderDoc { }
+
+///////////////////////////////////////////////////////////////////////////////
+//  functions                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Capture the next frame presented by the active window, returning false if
+/// the application is not running under RenderDoc.
+pub fn trigger_capture() -> bool {
+  match RenderDoc::get() {
+    Some (renderdoc) => {
+      renderdoc.trigger_capture();
+      true
+    }
+    None => false
+  }
+}
+
+//
+// private
+//
+
+/// Look up `RENDERDOC_GetAPI` in the loaded RenderDoc library and get the
+/// API, returning null if RenderDoc is not loaded.
+unsafe fn lookup_api() -> *const RenderDocApi {
+  let library = renderdoc_library();
+  if library.is_null() {
+    return std::ptr::null()
+  }
+  let get_api = sdl2_sys::SDL_LoadFunction (
+    library, b"RENDERDOC_GetAPI\0".as_ptr() as *const _);
+  if get_api.is_null() {
+    return std::ptr::null()
+  }
+  let get_api : GetApi = std::mem::transmute (get_api);
+  let mut api = std::ptr::null_mut();
+  if get_api (RENDERDOC_API_VERSION_1_0_0, &mut api) == 1 {
+    api as *const RenderDocApi
+  } else {
+    std::ptr::null()
+  }
+}
+
+/// The RenderDoc module if it is loaded.
+#[cfg(windows)]
+unsafe fn renderdoc_library() -> *mut c_void {
+  extern "system" {
+    fn GetModuleHandleA (name : *const u8) -> *mut c_void;
+  }
+  GetModuleHandleA (RENDERDOC_LIBRARY.as_ptr())
+}
+
+/// The global namespace of the process, which contains the symbols of an
+/// injected RenderDoc.
+#[cfg(not(windows))]
+unsafe fn renderdoc_library() -> *mut c_void {
+  sdl2_sys::SDL_LoadObject (std::ptr::null())
+}
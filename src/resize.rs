@@ -0,0 +1,127 @@
+//! Caching the drawable size of a window.
+//!
+//! glium queries the framebuffer dimensions of the backend every frame. Rather
+//! than calling `SDL_GL_GetDrawableSize` from the render thread each time, a
+//! window backend keeps the size in a `SizeCache`, which is updated by an SDL
+//! event watch whenever SDL reports `SDL_WINDOWEVENT_SIZE_CHANGED` for the
+//! window.
+//!
+//! Event watches run when the event is added to the queue, i.e. on the main
+//! thread while it pumps events, so the cached size is current by the time
+//! the size change event reaches the render thread. The watch is removed when
+//! the cache is dropped; SDL holds its watcher lock while calling watches, so
+//! the cache may be dropped on the render thread.
+
+use sdl2_sys;
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Drawable size of a window, updated on size change events.
+pub(crate) struct SizeCache {
+  /// Boxed so that the address given to SDL is stable
+  watch : Box <SizeWatch>
+}
+
+//
+// private
+//
+
+/// State shared with the event watch.
+struct SizeWatch {
+  window_id : u32,
+  /// Width in the high and height in the low 32 bits
+  size      : std::sync::atomic::AtomicU64
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+impl SizeCache {
+  /// Cache the current drawable size of the window and watch for changes.
+  ///
+  /// Must be called on the main thread.
+  pub(crate) fn new (window_raw : *mut sdl2_sys::SDL_Window) -> Self {
+    let window_id = unsafe { sdl2_sys::SDL_GetWindowID (window_raw) };
+    let size
+      = std::sync::atomic::AtomicU64::new (pack (drawable_size (window_raw)));
+    let watch = Box::new (SizeWatch { window_id, size });
+    unsafe {
+      sdl2_sys::SDL_AddEventWatch (Some (size_changed),
+        &*watch as *const SizeWatch as *mut std::os::raw::c_void)
+    };
+    SizeCache { watch }
+  }
+
+  /// The cached drawable size in pixels.
+  pub(crate) fn get (&self) -> (u32, u32) {
+    unpack (self.watch.size.load (std::sync::atomic::Ordering::Relaxed))
+  }
+}
+
+impl Drop for SizeCache {
+  fn drop (&mut self) {
+    unsafe {
+      sdl2_sys::SDL_DelEventWatch (Some (size_changed),
+        &*self.watch as *const SizeWatch as *mut std::os::raw::c_void)
+    };
+  }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  functions                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+//
+// private
+//
+
+/// Event watch updating the cache of a window on a size change.
+unsafe extern "C" fn size_changed (
+  userdata : *mut std::os::raw::c_void, event : *mut sdl2_sys::SDL_Event
+) -> std::os::raw::c_int {
+  use sdl2_sys::SDL_WindowEventID::SDL_WINDOWEVENT_SIZE_CHANGED;
+  let watch = &*(userdata as *const SizeWatch);
+  if (*event).type_ == sdl2_sys::SDL_EventType::SDL_WINDOWEVENT as u32
+    && (*event).window.event == SDL_WINDOWEVENT_SIZE_CHANGED as u8
+    && (*event).window.windowID == watch.window_id
+  {
+    // the event carries the size in window coordinates, which differ from
+    // pixels on high-DPI displays
+    let window_raw = sdl2_sys::SDL_GetWindowFromID (watch.window_id);
+    if !window_raw.is_null() {
+      watch.size.store (pack (drawable_size (window_raw)),
+        std::sync::atomic::Ordering::Relaxed);
+    }
+  }
+  // the return value of a watch is ignored
+  0
+}
+
+fn drawable_size (window_raw : *mut sdl2_sys::SDL_Window) -> (u32, u32) {
+  let (mut width, mut height) = (0, 0);
+  unsafe {
+    sdl2_sys::SDL_GL_GetDrawableSize (window_raw, &mut width, &mut height)
+  };
+  (width as u32, height as u32)
+}
+
+fn pack ((width, height) : (u32, u32)) -> u64 {
+  (width as u64) << 32 | height as u64
+}
+
+fn unpack (size : u64) -> (u32, u32) {
+  ((size >> 32) as u32, size as u32)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  #[test]
+  fn test_pack_size() {
+    assert_eq!(unpack (pack ((1920, 1080))), (1920, 1080));
+    assert_eq!(unpack (pack ((u32::max_value(), 0))), (u32::max_value(), 0));
+  }
+}
@@ -0,0 +1,444 @@
+//! Spawning and supervision of the render thread.
+//!
+//! A `RenderThread` moves a window backend to a new thread, builds the glium
+//! display there and runs the render function with it. `spawn` returns once
+//! the display has been built, so the main thread can start processing
+//! events without any further synchronization.
+//!
+//! When the render thread exits, whether by returning or by panicking, it
+//! signals shutdown to the main thread by pushing an SDL user event, which
+//! wakes a main thread blocked in `EventPump::wait_event`:
+//!
+//! ```ignore
+//! let render_thread = RenderThread::new()
+//!   .error_dialog ("Fatal error")
+//!   .spawn (window_backend, |display| {
+//!     while !shutdown::shutdown_requested() {
+//!       // ... render a frame ...
+//!     }
+//!   })
+//!   .unwrap();
+//! loop {
+//!   let event = event_pump.wait_event();
+//!   match event {
+//!     sdl2::event::Event::Quit { .. } => shutdown::request_shutdown(),
+//!     _ if render_thread.is_exit_event (&event) => break,
+//!     // ...
+//!   }
+//! }
+//! render_thread.join().unwrap();
+//! ```
+//!
+//! A `RunLoop` runs this main loop itself, passing every other event to an
+//! input function:
+//!
+//! ```ignore
+//! RunLoop::new (RenderThread::new().error_dialog ("Fatal error"))
+//!   .run (window_backend, &mut event_pump, |display| {
+//!     while !shutdown::shutdown_requested() {
+//!       // ... render a frame ...
+//!     }
+//!   }, |event| {
+//!     // ... handle the event on the main thread ...
+//!   })
+//!   .unwrap();
+//! ```
+//!
+//! # Teardown
+//!
+//! The runner tears down the window in a fixed order that holds whether the
+//! render function returns or panics:
+//!
+//! 1. On the render thread, the render function returns or unwinds, dropping
+//!    the GL objects it owns while the context is still current. If an error
+//!    dialog was requested for a panic it is shown now.
+//! 2. On the render thread, the runner drops its own reference to the
+//!    display: the glium context and the GL objects of the facade are
+//!    deleted, then the GL context itself. The window is detached and sent
+//!    back to the main thread. If the render function leaked a clone of the
+//!    display or a GL object, this step is skipped and the window is
+//!    destroyed when the last reference is dropped.
+//! 3. The render thread signals its exit with the exit event.
+//! 4. On the main thread, `join` releases mouse grabs and relative mouse
+//!    mode, leaves fullscreen (restoring the desktop display mode), restores
+//!    the gamma ramp saved at spawn and shows the cursor, then destroys the
+//!    window.
+//!
+//! If the handle is dropped without joining, e.g. because the main loop
+//! returned an error or is unwinding, the thread is joined if it has already
+//! exited; otherwise the window state of step 4 is restored immediately so
+//! that the desktop is usable and the thread is left running. The handle
+//! should be joined or dropped before the `sdl2::Sdl` context. Nothing can be
+//! restored if the process aborts.
+
+use glium;
+use sdl2;
+use sdl2_sys;
+
+use {SdlGlWindowBackend, SdlGliumDisplayFacade, ShutdownToken};
+use command;
+use reaper::DetachedWindow;
+use shutdown;
+use system;
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Options for spawning a render thread.
+#[derive(Clone, Debug, Default)]
+pub struct RenderThread {
+  name         : Option <String>,
+  /// Title of the error dialog shown when the render function panics
+  error_dialog : Option <String>
+}
+
+/// Main loop that spawns a render thread, forwards events to an input function
+/// until the render thread exits, then joins it.
+#[derive(Clone, Debug, Default)]
+pub struct RunLoop {
+  render_thread : RenderThread
+}
+
+/// Main thread handle of a running render thread.
+///
+/// Dropping the handle joins the thread if it has exited; see the module
+/// documentation.
+pub struct RenderThreadHandle {
+  thread     : Option <std::thread::JoinHandle <RenderExit>>,
+  window_id  : u32,
+  /// Gamma ramp of the window at spawn
+  gamma      : Option <Box <GammaRamp>>,
+  exited     : std::sync::Arc <std::sync::atomic::AtomicBool>,
+  exit_event : u32,
+  shutdown   : ShutdownToken
+}
+
+//
+// private
+//
+
+/// Red, green and blue gamma tables.
+type GammaRamp = [[u16; 256]; 3];
+
+/// Value returned by the render thread.
+struct RenderExit {
+  result : Result <(), RenderThreadError>,
+  /// Set if the GL context was deleted on the render thread
+  window : Option <DetachedWindow>
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  enums                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug)]
+pub enum RenderThreadError {
+  SpawnError (std::io::Error),
+  /// Glium could not be built on the render thread
+  BuildError (glium::IncompatibleOpenGl),
+  /// The render function panicked; carries the panic message
+  Panic (String)
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+impl RenderThread {
+  pub fn new() -> Self {
+    RenderThread::default()
+  }
+
+  /// Name of the spawned thread, shown in panic messages and debuggers.
+  pub fn name (mut self, name : &str) -> Self {
+    self.name = Some (name.to_string());
+    self
+  }
+
+  /// Show an error dialog with the panic message if the render function
+  /// panics, before shutdown is signalled to the main thread.
+  ///
+  /// The dialog is shown from the render thread (see
+  /// `system::show_error_dialog`) so the main thread keeps processing events
+  /// until it is dismissed.
+  pub fn error_dialog (mut self, title : &str) -> Self {
+    self.error_dialog = Some (title.to_string());
+    self
+  }
+
+  /// Spawn the render thread, build glium on it and run the render function
+  /// with the display, blocking until the display has been built.
+  ///
+  /// This must be called on the main thread after SDL has been initialized.
+  pub fn spawn <F> (self, window_backend : SdlGlWindowBackend, render : F)
+    -> Result <RenderThreadHandle, RenderThreadError>
+  where
+    F : FnOnce (SdlGliumDisplayFacade) + Send + 'static
+  {
+    let exit_event = unsafe { sdl2_sys::SDL_RegisterEvents (1) };
+    let window_id  = window_backend.window_id();
+    let shutdown   = window_backend.shutdown_handle();
+    let gamma      = if window_backend.mock.is_none() {
+      gamma_ramp (window_backend.window_raw.as_ptr())
+    } else {
+      None
+    };
+    let exited
+      = std::sync::Arc::new (std::sync::atomic::AtomicBool::new (false));
+    let (started, startup) = std::sync::mpsc::channel();
+    let mut builder = std::thread::Builder::new();
+    if let Some (ref name) = self.name {
+      builder = builder.name (name.clone());
+    }
+    let error_dialog = self.error_dialog;
+    let render_exited = exited.clone();
+    log_record!(Info, "spawning render thread {} for window {}",
+      self.name.as_ref().map_or ("<unnamed>", |name| name.as_str()),
+      window_id);
+    let thread = try!{
+      builder.spawn (move || {
+        let exit = match window_backend.build_glium() {
+          Ok (display) => {
+            let _ = started.send (Ok (()));
+            let render_display = display.clone();
+            let result = std::panic::catch_unwind (
+              std::panic::AssertUnwindSafe (|| render (render_display))
+            ).map_err (|payload| {
+              let message = panic_message (&*payload);
+              if let Some (title) = error_dialog {
+                let _ = system::show_error_dialog (&title, &message);
+              }
+              RenderThreadError::Panic (message)
+            });
+            let window = std::panic::catch_unwind (
+              std::panic::AssertUnwindSafe (|| detach_window (display))
+            ).unwrap_or (None);
+            RenderExit { result, window }
+          }
+          Err (err) => {
+            let _ = started.send (Err (err));
+            RenderExit { result: Ok (()), window: None }
+          }
+        };
+        render_exited.store (true, std::sync::atomic::Ordering::SeqCst);
+        command::push_user_event (exit_event, 0);
+        exit
+      })
+    };
+    match startup.recv() {
+      Ok (Ok (())) => Ok (RenderThreadHandle {
+        thread: Some (thread), window_id, gamma, exited, exit_event, shutdown
+      }),
+      Ok (Err (err)) => {
+        let _ = thread.join();
+        Err (RenderThreadError::BuildError (err))
+      }
+      // the thread panicked while building glium
+      Err (_) => Err (RenderThreadError::Panic (
+        thread.join().err().map_or_else (String::new,
+          |payload| panic_message (&*payload))))
+    }
+  }
+}
+
+impl RunLoop {
+  /// Create a run loop spawning its render thread with the given options.
+  pub fn new (render_thread : RenderThread) -> Self {
+    RunLoop { render_thread }
+  }
+
+  /// Spawn the render thread, then wait for events on the main thread and
+  /// pass them to `input` until the render thread exits, and join it.
+  ///
+  /// `SDL_QUIT` is passed to `input` and then requests shutdown; `input` may
+  /// also call `shutdown::request_shutdown` to quit. The render function
+  /// should return once `shutdown::shutdown_requested` returns true. If it
+  /// returns or panics before, the loop ends without a shutdown request.
+  ///
+  /// This must be called on the main thread after SDL has been initialized.
+  pub fn run <R, I> (self,
+    window_backend : SdlGlWindowBackend,
+    event_pump     : &mut sdl2::EventPump,
+    render         : R,
+    mut input      : I
+  ) -> Result <(), RenderThreadError> where
+    R : FnOnce (SdlGliumDisplayFacade) + Send + 'static,
+    I : FnMut (&sdl2::event::Event)
+  {
+    let render_thread
+      = try!{ self.render_thread.spawn (window_backend, render) };
+    loop {
+      let event = event_pump.wait_event();
+      if render_thread.is_exit_event (&event) {
+        break
+      }
+      input (&event);
+      if let sdl2::event::Event::Quit { .. } = event {
+        shutdown::request_shutdown();
+      }
+    }
+    render_thread.join()
+  }
+}
+
+impl RenderThreadHandle {
+  /// Returns true once the render thread has exited or is about to.
+  pub fn has_exited (&self) -> bool {
+    self.exited.load (std::sync::atomic::Ordering::SeqCst)
+  }
+
+  /// Token for shutting down the render loop of this window only, see
+  /// `ShutdownToken`.
+  pub fn shutdown_handle (&self) -> ShutdownToken {
+    self.shutdown.clone()
+  }
+
+  /// Returns true if the event was pushed to signal that the render thread
+  /// has exited.
+  pub fn is_exit_event (&self, event : &sdl2::event::Event) -> bool {
+    match *event {
+      sdl2::event::Event::User { type_, .. } => type_ == self.exit_event,
+      _ => false
+    }
+  }
+
+  /// Wait for the render thread to exit, then restore the window state and
+  /// destroy the window.
+  ///
+  /// This must be called on the main thread.
+  pub fn join (mut self) -> Result <(), RenderThreadError> {
+    self.finish()
+  }
+
+  fn finish (&mut self) -> Result <(), RenderThreadError> {
+    let thread = match self.thread.take() {
+      Some (thread) => thread,
+      None => return Ok (())
+    };
+    let exit = thread.join();
+    log_record!(Info, "render thread of window {} joined", self.window_id);
+    match exit {
+      Ok (RenderExit { result, window: Some (window) }) => {
+        restore_window (window.window_raw, self.gamma.as_deref());
+        window.destroy();
+        result
+      }
+      Ok (RenderExit { result, window: None }) => {
+        self.restore_window();
+        result
+      }
+      Err (payload) => {
+        self.restore_window();
+        Err (RenderThreadError::Panic (panic_message (&*payload)))
+      }
+    }
+  }
+
+  /// Restore the state of the window if it still exists.
+  fn restore_window (&self) {
+    let window_raw = unsafe { sdl2_sys::SDL_GetWindowFromID (self.window_id) };
+    if !window_raw.is_null() {
+      restore_window (window_raw, self.gamma.as_deref());
+    }
+  }
+}
+
+impl Drop for RenderThreadHandle {
+  fn drop (&mut self) {
+    if self.thread.is_some() {
+      if self.has_exited() {
+        let _ = self.finish();
+      } else {
+        self.restore_window();
+      }
+    }
+  }
+}
+
+impl From <std::io::Error> for RenderThreadError {
+  fn from (err : std::io::Error) -> Self {
+    RenderThreadError::SpawnError (err)
+  }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  functions                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Message of a panic payload, which is a `&str` or a `String` for panics
+/// raised with `panic!`.
+pub fn panic_message (payload : &(dyn std::any::Any + Send)) -> String {
+  if let Some (message) = payload.downcast_ref::<&str>() {
+    message.to_string()
+  } else if let Some (message) = payload.downcast_ref::<String>() {
+    message.clone()
+  } else {
+    "unknown panic payload".to_string()
+  }
+}
+
+//
+// private
+//
+
+/// Delete the GL context of the display on the current thread and detach its
+/// window, if this is the last reference to the display and it has a window.
+fn detach_window (display : SdlGliumDisplayFacade) -> Option <DetachedWindow> {
+  display.into_window_backend()
+    .filter (|window_backend| window_backend.mock.is_none())
+    .map (|mut window_backend| {
+      window_backend.owns_window = false;
+      DetachedWindow { window_raw: window_backend.window_raw.as_ptr() }
+    })
+}
+
+fn gamma_ramp (window_raw : *mut sdl2_sys::SDL_Window)
+  -> Option <Box <GammaRamp>>
+{
+  let mut ramp = Box::new ([[0; 256]; 3]);
+  let result = unsafe {
+    sdl2_sys::SDL_GetWindowGammaRamp (window_raw,
+      ramp[0].as_mut_ptr(), ramp[1].as_mut_ptr(), ramp[2].as_mut_ptr())
+  };
+  if result == 0 {
+    Some (ramp)
+  } else {
+    None
+  }
+}
+
+/// Release input grabs, leave fullscreen, restore the gamma ramp and show the
+/// cursor.
+///
+/// This must be called on the main thread.
+fn restore_window (
+  window_raw : *mut sdl2_sys::SDL_Window, gamma : Option <&GammaRamp>
+) {
+  unsafe {
+    sdl2_sys::SDL_SetWindowGrab (window_raw, sdl2_sys::SDL_bool::SDL_FALSE);
+    sdl2_sys::SDL_SetRelativeMouseMode (sdl2_sys::SDL_bool::SDL_FALSE);
+    sdl2_sys::SDL_SetWindowFullscreen (window_raw, 0);
+    if let Some (ramp) = gamma {
+      sdl2_sys::SDL_SetWindowGammaRamp (window_raw,
+        ramp[0].as_ptr(), ramp[1].as_ptr(), ramp[2].as_ptr());
+    }
+    sdl2_sys::SDL_ShowCursor (1);
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  #[test]
+  fn test_panic_message() {
+    let payload = std::panic::catch_unwind (|| panic!("static")).unwrap_err();
+    assert_eq!(panic_message (&*payload), "static");
+    let payload
+      = std::panic::catch_unwind (|| panic!("formatted {}", 1)).unwrap_err();
+    assert_eq!(panic_message (&*payload), "formatted 1");
+    let payload = std::panic::catch_unwind (
+      || std::panic::resume_unwind (Box::new (1))).unwrap_err();
+    assert_eq!(panic_message (&*payload), "unknown panic payload");
+  }
+}
@@ -0,0 +1,264 @@
+//! On-disk cache of linked shader programs.
+//!
+//! Compiling and linking shaders on the render thread at startup causes
+//! stutter in applications with many programs. A `ShaderCache` stores the
+//! driver's binary of each program linked through the display (retrieved
+//! with `glGetProgramBinary`) in a directory, and the binaries are read back
+//! when the cache is opened on the next run, so that programs are loaded
+//! without compiling:
+//!
+//! ```ignore
+//! display.set_shader_cache (Some (ShaderCache::open ("cache/shaders")?));
+//! let program = display.build_program (VERTEX_SRC, FRAGMENT_SRC, None)?;
+//! ```
+//!
+//! Binaries are keyed by a hash of the shader sources and of the GL vendor,
+//! renderer and version strings, so that a driver update misses the cache
+//! rather than loading a stale binary. A binary that the driver rejects is
+//! replaced by compiling the sources. Binaries are written as
+//! `<key>.bin` files holding the binary format as a little-endian `u32`
+//! followed by the binary; entries for old drivers or sources are not
+//! removed automatically, see `ShaderCache::clear`.
+//!
+//! Program binaries require OpenGL 4.1, `GL_ARB_get_program_binary` or
+//! OpenGL ES 3.0; otherwise programs are compiled from source every time.
+
+use glium;
+
+///////////////////////////////////////////////////////////////////////////////
+//  constants                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Extension of cache entries
+const ENTRY_EXTENSION : &str = "bin";
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// A directory of program binaries; see `shaders`.
+#[derive(Debug)]
+pub struct ShaderCache {
+  directory : std::path::PathBuf,
+  /// Binaries read from the directory or stored since, by key, as their
+  /// format and content
+  binaries  : std::collections::HashMap <u64, (u32, Vec <u8>)>,
+  hits      : usize,
+  misses    : usize
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+impl ShaderCache {
+  /// Open a cache directory, creating it if it does not exist, and read the
+  /// binaries stored in it.
+  ///
+  /// Unreadable or malformed entries are skipped.
+  pub fn open <P : AsRef <std::path::Path>> (directory : P)
+    -> std::io::Result <Self>
+  {
+    let directory = directory.as_ref().to_path_buf();
+    try!{ std::fs::create_dir_all (&directory) };
+    let mut binaries = std::collections::HashMap::new();
+    for entry in try!{ std::fs::read_dir (&directory) } {
+      let path = try!{ entry }.path();
+      if path.extension().is_none_or (|ext| ext != ENTRY_EXTENSION) {
+        continue
+      }
+      let key = match path.file_stem().and_then (|stem| stem.to_str())
+        .and_then (|stem| u64::from_str_radix (stem, 16).ok())
+      {
+        Some (key) => key,
+        None => continue
+      };
+      if let Some (binary) = std::fs::read (&path).ok()
+        .and_then (|data| decode (&data))
+      {
+        binaries.insert (key, binary);
+      }
+    }
+    Ok (ShaderCache { directory, binaries, hits: 0, misses: 0 })
+  }
+
+  pub fn directory (&self) -> &std::path::Path {
+    &self.directory
+  }
+
+  /// Number of binaries in the cache.
+  pub fn len (&self) -> usize {
+    self.binaries.len()
+  }
+
+  pub fn is_empty (&self) -> bool {
+    self.binaries.is_empty()
+  }
+
+  /// Number of programs loaded from a binary.
+  pub fn hits (&self) -> usize {
+    self.hits
+  }
+
+  /// Number of programs compiled from source.
+  pub fn misses (&self) -> usize {
+    self.misses
+  }
+
+  /// Remove all binaries from the cache and its directory.
+  pub fn clear (&mut self) -> std::io::Result <()> {
+    for key in self.binaries.keys() {
+      match std::fs::remove_file (self.entry_path (*key)) {
+        Err (ref err) if err.kind() != std::io::ErrorKind::NotFound =>
+          return Err (std::io::Error::new (err.kind(), err.to_string())),
+        _ => {}
+      }
+    }
+    self.binaries.clear();
+    Ok (())
+  }
+
+  /// Load a program from its cached binary, or compile it from source and
+  /// store its binary.
+  ///
+  /// Failing to store a binary is not an error: the program is returned and
+  /// compiled again on the next run.
+  pub(crate) fn program (
+    &mut self,
+    context  : &std::rc::Rc <glium::backend::Context>,
+    vertex   : &str,
+    fragment : &str,
+    geometry : Option <&str>
+  ) -> Result <glium::Program, glium::ProgramCreationError> {
+    let key = cache_key (&[
+      Some (context.get_opengl_vendor_string()),
+      Some (context.get_opengl_renderer_string()),
+      Some (context.get_opengl_version_string()),
+      Some (vertex),
+      Some (fragment),
+      geometry
+    ]);
+    if let Some (&(format, ref content)) = self.binaries.get (&key) {
+      let binary = glium::program::Binary { format, content: content.clone() };
+      match glium::Program::new (context, binary) {
+        Ok (program) => {
+          self.hits += 1;
+          return Ok (program)
+        }
+        Err (err) => {
+          log_record!(Debug, "cached program binary {:016x} rejected: {:?}",
+            key, err);
+        }
+      }
+    }
+    self.misses += 1;
+    let program = try!{
+      glium::Program::from_source (context, vertex, fragment, geometry)
+    };
+    if let Ok (binary) = program.get_binary() {
+      if let Err (err) = self.store (key, (binary.format, binary.content)) {
+        log_record!(Warn, "storing program binary {:016x} failed: {}",
+          key, err);
+      }
+    }
+    Ok (program)
+  }
+
+  /// Write a binary to the directory and keep it.
+  fn store (&mut self, key : u64, binary : (u32, Vec <u8>))
+    -> std::io::Result <()>
+  {
+    use std::io::Write;
+    let path = self.entry_path (key);
+    // written to a temporary file first so that another process never reads
+    // a partial entry
+    let temporary = path.with_extension ("tmp");
+    {
+      let mut file = try!{ std::fs::File::create (&temporary) };
+      try!{ file.write_all (&encode (&binary)) };
+    }
+    try!{ std::fs::rename (&temporary, &path) };
+    self.binaries.insert (key, binary);
+    Ok (())
+  }
+
+  fn entry_path (&self, key : u64) -> std::path::PathBuf {
+    self.directory.join (format!("{:016x}.{}", key, ENTRY_EXTENSION))
+  }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  functions                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+//
+// private
+//
+
+/// 64-bit FNV-1a hash of the strings, distinguishing absent strings and
+/// string boundaries; unlike `std::hash` it is stable across builds.
+fn cache_key (strings : &[Option <&str>]) -> u64 {
+  let mut hash : u64 = 0xcbf2_9ce4_8422_2325;
+  let mut add = |byte : u8| {
+    hash ^= byte as u64;
+    hash = hash.wrapping_mul (0x0000_0100_0000_01b3);
+  };
+  for string in strings {
+    match *string {
+      Some (string) => {
+        add (1);
+        for byte in string.bytes() {
+          add (byte);
+        }
+        add (0);
+      }
+      None => add (2)
+    }
+  }
+  hash
+}
+
+fn encode (&(format, ref content) : &(u32, Vec <u8>)) -> Vec <u8> {
+  let mut data = Vec::with_capacity (4 + content.len());
+  for i in 0..4 {
+    data.push ((format >> (8 * i)) as u8);
+  }
+  data.extend_from_slice (content);
+  data
+}
+
+fn decode (data : &[u8]) -> Option <(u32, Vec <u8>)> {
+  if data.len() <= 4 {
+    return None
+  }
+  let format = data[..4].iter().rev()
+    .fold (0, |format, byte| format << 8 | *byte as u32);
+  Some ((format, data[4..].to_vec()))
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  #[test]
+  fn test_cache_entries() {
+    let binary = (0x8740, vec![1, 2, 3]);
+    assert_eq!(encode (&binary), vec![0x40, 0x87, 0, 0, 1, 2, 3]);
+    assert_eq!(decode (&encode (&binary)), Some (binary.clone()));
+    assert_eq!(decode (&[0x40, 0x87, 0, 0]), None);
+    // keys depend on the driver and on which sources are present
+    let key = cache_key (&[Some ("vendor"), Some ("vs"), None]);
+    assert_ne!(key, cache_key (&[Some ("vendor 2"), Some ("vs"), None]));
+    assert_ne!(key, cache_key (&[Some ("vendor"), None, Some ("vs")]));
+    assert_ne!(key, cache_key (&[Some ("vendorvs"), Some (""), None]));
+    // entries are read back when the cache is opened again
+    let directory = std::env::temp_dir().join (
+      format!("glium-sdl2-hack-shaders-{}", std::process::id()));
+    let mut cache = ShaderCache::open (&directory).unwrap();
+    cache.store (key, binary.clone()).unwrap();
+    let mut cache = ShaderCache::open (&directory).unwrap();
+    assert_eq!(cache.binaries.get (&key), Some (&binary));
+    cache.clear().unwrap();
+    assert!(ShaderCache::open (&directory).unwrap().is_empty());
+    std::fs::remove_dir (&directory).unwrap();
+  }
+}
@@ -0,0 +1,213 @@
+//! Process-wide and per-window shutdown requests.
+//!
+//! A process-wide shutdown request sets a flag polled by the render loop,
+//! which finishes its current frame and returns, and pushes an `SDL_QUIT`
+//! event so that a main thread blocked in `EventPump::wait_event` wakes up
+//! and handles it like the user closing the window.
+//!
+//! With the `signals` feature, `install_signal_handlers` turns SIGINT and
+//! SIGTERM (console control events on Windows) into shutdown requests, so
+//! that tools built on this crate exit cleanly on Ctrl-C. SDL installs its own
+//! handlers only for signals that have none, so these may be installed before
+//! or after SDL is initialized.
+//!
+//! A `ShutdownToken` shuts down the render loop of a single window without
+//! affecting the rest of the process. The main thread takes the token from
+//! the window backend before sending it to the render thread, then requests
+//! shutdown and waits for the render thread to drop the display:
+//!
+//! ```ignore
+//! let shutdown_token = window_backend.shutdown_handle();
+//! // ... send the window backend to the render thread, where the frame loop
+//! // runs while !display.shutdown_requested()
+//! shutdown_token.request();
+//! shutdown_token.wait_for_render_exit();
+//! ```
+//!
+//! The render thread is considered to have exited once the window backend has
+//! been dropped, and so the GL context deleted.
+
+#[cfg(feature = "signals")]
+use ctrlc;
+use sdl2_sys;
+
+use command;
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Shutdown state of the render loop of one window, shared between the main
+/// thread and the render thread.
+#[derive(Clone)]
+pub struct ShutdownToken {
+  shared : std::sync::Arc <(std::sync::Mutex <TokenState>, std::sync::Condvar)>
+}
+
+/// Held by a window backend, signalling render exit to its tokens when
+/// dropped after the backend itself.
+pub(crate) struct ExitSignal {
+  token : ShutdownToken
+}
+
+//
+// private
+//
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct TokenState {
+  requested : bool,
+  exited    : bool
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  statics                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+static SHUTDOWN_REQUESTED : std::sync::atomic::AtomicBool =
+  std::sync::atomic::AtomicBool::new (false);
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+impl ShutdownToken {
+  /// Request shutdown of the render loop. This may be called from any thread.
+  pub fn request (&self) {
+    let (_, condvar) = &*self.shared;
+    self.lock().requested = true;
+    condvar.notify_all();
+  }
+
+  /// Returns true once shutdown has been requested through this token or for
+  /// the whole process.
+  ///
+  /// Render loops should check this once per frame.
+  pub fn is_requested (&self) -> bool {
+    self.lock().requested || shutdown_requested()
+  }
+
+  /// Returns true once the window backend has been dropped.
+  pub fn has_render_exited (&self) -> bool {
+    self.lock().exited
+  }
+
+  /// Block until the window backend has been dropped.
+  ///
+  /// If the render thread may block on the main thread, e.g. waiting for a
+  /// command reply, use `wait_for_render_exit_timeout` and keep handling
+  /// events between waits.
+  pub fn wait_for_render_exit (&self) {
+    let (_, condvar) = &*self.shared;
+    let mut state = self.lock();
+    while !state.exited {
+      state = condvar.wait (state).unwrap_or_else (|err| err.into_inner());
+    }
+  }
+
+  /// Block until the window backend has been dropped or the timeout expires,
+  /// returning true if it has been dropped.
+  pub fn wait_for_render_exit_timeout (&self, timeout : std::time::Duration)
+    -> bool
+  {
+    let (_, condvar) = &*self.shared;
+    let deadline = std::time::Instant::now() + timeout;
+    let mut state = self.lock();
+    while !state.exited {
+      let now = std::time::Instant::now();
+      if deadline <= now {
+        return false
+      }
+      state = condvar.wait_timeout (state, deadline - now)
+        .map (|(state, _)| state)
+        .unwrap_or_else (|err| err.into_inner().0);
+    }
+    true
+  }
+
+  fn lock (&self) -> std::sync::MutexGuard <'_, TokenState> {
+    let (mutex, _) = &*self.shared;
+    // the state is always valid so a poisoned lock can be recovered
+    mutex.lock().unwrap_or_else (|err| err.into_inner())
+  }
+}
+
+impl ExitSignal {
+  pub(crate) fn new() -> Self {
+    ExitSignal {
+      token: ShutdownToken {
+        shared: std::sync::Arc::new ((
+          std::sync::Mutex::new (TokenState::default()),
+          std::sync::Condvar::new()))
+      }
+    }
+  }
+
+  pub(crate) fn token (&self) -> ShutdownToken {
+    self.token.clone()
+  }
+}
+
+impl Drop for ExitSignal {
+  fn drop (&mut self) {
+    let (_, condvar) = &*self.token.shared;
+    self.token.lock().exited = true;
+    condvar.notify_all();
+  }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  functions                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Request shutdown and wake the main thread with an `SDL_QUIT` event.
+///
+/// Only the first request pushes an event, so this can also be called by the
+/// main thread in response to `SDL_QUIT`. This may be called from any thread.
+pub fn request_shutdown() {
+  if !SHUTDOWN_REQUESTED.swap (true, std::sync::atomic::Ordering::SeqCst) {
+    command::push_user_event (sdl2_sys::SDL_EventType::SDL_QUIT as u32, 0);
+  }
+}
+
+/// Returns true once shutdown has been requested.
+///
+/// Render loops should check this once per frame.
+pub fn shutdown_requested() -> bool {
+  SHUTDOWN_REQUESTED.load (std::sync::atomic::Ordering::SeqCst)
+}
+
+/// Handle SIGINT and SIGTERM, or console control events on Windows, by
+/// requesting shutdown.
+///
+/// The handler runs on a thread spawned for the purpose rather than in the
+/// signal handler itself, so it can safely push the SDL event. Handlers can
+/// only be installed once per process.
+#[cfg(feature = "signals")]
+pub fn install_signal_handlers() -> Result <(), String> {
+  ctrlc::set_handler (request_shutdown).map_err (|err| err.to_string())
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  #[test]
+  fn test_shutdown_token() {
+    let signal = ExitSignal::new();
+    let token  = signal.token();
+    assert!(!token.has_render_exited());
+    assert!(!token.wait_for_render_exit_timeout (
+      std::time::Duration::from_millis (1)));
+    let render = std::thread::spawn (move || {
+      let token = signal.token();
+      while !token.is_requested() {
+        std::thread::yield_now();
+      }
+      drop (signal);
+    });
+    token.request();
+    token.wait_for_render_exit();
+    assert!(token.has_render_exited());
+    render.join().unwrap();
+  }
+}
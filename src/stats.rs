@@ -0,0 +1,173 @@
+//! Frame rate and frame time statistics.
+//!
+//! The facade records the time between successive calls to `draw` and the
+//! time spent swapping buffers when each frame is finished, over a rolling
+//! window of the last `STATS_WINDOW` frames. `SdlGliumDisplayFacade::stats`
+//! summarizes them:
+//!
+//! ```ignore
+//! let stats = display.stats();
+//! println!("{:.1} fps, p99 {:?}", stats.fps, stats.frame_time_p99);
+//! ```
+//!
+//! Frame times include any time the render thread spends between frames,
+//! e.g. waiting while paused, so they reflect the rate frames are actually
+//! drawn at.
+
+///////////////////////////////////////////////////////////////////////////////
+//  constants                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Number of frames statistics are computed over
+pub const STATS_WINDOW : usize = 120;
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Summary of the recent frames of a display.
+///
+/// Durations are zero until enough frames have been drawn.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FrameStats {
+  /// Frames drawn since the display was built
+  pub frames             : u64,
+  /// Frames per second over the window
+  pub fps                : f64,
+  pub frame_time_average : std::time::Duration,
+  pub frame_time_p50     : std::time::Duration,
+  pub frame_time_p95     : std::time::Duration,
+  pub frame_time_p99     : std::time::Duration,
+  pub frame_time_max     : std::time::Duration,
+  /// Time spent in buffer swaps, which includes waiting for vertical
+  /// retrace with vsync
+  pub swap_time_average  : std::time::Duration,
+  pub swap_time_max      : std::time::Duration
+}
+
+/// Records frame and swap times for `FrameStats`.
+#[derive(Debug, Default)]
+pub(crate) struct StatsCollector {
+  frames      : u64,
+  last_draw   : Option <std::time::Instant>,
+  frame_times : std::collections::VecDeque <std::time::Duration>,
+  swap_times  : std::collections::VecDeque <std::time::Duration>
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+impl StatsCollector {
+  /// Record the start of a frame.
+  pub(crate) fn frame_started (&mut self, now : std::time::Instant) {
+    if let Some (last_draw) = self.last_draw {
+      push_bounded (&mut self.frame_times, now - last_draw);
+    }
+    self.last_draw = Some (now);
+    self.frames += 1;
+  }
+
+  /// Record the duration of a buffer swap.
+  pub(crate) fn swapped (&mut self, swap_time : std::time::Duration) {
+    push_bounded (&mut self.swap_times, swap_time);
+  }
+
+  pub(crate) fn stats (&self) -> FrameStats {
+    let mut frame_times = self.frame_times.iter().cloned()
+      .collect::<Vec <_>>();
+    frame_times.sort();
+    let frame_time_total = frame_times.iter().sum::<std::time::Duration>();
+    let fps = if frame_time_total > std::time::Duration::from_secs (0) {
+      frame_times.len() as f64 / duration_secs (frame_time_total)
+    } else {
+      0.0
+    };
+    FrameStats {
+      frames:             self.frames,
+      fps,
+      frame_time_average: average (&frame_times),
+      frame_time_p50:     percentile (&frame_times, 50),
+      frame_time_p95:     percentile (&frame_times, 95),
+      frame_time_p99:     percentile (&frame_times, 99),
+      frame_time_max:     frame_times.last().cloned().unwrap_or_default(),
+      swap_time_average:
+        average (&self.swap_times.iter().cloned().collect::<Vec <_>>()),
+      swap_time_max:      self.swap_times.iter().cloned().max()
+        .unwrap_or_default()
+    }
+  }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  functions                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+//
+// private
+//
+
+fn push_bounded (
+  times : &mut std::collections::VecDeque <std::time::Duration>,
+  time  : std::time::Duration
+) {
+  if times.len() == STATS_WINDOW {
+    times.pop_front();
+  }
+  times.push_back (time);
+}
+
+fn average (times : &[std::time::Duration]) -> std::time::Duration {
+  if times.is_empty() {
+    return std::time::Duration::default()
+  }
+  times.iter().sum::<std::time::Duration>() / times.len() as u32
+}
+
+/// Nearest-rank percentile of sorted times.
+fn percentile (sorted : &[std::time::Duration], percent : usize)
+  -> std::time::Duration
+{
+  if sorted.is_empty() {
+    return std::time::Duration::default()
+  }
+  let rank = (percent * sorted.len()).div_ceil (100);
+  sorted[rank.max (1) - 1]
+}
+
+fn duration_secs (duration : std::time::Duration) -> f64 {
+  duration.as_secs() as f64 + duration.subsec_nanos() as f64 * 1e-9
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  #[test]
+  fn test_frame_stats() {
+    let ms = std::time::Duration::from_millis;
+    let mut collector = StatsCollector::default();
+    assert_eq!(collector.stats(), FrameStats::default());
+    let start = std::time::Instant::now();
+    // 99 frames of 10ms then one of 50ms
+    let mut now = start;
+    collector.frame_started (now);
+    for frame in 0..100 {
+      now += if frame == 99 { ms (50) } else { ms (10) };
+      collector.frame_started (now);
+      collector.swapped (ms (2));
+    }
+    let stats = collector.stats();
+    assert_eq!(stats.frames, 101);
+    assert_eq!(stats.frame_time_p50, ms (10));
+    assert_eq!(stats.frame_time_p99, ms (10));
+    assert_eq!(stats.frame_time_max, ms (50));
+    assert_eq!(stats.swap_time_average, ms (2));
+    assert!((stats.fps - 100.0 / 1.04).abs() < 1e-6);
+    // the window is bounded
+    for _ in 0..STATS_WINDOW {
+      now += ms (20);
+      collector.frame_started (now);
+    }
+    assert_eq!(collector.stats().frame_time_max, ms (20));
+  }
+}
@@ -0,0 +1,167 @@
+//! Conversions between SDL surfaces and glium textures.
+//!
+//! For applications combining SDL image loading or blitting with glium:
+//!
+//! ```ignore
+//! let surface = sdl2::surface::Surface::load_bmp ("sprite.bmp")?;
+//! let texture = display.texture_from_surface (&surface)?;
+//! // ...
+//! let screenshot = display.surface_from_texture (&texture)?;
+//! screenshot.save_bmp ("texture.bmp")?;
+//! ```
+//!
+//! Surfaces of any pixel format are converted to 8-bit RGBA, and row padding
+//! (the surface pitch) is removed. SDL stores rows from top to bottom while
+//! GL stores them from bottom to top, so rows are flipped in both directions:
+//! the top of the surface is the top of the texture when drawn with the usual
+//! texture coordinates.
+
+use glium;
+use sdl2;
+use sdl2_sys;
+
+use glium::texture::{Texture2d, TextureCreationError};
+
+///////////////////////////////////////////////////////////////////////////////
+//  enums                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug)]
+pub enum SurfaceError {
+  SdlError (String),
+  TextureCreationError (TextureCreationError)
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+impl From <String> for SurfaceError {
+  fn from (err : String) -> Self {
+    SurfaceError::SdlError (err)
+  }
+}
+
+impl From <TextureCreationError> for SurfaceError {
+  fn from (err : TextureCreationError) -> Self {
+    SurfaceError::TextureCreationError (err)
+  }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  functions                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Create a texture with the pixels of a surface, converted to RGBA.
+pub fn texture_from_surface <F : glium::backend::Facade + ?Sized> (
+  facade  : &F,
+  surface : &sdl2::surface::Surface
+) -> Result <Texture2d, SurfaceError> {
+  let image = glium::texture::RawImage2d {
+    data:   std::borrow::Cow::Owned (try!{ rgba_pixels (surface) }),
+    width:  surface.width(),
+    height: surface.height(),
+    format: glium::texture::ClientFormat::U8U8U8U8
+  };
+  Ok (try!{ Texture2d::new (facade, image) })
+}
+
+/// Read back the pixels of a texture into a new RGBA surface.
+///
+/// The context of the texture must be current.
+pub fn surface_from_texture (texture : &Texture2d)
+  -> Result <sdl2::surface::Surface <'static>, SurfaceError>
+{
+  let image : glium::texture::RawImage2d <u8> = texture.read();
+  let (width, height) = (image.width, image.height);
+  let row = 4 * width as usize;
+  let mut surface
+    = try!{ sdl2::surface::Surface::new (width, height, rgba_format()) };
+  let pitch = surface.pitch() as usize;
+  surface.with_lock_mut (|pixels| copy_rows_flipped (
+    &image.data, row, pixels, pitch, row, height as usize));
+  Ok (surface)
+}
+
+/// The pixels of a surface converted to RGBA, without padding and with rows
+/// from bottom to top.
+pub(crate) fn rgba_pixels (surface : &sdl2::surface::Surface)
+  -> Result <Vec <u8>, String>
+{
+  let converted;
+  let surface = if surface.pixel_format_enum() == rgba_format() {
+    surface
+  } else {
+    converted = try!{ convert_format (surface, rgba_format()) };
+    &converted
+  };
+  let (width, height) = (surface.width(), surface.height());
+  let row = 4 * width as usize;
+  let mut data = vec![0; row * height as usize];
+  surface.with_lock (|pixels| copy_rows_flipped (
+    pixels, surface.pitch() as usize, &mut data, row, row, height as usize));
+  Ok (data)
+}
+
+//
+// private
+//
+
+/// A copy of a surface converted to another pixel format.
+fn convert_format (
+  surface : &sdl2::surface::SurfaceRef,
+  format  : sdl2::pixels::PixelFormatEnum
+) -> Result <sdl2::surface::Surface <'static>, String> {
+  unsafe {
+    let converted
+      = sdl2_sys::SDL_ConvertSurfaceFormat (surface.raw(), format as u32, 0);
+    if converted.is_null() {
+      Err (sdl2::get_error())
+    } else {
+      Ok (sdl2::surface::Surface::from_ll (converted))
+    }
+  }
+}
+
+/// The SDL format of 8-bit RGBA pixels stored in that order in memory.
+fn rgba_format() -> sdl2::pixels::PixelFormatEnum {
+  if cfg!(target_endian = "little") {
+    sdl2::pixels::PixelFormatEnum::ABGR8888
+  } else {
+    sdl2::pixels::PixelFormatEnum::RGBA8888
+  }
+}
+
+/// Copy `height` rows of `row` bytes between buffers with the given pitches,
+/// reversing their order.
+fn copy_rows_flipped (
+  source       : &[u8],
+  source_pitch : usize,
+  target       : &mut [u8],
+  target_pitch : usize,
+  row          : usize,
+  height       : usize
+) {
+  for y in 0..height {
+    let source_start = y * source_pitch;
+    let target_start = (height - 1 - y) * target_pitch;
+    target[target_start..target_start + row]
+      .copy_from_slice (&source[source_start..source_start + row]);
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  #[test]
+  fn test_copy_rows_flipped() {
+    // two rows of two bytes, padded to a pitch of three
+    let padded = [1, 2, 0, 3, 4, 0];
+    let mut packed = [0; 4];
+    copy_rows_flipped (&padded, 3, &mut packed, 2, 2, 2);
+    assert_eq!(packed, [3, 4, 1, 2]);
+    let mut repadded = [9; 6];
+    copy_rows_flipped (&packed, 2, &mut repadded, 3, 2, 2);
+    assert_eq!(repadded, [1, 2, 9, 3, 4, 9]);
+  }
+}
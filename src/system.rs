@@ -0,0 +1,594 @@
+//! Queries for desktop and platform state that SDL does not report through
+//! events.
+
+use sdl2;
+use sdl2_sys;
+
+use command;
+
+///////////////////////////////////////////////////////////////////////////////
+//  constants                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+/// `SDL_NUM_SCANCODES`
+const NUM_SCANCODES : usize = 512;
+/// `SDL_WINDOWPOS_CENTERED_MASK`
+const WINDOWPOS_CENTERED_MASK : u32 = 0x2FFF0000;
+/// `SDL_HINT_VIDEO_X11_NET_WM_BYPASS_COMPOSITOR`
+const HINT_BYPASS_COMPOSITOR : &str = "SDL_VIDEO_X11_NET_WM_BYPASS_COMPOSITOR";
+/// `SDL_HINT_VIDEO_MINIMIZE_ON_FOCUS_LOSS`
+const HINT_MINIMIZE_ON_FOCUS_LOSS : &str = "SDL_VIDEO_MINIMIZE_ON_FOCUS_LOSS";
+/// Interval between theme probes where no change notification is available
+const THEME_POLL_INTERVAL : std::time::Duration
+  = std::time::Duration::from_secs (5);
+/// `CREATE_NO_WINDOW`: do not open a console window for helper processes
+#[cfg(target_os = "windows")]
+const CREATE_NO_WINDOW : u32 = 0x0800_0000;
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Snapshot of the current keyboard layout.
+///
+/// This maps physical keys (scancodes) to the keycodes and display names they
+/// produce under the active layout, so that keybinding names can be shown
+/// from a thread other than the main thread.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyboardLayout {
+  /// Preferred user locale, e.g. `"en_US"`, if known
+  pub locale : Option <String>,
+  keycodes   : Vec <Option <sdl2::keyboard::Keycode>>,
+  key_names  : Vec <String>
+}
+
+/// A connected video display.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DisplayInfo {
+  /// SDL display index; indices of the remaining displays may change when a
+  /// display is disconnected
+  pub index  : i32,
+  pub name   : String,
+  /// Desktop x coordinate of the display
+  pub x      : i32,
+  /// Desktop y coordinate of the display
+  pub y      : i32,
+  pub width  : u32,
+  pub height : u32
+}
+
+/// Result of comparing two display enumerations.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DisplayChanges {
+  pub connected    : Vec <DisplayInfo>,
+  pub disconnected : Vec <DisplayInfo>,
+  /// Displays whose orientation changed, with their new bounds
+  pub reoriented   : Vec <DisplayInfo>
+}
+
+/// Size and display of a window after it has been moved.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WindowPlacement {
+  pub display : i32,
+  /// Drawable width in pixels
+  pub width   : u32,
+  /// Drawable height in pixels
+  pub height  : u32,
+  /// Ratio of drawable pixels to window coordinates
+  pub scale   : f32
+}
+
+/// Probes the desktop theme on a background thread, waking the main thread
+/// when it changes.
+///
+/// On GNOME desktops changes are reported by `gsettings monitor`; elsewhere
+/// the theme is polled every `THEME_POLL_INTERVAL`.
+pub(crate) struct ThemeWatcher {
+  wake_event : u32,
+  receiver   : std::sync::mpsc::Receiver <SystemTheme>,
+  /// Shared with the watcher thread, which stops once this is set
+  stopped    : std::sync::Arc <std::sync::atomic::AtomicBool>,
+  /// Running `gsettings monitor` process, killed when the watcher is dropped
+  monitor    : std::sync::Arc <
+    std::sync::Mutex <Option <std::process::Child>>>
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  enums                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Display orientation.
+///
+/// This is derived from the display bounds, so a display rotated by 180
+/// degrees is not distinguished from its original orientation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DisplayOrientation {
+  Landscape,
+  Portrait
+}
+
+/// Desktop color scheme preference.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SystemTheme {
+  Light,
+  Dark,
+  /// The preference could not be determined on this platform
+  Unknown
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+impl KeyboardLayout {
+  /// Query the current layout.
+  ///
+  /// This must be called on the main thread; from other threads use
+  /// `WindowCommand::QueryKeyboardLayout`.
+  pub fn query() -> Self {
+    let mut keycodes  = Vec::with_capacity (NUM_SCANCODES);
+    let mut key_names = Vec::with_capacity (NUM_SCANCODES);
+    for scancode in 0..NUM_SCANCODES {
+      let keycode = sdl2::keyboard::Scancode::from_i32 (scancode as i32)
+        .and_then (sdl2::keyboard::Keycode::from_scancode);
+      // key names are formatted into a static buffer by SDL and so are
+      // resolved here rather than on the receiving thread
+      key_names.push (keycode.map (|keycode| keycode.name())
+        .unwrap_or_default());
+      keycodes.push (keycode);
+    }
+    KeyboardLayout { locale: system_locale(), keycodes, key_names }
+  }
+
+  /// The keycode produced by the given physical key.
+  pub fn keycode (&self, scancode : sdl2::keyboard::Scancode)
+    -> Option <sdl2::keyboard::Keycode>
+  {
+    self.keycodes.get (scancode as usize).and_then (|keycode| *keycode)
+  }
+
+  /// Human-readable name of the key produced by the given physical key, or an
+  /// empty string if the key has no name.
+  pub fn key_name (&self, scancode : sdl2::keyboard::Scancode) -> &str {
+    self.key_names.get (scancode as usize).map (|name| name.as_str())
+      .unwrap_or ("")
+  }
+}
+
+impl DisplayInfo {
+  pub fn orientation (&self) -> DisplayOrientation {
+    if self.height > self.width {
+      DisplayOrientation::Portrait
+    } else {
+      DisplayOrientation::Landscape
+    }
+  }
+
+  /// Returns true if the given desktop rectangle overlaps this display.
+  pub fn intersects (&self, x : i32, y : i32, width : u32, height : u32)
+    -> bool
+  {
+    let (x, y) = (x as i64, y as i64);
+    let (left, top) = (self.x as i64, self.y as i64);
+    x < left + self.width as i64 && left < x + width as i64 &&
+    y < top + self.height as i64 && top < y + height as i64
+  }
+}
+
+impl ThemeWatcher {
+  /// Start the watcher thread; the first probe is reported as a change.
+  pub(crate) fn new() -> Self {
+    let wake_event = unsafe { sdl2_sys::SDL_RegisterEvents (1) };
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let stopped
+      = std::sync::Arc::new (std::sync::atomic::AtomicBool::new (false));
+    let monitor = std::sync::Arc::new (std::sync::Mutex::new (None));
+    let watcher = {
+      let (stopped, monitor) = (stopped.clone(), monitor.clone());
+      std::thread::Builder::new().name ("theme".to_string())
+        .spawn (move || watch_theme (sender, wake_event, &stopped, &monitor))
+    };
+    if let Err (err) = watcher {
+      log_record!(Warn, "failed to start the theme watcher: {}", err);
+    }
+    ThemeWatcher { wake_event, receiver, stopped, monitor }
+  }
+
+  /// Returns true if the event was pushed by the watcher thread.
+  pub(crate) fn is_wake_event (&self, event : &sdl2::event::Event) -> bool {
+    match *event {
+      sdl2::event::Event::User { type_, .. } =>
+        self.wake_event != std::u32::MAX && type_ == self.wake_event,
+      _ => false
+    }
+  }
+
+  /// The latest theme reported since the last call, without blocking.
+  pub(crate) fn changed (&self) -> Option <SystemTheme> {
+    self.receiver.try_iter().last()
+  }
+}
+
+impl Drop for ThemeWatcher {
+  fn drop (&mut self) {
+    // set under the lock so that the thread either sees it before storing a
+    // monitor process or stores it before it is killed here
+    let mut monitor = self.monitor.lock().unwrap();
+    self.stopped.store (true, std::sync::atomic::Ordering::SeqCst);
+    if let Some (mut child) = monitor.take() {
+      let _ = child.kill();
+      let _ = child.wait();
+    }
+  }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  functions                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Enumerate the connected displays.
+///
+/// This must be called on the main thread.
+pub fn displays() -> Vec <DisplayInfo> {
+  let count = unsafe { sdl2_sys::SDL_GetNumVideoDisplays() };
+  (0..count).filter_map (|index| unsafe {
+    let mut bounds : sdl2_sys::SDL_Rect = std::mem::zeroed();
+    if sdl2_sys::SDL_GetDisplayBounds (index, &mut bounds) != 0 {
+      return None
+    }
+    let name_raw = sdl2_sys::SDL_GetDisplayName (index);
+    let name = if name_raw.is_null() {
+      String::new()
+    } else {
+      std::ffi::CStr::from_ptr (name_raw).to_string_lossy().into_owned()
+    };
+    Some (DisplayInfo {
+      index, name,
+      x:      bounds.x,
+      y:      bounds.y,
+      width:  bounds.w as u32,
+      height: bounds.h as u32
+    })
+  }).collect()
+}
+
+/// Compare two display enumerations.
+///
+/// Displays are matched by name since indices shift when a display is
+/// removed.
+pub fn diff_displays (old : &[DisplayInfo], new : &[DisplayInfo])
+  -> DisplayChanges
+{
+  let mut unmatched : Vec <&DisplayInfo> = new.iter().collect();
+  let mut changes = DisplayChanges::default();
+  for display in old {
+    match unmatched.iter().position (|other| other.name == display.name) {
+      Some (i) => {
+        let other = unmatched.remove (i);
+        if other.orientation() != display.orientation() {
+          changes.reoriented.push (other.clone());
+        }
+      }
+      None => changes.disconnected.push (display.clone())
+    }
+  }
+  changes.connected = unmatched.into_iter().cloned().collect();
+  changes
+}
+
+/// Returns true if any part of the window lies on one of the given displays.
+pub(crate) fn window_is_visible (
+  window_raw : *mut sdl2_sys::SDL_Window, displays : &[DisplayInfo]
+) -> bool {
+  let (mut x, mut y, mut width, mut height) = (0, 0, 0, 0);
+  unsafe {
+    sdl2_sys::SDL_GetWindowPosition (window_raw, &mut x, &mut y);
+    sdl2_sys::SDL_GetWindowSize (window_raw, &mut width, &mut height);
+  }
+  displays.iter().any (|display|
+    display.intersects (x, y, width as u32, height as u32))
+}
+
+/// Center the window on the given display and return its new placement.
+///
+/// This must be called on the main thread.
+pub(crate) fn migrate_window (
+  window_raw : *mut sdl2_sys::SDL_Window, display : i32
+) -> WindowPlacement {
+  let position = (WINDOWPOS_CENTERED_MASK | display as u32) as i32;
+  unsafe { sdl2_sys::SDL_SetWindowPosition (window_raw, position, position) };
+  window_placement (window_raw)
+}
+
+/// Current display and drawable size of the window.
+pub(crate) fn window_placement (window_raw : *mut sdl2_sys::SDL_Window)
+  -> WindowPlacement
+{
+  let (mut window_width, mut window_height) = (0, 0);
+  let (mut width, mut height) = (0, 0);
+  let display = unsafe {
+    sdl2_sys::SDL_GetWindowSize (
+      window_raw, &mut window_width, &mut window_height);
+    sdl2_sys::SDL_GL_GetDrawableSize (window_raw, &mut width, &mut height);
+    sdl2_sys::SDL_GetWindowDisplayIndex (window_raw)
+  };
+  let scale = if window_width > 0 {
+    width as f32 / window_width as f32
+  } else {
+    1.0
+  };
+  WindowPlacement { display, width: width as u32, height: height as u32, scale }
+}
+
+/// Set whether X11 windows ask the compositing manager to bypass
+/// compositing (`_NET_WM_BYPASS_COMPOSITOR`), returning false if the hint
+/// could not be set.
+///
+/// SDL enables this by default. Compositors honor it for fullscreen windows,
+/// which are then presented with lower latency and more regular timing, but
+/// some desktops flicker when switching. This only affects windows created
+/// afterwards.
+pub fn set_compositor_bypass (bypass : bool) -> bool {
+  sdl2::hint::set (HINT_BYPASS_COMPOSITOR, if bypass { "1" } else { "0" })
+}
+
+/// Whether X11 windows created now would ask to bypass compositing.
+pub fn compositor_bypass() -> bool {
+  sdl2::hint::get (HINT_BYPASS_COMPOSITOR).is_none_or (|value| value != "0")
+}
+
+/// Set whether a window in exclusive fullscreen is minimized when it loses
+/// focus (e.g. on alt-tab), returning false if the hint could not be set.
+///
+/// Minimizing also restores the desktop display mode until the window is
+/// restored. SDL enables this by default; when disabled the window stays in
+/// fullscreen with its display mode behind other windows. See
+/// `Event::FullscreenSuspended`.
+pub fn set_minimize_on_focus_loss (minimize : bool) -> bool {
+  sdl2::hint::set (
+    HINT_MINIMIZE_ON_FOCUS_LOSS, if minimize { "1" } else { "0" })
+}
+
+/// Whether a window in exclusive fullscreen is minimized when it loses focus.
+pub fn minimize_on_focus_loss() -> bool {
+  sdl2::hint::get (HINT_MINIMIZE_ON_FOCUS_LOSS)
+    .is_none_or (|value| value != "0" && value != "false")
+}
+
+/// Returns true if the window is fullscreen with a display mode change, as
+/// opposed to fullscreen at the desktop resolution.
+pub(crate) fn is_exclusive_fullscreen (window_raw : *mut sdl2_sys::SDL_Window)
+  -> bool
+{
+  let fullscreen
+    = sdl2_sys::SDL_WindowFlags::SDL_WINDOW_FULLSCREEN as u32;
+  let fullscreen_desktop
+    = sdl2_sys::SDL_WindowFlags::SDL_WINDOW_FULLSCREEN_DESKTOP as u32;
+  let flags = unsafe { sdl2_sys::SDL_GetWindowFlags (window_raw) };
+  flags & fullscreen_desktop == fullscreen
+}
+
+/// Show a modal error message box, blocking until it is dismissed.
+///
+/// Unlike most SDL functions this is safe to call from any thread, including
+/// the render thread after a fatal error. It can also be called before SDL is
+/// initialized.
+pub fn show_error_dialog (title : &str, message : &str) -> Result <(), String> {
+  let title   = std::ffi::CString::new (title.replace ('\0', "")).unwrap();
+  let message = std::ffi::CString::new (message.replace ('\0', "")).unwrap();
+  let result = unsafe {
+    sdl2_sys::SDL_ShowSimpleMessageBox (
+      sdl2_sys::SDL_MessageBoxFlags::SDL_MESSAGEBOX_ERROR as u32,
+      title.as_ptr(),
+      message.as_ptr(),
+      std::ptr::null_mut())
+  };
+  if result != 0 {
+    return Err (sdl2::get_error())
+  }
+  Ok (())
+}
+
+/// Probe the desktop color scheme preference.
+///
+/// SDL2 has no system theme API, so this queries the platform settings
+/// directly (the Windows registry, macOS user defaults, or the GTK/GNOME
+/// settings on other unix platforms). This spawns a helper process and blocks
+/// until it exits, so it should not be called on the main thread or every
+/// frame; `EventForwarder::watch_theme` watches the theme on a thread of its
+/// own and forwards `Event::ThemeChanged`.
+pub fn system_theme() -> SystemTheme {
+  probe_theme()
+}
+
+/// Preferred user locale taken from the standard locale environment
+/// variables, with any encoding suffix removed.
+pub fn system_locale() -> Option <String> {
+  ["LC_ALL", "LC_MESSAGES", "LANG"].iter()
+    .filter_map (|var| std::env::var (var).ok())
+    .find (|value| !value.is_empty())
+    .map (|value| value.split ('.').next().unwrap_or ("").to_string())
+}
+
+#[cfg(target_os = "windows")]
+fn probe_theme() -> SystemTheme {
+  let output = command_output ("reg", &[
+    "query",
+    "HKCU\\Software\\Microsoft\\Windows\\CurrentVersion\\Themes\\Personalize",
+    "/v", "AppsUseLightTheme"
+  ]);
+  match output {
+    Some (ref text) if text.contains ("0x0") => SystemTheme::Dark,
+    Some (ref text) if text.contains ("0x1") => SystemTheme::Light,
+    _ => SystemTheme::Unknown
+  }
+}
+
+#[cfg(target_os = "macos")]
+fn probe_theme() -> SystemTheme {
+  // the key is absent (and the command fails) in light mode
+  match command_output ("defaults", &["read", "-g", "AppleInterfaceStyle"]) {
+    Some (ref text) if text.trim() == "Dark" => SystemTheme::Dark,
+    _ => SystemTheme::Light
+  }
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn probe_theme() -> SystemTheme {
+  if let Ok (gtk_theme) = std::env::var ("GTK_THEME") {
+    return theme_from_name (&gtk_theme)
+  }
+  let color_scheme = command_output ("gsettings",
+    &["get", "org.gnome.desktop.interface", "color-scheme"]);
+  if let Some (text) = color_scheme {
+    if text.contains ("prefer-dark") {
+      return SystemTheme::Dark
+    } else if text.contains ("prefer-light") {
+      return SystemTheme::Light
+    }
+  }
+  command_output ("gsettings",
+    &["get", "org.gnome.desktop.interface", "gtk-theme"])
+    .map (|text| theme_from_name (&text))
+    .unwrap_or (SystemTheme::Unknown)
+}
+
+/// GTK themes conventionally mark dark variants with a `-dark` or `:dark`
+/// suffix.
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn theme_from_name (name : &str) -> SystemTheme {
+  if name.to_lowercase().contains ("dark") {
+    SystemTheme::Dark
+  } else {
+    SystemTheme::Light
+  }
+}
+
+/// Body of the theme watcher thread.
+fn watch_theme (
+  sender     : std::sync::mpsc::Sender <SystemTheme>,
+  wake_event : u32,
+  stopped    : &std::sync::atomic::AtomicBool,
+  monitor    : &std::sync::Mutex <Option <std::process::Child>>
+) {
+  use std::sync::atomic::Ordering;
+  let mut theme = None;
+  // returns false once the watcher has been dropped
+  let mut report = |probed : SystemTheme| {
+    if stopped.load (Ordering::SeqCst) {
+      return false
+    }
+    if theme != Some (probed) {
+      theme = Some (probed);
+      if sender.send (probed).is_err() {
+        return false
+      }
+      if wake_event != std::u32::MAX {
+        command::push_user_event (wake_event, 0);
+      }
+    }
+    true
+  };
+  if !report (probe_theme()) {
+    return
+  }
+  #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+  {
+    // the theme forced by the environment can not change
+    if std::env::var_os ("GTK_THEME").is_some() {
+      return
+    }
+    if let Some (stdout) = spawn_theme_monitor (stopped, monitor) {
+      use std::io::BufRead;
+      for line in std::io::BufReader::new (stdout).lines() {
+        if line.is_err() || !report (probe_theme()) {
+          break
+        }
+      }
+      if stopped.load (Ordering::SeqCst) {
+        return
+      }
+      log_record!(Debug, "gsettings monitor exited, polling the theme");
+    }
+  }
+  // no change notification on other platforms
+  #[cfg(any(target_os = "windows", target_os = "macos"))]
+  let _ = monitor;
+  loop {
+    std::thread::sleep (THEME_POLL_INTERVAL);
+    if !report (probe_theme()) {
+      return
+    }
+  }
+}
+
+/// Start `gsettings monitor` on the color scheme key, which prints a line
+/// whenever it changes, and return its output.
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn spawn_theme_monitor (
+  stopped : &std::sync::atomic::AtomicBool,
+  monitor : &std::sync::Mutex <Option <std::process::Child>>
+) -> Option <std::process::ChildStdout> {
+  let mut child = match std::process::Command::new ("gsettings")
+    .args (["monitor", "org.gnome.desktop.interface", "color-scheme"])
+    .stdin (std::process::Stdio::null())
+    .stdout (std::process::Stdio::piped())
+    .stderr (std::process::Stdio::null())
+    .spawn()
+  {
+    Ok (child) => child,
+    Err (_)    => return None
+  };
+  let stdout = child.stdout.take();
+  let mut monitor = monitor.lock().unwrap();
+  if stopped.load (std::sync::atomic::Ordering::SeqCst) {
+    let _ = child.kill();
+    let _ = child.wait();
+    return None
+  }
+  *monitor = Some (child);
+  stdout
+}
+
+/// Run a command and return its standard output if it exits successfully.
+fn command_output (program : &str, args : &[&str]) -> Option <String> {
+  let mut command = std::process::Command::new (program);
+  command.args (args);
+  #[cfg(target_os = "windows")]
+  {
+    use std::os::windows::process::CommandExt;
+    command.creation_flags (CREATE_NO_WINDOW);
+  }
+  let output = match command.output() {
+    Ok (output) => output,
+    Err (_)     => return None
+  };
+  if output.status.success() {
+    String::from_utf8 (output.stdout).ok()
+  } else {
+    None
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  fn display (index : i32, name : &str) -> DisplayInfo {
+    DisplayInfo {
+      index, name: name.to_string(), x: 0, y: 0, width: 640, height: 480
+    }
+  }
+  #[test]
+  fn test_diff_displays() {
+    let old = vec![display (0, "a"), display (1, "b"), display (2, "b")];
+    let new = vec![display (0, "b"), display (1, "c")];
+    let changes = diff_displays (&old, &new);
+    assert_eq!(changes.connected, vec![display (1, "c")]);
+    assert_eq!(changes.disconnected, vec![display (0, "a"), display (2, "b")]);
+    assert!(changes.reoriented.is_empty());
+    assert_eq!(diff_displays (&new, &new), DisplayChanges::default());
+    let rotated = DisplayInfo { width: 480, height: 640, .. display (0, "b") };
+    let changes = diff_displays (&new, &[rotated.clone(), display (1, "c")]);
+    assert_eq!(changes.reoriented, vec![rotated]);
+  }
+}
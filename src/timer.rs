@@ -0,0 +1,302 @@
+//! Timers whose expirations are delivered as events.
+//!
+//! `SDL_AddTimer` callbacks run on a separate SDL thread. The callback used
+//! here only pushes an SDL user event carrying the timer ID, so expirations
+//! are handled on the main thread when the event is pumped and given to the
+//! `EventForwarder`, which can in turn forward them as `Event::Timer`.
+//!
+//! This module also manages the system timer resolution used for precise
+//! sleeps. On Windows the default scheduler granularity is around 15ms, far
+//! too coarse for frame pacing; a `TimerResolution` guard raises it to 1ms
+//! with `timeBeginPeriod` while any guard is alive.
+
+use sdl2;
+use sdl2_sys;
+
+use command;
+
+///////////////////////////////////////////////////////////////////////////////
+//  statics                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// User event type shared by all timers, registered on first use
+static TIMER_EVENT : std::sync::atomic::AtomicUsize =
+  std::sync::atomic::AtomicUsize::new (0);
+static TIMER_EVENT_REGISTER : std::sync::Once = std::sync::Once::new();
+/// Number of live `TimerResolution` guards
+static TIMER_RESOLUTION_USERS : std::sync::Mutex <usize> =
+  std::sync::Mutex::new (0);
+
+///////////////////////////////////////////////////////////////////////////////
+//  constants                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Timer resolution requested by `TimerResolution`, in milliseconds
+pub const TIMER_RESOLUTION_MS : u32 = 1;
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Identifies a timer added with `EventForwarder::add_timer` or
+/// `EventForwarder::add_repeating_timer`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TimerId (i32);
+
+/// Main thread set of running timers.
+pub(crate) struct Timers {
+  timers  : std::collections::HashMap <TimerId, Timer>,
+  next_id : i32
+}
+
+/// Reference-counted guard raising the system timer resolution to
+/// `TIMER_RESOLUTION_MS` while alive.
+///
+/// The resolution is restored when the last guard is dropped. This has no
+/// effect on platforms other than Windows, whose sleeps are already precise.
+#[derive(Debug)]
+pub struct TimerResolution {
+  _private : ()
+}
+
+//
+// private
+//
+
+struct Timer {
+  sdl_id  : sdl2_sys::SDL_TimerID,
+  repeat  : bool,
+  forward : bool
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+impl TimerId {
+  /// Decode a timer expiration event.
+  ///
+  /// Returns `None` if the event is not a timer expiration.
+  pub fn from_event (event : &sdl2::event::Event) -> Option <TimerId> {
+    match *event {
+      // no timer has been added if the event type has not been registered
+      sdl2::event::Event::User { type_, code, .. } =>
+        if type_ != 0 && type_ as usize == TIMER_EVENT.load (
+          std::sync::atomic::Ordering::SeqCst)
+        {
+          Some (TimerId (code))
+        } else {
+          None
+        },
+      _ => None
+    }
+  }
+}
+
+impl Timers {
+  pub(crate) fn new() -> Self {
+    Timers { timers: std::collections::HashMap::new(), next_id: 1 }
+  }
+
+  /// Start a timer, returning the SDL error message on failure.
+  pub(crate) fn add (&mut self,
+    interval : std::time::Duration, repeat : bool, forward : bool
+  ) -> Result <TimerId, String> {
+    if timer_event() == std::u32::MAX {
+      return Err (sdl2::get_error())
+    }
+    let id = TimerId (self.next_id);
+    self.next_id = self.next_id.checked_add (1).unwrap_or (1);
+    let interval = std::cmp::max (1, duration_to_ms (interval));
+    // the callback parameter encodes the ID and repeat flag directly rather
+    // than pointing to data that could be freed while a callback is running
+    let param = ((id.0 as usize) << 1 | repeat as usize) as *mut _;
+    let sdl_id = unsafe {
+      sdl2_sys::SDL_AddTimer (interval, Some (timer_callback), param)
+    };
+    if sdl_id == 0 {
+      return Err (sdl2::get_error())
+    }
+    self.timers.insert (id, Timer { sdl_id, repeat, forward });
+    Ok (id)
+  }
+
+  /// Stop a timer; returns false if it was not running.
+  pub(crate) fn remove (&mut self, id : TimerId) -> bool {
+    match self.timers.remove (&id) {
+      Some (timer) => {
+        unsafe { sdl2_sys::SDL_RemoveTimer (timer.sdl_id) };
+        true
+      }
+      None => false
+    }
+  }
+
+  /// Record an expiration, returning whether it should be forwarded.
+  ///
+  /// Returns `None` for timers that are no longer running, whose expiration
+  /// was queued before they were removed.
+  pub(crate) fn expired (&mut self, id : TimerId) -> Option <bool> {
+    let (repeat, forward) = match self.timers.get (&id) {
+      Some (timer) => (timer.repeat, timer.forward),
+      None => return None
+    };
+    if !repeat {
+      // SDL has already removed the timer
+      self.timers.remove (&id);
+    }
+    Some (forward)
+  }
+}
+
+impl Drop for Timers {
+  fn drop (&mut self) {
+    for (_, timer) in self.timers.drain() {
+      unsafe { sdl2_sys::SDL_RemoveTimer (timer.sdl_id) };
+    }
+  }
+}
+
+impl TimerResolution {
+  /// Raise the timer resolution if this is the first live guard.
+  pub fn acquire() -> Self {
+    let mut users = lock_resolution_users();
+    if *users == 0 {
+      begin_timer_period();
+    }
+    *users += 1;
+    TimerResolution { _private: () }
+  }
+
+  /// Number of live guards.
+  pub fn users() -> usize {
+    *lock_resolution_users()
+  }
+}
+
+impl Clone for TimerResolution {
+  fn clone (&self) -> Self {
+    TimerResolution::acquire()
+  }
+}
+
+impl Drop for TimerResolution {
+  fn drop (&mut self) {
+    let mut users = lock_resolution_users();
+    *users -= 1;
+    if *users == 0 {
+      end_timer_period();
+    }
+  }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  functions                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Sleep for the given duration with millisecond precision.
+///
+/// The timer resolution is raised for the duration of the sleep, and the last
+/// fraction of a millisecond is spent yielding rather than sleeping.
+pub fn sleep_precise (duration : std::time::Duration) {
+  let deadline = std::time::Instant::now() + duration;
+  let _resolution = TimerResolution::acquire();
+  let margin = std::time::Duration::from_millis (TIMER_RESOLUTION_MS as u64);
+  if duration > margin {
+    std::thread::sleep (duration - margin);
+  }
+  while std::time::Instant::now() < deadline {
+    std::thread::yield_now();
+  }
+}
+
+fn lock_resolution_users() -> std::sync::MutexGuard <'static, usize> {
+  // the count is always valid so a poisoned lock can be recovered
+  TIMER_RESOLUTION_USERS.lock().unwrap_or_else (|err| err.into_inner())
+}
+
+#[cfg(windows)]
+fn begin_timer_period() {
+  unsafe { winmm::timeBeginPeriod (TIMER_RESOLUTION_MS) };
+}
+
+#[cfg(windows)]
+fn end_timer_period() {
+  unsafe { winmm::timeEndPeriod (TIMER_RESOLUTION_MS) };
+}
+
+#[cfg(not(windows))]
+fn begin_timer_period() { }
+
+#[cfg(not(windows))]
+fn end_timer_period() { }
+
+/// The timer user event type, or `u32::MAX` if it could not be registered.
+fn timer_event() -> u32 {
+  use std::sync::atomic::Ordering;
+  TIMER_EVENT_REGISTER.call_once (|| {
+    let event_type = unsafe { sdl2_sys::SDL_RegisterEvents (1) };
+    TIMER_EVENT.store (event_type as usize, Ordering::SeqCst);
+  });
+  TIMER_EVENT.load (Ordering::SeqCst) as u32
+}
+
+fn duration_to_ms (duration : std::time::Duration) -> u32 {
+  let ms = duration.as_secs().saturating_mul (1000)
+    .saturating_add (duration.subsec_nanos() as u64 / 1_000_000);
+  std::cmp::min (ms, std::u32::MAX as u64) as u32
+}
+
+/// Runs on the SDL timer thread.
+unsafe extern "C" fn timer_callback (
+  interval : u32, param : *mut std::os::raw::c_void
+) -> u32 {
+  let param  = param as usize;
+  let repeat = param & 1 != 0;
+  let id     = (param >> 1) as i32;
+  command::push_user_event (
+    TIMER_EVENT.load (std::sync::atomic::Ordering::SeqCst) as u32, id);
+  if repeat {
+    interval
+  } else {
+    0
+  }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  modules                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(windows)]
+mod winmm {
+  #[link(name = "winmm")]
+  extern "system" {
+    pub fn timeBeginPeriod (period : u32) -> u32;
+    pub fn timeEndPeriod (period : u32) -> u32;
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  #[test]
+  fn test_duration_to_ms() {
+    assert_eq!(duration_to_ms (std::time::Duration::from_millis (1500)), 1500);
+    assert_eq!(duration_to_ms (std::time::Duration::new (0, 999_999)), 0);
+    assert_eq!(
+      duration_to_ms (std::time::Duration::from_secs (std::u64::MAX)),
+      std::u32::MAX);
+  }
+  #[test]
+  fn test_timer_resolution() {
+    let before = TimerResolution::users();
+    let resolution = TimerResolution::acquire();
+    let clone = resolution.clone();
+    assert!(TimerResolution::users() >= before + 2);
+    drop (resolution);
+    drop (clone);
+    let start = std::time::Instant::now();
+    sleep_precise (std::time::Duration::from_millis (2));
+    assert!(start.elapsed() >= std::time::Duration::from_millis (2));
+  }
+}
@@ -0,0 +1,148 @@
+//! Zones for the Tracy profiler.
+//!
+//! With the `tracy` feature, the crate emits CPU zones around making the
+//! context current, drawing each frame (from `SdlGliumDisplayFacade::draw`
+//! until the buffer swap) and swapping buffers, marks the end of each frame,
+//! and reports the scopes of `SdlGliumDisplayFacade::gpu_timer` as GPU zones,
+//! so that the work of the render thread shows up next to that of the main
+//! thread in Tracy.
+//!
+//! Nothing is emitted until the application starts the Tracy client:
+//!
+//! ```ignore
+//! fn main() {
+//!   let _client = tracy_client::Client::start();
+//!   // ...
+//! }
+//! ```
+//!
+//! GPU zones require timer queries, see `profiler`. Without the feature the
+//! zones compile to nothing.
+
+#[cfg(feature = "tracy")]
+use tracy_client;
+
+#[cfg(feature = "glium")]
+use gl;
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// A CPU zone, ended when dropped; created with the `tracy_zone!` macro.
+#[derive(Default)]
+pub(crate) struct Zone {
+  #[cfg(feature = "tracy")]
+  _span : Option <tracy_client::Span>
+}
+
+/// The Tracy GPU context of a display, created with the first GPU zone.
+#[cfg(feature = "glium")]
+#[derive(Default)]
+pub(crate) struct GpuContext {
+  #[cfg(feature = "tracy")]
+  context : Option <tracy_client::GpuContext>
+}
+
+/// A GPU zone whose timestamps are uploaded once the results of its timer
+/// queries are available.
+#[cfg(feature = "glium")]
+#[derive(Default)]
+pub(crate) struct GpuZone {
+  #[cfg(feature = "tracy")]
+  span : Option <tracy_client::GpuSpan>
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+impl Zone {
+  #[cfg(feature = "tracy")]
+  pub(crate) fn new (name : &'static str, file : &'static str, line : u32)
+    -> Self
+  {
+    Zone {
+      _span: tracy_client::Client::running()
+        .map (|client| client.span_alloc (Some (name), "", file, line, 0))
+    }
+  }
+
+  #[cfg(not(feature = "tracy"))]
+  pub(crate) fn new (_name : &'static str, _file : &'static str, _line : u32)
+    -> Self
+  {
+    Zone { }
+  }
+}
+
+#[cfg(feature = "glium")]
+impl GpuContext {
+  /// Begin a GPU zone at the timestamp about to be recorded.
+  ///
+  /// The context must be current.
+  #[cfg(feature = "tracy")]
+  pub(crate) fn begin (&mut self, gl : &gl::Gl, name : &str) -> GpuZone {
+    let client = match tracy_client::Client::running() {
+      Some (client) => client,
+      None => return GpuZone::default()
+    };
+    if self.context.is_none() {
+      let mut timestamp = 0;
+      unsafe { gl.GetInteger64v (gl::TIMESTAMP, &mut timestamp) };
+      // timestamps are in nanoseconds
+      self.context = client.new_gpu_context (Some ("render thread"),
+        tracy_client::GpuContextType::OpenGL, timestamp, 1.0).ok();
+    }
+    GpuZone {
+      span: self.context.as_ref()
+        .and_then (|context| context.span_alloc (name, "", "", 0).ok())
+    }
+  }
+
+  #[cfg(not(feature = "tracy"))]
+  pub(crate) fn begin (&mut self, _gl : &gl::Gl, _name : &str) -> GpuZone {
+    GpuZone { }
+  }
+}
+
+#[cfg(feature = "glium")]
+impl GpuZone {
+  /// End the zone at the timestamp just recorded.
+  pub(crate) fn end (&mut self) {
+    #[cfg(feature = "tracy")]
+    {
+      if let Some (ref mut span) = self.span {
+        span.end_zone();
+      }
+    }
+  }
+
+  /// Upload the GPU timestamps, in nanoseconds, of the start and end of the
+  /// zone.
+  #[cfg_attr(not(feature = "tracy"), allow(unused_variables))]
+  pub(crate) fn upload (self, start : u64, end : u64) {
+    #[cfg(feature = "tracy")]
+    {
+      if let Some (span) = self.span {
+        span.upload_timestamp_start (start as i64);
+        span.upload_timestamp_end (end as i64);
+      }
+    }
+  }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  functions                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Mark the end of a frame.
+#[cfg(feature = "glium")]
+pub(crate) fn frame_mark() {
+  #[cfg(feature = "tracy")]
+  {
+    if let Some (client) = tracy_client::Client::running() {
+      client.frame_mark();
+    }
+  }
+}
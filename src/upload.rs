@@ -0,0 +1,309 @@
+//! Uploading textures decoded on other threads.
+//!
+//! Decoding images is slow and can be done on any thread, but glium textures
+//! can only be created on the render thread. A `TextureUploadQueue` obtained
+//! from the display is sent to the main or a loader thread, which enqueues
+//! decoded pixels; the display uploads queued textures at the start of each
+//! frame, in `draw`, and the texture of a handle is then taken on the render
+//! thread:
+//!
+//! ```ignore
+//! let queue = display.texture_upload_queue();
+//! std::thread::spawn (move || {
+//!   let (pixels, dimensions) = decode ("sprite.png");
+//!   let handle = queue.enqueue (
+//!     TextureUpload::new (pixels, dimensions, UploadFormat::Rgba8)).unwrap();
+//!   render_sender.send (handle).unwrap();
+//! });
+//! // on the render thread, after a frame
+//! if let Some (texture) = display.take_uploaded_texture (&handle) {
+//!   let texture = texture.unwrap();
+//!   // ...
+//! }
+//! ```
+//!
+//! Textures are uploaded in the order they were enqueued. Uploading many
+//! large textures in one frame causes a hitch, so the time spent uploading
+//! per frame can be bounded with `set_texture_upload_budget`; at least one
+//! texture is uploaded each frame so that the queue always makes progress.
+//!
+//! Uploaded textures are kept by the display until taken, or until every
+//! clone of their handle is dropped.
+
+use glium;
+
+use glium::texture::{Texture2d, TextureCreationError};
+
+///////////////////////////////////////////////////////////////////////////////
+//  constants                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+const STATUS_PENDING  : usize = 0;
+const STATUS_UPLOADED : usize = 1;
+const STATUS_FAILED   : usize = 2;
+
+///////////////////////////////////////////////////////////////////////////////
+//  structs                                                                  //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Sends decoded pixels to the display for uploading; see `upload`.
+#[derive(Clone)]
+pub struct TextureUploadQueue {
+  sender  : std::sync::mpsc::Sender <UploadRequest>,
+  next_id : std::sync::Arc <std::sync::atomic::AtomicUsize>
+}
+
+/// Decoded pixels of a texture, with rows from bottom to top as in GL.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextureUpload {
+  pub pixels     : Vec <u8>,
+  pub dimensions : (u32, u32),
+  pub format     : UploadFormat,
+  /// Generate mipmaps after uploading
+  pub mipmaps    : bool
+}
+
+/// Refers to an enqueued texture; may be sent to and shared between threads.
+#[derive(Clone, Debug)]
+pub struct TextureHandle {
+  id     : usize,
+  status : std::sync::Arc <std::sync::atomic::AtomicUsize>
+}
+
+/// The upload side of a display.
+pub(crate) struct TextureUploads {
+  queue    : TextureUploadQueue,
+  receiver : std::sync::mpsc::Receiver <UploadRequest>,
+  /// Maximum time spent uploading per frame
+  budget   : Option <std::time::Duration>,
+  /// Uploaded textures not yet taken, by handle id
+  textures : std::collections::HashMap <usize, UploadedTexture>
+}
+
+//
+// private
+//
+
+struct UploadRequest {
+  upload : TextureUpload,
+  handle : TextureHandle
+}
+
+struct UploadedTexture {
+  /// Status shared with the handles, used to tell when they are all dropped
+  status  : std::sync::Arc <std::sync::atomic::AtomicUsize>,
+  texture : Result <Texture2d, TextureCreationError>
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  enums                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+/// Layout of uploaded pixels, with 8-bit channels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UploadFormat {
+  R8,
+  Rg8,
+  Rgb8,
+  Rgba8
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  impls                                                                    //
+///////////////////////////////////////////////////////////////////////////////
+
+impl TextureUploadQueue {
+  /// Enqueue a texture for uploading, returning `None` if the display has
+  /// been dropped.
+  ///
+  /// Panics if the size of the pixel data does not match the dimensions and
+  /// format.
+  pub fn enqueue (&self, upload : TextureUpload) -> Option <TextureHandle> {
+    use std::sync::atomic::Ordering;
+    let (width, height) = upload.dimensions;
+    assert_eq!(upload.pixels.len(),
+      width as usize * height as usize * upload.format.bytes_per_pixel(),
+      "pixel data size does not match the texture dimensions");
+    let handle = TextureHandle {
+      id:     self.next_id.fetch_add (1, Ordering::SeqCst),
+      status: std::sync::Arc::new (
+        std::sync::atomic::AtomicUsize::new (STATUS_PENDING))
+    };
+    self.sender.send (UploadRequest { upload, handle: handle.clone() }).ok()
+      .map (|()| handle)
+  }
+}
+
+impl TextureUpload {
+  /// A texture without mipmaps.
+  pub fn new (pixels : Vec <u8>, dimensions : (u32, u32), format : UploadFormat)
+    -> Self
+  {
+    TextureUpload { pixels, dimensions, format, mipmaps: false }
+  }
+}
+
+impl TextureHandle {
+  /// Returns true once the texture has been uploaded, or its upload failed.
+  pub fn is_complete (&self) -> bool {
+    self.status.load (std::sync::atomic::Ordering::SeqCst) != STATUS_PENDING
+  }
+
+  /// Returns true if uploading the texture failed.
+  pub fn is_failed (&self) -> bool {
+    self.status.load (std::sync::atomic::Ordering::SeqCst) == STATUS_FAILED
+  }
+}
+
+impl UploadFormat {
+  pub fn bytes_per_pixel (self) -> usize {
+    match self {
+      UploadFormat::R8    => 1,
+      UploadFormat::Rg8   => 2,
+      UploadFormat::Rgb8  => 3,
+      UploadFormat::Rgba8 => 4
+    }
+  }
+
+  fn client_format (self) -> glium::texture::ClientFormat {
+    use glium::texture::ClientFormat;
+    match self {
+      UploadFormat::R8    => ClientFormat::U8,
+      UploadFormat::Rg8   => ClientFormat::U8U8,
+      UploadFormat::Rgb8  => ClientFormat::U8U8U8,
+      UploadFormat::Rgba8 => ClientFormat::U8U8U8U8
+    }
+  }
+}
+
+impl TextureUploads {
+  pub(crate) fn queue (&self) -> TextureUploadQueue {
+    self.queue.clone()
+  }
+
+  pub(crate) fn budget (&self) -> Option <std::time::Duration> {
+    self.budget
+  }
+
+  pub(crate) fn set_budget (&mut self, budget : Option <std::time::Duration>) {
+    self.budget = budget
+  }
+
+  /// Upload queued textures until the queue is empty or the budget is spent,
+  /// returning the number uploaded.
+  ///
+  /// The context must be current.
+  pub(crate) fn upload (&mut self,
+    context : &std::rc::Rc <glium::backend::Context>
+  ) -> usize {
+    use std::sync::atomic::Ordering;
+    // textures whose handles have all been dropped can never be taken
+    self.textures.retain (|_, texture|
+      std::sync::Arc::strong_count (&texture.status) > 1);
+    let start = std::time::Instant::now();
+    let mut uploaded = 0;
+    while self.budget.is_none_or (|budget| uploaded == 0
+      || start.elapsed() < budget)
+    {
+      let UploadRequest { upload, handle } = match self.receiver.try_recv() {
+        Ok (request) => request,
+        Err (_) => break
+      };
+      let texture = create_texture (context, upload);
+      if let Err (ref err) = texture {
+        log_record!(Warn, "texture upload failed: {:?}", err);
+      }
+      handle.status.store (
+        if texture.is_ok() { STATUS_UPLOADED } else { STATUS_FAILED },
+        Ordering::SeqCst);
+      let TextureHandle { id, status } = handle;
+      self.textures.insert (id, UploadedTexture { status, texture });
+      uploaded += 1;
+    }
+    uploaded
+  }
+
+  /// Take the texture of a handle, or `None` if it has not been uploaded or
+  /// was already taken.
+  pub(crate) fn take (&mut self, handle : &TextureHandle)
+    -> Option <Result <Texture2d, TextureCreationError>>
+  {
+    self.textures.remove (&handle.id).map (|texture| texture.texture)
+  }
+}
+
+impl Default for TextureUploads {
+  fn default() -> Self {
+    let (sender, receiver) = std::sync::mpsc::channel();
+    TextureUploads {
+      queue:    TextureUploadQueue {
+        sender,
+        next_id: std::sync::Arc::new (std::sync::atomic::AtomicUsize::new (0))
+      },
+      receiver,
+      budget:   None,
+      textures: std::collections::HashMap::new()
+    }
+  }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  functions                                                                //
+///////////////////////////////////////////////////////////////////////////////
+
+//
+// private
+//
+
+fn create_texture (
+  context : &std::rc::Rc <glium::backend::Context>,
+  upload  : TextureUpload
+) -> Result <Texture2d, TextureCreationError> {
+  let TextureUpload { pixels, dimensions: (width, height), format, mipmaps }
+    = upload;
+  let image = glium::texture::RawImage2d {
+    data:   std::borrow::Cow::Owned (pixels),
+    width,
+    height,
+    format: format.client_format()
+  };
+  let mipmaps = if mipmaps {
+    glium::texture::MipmapsOption::AutoGeneratedMipmaps
+  } else {
+    glium::texture::MipmapsOption::NoMipmap
+  };
+  Texture2d::with_mipmaps (context, image, mipmaps)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  #[test]
+  fn test_enqueue() {
+    let uploads = TextureUploads::default();
+    let queue   = uploads.queue();
+    let first   = queue.enqueue (
+      TextureUpload::new (vec![0; 2 * 3 * 4], (2, 3), UploadFormat::Rgba8)
+    ).unwrap();
+    let second  = queue.clone().enqueue (
+      TextureUpload::new (vec![0; 3], (1, 1), UploadFormat::Rgb8)
+    ).unwrap();
+    assert_ne!(first.id, second.id);
+    assert!(!first.is_complete());
+    let request = uploads.receiver.try_recv().unwrap();
+    assert_eq!(request.handle.id, first.id);
+    assert_eq!(request.upload.dimensions, (2, 3));
+    drop (request);
+    drop (uploads);
+    assert!(queue.enqueue (
+      TextureUpload::new (vec![0], (1, 1), UploadFormat::R8)).is_none());
+  }
+
+  #[test]
+  #[should_panic(expected = "pixel data size")]
+  fn test_enqueue_size_mismatch() {
+    let uploads = TextureUploads::default();
+    uploads.queue().enqueue (
+      TextureUpload::new (vec![0; 3], (1, 1), UploadFormat::Rgba8));
+  }
+}